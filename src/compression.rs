@@ -0,0 +1,234 @@
+//! DEFLATE compression (RFC 1951) and the zlib (RFC 1950) and gzip (RFC 1952) formats around it
+
+use std::fmt::Display;
+use std::io::Read;
+
+mod compressor;
+mod decompressor;
+mod huffman;
+
+pub use compressor::Encoder;
+pub use decompressor::Decoder;
+
+/// A reasonable trade-off between speed and size, the same default zlib uses
+pub const DEFAULT_LEVEL: u32 = 6;
+
+/// How the compressed data is framed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw DEFLATE data without a header or checksum
+    Deflate,
+    /// A two byte header and an Adler-32 checksum, what HTTP calls `deflate`
+    Zlib,
+    /// A header that could carry a file name and a CRC-32 checksum, what HTTP calls `gzip`
+    Gzip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressError {
+    /// A zlib or gzip header that's malformed or uses an unsupported feature
+    InvalidHeader,
+    /// A reserved block type or a stored block whose length check fails
+    InvalidBlock,
+    /// A Huffman code that isn't a prefix code, or a bit pattern that isn't part of the code
+    InvalidCode,
+    /// A back-reference to before the start of the output
+    InvalidDistance,
+    ChecksumMismatch,
+    /// The gzip trailer has the wrong uncompressed length
+    LengthMismatch,
+    UnexpectedEnd,
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "Invalid Compressed Stream Header"),
+            Self::InvalidBlock => write!(f, "Invalid Deflate Block"),
+            Self::InvalidCode => write!(f, "Invalid Huffman Code"),
+            Self::InvalidDistance => write!(f, "Invalid Back-Reference Distance"),
+            Self::ChecksumMismatch => write!(f, "Checksum Mismatch"),
+            Self::LengthMismatch => write!(f, "Length Mismatch"),
+            Self::UnexpectedEnd => write!(f, "Unexpected End of Compressed Data"),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {}
+
+impl From<DecompressError> for std::io::Error {
+    fn from(err: DecompressError) -> Self {
+        match err {
+            DecompressError::UnexpectedEnd => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, err)
+            }
+            err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Compresses `data` in one go, `level` goes from 0 for no compression to 9 for the smallest
+/// output
+pub fn compress(data: &[u8], format: Format, level: u32) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    Encoder::new(data, format, level)
+        .read_to_end(&mut compressed)
+        .expect("reading from a slice can't fail");
+    compressed
+}
+
+/// Decompresses `data` in one go, there's no limit on the size of the output
+pub fn decompress(data: &[u8], format: Format) -> Result<Vec<u8>, DecompressError> {
+    let mut decompressed = Vec::new();
+    Decoder::new(data, format)
+        .read_to_end(&mut decompressed)
+        .map_err(|err| {
+            // Reading from a slice can't fail, so the error is always one of ours
+            err.into_inner()
+                .and_then(|inner| inner.downcast::<DecompressError>().ok())
+                .map_or(DecompressError::UnexpectedEnd, |err| *err)
+        })?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Text-like data with plenty of repetition at all distances
+    fn sample(len: usize) -> Vec<u8> {
+        let words = ["alpha ", "beta ", "gamma ", "delta\n", "{\"id\": ", "42, "];
+        let mut state = 12345_u32;
+        let mut data = Vec::new();
+
+        while data.len() < len {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            data.extend_from_slice(words[(state >> 16) as usize % words.len()].as_bytes());
+            if state.is_multiple_of(7) {
+                data.push((state >> 8) as u8);
+            }
+        }
+
+        data.truncate(len);
+        data
+    }
+
+    #[test]
+    fn round_trips() {
+        let inputs = [
+            Vec::new(),
+            b"a".to_vec(),
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_vec(),
+            (0..=255).collect(),
+            sample(1000),
+            sample(70_000),
+        ];
+
+        for data in &inputs {
+            for format in [Format::Deflate, Format::Zlib, Format::Gzip] {
+                for level in [0, 1, 6, 9] {
+                    let compressed = compress(data, format, level);
+                    let decompressed = decompress(&compressed, format).unwrap();
+                    assert!(
+                        decompressed == *data,
+                        "{:?} level {} len {}",
+                        format,
+                        level,
+                        data.len()
+                    );
+
+                    if level > 0 && data.len() > 1000 {
+                        assert!(compressed.len() < data.len() / 3);
+                    }
+                }
+            }
+        }
+
+        // Streams that aren't cut along the block boundaries
+        let data = sample(100_000);
+        let mut decoder = Decoder::new(Encoder::new(&data[..], Format::Gzip, 6), Format::Gzip);
+        let mut decompressed = Vec::new();
+        let mut buf = [0; 1000];
+        loop {
+            match decoder.read(&mut buf[..777]).unwrap() {
+                0 => break,
+                len => decompressed.extend_from_slice(&buf[..len]),
+            }
+        }
+        assert!(decompressed == data);
+    }
+
+    #[test]
+    fn known_streams() {
+        // zlib.compress(b"hello hello hello hello")
+        let zlib = [
+            0x78, 0x9C, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01, 0x68, 0x03,
+            0x08, 0xB1,
+        ];
+        assert_eq!(
+            decompress(&zlib, Format::Zlib).unwrap(),
+            b"hello hello hello hello"
+        );
+
+        // Raw deflate with a stored block followed by a fixed Huffman block
+        let raw = [0x00, 0x02, 0x00, 0xFD, 0xFF, b'h', b'i', 0x03, 0x00];
+        assert_eq!(decompress(&raw, Format::Deflate).unwrap(), b"hi");
+
+        // `printf abc | gzip -9n` followed by a second member
+        let gzip = [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4B, 0x4C, 0x4A, 0x06,
+            0x00, 0xC2, 0x41, 0x24, 0x35, 0x03, 0x00, 0x00, 0x00,
+        ];
+        let mut twice = gzip.to_vec();
+        twice.extend_from_slice(&gzip);
+        assert_eq!(decompress(&gzip, Format::Gzip).unwrap(), b"abc");
+        assert_eq!(decompress(&twice, Format::Gzip).unwrap(), b"abcabc");
+    }
+
+    #[test]
+    fn corrupt_streams() {
+        let data = sample(10_000);
+        let gzip = compress(&data, Format::Gzip, 6);
+
+        let mut bad_crc = gzip.clone();
+        let len = bad_crc.len();
+        bad_crc[len - 8] ^= 1;
+        assert_eq!(
+            decompress(&bad_crc, Format::Gzip),
+            Err(DecompressError::ChecksumMismatch)
+        );
+
+        assert_eq!(
+            decompress(&gzip[..gzip.len() / 2], Format::Gzip),
+            Err(DecompressError::UnexpectedEnd)
+        );
+        assert_eq!(
+            decompress(&gzip, Format::Zlib),
+            Err(DecompressError::InvalidHeader)
+        );
+
+        // Reserved block type
+        assert_eq!(
+            decompress(&[0x07], Format::Deflate),
+            Err(DecompressError::InvalidBlock)
+        );
+
+        // A fixed block whose first match reaches back before the start
+        assert_eq!(
+            decompress(&[0x03, 0x02], Format::Deflate),
+            Err(DecompressError::InvalidDistance)
+        );
+
+        // Random garbage fails one way or another but never panics
+        let mut state = 1_u32;
+        for _ in 0..200 {
+            let garbage: Vec<u8> = (0..64)
+                .map(|_| {
+                    state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                    (state >> 16) as u8
+                })
+                .collect();
+            let _ = decompress(&garbage, Format::Deflate);
+        }
+    }
+}
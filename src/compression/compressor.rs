@@ -0,0 +1,607 @@
+use std::io::{self, Read};
+
+use super::huffman::{
+    self, CODE_LEN_ORDER, DIST_BASE, DIST_EXTRA, END_OF_BLOCK, LENGTH_BASE, LENGTH_EXTRA,
+    MAX_CODE_LEN, MAX_CODE_LEN_CODE_LEN,
+};
+use super::Format;
+use crate::hash::{Adler32, Crc32};
+
+/// How much input goes into one block, also how much is read before anything comes out
+const BLOCK_SIZE: usize = 64 * 1024;
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_STORED: usize = u16::MAX as usize;
+
+const HASH_BITS: u32 = 15;
+
+/// Writes bits least significant first, as DEFLATE packs them
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+
+        while self.bit_count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.write(0, 8 - self.bit_count);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 },
+}
+
+/// How hard the match finder tries at a level
+struct Effort {
+    /// How many earlier positions with the same hash are compared at most
+    max_chain: usize,
+    /// A match at least this long is taken without looking further
+    nice_len: usize,
+    /// Whether a match is put off by a byte when the next position has a longer one
+    lazy: bool,
+}
+
+impl Effort {
+    fn for_level(level: u32) -> Self {
+        let (max_chain, nice_len, lazy) = match level {
+            1 => (4, 8, false),
+            2 => (8, 16, false),
+            3 => (16, 32, false),
+            4 => (16, 16, true),
+            5 => (32, 32, true),
+            6 => (128, 128, true),
+            7 => (256, MAX_MATCH, true),
+            8 => (1024, MAX_MATCH, true),
+            _ => (4096, MAX_MATCH, true),
+        };
+
+        Self {
+            max_chain,
+            nice_len,
+            lazy,
+        }
+    }
+}
+
+/// Hash chains over the window and the block being compressed
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    /// Most recent position for each hash of three bytes
+    head: Vec<u32>,
+    /// The position before this one with the same hash
+    prev: Vec<u32>,
+    /// Positions before this one have been inserted
+    inserted: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    const NONE: u32 = u32::MAX;
+
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![Self::NONE; 1 << HASH_BITS],
+            prev: vec![Self::NONE; data.len()],
+            inserted: 0,
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = [self.data[pos], self.data[pos + 1], self.data[pos + 2], 0];
+        (u32::from_le_bytes(bytes).wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert_until(&mut self, end: usize) {
+        let end = end.min(self.data.len().saturating_sub(MIN_MATCH - 1));
+
+        while self.inserted < end {
+            let hash = self.hash(self.inserted);
+            self.prev[self.inserted] = self.head[hash];
+            self.head[hash] = self.inserted as u32;
+            self.inserted += 1;
+        }
+    }
+
+    /// The longest earlier match for the bytes at `pos` as length and distance
+    fn find(&mut self, pos: usize, effort: &Effort) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > self.data.len() {
+            return None;
+        }
+
+        self.insert_until(pos);
+
+        let max_len = MAX_MATCH.min(self.data.len() - pos);
+        let target = &self.data[pos..pos + max_len];
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(pos)];
+
+        for _ in 0..effort.max_chain {
+            if candidate == Self::NONE || pos - candidate as usize > WINDOW_SIZE {
+                break;
+            }
+
+            let start = candidate as usize;
+            let best_len = best.map_or(MIN_MATCH - 1, |(len, _)| len);
+
+            // Only worth comparing in full if it could beat the best match so far
+            if self.data[start + best_len] == target[best_len] {
+                let len = target
+                    .iter()
+                    .zip(&self.data[start..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if len > best_len {
+                    best = Some((len, pos - start));
+                    if len >= effort.nice_len || len == max_len {
+                        break;
+                    }
+                }
+            }
+
+            candidate = self.prev[start];
+        }
+
+        best
+    }
+}
+
+/// Streaming raw DEFLATE compression (RFC 1951), one block per call
+struct Deflater {
+    level: u32,
+    /// The end of the previous blocks, for matches across block boundaries
+    history: Vec<u8>,
+    writer: BitWriter,
+}
+
+impl Deflater {
+    fn new(level: u32) -> Self {
+        Self {
+            level: level.min(9),
+            history: Vec::new(),
+            writer: BitWriter {
+                out: Vec::new(),
+                bits: 0,
+                bit_count: 0,
+            },
+        }
+    }
+
+    /// Whole bytes written so far, a few bits may be left over until the next block
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.writer.out)
+    }
+
+    fn compress_block(&mut self, data: &[u8], last: bool) {
+        if self.level == 0 {
+            self.write_stored(data, last);
+            return;
+        }
+
+        let tokens = self.find_matches(data);
+
+        let mut lit_freqs = [0_u32; 286];
+        let mut dist_freqs = [0_u32; 30];
+        lit_freqs[END_OF_BLOCK as usize] = 1;
+        for token in &tokens {
+            match *token {
+                Token::Literal(byte) => lit_freqs[byte as usize] += 1,
+                Token::Match { len, dist } => {
+                    lit_freqs[257 + huffman::length_index(len)] += 1;
+                    dist_freqs[huffman::dist_index(dist)] += 1;
+                }
+            }
+        }
+
+        let lit_lengths = huffman::code_lengths(&lit_freqs, MAX_CODE_LEN);
+        let dist_lengths = huffman::code_lengths(&dist_freqs, MAX_CODE_LEN);
+        let header = DynamicHeader::new(&lit_lengths, &dist_lengths);
+
+        let fixed_lit_lengths = huffman::fixed_lit_lengths();
+        let fixed_dist_lengths = huffman::fixed_dist_lengths();
+
+        let dynamic_cost = header.cost() + tokens_cost(&tokens, &lit_lengths, &dist_lengths);
+        let fixed_cost = tokens_cost(&tokens, &fixed_lit_lengths, &fixed_dist_lengths);
+        let stored_cost = (data.len() / MAX_STORED + 1) * (3 + 7 + 32) + data.len() * 8;
+
+        if stored_cost <= dynamic_cost.min(fixed_cost) {
+            self.write_stored(data, last);
+        } else if fixed_cost <= dynamic_cost {
+            self.writer.write(last as u32, 1);
+            self.writer.write(1, 2);
+            self.write_tokens(&tokens, &fixed_lit_lengths, &fixed_dist_lengths);
+        } else {
+            self.writer.write(last as u32, 1);
+            self.writer.write(2, 2);
+            header.write(&mut self.writer);
+            self.write_tokens(&tokens, &lit_lengths, &dist_lengths);
+        }
+
+        if last {
+            self.writer.align();
+        }
+
+        self.history.extend_from_slice(data);
+        if self.history.len() > WINDOW_SIZE {
+            self.history.drain(..self.history.len() - WINDOW_SIZE);
+        }
+    }
+
+    fn write_stored(&mut self, data: &[u8], last: bool) {
+        // An empty block still has to be written to end the stream
+        let chunks: Vec<&[u8]> = match data.is_empty() {
+            true => vec![&[]],
+            false => data.chunks(MAX_STORED).collect(),
+        };
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            self.writer
+                .write((last && index == chunks.len() - 1) as u32, 1);
+            self.writer.write(0, 2);
+            self.writer.align();
+            self.writer.write(chunk.len() as u32, 16);
+            self.writer.write(!(chunk.len() as u16) as u32, 16);
+            self.writer.out.extend_from_slice(chunk);
+        }
+    }
+
+    fn find_matches(&self, data: &[u8]) -> Vec<Token> {
+        let effort = Effort::for_level(self.level);
+        let buf = [&self.history[..], data].concat();
+        let mut finder = MatchFinder::new(&buf);
+        let mut tokens = Vec::with_capacity(data.len() / 2);
+
+        let mut pos = self.history.len();
+        let mut pending = finder.find(pos, &effort);
+
+        while pos < buf.len() {
+            let Some((len, dist)) = pending else {
+                tokens.push(Token::Literal(buf[pos]));
+                pos += 1;
+                pending = finder.find(pos, &effort);
+                continue;
+            };
+
+            // A longer match starting at the next byte is worth a literal
+            if effort.lazy && len < effort.nice_len {
+                let next = finder.find(pos + 1, &effort);
+                if next.is_some_and(|(next_len, _)| next_len > len) {
+                    tokens.push(Token::Literal(buf[pos]));
+                    pos += 1;
+                    pending = next;
+                    continue;
+                }
+            }
+
+            tokens.push(Token::Match {
+                len: len as u16,
+                dist: dist as u16,
+            });
+            pos += len;
+            pending = finder.find(pos, &effort);
+        }
+
+        tokens
+    }
+
+    fn write_tokens(&mut self, tokens: &[Token], lit_lengths: &[u8], dist_lengths: &[u8]) {
+        let lit_codes = huffman::codes(lit_lengths);
+        let dist_codes = huffman::codes(dist_lengths);
+        let writer = &mut self.writer;
+
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => {
+                    let byte = byte as usize;
+                    writer.write(lit_codes[byte] as u32, lit_lengths[byte] as u32);
+                }
+                Token::Match { len, dist } => {
+                    let index = huffman::length_index(len);
+                    let symbol = 257 + index;
+                    writer.write(lit_codes[symbol] as u32, lit_lengths[symbol] as u32);
+                    writer.write(
+                        (len - LENGTH_BASE[index]) as u32,
+                        LENGTH_EXTRA[index] as u32,
+                    );
+
+                    let index = huffman::dist_index(dist);
+                    writer.write(dist_codes[index] as u32, dist_lengths[index] as u32);
+                    writer.write((dist - DIST_BASE[index]) as u32, DIST_EXTRA[index] as u32);
+                }
+            }
+        }
+
+        let end = END_OF_BLOCK as usize;
+        writer.write(lit_codes[end] as u32, lit_lengths[end] as u32);
+    }
+}
+
+/// Size of the tokens in bits, including the end of the block
+fn tokens_cost(tokens: &[Token], lit_lengths: &[u8], dist_lengths: &[u8]) -> usize {
+    let mut cost = 3 + lit_lengths[END_OF_BLOCK as usize] as usize;
+
+    for token in tokens {
+        cost += match *token {
+            Token::Literal(byte) => lit_lengths[byte as usize] as usize,
+            Token::Match { len, dist } => {
+                let len_index = huffman::length_index(len);
+                let dist_index = huffman::dist_index(dist);
+                (lit_lengths[257 + len_index] + LENGTH_EXTRA[len_index]) as usize
+                    + (dist_lengths[dist_index] + DIST_EXTRA[dist_index]) as usize
+            }
+        };
+    }
+
+    cost
+}
+
+/// The code lengths of a dynamic block, themselves run-length and Huffman coded
+struct DynamicHeader {
+    lit_count: usize,
+    dist_count: usize,
+    /// Code length symbols with the value of their extra bits
+    symbols: Vec<(u8, u8)>,
+    code_len_lengths: Vec<u8>,
+    code_len_count: usize,
+}
+
+impl DynamicHeader {
+    fn new(lit_lengths: &[u8], dist_lengths: &[u8]) -> Self {
+        let lit_count = 257.max(lit_lengths.iter().rposition(|len| *len > 0).unwrap_or(0) + 1);
+        let dist_count = 1.max(dist_lengths.iter().rposition(|len| *len > 0).unwrap_or(0) + 1);
+
+        let lengths = [&lit_lengths[..lit_count], &dist_lengths[..dist_count]].concat();
+        let mut symbols = Vec::new();
+        let mut pos = 0;
+
+        while pos < lengths.len() {
+            let len = lengths[pos];
+            let run = lengths[pos..].iter().take_while(|l| **l == len).count();
+
+            if len == 0 && run >= 11 {
+                let run = run.min(138);
+                symbols.push((18, (run - 11) as u8));
+                pos += run;
+            } else if len == 0 && run >= 3 {
+                symbols.push((17, (run - 3) as u8));
+                pos += run;
+            } else if len != 0 && run >= 4 {
+                // The first one is sent as is, the rest repeat it
+                let run = (run - 1).min(6);
+                symbols.push((len, 0));
+                symbols.push((16, (run - 3) as u8));
+                pos += 1 + run;
+            } else {
+                symbols.push((len, 0));
+                pos += 1;
+            }
+        }
+
+        let mut freqs = [0_u32; 19];
+        for (symbol, _) in &symbols {
+            freqs[*symbol as usize] += 1;
+        }
+
+        let code_len_lengths = huffman::code_lengths(&freqs, MAX_CODE_LEN_CODE_LEN);
+        let code_len_count = 4.max(
+            CODE_LEN_ORDER
+                .iter()
+                .rposition(|symbol| code_len_lengths[*symbol] > 0)
+                .unwrap_or(0)
+                + 1,
+        );
+
+        Self {
+            lit_count,
+            dist_count,
+            symbols,
+            code_len_lengths,
+            code_len_count,
+        }
+    }
+
+    fn extra_bits(symbol: u8) -> u32 {
+        match symbol {
+            16 => 2,
+            17 => 3,
+            18 => 7,
+            _ => 0,
+        }
+    }
+
+    fn cost(&self) -> usize {
+        let symbols: usize = self
+            .symbols
+            .iter()
+            .map(|(symbol, _)| {
+                self.code_len_lengths[*symbol as usize] as usize
+                    + Self::extra_bits(*symbol) as usize
+            })
+            .sum();
+
+        5 + 5 + 4 + 3 * self.code_len_count + symbols
+    }
+
+    fn write(&self, writer: &mut BitWriter) {
+        writer.write(self.lit_count as u32 - 257, 5);
+        writer.write(self.dist_count as u32 - 1, 5);
+        writer.write(self.code_len_count as u32 - 4, 4);
+
+        for symbol in &CODE_LEN_ORDER[..self.code_len_count] {
+            writer.write(self.code_len_lengths[*symbol] as u32, 3);
+        }
+
+        let codes = huffman::codes(&self.code_len_lengths);
+        for (symbol, extra) in &self.symbols {
+            let symbol = *symbol as usize;
+            writer.write(codes[symbol] as u32, self.code_len_lengths[symbol] as u32);
+            writer.write(*extra as u32, Self::extra_bits(symbol as u8));
+        }
+    }
+}
+
+/// Compresses the data read from `R` as it is read itself
+///
+/// Input is compressed in blocks of 64 KiB, so this doesn't suit interactive streams where
+/// each piece has to come out as soon as it was written
+pub struct Encoder<R: Read> {
+    rx: R,
+    format: Format,
+    deflater: Deflater,
+    adler32: Adler32,
+    crc32: Crc32,
+    len: u32,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    output_pos: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> Encoder<R> {
+    /// `level` goes from 0 for no compression to 9 for the smallest output
+    pub fn new(rx: R, format: Format, level: u32) -> Self {
+        Self {
+            rx,
+            format,
+            deflater: Deflater::new(level),
+            adler32: Adler32::new(),
+            crc32: Crc32::new(),
+            len: 0,
+            input: Vec::new(),
+            output: Vec::new(),
+            output_pos: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.rx
+    }
+
+    pub fn into_inner(self) -> R {
+        self.rx
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let level = self.deflater.level;
+
+        match self.format {
+            Format::Deflate => Vec::new(),
+            Format::Zlib => {
+                // Deflate with a 32 KiB window, FLEVEL is only informative
+                let cmf = 0x78;
+                let flevel = match level {
+                    0..=1 => 0,
+                    2..=5 => 1,
+                    6 => 2,
+                    _ => 3,
+                };
+                let flg = flevel << 6;
+                let check = (31 - u16::from_be_bytes([cmf, flg]) % 31) % 31;
+                vec![cmf, flg | check as u8]
+            }
+            Format::Gzip => {
+                // No file name or modification time, and an unknown operating system
+                let extra_flags = match level {
+                    9 => 2,
+                    1 => 4,
+                    _ => 0,
+                };
+                vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, extra_flags, 255]
+            }
+        }
+    }
+
+    fn trailer(&self) -> Vec<u8> {
+        match self.format {
+            Format::Deflate => Vec::new(),
+            Format::Zlib => self.adler32.finish().to_be_bytes().to_vec(),
+            Format::Gzip => [self.crc32.finish().to_le_bytes(), self.len.to_le_bytes()].concat(),
+        }
+    }
+
+    /// Reads a block of input, true at the end of the input
+    fn fill_input(&mut self) -> io::Result<bool> {
+        self.input.resize(BLOCK_SIZE, 0);
+        let mut len = 0;
+
+        let eof = loop {
+            if len == BLOCK_SIZE {
+                break false;
+            }
+
+            match self.rx.read(&mut self.input[len..]) {
+                Ok(0) => break true,
+                Ok(read) => len += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.input.truncate(len);
+                    return Err(err);
+                }
+            }
+        };
+
+        self.input.truncate(len);
+        Ok(eof)
+    }
+}
+
+impl<R: Read> Read for Encoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_pos == self.output.len() && !self.finished {
+            self.output.clear();
+            self.output_pos = 0;
+
+            if !self.started {
+                self.output = self.header();
+                self.started = true;
+            }
+
+            let eof = self.fill_input()?;
+            match self.format {
+                Format::Deflate => {}
+                Format::Zlib => {
+                    self.adler32.update(&self.input);
+                }
+                Format::Gzip => {
+                    self.crc32.update(&self.input);
+                    self.len = self.len.wrapping_add(self.input.len() as u32);
+                }
+            }
+
+            self.deflater.compress_block(&self.input, eof);
+            self.output.extend(self.deflater.take_output());
+
+            if eof {
+                let trailer = self.trailer();
+                self.output.extend(trailer);
+                self.finished = true;
+            }
+        }
+
+        let len = buf.len().min(self.output.len() - self.output_pos);
+        buf[..len].copy_from_slice(&self.output[self.output_pos..self.output_pos + len]);
+        self.output_pos += len;
+        Ok(len)
+    }
+}
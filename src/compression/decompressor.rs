@@ -0,0 +1,546 @@
+use std::io::{self, Read};
+
+use super::huffman::{
+    self, HuffmanTable, CODE_LEN_ORDER, DIST_BASE, DIST_EXTRA, END_OF_BLOCK, LENGTH_BASE,
+    LENGTH_EXTRA,
+};
+use super::{DecompressError, Format};
+use crate::hash::{Adler32, Crc32};
+
+/// Back-references reach at most this far into the output
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Reads the input least significant bit first, as DEFLATE packs it
+pub(crate) struct BitReader<R: Read> {
+    rx: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    bits: u64,
+    bit_count: u32,
+    eof: bool,
+}
+
+impl<R: Read> BitReader<R> {
+    pub(crate) fn new(rx: R) -> Self {
+        Self {
+            rx,
+            buf: vec![0; 8192].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            bits: 0,
+            bit_count: 0,
+            eof: false,
+        }
+    }
+
+    fn refill(&mut self) -> io::Result<()> {
+        while self.bit_count <= 56 {
+            if self.pos == self.len && (self.eof || !self.fill_buf()?) {
+                break;
+            }
+
+            self.bits |= (self.buf[self.pos] as u64) << self.bit_count;
+            self.pos += 1;
+            self.bit_count += 8;
+        }
+
+        Ok(())
+    }
+
+    /// Reads more input into the byte buffer, false at the end of the input
+    fn fill_buf(&mut self) -> io::Result<bool> {
+        loop {
+            match self.rx.read(&mut self.buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(false);
+                }
+                Ok(len) => {
+                    self.pos = 0;
+                    self.len = len;
+                    return Ok(true);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// The next `count` bits, which may run past the end of the input as zeros
+    fn peek(&mut self, count: u32) -> io::Result<u32> {
+        if self.bit_count < count {
+            self.refill()?;
+        }
+
+        Ok((self.bits & ((1 << count) - 1)) as u32)
+    }
+
+    fn consume(&mut self, count: u32) -> Result<(), DecompressError> {
+        if count > self.bit_count {
+            return Err(DecompressError::UnexpectedEnd);
+        }
+
+        self.bits >>= count;
+        self.bit_count -= count;
+        Ok(())
+    }
+
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        let value = self.peek(count)?;
+        self.consume(count)?;
+        Ok(value)
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> io::Result<u16> {
+        let bits = self.peek(table.max_len())?;
+        let (symbol, len) = table.lookup(bits)?;
+        self.consume(len)?;
+        Ok(symbol)
+    }
+
+    /// Whether the input ended, only meaningful on a byte boundary
+    pub(crate) fn is_at_end(&mut self) -> io::Result<bool> {
+        if self.bit_count == 0 {
+            self.refill()?;
+        }
+
+        Ok(self.bit_count == 0)
+    }
+
+    /// Skips to the next byte boundary
+    pub(crate) fn align(&mut self) {
+        let skip = self.bit_count % 8;
+        self.bits >>= skip;
+        self.bit_count -= skip;
+    }
+
+    /// The next whole byte after [BitReader::align], [None] at the end of the input
+    pub(crate) fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.bit_count == 0 {
+            if self.pos == self.len && (self.eof || !self.fill_buf()?) {
+                return Ok(None);
+            }
+
+            self.pos += 1;
+            return Ok(Some(self.buf[self.pos - 1]));
+        }
+
+        Ok(Some(self.bits(8)? as u8))
+    }
+
+    pub(crate) fn read_bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        for byte in &mut bytes {
+            *byte = self.read_byte()?.ok_or(DecompressError::UnexpectedEnd)?;
+        }
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.rx
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.rx
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.rx
+    }
+}
+
+enum Block {
+    /// Between blocks, reading the next block header
+    Header,
+    /// Bytes left in an uncompressed block
+    Stored(u16),
+    Huffman(Box<(HuffmanTable, HuffmanTable)>),
+    Done,
+}
+
+/// Streaming raw DEFLATE decompression (RFC 1951)
+pub(crate) struct Inflater<R: Read> {
+    input: BitReader<R>,
+    /// The last [WINDOW_SIZE] bytes handed out followed by those that haven't been yet
+    window: Vec<u8>,
+    read_pos: usize,
+    block: Block,
+    last_block: bool,
+}
+
+impl<R: Read> Inflater<R> {
+    pub(crate) fn new(input: BitReader<R>) -> Self {
+        Self {
+            input,
+            window: Vec::new(),
+            read_pos: 0,
+            block: Block::Header,
+            last_block: false,
+        }
+    }
+
+    pub(crate) fn input(&mut self) -> &mut BitReader<R> {
+        &mut self.input
+    }
+
+    pub(crate) fn into_input(self) -> BitReader<R> {
+        self.input
+    }
+
+    /// Starts over with another stream from the same input
+    pub(crate) fn reset(&mut self) {
+        self.window.clear();
+        self.read_pos = 0;
+        self.block = Block::Header;
+        self.last_block = false;
+    }
+
+    /// Decompresses until `target` bytes are waiting to be read or the stream ends
+    fn inflate(&mut self, target: usize) -> io::Result<()> {
+        // Only the window is needed for back-references, drop what's before it now and then
+        if self.read_pos > 2 * WINDOW_SIZE {
+            self.window.drain(..self.read_pos - WINDOW_SIZE);
+            self.read_pos = WINDOW_SIZE;
+        }
+
+        while self.window.len() - self.read_pos < target {
+            match &mut self.block {
+                Block::Header if self.last_block => self.block = Block::Done,
+                Block::Header => self.read_block_header()?,
+                Block::Stored(0) => self.block = Block::Header,
+                Block::Stored(remaining) => {
+                    let byte = self
+                        .input
+                        .read_byte()?
+                        .ok_or(DecompressError::UnexpectedEnd)?;
+                    self.window.push(byte);
+                    *remaining -= 1;
+                }
+                Block::Huffman(decoders) => {
+                    let (input, window) = (&mut self.input, &mut self.window);
+                    if inflate_codes(input, window, self.read_pos, decoders, target)? {
+                        self.block = Block::Header;
+                    }
+                }
+                Block::Done => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_block_header(&mut self) -> io::Result<()> {
+        self.last_block = self.input.bits(1)? == 1;
+
+        self.block = match self.input.bits(2)? {
+            0 => {
+                self.input.align();
+                let [len_lo, len_hi, nlen_lo, nlen_hi] = self.input.read_bytes()?;
+                let len = u16::from_le_bytes([len_lo, len_hi]);
+                if len != !u16::from_le_bytes([nlen_lo, nlen_hi]) {
+                    return Err(DecompressError::InvalidBlock.into());
+                }
+                Block::Stored(len)
+            }
+            1 => Block::Huffman(Box::new((
+                HuffmanTable::new(&huffman::fixed_lit_lengths())?,
+                HuffmanTable::new(&huffman::fixed_dist_lengths())?,
+            ))),
+            2 => Block::Huffman(Box::new(self.read_dynamic_codes()?)),
+            _ => return Err(DecompressError::InvalidBlock.into()),
+        };
+
+        Ok(())
+    }
+
+    fn read_dynamic_codes(&mut self) -> io::Result<(HuffmanTable, HuffmanTable)> {
+        let lit_count = self.input.bits(5)? as usize + 257;
+        let dist_count = self.input.bits(5)? as usize + 1;
+        let code_len_count = self.input.bits(4)? as usize + 4;
+
+        if lit_count > 286 || dist_count > 30 {
+            return Err(DecompressError::InvalidBlock.into());
+        }
+
+        let mut code_len_lengths = [0; 19];
+        for symbol in &CODE_LEN_ORDER[..code_len_count] {
+            code_len_lengths[*symbol] = self.input.bits(3)? as u8;
+        }
+        let code_len_table = HuffmanTable::new(&code_len_lengths)?;
+
+        // The two codes are sent as one sequence, repeats may cross from one into the other
+        let mut lengths = Vec::with_capacity(lit_count + dist_count);
+        while lengths.len() < lit_count + dist_count {
+            let (len, repeat) = match self.input.decode(&code_len_table)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths.last().ok_or(DecompressError::InvalidBlock)?;
+                    (previous, 3 + self.input.bits(2)?)
+                }
+                17 => (0, 3 + self.input.bits(3)?),
+                _ => (0, 11 + self.input.bits(7)?),
+            };
+
+            if lengths.len() + repeat as usize > lit_count + dist_count {
+                return Err(DecompressError::InvalidBlock.into());
+            }
+            lengths.extend(std::iter::repeat_n(len, repeat as usize));
+        }
+
+        if lengths[END_OF_BLOCK as usize] == 0 {
+            return Err(DecompressError::InvalidBlock.into());
+        }
+
+        Ok((
+            HuffmanTable::new(&lengths[..lit_count])?,
+            HuffmanTable::new(&lengths[lit_count..])?,
+        ))
+    }
+}
+
+impl<R: Read> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.read_pos == self.window.len() {
+            self.inflate(buf.len().max(4096))?;
+        }
+
+        let len = buf.len().min(self.window.len() - self.read_pos);
+        buf[..len].copy_from_slice(&self.window[self.read_pos..self.read_pos + len]);
+        self.read_pos += len;
+        Ok(len)
+    }
+}
+
+/// Decodes symbols of a compressed block, true once its end was reached
+fn inflate_codes<R: Read>(
+    input: &mut BitReader<R>,
+    window: &mut Vec<u8>,
+    read_pos: usize,
+    (lit, dist): &(HuffmanTable, HuffmanTable),
+    target: usize,
+) -> io::Result<bool> {
+    while window.len() - read_pos < target {
+        let symbol = input.decode(lit)?;
+        if symbol < END_OF_BLOCK {
+            window.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(true);
+        }
+
+        let index = symbol as usize - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(DecompressError::InvalidCode.into());
+        }
+        let len = LENGTH_BASE[index] as usize + input.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let index = input.decode(dist)? as usize;
+        if index >= DIST_BASE.len() {
+            return Err(DecompressError::InvalidCode.into());
+        }
+        let distance = DIST_BASE[index] as usize + input.bits(DIST_EXTRA[index] as u32)? as usize;
+
+        if distance > window.len() {
+            return Err(DecompressError::InvalidDistance.into());
+        }
+
+        // The source may overlap what is being written, so go byte by byte
+        let start = window.len() - distance;
+        for offset in 0..len {
+            window.push(window[start + offset]);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Decompresses the data read from `R` as it is read itself, checking the header and the
+/// checksum along the way
+pub struct Decoder<R: Read> {
+    inflater: Inflater<R>,
+    format: Format,
+    /// Whether zlib data without a zlib header is taken to be raw DEFLATE data
+    raw_fallback: bool,
+    adler32: Adler32,
+    crc32: Crc32,
+    len: u32,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(rx: R, format: Format) -> Self {
+        Self {
+            inflater: Inflater::new(BitReader::new(rx)),
+            format,
+            raw_fallback: false,
+            adler32: Adler32::new(),
+            crc32: Crc32::new(),
+            len: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Decodes zlib data but also accepts raw DEFLATE data, since some HTTP servers send that
+    /// for `Content-Encoding: deflate`
+    pub(crate) fn zlib_or_deflate(rx: R) -> Self {
+        Self {
+            raw_fallback: true,
+            ..Self::new(rx, Format::Zlib)
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inflater.input.get_ref()
+    }
+
+    /// Reading from it directly skips input the decoder may need
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inflater.input.get_mut()
+    }
+
+    /// The input after the end of the compressed data may already have been read
+    pub fn into_inner(self) -> R {
+        self.inflater.into_input().into_inner()
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let input = self.inflater.input();
+
+        match self.format {
+            Format::Deflate => {}
+            Format::Zlib if self.raw_fallback => {
+                let header = input.peek(16)?.to_le_bytes();
+                if read_zlib_header([header[0], header[1]]).is_ok() {
+                    input.read_bytes::<2>()?;
+                } else {
+                    self.format = Format::Deflate;
+                }
+            }
+            Format::Zlib => read_zlib_header(input.read_bytes()?)?,
+            Format::Gzip => read_gzip_header(input)?,
+        }
+
+        Ok(())
+    }
+
+    /// Checks the trailer once the compressed data ended, true if another gzip member follows
+    fn read_trailer(&mut self) -> io::Result<bool> {
+        let input = self.inflater.input();
+        input.align();
+
+        match self.format {
+            Format::Zlib => {
+                if u32::from_be_bytes(input.read_bytes()?) != self.adler32.finish() {
+                    return Err(DecompressError::ChecksumMismatch.into());
+                }
+                Ok(false)
+            }
+            Format::Gzip => {
+                let crc32 = u32::from_le_bytes(input.read_bytes()?);
+                let len = u32::from_le_bytes(input.read_bytes()?);
+                if crc32 != self.crc32.finish() {
+                    return Err(DecompressError::ChecksumMismatch.into());
+                }
+                if len != self.len {
+                    return Err(DecompressError::LengthMismatch.into());
+                }
+
+                // Concatenated gzip files decompress to the concatenation of their contents
+                Ok(!input.is_at_end()?)
+            }
+            Format::Deflate => Ok(false),
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            self.read_header()?;
+            self.started = true;
+        }
+
+        while !self.finished && !buf.is_empty() {
+            let len = self.inflater.read(buf)?;
+            if len > 0 {
+                match self.format {
+                    Format::Zlib => {
+                        self.adler32.update(&buf[..len]);
+                    }
+                    Format::Gzip => {
+                        self.crc32.update(&buf[..len]);
+                        self.len = self.len.wrapping_add(len as u32);
+                    }
+                    Format::Deflate => {}
+                }
+
+                return Ok(len);
+            }
+
+            if self.read_trailer()? {
+                self.inflater.reset();
+                self.crc32 = Crc32::new();
+                self.len = 0;
+                self.read_header()?;
+            } else {
+                self.finished = true;
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+fn read_zlib_header([cmf, flg]: [u8; 2]) -> Result<(), DecompressError> {
+    // Deflate with a window of at most 32 KiB, no preset dictionary and a valid check value
+    if cmf & 0x0F != 8
+        || cmf >> 4 > 7
+        || flg & 0x20 != 0
+        || !u16::from_be_bytes([cmf, flg]).is_multiple_of(31)
+    {
+        return Err(DecompressError::InvalidHeader);
+    }
+
+    Ok(())
+}
+
+fn read_gzip_header<R: Read>(input: &mut BitReader<R>) -> io::Result<()> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let [id1, id2, method, flags, _mtime @ .., _extra_flags, _os] = input.read_bytes::<10>()?;
+    if id1 != 0x1F || id2 != 0x8B || method != 8 || flags & 0xE0 != 0 {
+        return Err(DecompressError::InvalidHeader.into());
+    }
+
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes(input.read_bytes()?);
+        for _ in 0..len {
+            input.read_bytes::<1>()?;
+        }
+    }
+
+    // The original file name and a comment, both zero terminated
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            while input.read_bytes::<1>()? != [0] {}
+        }
+    }
+
+    if flags & FHCRC != 0 {
+        input.read_bytes::<2>()?;
+    }
+
+    Ok(())
+}
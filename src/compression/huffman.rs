@@ -0,0 +1,295 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use super::DecompressError;
+
+/// Longest code DEFLATE allows for literals, lengths and distances
+pub(crate) const MAX_CODE_LEN: u8 = 15;
+
+/// Longest code DEFLATE allows for the code length alphabet
+pub(crate) const MAX_CODE_LEN_CODE_LEN: u8 = 7;
+
+pub(crate) const END_OF_BLOCK: u16 = 256;
+
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+pub(crate) const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+pub(crate) const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order the code length code lengths are sent in, most likely used first
+pub(crate) const CODE_LEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Code lengths of the fixed literal/length code (RFC 1951 3.2.6)
+pub(crate) fn fixed_lit_lengths() -> [u8; 288] {
+    let mut lengths = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths
+}
+
+/// Code lengths of the fixed distance code, which includes the two unused distances
+pub(crate) fn fixed_dist_lengths() -> [u8; 32] {
+    [5; 32]
+}
+
+/// Index into [LENGTH_BASE] for a match length of 3 to 258
+pub(crate) fn length_index(len: u16) -> usize {
+    LENGTH_BASE.partition_point(|base| *base <= len) - 1
+}
+
+/// Index into [DIST_BASE] for a distance of 1 to 32768
+pub(crate) fn dist_index(dist: u16) -> usize {
+    DIST_BASE.partition_point(|base| *base <= dist) - 1
+}
+
+/// A table based decoder that resolves a code with a single lookup of its longest length
+pub(crate) struct HuffmanTable {
+    /// Symbol shifted left by 4 with the code length in the low bits, a length of 0 marks bit
+    /// patterns of an incomplete code that no symbol uses
+    table: Vec<u16>,
+    max_len: u32,
+}
+
+impl HuffmanTable {
+    pub(crate) fn new(lengths: &[u8]) -> Result<Self, DecompressError> {
+        let max_len = lengths.iter().copied().max().unwrap_or(0);
+        if max_len > MAX_CODE_LEN {
+            return Err(DecompressError::InvalidCode);
+        }
+
+        let mut counts = [0_u16; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // More codes of a length than there are bit patterns left over from the shorter ones
+        let mut left = 1_i32;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(DecompressError::InvalidCode);
+            }
+        }
+
+        let mut next_code = [0_u16; 16];
+        for len in 1..16 {
+            next_code[len] = (next_code[len - 1] + counts[len - 1]) << 1;
+        }
+
+        let mut table = vec![0; 1 << max_len];
+        for (symbol, len) in lengths.iter().enumerate() {
+            let len = *len as usize;
+            if len == 0 {
+                continue;
+            }
+
+            let code = reverse_bits(next_code[len], len as u8) as usize;
+            next_code[len] += 1;
+
+            // Every pattern that starts with the code, bits are read least significant first
+            for index in (code..table.len()).step_by(1 << len) {
+                table[index] = ((symbol as u16) << 4) | len as u16;
+            }
+        }
+
+        Ok(Self {
+            table,
+            max_len: max_len as u32,
+        })
+    }
+
+    pub(crate) fn max_len(&self) -> u32 {
+        self.max_len
+    }
+
+    /// Looks up the next `max_len` bits, returns the symbol and the length of its code
+    pub(crate) fn lookup(&self, bits: u32) -> Result<(u16, u32), DecompressError> {
+        match self.table.get(bits as usize) {
+            Some(entry) if entry & 0xF != 0 => Ok((entry >> 4, (entry & 0xF) as u32)),
+            _ => Err(DecompressError::InvalidCode),
+        }
+    }
+}
+
+pub(crate) fn reverse_bits(code: u16, len: u8) -> u16 {
+    code.reverse_bits() >> (16 - len as u32)
+}
+
+/// Canonical codes for the given lengths, already bit reversed for writing
+pub(crate) fn codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0_u16; 16];
+    for len in lengths {
+        counts[*len as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next_code = [0_u16; 16];
+    for len in 1..16 {
+        next_code[len] = (next_code[len - 1] + counts[len - 1]) << 1;
+    }
+
+    lengths
+        .iter()
+        .map(|len| match *len {
+            0 => 0,
+            len => {
+                let code = next_code[len as usize];
+                next_code[len as usize] += 1;
+                reverse_bits(code, len)
+            }
+        })
+        .collect()
+}
+
+/// Huffman code lengths for the symbol frequencies, none longer than `limit`
+///
+/// The code is always complete and has at least two symbols, since some decoders reject
+/// anything else
+pub(crate) fn code_lengths(freqs: &[u32], limit: u8) -> Vec<u8> {
+    let mut lengths = vec![0_u8; freqs.len()];
+    let mut used: Vec<usize> = (0..freqs.len()).filter(|s| freqs[*s] > 0).collect();
+
+    // Unused symbols fill in for missing ones
+    for (symbol, freq) in freqs.iter().enumerate() {
+        if used.len() >= 2 {
+            break;
+        }
+        if *freq == 0 {
+            used.push(symbol);
+        }
+    }
+
+    if used.len() == 2 {
+        for symbol in used {
+            lengths[symbol] = 1;
+        }
+        return lengths;
+    }
+
+    // Leaves are the used symbols, internal nodes are appended after them
+    let mut parents = vec![0_usize; used.len() * 2 - 1];
+    let mut heap: BinaryHeap<_> = used
+        .iter()
+        .enumerate()
+        .map(|(node, symbol)| Reverse((freqs[*symbol] as u64, node)))
+        .collect();
+
+    let mut next_node = used.len();
+    while heap.len() > 1 {
+        let Reverse((weight_a, a)) = heap.pop().unwrap();
+        let Reverse((weight_b, b)) = heap.pop().unwrap();
+        parents[a] = next_node;
+        parents[b] = next_node;
+        heap.push(Reverse((weight_a + weight_b, next_node)));
+        next_node += 1;
+    }
+
+    // Parents always come after their children, so depths fill in walking backwards
+    let root = next_node - 1;
+    let mut depths = vec![0_u32; next_node];
+    for node in (0..root).rev() {
+        depths[node] = depths[parents[node]] + 1;
+    }
+
+    for (node, symbol) in used.iter().enumerate() {
+        lengths[*symbol] = depths[node].min(limit as u32) as u8;
+    }
+
+    limit_lengths(&mut lengths, &used, freqs, limit);
+    lengths
+}
+
+/// Fixes up lengths that were cut to `limit` so they form a complete prefix code again
+fn limit_lengths(lengths: &mut [u8], used: &[usize], freqs: &[u32], limit: u8) {
+    // Kraft sum scaled so that a code of length `limit` counts as 1
+    let full = 1_u64 << limit;
+    let weight = |len: u8| 1_u64 << (limit - len);
+    let mut kraft: u64 = used.iter().map(|symbol| weight(lengths[*symbol])).sum();
+
+    if kraft == full {
+        return;
+    }
+
+    // Least frequent symbols are the cheapest to lengthen and the last to shorten
+    let mut by_freq = used.to_vec();
+    by_freq.sort_by_key(|symbol| (freqs[*symbol], Reverse(lengths[*symbol])));
+
+    while kraft > full {
+        let symbol = by_freq
+            .iter()
+            .copied()
+            .filter(|symbol| lengths[*symbol] < limit)
+            .max_by_key(|symbol| (lengths[*symbol], Reverse(freqs[*symbol])))
+            .unwrap();
+
+        lengths[symbol] += 1;
+        kraft -= weight(lengths[symbol]);
+    }
+
+    while kraft < full {
+        let symbol = by_freq
+            .iter()
+            .rev()
+            .copied()
+            .filter(|symbol| lengths[*symbol] > 1 && weight(lengths[*symbol]) <= full - kraft)
+            .max_by_key(|symbol| lengths[*symbol])
+            .unwrap();
+
+        kraft += weight(lengths[symbol]);
+        lengths[symbol] -= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kraft(lengths: &[u8]) -> f64 {
+        lengths
+            .iter()
+            .filter(|len| **len > 0)
+            .map(|len| 0.5_f64.powi(*len as i32))
+            .sum()
+    }
+
+    #[test]
+    fn length_limited_codes() {
+        assert_eq!(code_lengths(&[0, 0, 0], 15), [1, 1, 0]);
+        assert_eq!(code_lengths(&[0, 5, 0], 15), [1, 1, 0]);
+        assert_eq!(code_lengths(&[1, 1, 2], 15), [2, 2, 1]);
+
+        // Fibonacci frequencies make the deepest possible tree
+        let mut freqs = vec![1_u32, 1];
+        while freqs.len() < 30 {
+            freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+        }
+
+        let unlimited = code_lengths(&freqs, 32);
+        assert_eq!(unlimited.iter().max(), Some(&29));
+        assert_eq!(kraft(&unlimited), 1.0);
+
+        for limit in [7, 9, 15] {
+            let lengths = code_lengths(&freqs, limit);
+            assert_eq!(lengths.iter().max(), Some(&limit));
+            assert_eq!(kraft(&lengths), 1.0);
+            HuffmanTable::new(&lengths).unwrap_or_else(|_| panic!("limit {}", limit));
+        }
+    }
+}
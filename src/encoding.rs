@@ -0,0 +1,3 @@
+//! Binary-to-text encodings
+
+pub mod base64;
@@ -0,0 +1,150 @@
+//! Base64 as described by RFC 4648, in the standard and in the URL and filename safe alphabet
+
+use std::fmt::Display;
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A byte outside the alphabet at the given position
+    InvalidCharacter(usize),
+    /// The input ends part way through a byte, or its padding is wrong
+    InvalidLength,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCharacter(pos) => write!(f, "Invalid Base64 Character at {}", pos),
+            Self::InvalidLength => write!(f, "Invalid Base64 Length"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Encodes `data` with the standard alphabet and padding
+pub fn encode(data: &[u8]) -> String {
+    encode_with(data, STANDARD, true)
+}
+
+/// Encodes `data` with the URL and filename safe alphabet and without padding
+pub fn encode_url_safe(data: &[u8]) -> String {
+    encode_with(data, URL_SAFE, false)
+}
+
+/// Decodes standard base64, the padding may be left out
+pub fn decode(text: &str) -> Result<Vec<u8>, DecodeError> {
+    decode_with(text, STANDARD)
+}
+
+/// Decodes URL and filename safe base64, the padding may be left out
+pub fn decode_url_safe(text: &str) -> Result<Vec<u8>, DecodeError> {
+    decode_with(text, URL_SAFE)
+}
+
+fn encode_with(data: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        // n bytes make n + 1 characters
+        for index in 0..=chunk.len() {
+            let sextet = (group >> (18 - 6 * index)) & 0x3F;
+            text.push(alphabet[sextet as usize] as char);
+        }
+
+        if pad {
+            for _ in chunk.len()..3 {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn decode_with(text: &str, alphabet: &[u8; 64]) -> Result<Vec<u8>, DecodeError> {
+    let bytes = text.as_bytes();
+
+    // Padding is only allowed to fill up the last group of four
+    let unpadded = bytes.strip_suffix(b"==").or(bytes.strip_suffix(b"="));
+    let bytes = match unpadded {
+        Some(_) if !bytes.len().is_multiple_of(4) => return Err(DecodeError::InvalidLength),
+        Some(unpadded) => unpadded,
+        None => bytes,
+    };
+
+    if bytes.len() % 4 == 1 {
+        return Err(DecodeError::InvalidLength);
+    }
+
+    let mut data = Vec::with_capacity(bytes.len() / 4 * 3 + 2);
+
+    for (chunk_index, chunk) in bytes.chunks(4).enumerate() {
+        let mut group = 0_u32;
+
+        for (index, byte) in chunk.iter().enumerate() {
+            let sextet = alphabet
+                .iter()
+                .position(|c| c == byte)
+                .ok_or(DecodeError::InvalidCharacter(chunk_index * 4 + index))?;
+            group |= (sextet as u32) << (18 - 6 * index);
+        }
+
+        let group = group.to_be_bytes();
+        let len = chunk.len() - 1;
+
+        // The bits left over after the last whole byte have to be zero
+        if group[1 + len..].iter().any(|b| *b != 0) {
+            return Err(DecodeError::InvalidLength);
+        }
+
+        data.extend_from_slice(&group[1..1 + len]);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rfc_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (data, text) in vectors {
+            assert_eq!(encode(data.as_bytes()), text);
+            assert_eq!(decode(text).unwrap(), data.as_bytes());
+            assert_eq!(decode(text.trim_end_matches('=')).unwrap(), data.as_bytes());
+        }
+
+        assert_eq!(encode_url_safe(&[0xFB, 0xFF]), "-_8");
+        assert_eq!(decode_url_safe("-_8").unwrap(), [0xFB, 0xFF]);
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(decode("Zm9vY!=="), Err(DecodeError::InvalidCharacter(5)));
+        assert_eq!(decode("Z"), Err(DecodeError::InvalidLength));
+        assert_eq!(decode("Zg="), Err(DecodeError::InvalidLength));
+        assert_eq!(decode("Zh=="), Err(DecodeError::InvalidLength));
+        assert_eq!(decode("-_8"), Err(DecodeError::InvalidCharacter(0)));
+    }
+}
@@ -0,0 +1,9 @@
+//! Hash functions for protocols that need them, none of these are suitable for storing passwords
+
+mod adler32;
+mod crc32;
+mod sha1;
+
+pub use adler32::{adler32, Adler32};
+pub use crc32::{crc32, Crc32};
+pub use sha1::{sha1, Sha1};
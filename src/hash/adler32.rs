@@ -0,0 +1,70 @@
+const MODULUS: u32 = 65521;
+
+/// The most bytes that can be summed before `b` could overflow a u32
+const MAX_RUN: usize = 5552;
+
+/// Computes the Adler-32 checksum used by zlib (RFC 1950) incrementally
+///
+/// Faster than [Crc32](super::Crc32) but weaker, especially for short inputs
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> &mut Self {
+        for run in data.chunks(MAX_RUN) {
+            for byte in run {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+
+            self.a %= MODULUS;
+            self.b %= MODULUS;
+        }
+
+        self
+    }
+
+    pub fn finish(self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The Adler-32 checksum of `data`
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut hasher = Adler32::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_checksums() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+
+        // Long enough for the sums to wrap around the modulus many times
+        let data = vec![0xFF; 100_000];
+        let mut hasher = Adler32::new();
+        for chunk in data.chunks(7777) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), adler32(&data));
+        assert_eq!(adler32(&data), 0x149A302C);
+    }
+}
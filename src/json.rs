@@ -0,0 +1,199 @@
+//! JSON as described by RFC 8259
+//!
+//! [JsonValue::parse] only accepts what the grammar allows: no comments, trailing commas, single
+//! quotes, `NaN` or leading zeros. Errors point at the line and column where the input went
+//! wrong. Values are written compactly with [Display](std::fmt::Display) and indented with
+//! [JsonValue::to_pretty_string]
+
+mod json_parser;
+mod json_value;
+mod json_writer;
+
+use std::fmt::Display;
+
+pub use json_value::JsonValue;
+
+/// Arrays and objects nested deeper than this are rejected rather than risking the stack
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonErrorKind {
+    /// The input ends in the middle of a value, or is empty
+    UnexpectedEnd,
+    UnexpectedCharacter(char),
+    /// A number that doesn't follow the grammar or is too large for an `f64`
+    InvalidNumber,
+    /// An unknown escape sequence, or a `\u` escape that is half of a surrogate pair
+    InvalidEscape,
+    /// Control characters have to be escaped inside strings
+    ControlCharacter,
+    InvalidUtf8,
+    /// Anything but whitespace after the value
+    TrailingCharacters,
+    /// Arrays and objects nested deeper than [MAX_DEPTH]
+    TooDeep,
+}
+
+impl Display for JsonErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "Unexpected end of JSON input"),
+            Self::UnexpectedCharacter(c) => write!(f, "Unexpected character {:?}", c),
+            Self::InvalidNumber => write!(f, "Invalid number"),
+            Self::InvalidEscape => write!(f, "Invalid escape sequence"),
+            Self::ControlCharacter => write!(f, "Unescaped control character in string"),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8"),
+            Self::TrailingCharacters => write!(f, "Trailing characters after JSON value"),
+            Self::TooDeep => write!(f, "JSON nested too deeply"),
+        }
+    }
+}
+
+/// What went wrong while parsing and where
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonError {
+    kind: JsonErrorKind,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl JsonError {
+    fn new(kind: JsonErrorKind, input: &[u8], offset: usize) -> Self {
+        let before = &input[..offset];
+        let line_start = before
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |pos| pos + 1);
+
+        // Count characters rather than bytes, continuation bytes don't start a new one
+        let column = before[line_start..]
+            .iter()
+            .filter(|&&byte| byte & 0xC0 != 0x80)
+            .count()
+            + 1;
+
+        Self {
+            kind,
+            offset,
+            line: before.iter().filter(|&&byte| byte == b'\n').count() + 1,
+            column,
+        }
+    }
+
+    pub fn get_kind(&self) -> JsonErrorKind {
+        self.kind
+    }
+
+    /// The byte offset into the input
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Starts at 1
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    /// Starts at 1 and counts characters, not bytes
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.kind, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<JsonError> for std::io::Error {
+    fn from(err: JsonError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{
+        HeaderMap, HttpRequest, HttpResponse, Method, StatusCode, TestClient, Version,
+    };
+
+    #[test]
+    fn request_and_response_bodies() {
+        let client = TestClient::new(|request: &HttpRequest| match request.get_json() {
+            Ok(value) => {
+                let total: i64 = value
+                    .get("items")
+                    .and_then(JsonValue::as_array)
+                    .map_or(0, |items| items.iter().filter_map(JsonValue::as_i64).sum());
+
+                let mut response = HttpResponse::from_status(StatusCode::OK);
+                response.set_json_body(&[("total", total)].into_iter().collect());
+                response
+            }
+            Err(err) => {
+                let mut response = HttpResponse::from_status(StatusCode::BAD_REQUEST);
+                response.set_json_body(&[("error", err.to_string())].into_iter().collect());
+                response
+            }
+        });
+
+        let request = HttpRequest::builder()
+            .set_method(Method::Post)
+            .set_url("/sum".into())
+            .set_version(Version::Http11)
+            .set_json_body(&r#"{"items": [1, 2, 39]}"#.parse().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(request.get_header("Content-Type"), Some("application/json"));
+        assert_eq!(request.get_body(), br#"{"items":[1,2,39]}"#);
+
+        let response = client.send(&request);
+        response
+            .assert_status(StatusCode::OK)
+            .assert_header("Content-Type", "application/json")
+            .assert_body(r#"{"total":42}"#);
+        assert_eq!(
+            response.get_response().get_json().unwrap().get("total"),
+            Some(&JsonValue::Integer(42))
+        );
+
+        let response = client.post("/sum", &b"{\"items\": [1,]}"[..]);
+        response
+            .assert_status(StatusCode::BAD_REQUEST)
+            .assert_header("Content-Length", "56")
+            .assert_body(r#"{"error":"Unexpected character ']' at line 1 column 14"}"#);
+        assert_eq!(response.get_response().get_status_message(), "Bad Request");
+
+        // Replacing a body replaces the fields that described the old one
+        let mut response = HttpResponse::from_status(StatusCode::NOT_FOUND);
+        response.set_header("Content-Encoding".into(), "gzip".into());
+        response.set_json_body(&JsonValue::from("missing"));
+        assert_eq!(response.get_header("Content-Length"), Some("9"));
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert!(response
+            .as_bytes()
+            .starts_with(b"HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\n"));
+
+        let mut request = HttpRequest::new(
+            Method::Put,
+            "/".into(),
+            Version::Http11,
+            HeaderMap::new(),
+            Box::new(*b"old"),
+        );
+        request.set_header("Content-Length".into(), "3".into());
+        request.set_json_body(&vec![JsonValue::Null, true.into()].into());
+        assert_eq!(request.get_body(), b"[null,true]");
+        assert_eq!(request.get_header("Content-Length"), Some("11"));
+        assert_eq!(request.get_header("Content-Type"), Some("application/json"));
+    }
+}
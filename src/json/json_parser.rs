@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+
+use super::{JsonError, JsonErrorKind, JsonValue, MAX_DEPTH};
+
+/// A recursive descent parser over the grammar in RFC 8259, one byte of lookahead is enough
+pub(super) struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(super) fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    pub(super) fn parse_document(&mut self) -> Result<JsonValue, JsonError> {
+        // Checked up front so strings can be sliced without validating every byte
+        if let Err(err) = std::str::from_utf8(self.input) {
+            let valid = err.valid_up_to();
+            // An error before the bad byte is still the more useful one
+            let mut prefix = Parser::new(&self.input[..valid]);
+            return match prefix.parse_document() {
+                Err(err) if err.kind != JsonErrorKind::UnexpectedEnd => Err(err),
+                _ => Err(self.error_at(JsonErrorKind::InvalidUtf8, valid)),
+            };
+        }
+
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+
+        if self.pos < self.input.len() {
+            return Err(self.error(JsonErrorKind::TrailingCharacters));
+        }
+
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, JsonError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => Err(self.error(JsonErrorKind::UnexpectedEnd)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b'[') => self.nested(Self::parse_array),
+            Some(b'{') => self.nested(Self::parse_object),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<JsonValue, JsonError>,
+    ) -> Result<JsonValue, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(JsonErrorKind::TooDeep));
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        for &expected in literal.as_bytes() {
+            match self.peek() {
+                Some(byte) if byte == expected => self.pos += 1,
+                Some(_) => return Err(self.unexpected()),
+                None => return Err(self.error(JsonErrorKind::UnexpectedEnd)),
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, JsonError> {
+        self.pos += 1;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(values));
+        }
+
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+
+            match self.next_byte()? {
+                b',' => continue,
+                b']' => return Ok(JsonValue::Array(values)),
+                _ => return Err(self.unexpected_before()),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, JsonError> {
+        self.pos += 1;
+        let mut members = BTreeMap::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'"') => {}
+                Some(_) => return Err(self.unexpected()),
+                None => return Err(self.error(JsonErrorKind::UnexpectedEnd)),
+            }
+            let name = self.parse_string()?;
+
+            self.skip_whitespace();
+            if self.next_byte()? != b':' {
+                return Err(self.unexpected_before());
+            }
+
+            let value = self.parse_value()?;
+            members.insert(name, value);
+            self.skip_whitespace();
+
+            match self.next_byte()? {
+                b',' => continue,
+                b'}' => return Ok(JsonValue::Object(members)),
+                _ => return Err(self.unexpected_before()),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut string = String::new();
+        let mut start = self.pos;
+
+        loop {
+            let byte = self.next_byte()?;
+            match byte {
+                b'"' => {
+                    string.push_str(self.slice(start, self.pos - 1));
+                    return Ok(string);
+                }
+                b'\\' => {
+                    string.push_str(self.slice(start, self.pos - 1));
+                    let escape_start = self.pos - 1;
+                    let c = match self.next_byte()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{C}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape(escape_start)?,
+                        _ => return Err(self.error_at(JsonErrorKind::InvalidEscape, escape_start)),
+                    };
+                    string.push(c);
+                    start = self.pos;
+                }
+                0x00..=0x1F => {
+                    return Err(self.error_at(JsonErrorKind::ControlCharacter, self.pos - 1))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Called after `\u`, characters outside the BMP are escaped as a surrogate pair
+    fn parse_unicode_escape(&mut self, escape_start: usize) -> Result<char, JsonError> {
+        let high = self.parse_hex4()?;
+
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if self.input[self.pos..].starts_with(b"\\u") {
+                    self.pos += 2;
+                } else if b"\\u".starts_with(&self.input[self.pos..]) {
+                    return Err(self.error_at(JsonErrorKind::UnexpectedEnd, self.input.len()));
+                } else {
+                    return Err(self.error_at(JsonErrorKind::InvalidEscape, escape_start));
+                }
+
+                let low = self.parse_hex4()?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(self.error_at(JsonErrorKind::InvalidEscape, escape_start));
+                }
+                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+            }
+            0xDC00..=0xDFFF => {
+                return Err(self.error_at(JsonErrorKind::InvalidEscape, escape_start))
+            }
+            code => code,
+        };
+
+        Ok(char::from_u32(code).expect("surrogates were handled above"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            let digit = match self.next_byte()? {
+                byte @ b'0'..=b'9' => byte - b'0',
+                byte @ b'a'..=b'f' => byte - b'a' + 10,
+                byte @ b'A'..=b'F' => byte - b'A' + 10,
+                _ => return Err(self.unexpected_before()),
+            };
+            code = (code << 4) | digit as u32;
+        }
+
+        Ok(code)
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.pos;
+        let mut integer = true;
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+
+        // No leading zeros, `0` has to stand on its own
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.number_error()),
+        }
+
+        if self.peek() == Some(b'.') {
+            integer = false;
+            self.pos += 1;
+            if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                return Err(self.number_error());
+            }
+            self.skip_digits();
+        }
+
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            integer = false;
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                return Err(self.number_error());
+            }
+            self.skip_digits();
+        }
+
+        let text = self.slice(start, self.pos);
+        if integer {
+            if let Ok(value) = text.parse::<i64>() {
+                return Ok(JsonValue::Integer(value));
+            }
+        }
+
+        match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(JsonValue::Float(value)),
+            _ => Err(self.error_at(JsonErrorKind::InvalidNumber, start)),
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn next_byte(&mut self) -> Result<u8, JsonError> {
+        let byte = self
+            .peek()
+            .ok_or_else(|| self.error(JsonErrorKind::UnexpectedEnd))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Only ever called on boundaries next to ASCII bytes of input that's valid UTF-8
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        std::str::from_utf8(&self.input[start..end]).expect("input was checked to be UTF-8")
+    }
+
+    fn number_error(&self) -> JsonError {
+        match self.peek() {
+            None => self.error(JsonErrorKind::UnexpectedEnd),
+            Some(_) => self.unexpected(),
+        }
+    }
+
+    /// The character at the current position
+    fn unexpected(&self) -> JsonError {
+        self.unexpected_at(self.pos)
+    }
+
+    /// The character that was just consumed
+    fn unexpected_before(&self) -> JsonError {
+        self.unexpected_at(self.pos - 1)
+    }
+
+    /// `pos` is always at the start of a character, the parser only stops after ASCII bytes
+    fn unexpected_at(&self, pos: usize) -> JsonError {
+        let kind = self.slice(pos, self.input.len()).chars().next().map_or(
+            JsonErrorKind::UnexpectedEnd,
+            JsonErrorKind::UnexpectedCharacter,
+        );
+        self.error_at(kind, pos)
+    }
+
+    fn error(&self, kind: JsonErrorKind) -> JsonError {
+        self.error_at(kind, self.pos)
+    }
+
+    fn error_at(&self, kind: JsonErrorKind, offset: usize) -> JsonError {
+        JsonError::new(kind, self.input, offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error(input: &str) -> (JsonErrorKind, usize, usize) {
+        let err = JsonValue::parse(input.as_bytes()).unwrap_err();
+        (err.get_kind(), err.get_line(), err.get_column())
+    }
+
+    #[test]
+    fn parses_documents() {
+        let value: JsonValue = r#" {
+            "name": "caf\u00e9 \"\ud83d\ude00\"\n",
+            "id": 9007199254740993,
+            "ratio": -0.5e-3,
+            "big": 18446744073709551616,
+            "tags": ["a", [], {}, null, true, false, 0],
+            "name": "last"
+        } "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(value.get("name").unwrap().as_str(), Some("last"));
+        assert_eq!(value.get("id"), Some(&JsonValue::Integer(9007199254740993)));
+        assert_eq!(value.get("ratio"), Some(&JsonValue::Float(-0.0005)));
+        assert_eq!(
+            value.get("big").unwrap().as_f64(),
+            Some(18446744073709551616.0)
+        );
+        assert_eq!(value.pointer("/tags/4"), Some(&JsonValue::Bool(true)));
+        assert_eq!(value.pointer("/tags/01"), None);
+        assert_eq!(
+            value.get("tags").unwrap().get_index(3),
+            Some(&JsonValue::Null)
+        );
+
+        let escaped: JsonValue = r#""caf\u00e9 \"\ud83d\ude00\"\n\/\t""#.parse().unwrap();
+        assert_eq!(escaped.as_str(), Some("café \"😀\"\n/\t"));
+        assert_eq!("-0".parse::<JsonValue>(), Ok(JsonValue::Integer(0)));
+        assert_eq!("1E2".parse::<JsonValue>(), Ok(JsonValue::Float(100.0)));
+    }
+
+    #[test]
+    fn rejects_what_the_grammar_does_not_allow() {
+        assert_eq!(error(""), (JsonErrorKind::UnexpectedEnd, 1, 1));
+        assert_eq!(
+            error("[1,]"),
+            (JsonErrorKind::UnexpectedCharacter(']'), 1, 4)
+        );
+        assert_eq!(
+            error("{\n  \"a\": 01\n}"),
+            (JsonErrorKind::UnexpectedCharacter('1'), 2, 9)
+        );
+        assert_eq!(
+            error("{'a': 1}"),
+            (JsonErrorKind::UnexpectedCharacter('\''), 1, 2)
+        );
+        assert_eq!(
+            error("[1 2]"),
+            (JsonErrorKind::UnexpectedCharacter('2'), 1, 4)
+        );
+        assert_eq!(
+            error("{\"a\" 1}"),
+            (JsonErrorKind::UnexpectedCharacter('1'), 1, 6)
+        );
+        assert_eq!(
+            error("[NaN]"),
+            (JsonErrorKind::UnexpectedCharacter('N'), 1, 2)
+        );
+        assert_eq!(error("tru"), (JsonErrorKind::UnexpectedEnd, 1, 4));
+        assert_eq!(error("1."), (JsonErrorKind::UnexpectedEnd, 1, 3));
+        assert_eq!(error("-x"), (JsonErrorKind::UnexpectedCharacter('x'), 1, 2));
+        assert_eq!(error("1e400"), (JsonErrorKind::InvalidNumber, 1, 1));
+        assert_eq!(error("\"é\\x\""), (JsonErrorKind::InvalidEscape, 1, 3));
+        assert_eq!(error("\"\\ud83d\""), (JsonErrorKind::InvalidEscape, 1, 2));
+        assert_eq!(error("\"\\ude00\""), (JsonErrorKind::InvalidEscape, 1, 2));
+        assert_eq!(
+            error("\"\\u12g4\""),
+            (JsonErrorKind::UnexpectedCharacter('g'), 1, 6)
+        );
+        assert_eq!(error("\"a\tb\""), (JsonErrorKind::ControlCharacter, 1, 3));
+        assert_eq!(error("\"abc"), (JsonErrorKind::UnexpectedEnd, 1, 5));
+        assert_eq!(error("{} x"), (JsonErrorKind::TrailingCharacters, 1, 4));
+        assert_eq!(
+            error("// no\n1"),
+            (JsonErrorKind::UnexpectedCharacter('/'), 1, 1)
+        );
+        assert_eq!(
+            error("\u{FEFF}1"),
+            (JsonErrorKind::UnexpectedCharacter('\u{FEFF}'), 1, 1)
+        );
+
+        let err = JsonValue::parse(b"[\"\xFF\"]").unwrap_err();
+        assert_eq!(
+            (err.get_kind(), err.get_offset()),
+            (JsonErrorKind::InvalidUtf8, 2)
+        );
+        // A syntax error before invalid UTF-8 is reported first
+        let err = JsonValue::parse(b"[1;\"\xFF\"]").unwrap_err();
+        assert_eq!(err.get_kind(), JsonErrorKind::UnexpectedCharacter(';'));
+
+        let deep = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(JsonValue::parse(deep.as_bytes()).is_ok());
+        let too_deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        assert_eq!(error(&too_deep), (JsonErrorKind::TooDeep, 1, MAX_DEPTH + 1));
+
+        assert_eq!(
+            JsonValue::parse(b"{\"a\":\n tru }")
+                .unwrap_err()
+                .to_string(),
+            "Unexpected character ' ' at line 2 column 5"
+        );
+    }
+}
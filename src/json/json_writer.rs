@@ -0,0 +1,165 @@
+use std::fmt::{Display, Write};
+
+use super::JsonValue;
+
+/// Spaces per nesting level in [JsonValue::to_pretty_string]
+const INDENT: usize = 2;
+
+impl JsonValue {
+    /// One member or element per line, indented by two spaces per level
+    pub fn to_pretty_string(&self) -> String {
+        let mut text = String::new();
+        write_value(&mut text, self, Some(0)).expect("writing to a String can't fail");
+        text
+    }
+}
+
+/// Compact, without any whitespace
+impl Display for JsonValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_value(f, self, None)
+    }
+}
+
+/// `level` is the current nesting level when indenting, `None` for compact output
+fn write_value(out: &mut impl Write, value: &JsonValue, level: Option<usize>) -> std::fmt::Result {
+    match value {
+        JsonValue::Null => out.write_str("null"),
+        JsonValue::Bool(value) => write!(out, "{}", value),
+        JsonValue::Integer(value) => write!(out, "{}", value),
+        // Debug is the shortest representation that round trips and keeps a `.0` on whole
+        // numbers, large and small ones get an exponent instead of dozens of zeros
+        JsonValue::Float(value) if value.is_finite() => write!(out, "{:?}", value),
+        JsonValue::Float(_) => out.write_str("null"),
+        JsonValue::String(value) => write_string(out, value),
+        JsonValue::Array(values) => {
+            if values.is_empty() {
+                return out.write_str("[]");
+            }
+
+            out.write_char('[')?;
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    out.write_char(',')?;
+                }
+                write_newline(out, level.map(|level| level + 1))?;
+                write_value(out, value, level.map(|level| level + 1))?;
+            }
+            write_newline(out, level)?;
+            out.write_char(']')
+        }
+        JsonValue::Object(members) => {
+            if members.is_empty() {
+                return out.write_str("{}");
+            }
+
+            out.write_char('{')?;
+            for (index, (name, value)) in members.iter().enumerate() {
+                if index > 0 {
+                    out.write_char(',')?;
+                }
+                write_newline(out, level.map(|level| level + 1))?;
+                write_string(out, name)?;
+                out.write_str(if level.is_some() { ": " } else { ":" })?;
+                write_value(out, value, level.map(|level| level + 1))?;
+            }
+            write_newline(out, level)?;
+            out.write_char('}')
+        }
+    }
+}
+
+fn write_newline(out: &mut impl Write, level: Option<usize>) -> std::fmt::Result {
+    if let Some(level) = level {
+        write!(out, "\n{:1$}", "", level * INDENT)?;
+    }
+
+    Ok(())
+}
+
+/// Only what has to be escaped is, everything else is written as UTF-8
+fn write_string(out: &mut impl Write, value: &str) -> std::fmt::Result {
+    out.write_char('"')?;
+
+    let mut start = 0;
+    for (index, c) in value.char_indices() {
+        let escape = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\u{8}' => "\\b",
+            '\u{C}' => "\\f",
+            '\0'..='\u{1F}' => "",
+            _ => continue,
+        };
+
+        out.write_str(&value[start..index])?;
+        if escape.is_empty() {
+            write!(out, "\\u{:04x}", c as u32)?;
+        } else {
+            out.write_str(escape)?;
+        }
+        start = index + c.len_utf8();
+    }
+
+    out.write_str(&value[start..])?;
+    out.write_char('"')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_compact_and_pretty() {
+        let value: JsonValue = [
+            ("text", JsonValue::from("a\"b\\c\n\u{1}é😀</")),
+            (
+                "numbers",
+                vec![1.into(), 0.5.into(), 1e300.into(), 2.0.into()].into(),
+            ),
+            ("empty", JsonValue::Array(Vec::new())),
+            ("nothing", None::<bool>.into()),
+            ("nested", [("ok", true)].into_iter().collect()),
+            ("nan", f64::NAN.into()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            value.to_string(),
+            r#"{"empty":[],"nan":null,"nested":{"ok":true},"nothing":null,"numbers":[1,0.5,1e300,2.0],"text":"a\"b\\c\n\u0001é😀</"}"#
+        );
+        assert_eq!(
+            value.to_pretty_string(),
+            r#"{
+  "empty": [],
+  "nan": null,
+  "nested": {
+    "ok": true
+  },
+  "nothing": null,
+  "numbers": [
+    1,
+    0.5,
+    1e300,
+    2.0
+  ],
+  "text": "a\"b\\c\n\u0001é😀</"
+}"#
+        );
+
+        // Both forms parse back to the same value, except for NaN
+        let mut finite = value.clone();
+        finite.as_object_mut().unwrap().remove("nan");
+        assert_eq!(finite.to_string().parse::<JsonValue>(), Ok(finite.clone()));
+        assert_eq!(finite.to_pretty_string().parse::<JsonValue>(), Ok(finite));
+
+        for float in [0.1, -2.5e-8, 123456789.125, f64::MAX, f64::MIN_POSITIVE] {
+            let text = JsonValue::Float(float).to_string();
+            assert_eq!(text.parse::<JsonValue>(), Ok(JsonValue::Float(float)));
+        }
+    }
+}
@@ -1,6 +1,10 @@
 pub mod collections;
+pub mod compression;
 pub mod concurrency;
+pub mod encoding;
+pub mod hash;
 pub mod io;
+pub mod json;
 pub mod logging;
 pub mod net;
 pub mod time;
@@ -1 +1,6 @@
-pub mod http;
\ No newline at end of file
+pub mod http;
+pub mod http2;
+#[cfg(unix)]
+pub mod poll;
+pub mod url;
+pub mod websocket;
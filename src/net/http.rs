@@ -1,7 +1,52 @@
+mod http_body;
+mod http_chunked;
+mod http_client;
+mod http_compression;
+mod http_cookie;
+mod http_date;
+mod http_error;
+mod http_form;
+mod http_handler;
+mod http_headers;
+mod http_method;
+mod http_middleware;
+mod http_parser;
+mod http_proxy;
 mod http_request;
 mod http_response;
+mod http_router;
+mod http_server;
+mod http_sse;
+mod http_static;
+mod http_status;
 mod http_stream;
+mod http_testing;
+mod http_version;
 
-pub use http_request::HttpRequest;
-pub use http_response::HttpResponse;
+pub use http_body::Body;
+pub use http_chunked::ChunkedWriter;
+pub use http_client::HttpClient;
+pub use http_compression::Compression;
+pub use http_cookie::{Cookie, CookieJar, SameSite};
+pub use http_date::{format_http_date, parse_http_date};
+pub use http_error::HttpError;
+pub use http_form::{multipart_boundary, Form, FormError, MultipartReader, Part, PartReader};
+pub use http_handler::Handler;
+pub use http_headers::{HeaderMap, InvalidHeader};
+pub use http_method::{InvalidMethod, Method};
+pub use http_middleware::{
+    CatchPanic, Cors, Middleware, Next, Pipeline, RequestLogger, RequestTimer,
+};
+pub use http_parser::{HttpLimits, ParseError};
+pub use http_proxy::{Balancing, ReverseProxy};
+pub use http_request::{HttpRequest, HttpRequestBuildError, InvalidUrl};
+pub use http_response::{HttpResponse, HttpResponseBuildError, InvalidStatusMessage};
+pub use http_router::{PathParams, Router};
+pub(crate) use http_server::prepare_response;
+pub use http_server::{HttpServer, ShutdownHandle};
+pub use http_sse::{Event, EventParser, EventReader, EventWriter};
+pub use http_static::{mime_type, StaticFiles};
+pub use http_status::{InvalidStatusCode, StatusCode};
 pub use http_stream::*;
+pub use http_testing::{TestClient, TestResponse, TestServer};
+pub use http_version::{InvalidVersion, Version};
@@ -0,0 +1,149 @@
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+/// The body of an [super::HttpRequest] or [super::HttpResponse]
+///
+/// A body is either buffered in memory or streamed from a reader while the message is sent, so
+/// large bodies such as files never have to be held in memory as a whole. Reading from a body
+/// consumes it
+///
+/// Clones of a streaming body share its reader, whatever one of them reads is gone for the others
+#[derive(Clone, Default)]
+pub struct Body {
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Buffered {
+        bytes: Box<[u8]>,
+        /// How much has been read already
+        pos: usize,
+    },
+    Stream {
+        reader: Arc<Mutex<Box<dyn Read + Send>>>,
+        len: Option<u64>,
+    },
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self::Buffered {
+            bytes: Box::new([]),
+            pos: 0,
+        }
+    }
+}
+
+impl Body {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// A body that is read from `reader` as it is sent
+    ///
+    /// `len` is the number of bytes the reader produces, if known. Bodies of a known length are
+    /// sent with a Content-Length, all others with the chunked Transfer-Encoding
+    pub fn from_reader(reader: impl Read + Send + 'static, len: Option<u64>) -> Self {
+        Self {
+            inner: Inner::Stream {
+                reader: Arc::new(Mutex::new(Box::new(reader))),
+                len,
+            },
+        }
+    }
+
+    pub fn is_stream(&self) -> bool {
+        matches!(self.inner, Inner::Stream { .. })
+    }
+
+    /// The number of bytes left in the body, `None` for a streaming body of unknown length
+    pub fn content_length(&self) -> Option<u64> {
+        match &self.inner {
+            Inner::Buffered { bytes, pos } => Some((bytes.len() - pos) as u64),
+            Inner::Stream { len, .. } => *len,
+        }
+    }
+
+    /// The bytes left in a buffered body, a streaming body returns an empty slice
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.inner {
+            Inner::Buffered { bytes, pos } => &bytes[*pos..],
+            Inner::Stream { .. } => &[],
+        }
+    }
+
+    /// Reads the rest of the body into memory
+    pub fn into_bytes(self) -> std::io::Result<Box<[u8]>> {
+        match self.inner {
+            Inner::Buffered { bytes, pos: 0 } => Ok(bytes),
+            Inner::Buffered { bytes, pos } => Ok(bytes[pos..].into()),
+            Inner::Stream { .. } => {
+                let mut bytes = Vec::new();
+                let mut body = self;
+                body.read_to_end(&mut bytes)?;
+
+                Ok(bytes.into_boxed_slice())
+            }
+        }
+    }
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            Inner::Buffered { bytes, pos } => {
+                let len = buf.len().min(bytes.len() - *pos);
+                buf[..len].copy_from_slice(&bytes[*pos..*pos + len]);
+                *pos += len;
+
+                Ok(len)
+            }
+            Inner::Stream { reader, .. } => reader
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .read(buf),
+        }
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.inner {
+            Inner::Buffered { .. } => f.debug_tuple("Buffered").field(&self.as_bytes()).finish(),
+            Inner::Stream { len, .. } => f.debug_struct("Stream").field("len", len).finish(),
+        }
+    }
+}
+
+impl From<Box<[u8]>> for Body {
+    fn from(bytes: Box<[u8]>) -> Self {
+        Self {
+            inner: Inner::Buffered { bytes, pos: 0 },
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        bytes.into_boxed_slice().into()
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(bytes: &[u8]) -> Self {
+        Box::<[u8]>::from(bytes).into()
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        text.into_bytes().into()
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Self {
+        text.as_bytes().into()
+    }
+}
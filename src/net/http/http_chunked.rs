@@ -0,0 +1,155 @@
+use std::io::Write;
+
+use super::HeaderMap;
+use super::ParseError;
+
+/// Writes a message body using the chunked transfer coding, see
+/// [HttpStream::send_response_chunked](super::HttpStream::send_response_chunked)
+///
+/// The body is only complete once [ChunkedWriter::finish] or
+/// [ChunkedWriter::finish_with_trailers] has been called, dropping the writer early leaves the
+/// message unterminated
+///
+/// On a non-blocking [HttpStream](super::HttpStream) a [std::io::ErrorKind::WouldBlock] error
+/// means the data was queued but could not be sent completely yet, the rest is sent by the next
+/// write or by [HttpStream::flush](super::HttpStream::flush)
+pub struct ChunkedWriter<'w> {
+    tx: &'w mut dyn Write,
+}
+
+impl<'w> ChunkedWriter<'w> {
+    pub(crate) fn new(tx: &'w mut dyn Write) -> Self {
+        Self { tx }
+    }
+
+    /// Sends `data` as a single chunk, empty chunks are skipped since a chunk of size zero marks
+    /// the end of the body
+    pub fn send_chunk(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.tx.write_all(&encode_chunk(data))?;
+        self.tx.flush()
+    }
+
+    pub fn finish(self) -> std::io::Result<()> {
+        self.finish_with_trailers(&HeaderMap::new())
+    }
+
+    /// Terminates the body, sending `trailers` as trailer fields after the last chunk
+    pub fn finish_with_trailers(self, trailers: &HeaderMap) -> std::io::Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"0\r\n");
+
+        trailers.encode(&mut bytes);
+
+        bytes.extend_from_slice(b"\r\n");
+
+        self.tx.write_all(&bytes)?;
+        self.tx.flush()
+    }
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send_chunk(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.tx.flush()
+    }
+}
+
+pub(crate) fn encode_chunk(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len() + 12);
+
+    bytes.extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(b"\r\n");
+
+    bytes
+}
+
+/// Encodes a complete body as a single chunk followed by the last chunk
+pub(crate) fn encode_chunked_body(body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    if !body.is_empty() {
+        bytes.extend_from_slice(&encode_chunk(body));
+    }
+    bytes.extend_from_slice(b"0\r\n\r\n");
+
+    bytes
+}
+
+/// Returns `Some(true)` if the final transfer coding is chunked, `Some(false)` if some other
+/// transfer coding is applied and `None` if there is no Transfer-Encoding header
+pub(crate) fn is_chunked(headers: &HeaderMap) -> Option<bool> {
+    // The codings can be spread over several field lines, the last one is applied last
+    headers.get_all("Transfer-Encoding").last().map(|val| {
+        val.rsplit(',')
+            .next()
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+    })
+}
+
+pub(crate) fn parse_chunk_size(line: &str) -> Result<usize, ParseError> {
+    // chunk-size [ chunk-ext ] CRLF, with nothing around the size that two parsers could read
+    // differently
+    let end = line
+        .bytes()
+        .position(|b| !b.is_ascii_hexdigit())
+        .unwrap_or(line.len());
+    let (size, extensions) = line.split_at(end);
+
+    if size.is_empty() || !(extensions.is_empty() || extensions.starts_with(';')) {
+        return Err(ParseError::InvalidChunk);
+    }
+
+    usize::from_str_radix(size, 16).map_err(|_| ParseError::InvalidChunk)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chunk_size_with_extensions() {
+        assert_eq!(parse_chunk_size("1a").unwrap(), 26);
+        assert_eq!(parse_chunk_size("FF;name=value").unwrap(), 255);
+        assert_eq!(parse_chunk_size("0;last").unwrap(), 0);
+        assert!(parse_chunk_size("").is_err());
+        assert!(parse_chunk_size("-1").is_err());
+        assert!(parse_chunk_size("zz").is_err());
+    }
+
+    #[test]
+    fn chunk_size_rejects_whitespace() {
+        for line in [
+            " 1a", "1a ", "1a\t", "\t1a", "1 a", "0 ;last", "+1a", "0x1a",
+        ] {
+            assert!(parse_chunk_size(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn writer_output() {
+        let mut out = Vec::new();
+        let mut writer = ChunkedWriter::new(&mut out);
+
+        writer.send_chunk(b"hello ").unwrap();
+        writer.send_chunk(b"").unwrap();
+        writer.send_chunk(b"world").unwrap();
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("Expires", "never");
+        writer.finish_with_trailers(&trailers).unwrap();
+
+        assert_eq!(
+            out,
+            b"6\r\nhello \r\n5\r\nworld\r\n0\r\nExpires: never\r\n\r\n".to_vec()
+        );
+    }
+}
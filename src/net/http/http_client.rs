@@ -0,0 +1,818 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::http_chunked::is_chunked;
+use super::CookieJar;
+use super::HttpError;
+use super::HttpLimits;
+use super::HttpRequest;
+use super::HttpResponse;
+use super::HttpStream;
+use super::Method;
+use super::StatusCode;
+use super::Version;
+use crate::net::url::Url;
+
+type Connection = HttpStream<TcpStream, TcpStream>;
+type Pool = Mutex<HashMap<String, Vec<IdleConnection>>>;
+
+/// Idle connections kept per host, further ones are closed once their response has been received
+const MAX_IDLE_PER_HOST: usize = 8;
+
+/// A blocking HTTP/1.1 client
+///
+/// Connections are kept alive and reused for later requests to the same host and port, the
+/// client can be shared between threads to share its connections as well. Redirects are followed
+/// up to a limit and requests get a `Host`, `User-Agent` and `Content-Length` header unless they
+/// already have one. Only plain `http` URLs are supported
+///
+/// Responses compressed with gzip or deflate are decompressed, see [HttpClient::set_decompress]
+///
+/// Request bodies can be streamed (see [HttpRequest::set_body_reader]), and so can response
+/// bodies with [HttpClient::send_streaming]
+pub struct HttpClient {
+    pool: Arc<Pool>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    idle_timeout: Duration,
+    max_redirects: usize,
+    user_agent: String,
+    limits: HttpLimits,
+    cookie_jar: Option<Arc<Mutex<CookieJar>>>,
+    decompress: bool,
+}
+
+struct IdleConnection {
+    connection: Connection,
+    since: Instant,
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        Self {
+            pool: Arc::new(Mutex::new(HashMap::new())),
+            connect_timeout: Some(Duration::from_secs(10)),
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            idle_timeout: Duration::from_secs(30),
+            max_redirects: 10,
+            user_agent: concat!("fp_lib/", env!("CARGO_PKG_VERSION")).to_string(),
+            limits: HttpLimits::default(),
+            cookie_jar: None,
+            decompress: true,
+        }
+    }
+
+    /// Defaults to 10 seconds, `None` leaves it up to the OS
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Defaults to 30 seconds, `None` waits forever
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Defaults to 30 seconds, `None` waits forever
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// How long an unused connection is kept for reuse, defaults to 30 seconds
+    pub fn set_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Defaults to 10, 0 returns redirect responses as they are
+    pub fn set_max_redirects(&mut self, max_redirects: usize) -> &mut Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn set_user_agent(&mut self, user_agent: String) -> &mut Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Limits on the responses that are accepted, see [HttpLimits]
+    pub fn set_limits(&mut self, limits: HttpLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Stores the cookies set by every response, redirects included, in `cookie_jar` and sends
+    /// the matching ones with every request. Without a jar, the default, cookies are left alone
+    ///
+    /// The jar can be shared with other clients or inspected while the client is in use
+    pub fn set_cookie_jar(&mut self, cookie_jar: Option<Arc<Mutex<CookieJar>>>) -> &mut Self {
+        self.cookie_jar = cookie_jar;
+        self
+    }
+
+    /// Whether requests without an `Accept-Encoding` header ask for gzip or deflate and responses
+    /// with either Content-Encoding are decompressed, see [HttpResponse::decode_body]. Defaults
+    /// to true
+    pub fn set_decompress(&mut self, decompress: bool) -> &mut Self {
+        self.decompress = decompress;
+        self
+    }
+
+    pub fn get(&self, url: &str) -> std::io::Result<HttpResponse> {
+        self.send(request(Method::Get, url, None, Box::new([])))
+    }
+
+    pub fn head(&self, url: &str) -> std::io::Result<HttpResponse> {
+        self.send(request(Method::Head, url, None, Box::new([])))
+    }
+
+    pub fn delete(&self, url: &str) -> std::io::Result<HttpResponse> {
+        self.send(request(Method::Delete, url, None, Box::new([])))
+    }
+
+    pub fn post(
+        &self,
+        url: &str,
+        content_type: &str,
+        body: Box<[u8]>,
+    ) -> std::io::Result<HttpResponse> {
+        self.send(request(Method::Post, url, Some(content_type), body))
+    }
+
+    pub fn put(
+        &self,
+        url: &str,
+        content_type: &str,
+        body: Box<[u8]>,
+    ) -> std::io::Result<HttpResponse> {
+        self.send(request(Method::Put, url, Some(content_type), body))
+    }
+
+    /// Sends `request`, whose URL has to be absolute (`http://host:port/path?query`), and returns
+    /// the final response after following any redirects
+    ///
+    /// Running out of redirects is an error, interim 1xx responses are skipped. A 307 or 308
+    /// redirect can't repeat a streaming request body and is returned as is
+    pub fn send(&self, request: HttpRequest) -> std::io::Result<HttpResponse> {
+        self.send_with(request, false)
+    }
+
+    /// Like [HttpClient::send], but the response body is streamed from the connection instead of
+    /// being received as a whole
+    ///
+    /// The connection goes back to the pool once the body has been read to the end
+    pub fn send_streaming(&self, request: HttpRequest) -> std::io::Result<HttpResponse> {
+        self.send_with(request, true)
+    }
+
+    fn send_with(
+        &self,
+        mut request: HttpRequest,
+        streaming: bool,
+    ) -> std::io::Result<HttpResponse> {
+        let mut redirects = 0;
+
+        loop {
+            let target = Target::parse(request.get_url())?;
+            let mut response = self.exchange(&target, &request, streaming)?;
+            let status_code = response.get_status_code();
+
+            if let Some(jar) = &self.cookie_jar {
+                jar.lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .store_response(&target.url, &response);
+            }
+
+            let location = match response.get_header("Location") {
+                Some(location) if is_followed_redirect(status_code) => location.to_string(),
+                _ => return self.decode(&request, response),
+            };
+
+            let repeats_body = matches!(status_code.as_u16(), 307 | 308);
+            if repeats_body && request.is_body_streaming() {
+                return self.decode(&request, response);
+            }
+
+            if redirects == self.max_redirects {
+                if self.max_redirects == 0 {
+                    return self.decode(&request, response);
+                }
+
+                return Err(std::io::Error::other("Too many redirects"));
+            }
+            redirects += 1;
+
+            // Reading the body to the end frees up the connection
+            std::io::copy(response.get_body_mut(), &mut std::io::sink())?;
+
+            let next = target.resolve(&location)?;
+            redirect(&mut request, status_code, &target, &next);
+        }
+    }
+
+    /// Decompresses the body of the final response, unless there is none
+    fn decode(
+        &self,
+        request: &HttpRequest,
+        mut response: HttpResponse,
+    ) -> std::io::Result<HttpResponse> {
+        let status_code = response.get_status_code();
+        let has_body = request.get_method() != &Method::Head
+            && status_code != StatusCode::NO_CONTENT
+            && status_code != StatusCode::NOT_MODIFIED;
+
+        if self.decompress && has_body {
+            response.decode_body(self.limits.max_body_size)?;
+        }
+
+        Ok(response)
+    }
+
+    /// Sends a single request and receives its response, retrying once on a new connection if a
+    /// reused one turns out to have been closed by the server
+    fn exchange(
+        &self,
+        target: &Target,
+        request: &HttpRequest,
+        streaming: bool,
+    ) -> std::io::Result<HttpResponse> {
+        let wire = self.wire_request(target, request);
+
+        let (mut connection, reused) = match self.checkout(target) {
+            Some(connection) => (connection, true),
+            None => (self.connect(target)?, false),
+        };
+
+        // A streaming request body can only be sent once
+        let retry = reused && request.get_method().is_idempotent() && !wire.is_body_streaming();
+
+        let mut response = match round_trip(&mut connection, &wire, streaming) {
+            Err(err) if retry && err.is_retryable() => {
+                connection = self.connect(target)?;
+                round_trip(&mut connection, &wire, streaming)?
+            }
+            res => res?,
+        };
+
+        let reusable = is_reusable(&wire, &response);
+
+        if streaming {
+            let len = match response.get_header("Transfer-Encoding") {
+                Some(_) => None,
+                None => response
+                    .get_header("Content-Length")
+                    .and_then(|len| len.parse().ok()),
+            };
+
+            let body = ResponseBody {
+                connection: Some(connection),
+                pool: Arc::clone(&self.pool),
+                key: target.authority(),
+                reusable,
+            };
+            response.set_body_reader(body, len);
+        } else if reusable {
+            checkin(&self.pool, target.authority(), connection);
+        }
+
+        Ok(response)
+    }
+
+    /// The request as it is sent: in origin-form and with the default headers filled in
+    fn wire_request(&self, target: &Target, request: &HttpRequest) -> HttpRequest {
+        let mut wire = request.clone();
+        wire.set_url(target.path())
+            .expect("a parsed URL has no whitespace or control characters");
+        wire.set_version(Version::Http11);
+
+        if !wire.get_headers().contains("Host") {
+            wire.set_header("Host".into(), target.authority());
+        }
+
+        let jar_cookies = self.cookie_jar.as_ref().and_then(|jar| {
+            jar.lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .cookie_header(&target.url)
+        });
+
+        // Cookies set on the request itself come first
+        if let Some(jar_cookies) = jar_cookies {
+            let cookies = match wire.get_header("Cookie") {
+                Some(cookies) => format!("{}; {}", cookies, jar_cookies),
+                None => jar_cookies,
+            };
+            wire.set_header("Cookie".into(), cookies);
+        }
+
+        if !wire.get_headers().contains("User-Agent") {
+            wire.set_header("User-Agent".into(), self.user_agent.clone());
+        }
+
+        if self.decompress && !wire.get_headers().contains("Accept-Encoding") {
+            wire.set_header("Accept-Encoding".into(), "gzip, deflate".into());
+        }
+
+        // A request without either has no body, which is only the right default for methods
+        // that don't usually carry one. Streaming bodies of unknown length are sent chunked
+        if !wire.get_headers().contains("Transfer-Encoding") {
+            let expects_body = matches!(
+                wire.get_method(),
+                Method::Post | Method::Put | Method::Patch
+            );
+
+            match wire.get_body_length() {
+                Some(len) if expects_body || len > 0 => {
+                    wire.set_header("Content-Length".into(), len.to_string());
+                }
+                _ => (),
+            }
+        }
+
+        wire
+    }
+
+    fn connect(&self, target: &Target) -> std::io::Result<Connection> {
+        let mut last_err = None;
+
+        for addr in (target.host.as_str(), target.port).to_socket_addrs()? {
+            let res = match self.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+
+            match res {
+                Ok(stream) => {
+                    stream.set_read_timeout(self.read_timeout)?;
+                    stream.set_write_timeout(self.write_timeout)?;
+                    stream.set_nodelay(true)?;
+
+                    let mut connection = HttpStream::from_parts(stream.try_clone()?, stream);
+                    connection.set_limits(self.limits);
+
+                    return Ok(connection);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Could not resolve {}", target.host),
+            )
+        }))
+    }
+
+    /// Takes an idle connection to the target out of the pool, dropping any that have expired or
+    /// were closed in the meantime
+    fn checkout(&self, target: &Target) -> Option<Connection> {
+        let mut pool = self.pool.lock().unwrap_or_else(|err| err.into_inner());
+        let idle = pool.get_mut(&target.authority())?;
+
+        while let Some(entry) = idle.pop() {
+            if entry.since.elapsed() >= self.idle_timeout {
+                continue;
+            }
+
+            // An idle connection has nothing to read, unless the server closed it
+            #[cfg(unix)]
+            if entry
+                .connection
+                .wait_readable(Some(Duration::ZERO))
+                .unwrap_or(true)
+            {
+                continue;
+            }
+
+            return Some(entry.connection);
+        }
+
+        None
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An absolute `http` URL along with the address the client connects to for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Target {
+    url: Url,
+    host: String,
+    port: u16,
+}
+
+impl Target {
+    fn parse(url: &str) -> std::io::Result<Self> {
+        Self::from_url(Url::parse(url).map_err(invalid_url)?)
+    }
+
+    fn from_url(url: Url) -> std::io::Result<Self> {
+        let Some(scheme) = url.scheme() else {
+            return Err(invalid_url("Request URLs must be absolute"));
+        };
+
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("Unsupported URL scheme: {}", scheme),
+            ));
+        }
+
+        let host = match url.host() {
+            Some(host) if !host.is_empty() => host.to_string(),
+            _ => return Err(invalid_url("URL has no host")),
+        };
+
+        Ok(Self {
+            port: url.port_or_default().unwrap_or(80),
+            host,
+            url,
+        })
+    }
+
+    /// `host:port` as used in the `Host` header and as the pool key, the default port is left out
+    fn authority(&self) -> String {
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.clone(),
+        };
+
+        match self.port {
+            80 => host,
+            port => format!("{}:{}", host, port),
+        }
+    }
+
+    /// The request target in origin-form, the fragment is never sent
+    fn path(&self) -> String {
+        self.url.path_and_query()
+    }
+
+    /// Resolves a `Location` header against this target
+    fn resolve(&self, location: &str) -> std::io::Result<Self> {
+        Self::from_url(self.url.join(location).map_err(invalid_url)?)
+    }
+}
+
+fn invalid_url<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+}
+
+fn checkin(pool: &Pool, key: String, connection: Connection) {
+    let mut pool = pool.lock().unwrap_or_else(|err| err.into_inner());
+    let idle = pool.entry(key).or_default();
+
+    if idle.len() < MAX_IDLE_PER_HOST {
+        idle.push(IdleConnection {
+            connection,
+            since: Instant::now(),
+        });
+    }
+}
+
+/// A response body streamed from a connection, which goes back to the pool at the end of the body
+struct ResponseBody {
+    connection: Option<Connection>,
+    pool: Arc<Pool>,
+    key: String,
+    reusable: bool,
+}
+
+impl Read for ResponseBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(connection) = &mut self.connection else {
+            return Ok(0);
+        };
+
+        let bytes_read = connection.read_body(buf)?;
+
+        if bytes_read == 0 && !buf.is_empty() {
+            let connection = self.connection.take().unwrap();
+
+            if self.reusable {
+                checkin(&self.pool, std::mem::take(&mut self.key), connection);
+            }
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+fn request(method: Method, url: &str, content_type: Option<&str>, body: Box<[u8]>) -> HttpRequest {
+    let mut request = HttpRequest::new(
+        method,
+        url.to_string(),
+        Version::Http11,
+        Default::default(),
+        body,
+    );
+
+    if let Some(content_type) = content_type {
+        request.set_header("Content-Type".into(), content_type.into());
+    }
+
+    request
+}
+
+/// Receives only the head of the response when `streaming`, its body is left in the connection
+fn round_trip(
+    connection: &mut Connection,
+    request: &HttpRequest,
+    streaming: bool,
+) -> Result<HttpResponse, HttpError> {
+    connection.send_request(request)?;
+
+    loop {
+        let response = match streaming {
+            true => connection.recv_response_streaming(request.get_method())?.0,
+            false => connection.recv_response_to(request.get_method())?,
+        };
+
+        if !response.get_status_code().is_informational()
+            || response.get_status_code() == StatusCode::SWITCHING_PROTOCOLS
+        {
+            return Ok(response);
+        }
+    }
+}
+
+fn is_followed_redirect(status_code: StatusCode) -> bool {
+    matches!(status_code.as_u16(), 301 | 302 | 303 | 307 | 308)
+}
+
+/// Rewrites `request` to follow a redirect from `from` to `to`
+///
+/// 303 turns every request but HEAD into a GET and, as browsers do, so do 301 and 302 for POST
+/// requests. 307 and 308 always repeat the request as it was
+fn redirect(request: &mut HttpRequest, status_code: StatusCode, from: &Target, to: &Target) {
+    let becomes_get = match status_code.as_u16() {
+        303 => request.get_method() != &Method::Head,
+        301 | 302 => request.get_method() == &Method::Post,
+        _ => false,
+    };
+
+    if becomes_get {
+        request.set_method(Method::Get);
+        request.set_body(Box::new([]));
+
+        for name in ["Content-Length", "Content-Type", "Transfer-Encoding"] {
+            request.remove_header(name);
+        }
+    }
+
+    // Credentials are not meant for other hosts
+    if from.host != to.host || from.port != to.port {
+        for name in ["Host", "Authorization", "Cookie"] {
+            request.remove_header(name);
+        }
+    }
+
+    request
+        .set_url(to.url.to_string())
+        .expect("a parsed URL has no whitespace or control characters");
+}
+
+/// A connection can be reused if neither side asked to close it and the response wasn't
+/// delimited by closing the connection
+pub(crate) fn is_reusable(request: &HttpRequest, response: &HttpResponse) -> bool {
+    if !request.is_keep_alive() || !response.is_keep_alive() {
+        return false;
+    }
+
+    let status_code = response.get_status_code();
+
+    request.get_method() == &Method::Head
+        || status_code.is_informational()
+        || status_code == StatusCode::NO_CONTENT
+        || status_code == StatusCode::NOT_MODIFIED
+        || response.get_headers().contains("Content-Length")
+        || is_chunked(response.get_headers()) == Some(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{Compression, Cookie, HttpServer, Pipeline, Router};
+
+    #[test]
+    fn target_and_location() {
+        let target = Target::parse("http://example.com:8080/a/b?x=1#top").unwrap();
+        assert_eq!(target.host, "example.com");
+        assert_eq!(target.port, 8080);
+        assert_eq!(target.path(), "/a/b?x=1");
+        assert_eq!(target.authority(), "example.com:8080");
+
+        let resolve = |location| target.resolve(location).unwrap().url.to_string();
+        assert_eq!(resolve("c"), "http://example.com:8080/a/c");
+        assert_eq!(resolve("/c"), "http://example.com:8080/c");
+        assert_eq!(resolve("//other/c"), "http://other/c");
+
+        let target = Target::parse("http://[::1]?q").unwrap();
+        assert_eq!(target.host, "::1");
+        assert_eq!(target.path(), "/?q");
+        assert_eq!(target.authority(), "[::1]");
+
+        assert!(Target::parse("https://example.com").is_err());
+        assert!(Target::parse("/relative").is_err());
+    }
+
+    #[test]
+    fn redirects_and_connection_reuse() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 2).unwrap();
+        server.set_logging(false);
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let mut router = Router::new();
+        router
+            .get("/hello", |request, _| {
+                let mut response = HttpResponse::from_status(StatusCode::OK);
+                let agent = request.get_header("User-Agent").unwrap_or_default();
+                response.set_body(format!("hello {}", agent).into_bytes().into());
+                response
+            })
+            .post("/form", |_, _| {
+                let mut response = HttpResponse::from_status(StatusCode::SEE_OTHER);
+                response.set_header("Location".into(), "hello".into());
+                response
+            })
+            .get("/download", |_, _| {
+                let mut response = HttpResponse::from_status(StatusCode::OK);
+                response.set_body_reader(std::io::repeat(b'x').take(100_000), None);
+                response
+            })
+            .get("/loop", |_, _| {
+                let mut response = HttpResponse::from_status(StatusCode::FOUND);
+                response.set_header("Location".into(), "/loop".into());
+                response
+            });
+
+        let server = std::thread::spawn(move || server.serve(router));
+
+        let mut client = HttpClient::new();
+        client.set_user_agent("test".into()).set_max_redirects(3);
+
+        let url = format!("http://{}", addr);
+
+        let response = client.get(&format!("{}/hello", url)).unwrap();
+        assert_eq!(response.get_body(), b"hello test");
+
+        let response = client
+            .post(
+                &format!("{}/form", url),
+                "text/plain",
+                b"data".as_slice().into(),
+            )
+            .unwrap();
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert_eq!(response.get_body(), b"hello test");
+
+        let response = client.head(&format!("{}/hello", url)).unwrap();
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert!(response.get_body().is_empty());
+
+        assert!(client.get(&format!("{}/loop", url)).is_err());
+
+        let mut response = client
+            .send_streaming(request(
+                Method::Get,
+                &format!("{}/download", url),
+                None,
+                Box::new([]),
+            ))
+            .unwrap();
+        assert_eq!(response.get_header("Transfer-Encoding"), Some("chunked"));
+        assert!(response.get_body_length().is_none());
+
+        let body = response.take_body().into_bytes().unwrap();
+        assert_eq!(body.len(), 100_000);
+        assert!(body.iter().all(|byte| *byte == b'x'));
+
+        // Every request went over the same connection
+        let pool = client.pool.lock().unwrap();
+        assert_eq!(pool[&addr.to_string()].len(), 1);
+        drop(pool);
+
+        drop(client);
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn cookie_jar() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 1).unwrap();
+        server.set_logging(false);
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let mut router = Router::new();
+        router
+            .get("/login", |_, _| {
+                let mut response = HttpResponse::from_status(StatusCode::FOUND);
+                response.set_header("Location".into(), "/account/me".into());
+                response.add_cookie(Cookie::new("session", "s1").set_path("/account"));
+                response.add_cookie(&Cookie::new("theme", "dark"));
+                response
+            })
+            .get("/account/me", |request, _| {
+                let mut response = HttpResponse::from_status(StatusCode::OK);
+                let cookies = request.get_header("Cookie").unwrap_or_default();
+                response.set_body(cookies.as_bytes().into());
+                response
+            });
+
+        let server = std::thread::spawn(move || server.serve(router));
+
+        let jar = Arc::new(Mutex::new(CookieJar::new()));
+        let mut client = HttpClient::new();
+        client.set_cookie_jar(Some(Arc::clone(&jar)));
+
+        let response = client.get(&format!("http://{}/login", addr)).unwrap();
+        assert_eq!(response.get_body(), b"session=s1; theme=dark");
+        assert_eq!(jar.lock().unwrap().len(), 2);
+
+        let mut request = request(
+            Method::Get,
+            &format!("http://{}/account/me", addr),
+            None,
+            Box::new([]),
+        );
+        request.set_header("Cookie".into(), "own=1".into());
+        let response = client.send(request).unwrap();
+        assert_eq!(response.get_body(), b"own=1; session=s1; theme=dark");
+
+        drop(client);
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn decompression() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 1).unwrap();
+        server.set_logging(false);
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let mut router = Router::new();
+        router.get("/report", |request, _| {
+            let mut response = HttpResponse::from_status(StatusCode::OK);
+            response.set_header("Content-Type".into(), "text/plain".into());
+
+            let body = "all systems nominal\n".repeat(500);
+            match request.get_header("X-Stream") {
+                Some(_) => response.set_body_reader(std::io::Cursor::new(body), None),
+                None => response.set_body(body.into_bytes().into_boxed_slice()),
+            }
+            response
+        });
+
+        let pipeline = Pipeline::new(router).wrap(Compression::new());
+        let server = std::thread::spawn(move || server.serve(pipeline));
+
+        let url = format!("http://{}/report", addr);
+        let expected = "all systems nominal\n".repeat(500);
+        let mut client = HttpClient::new();
+
+        let response = client.get(&url).unwrap();
+        assert_eq!(response.get_body(), expected.as_bytes());
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert_eq!(
+            response.get_header("Content-Length"),
+            Some(expected.len().to_string().as_str())
+        );
+
+        let response = client.head(&url).unwrap();
+        assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+
+        let mut request = request(Method::Get, &url, None, Box::new([]));
+        request.set_header("X-Stream".into(), "1".into());
+        request.set_header("Accept-Encoding".into(), "deflate".into());
+        let mut response = client.send_streaming(request).unwrap();
+        let mut body = String::new();
+        response.get_body_mut().read_to_string(&mut body).unwrap();
+        assert_eq!(body, expected);
+
+        client.set_decompress(false);
+        let response = client.get(&url).unwrap();
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert_eq!(response.get_body(), expected.as_bytes());
+
+        drop(client);
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,444 @@
+use std::io::Read;
+
+use super::{Body, HeaderMap, HttpRequest, HttpResponse, Middleware, Next, ParseError, StatusCode};
+use crate::compression::{self, Decoder, Encoder, Format};
+
+/// The content codings that can be applied and removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Gzip,
+    Deflate,
+}
+
+impl Coding {
+    fn parse(token: &str) -> Option<Self> {
+        match token.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    /// HTTP's `deflate` is the zlib format, not raw DEFLATE
+    fn format(&self) -> Format {
+        match self {
+            Self::Gzip => Format::Gzip,
+            Self::Deflate => Format::Zlib,
+        }
+    }
+}
+
+/// Compresses response bodies with gzip or deflate when the request's `Accept-Encoding` allows it,
+/// and decompresses request bodies sent with either
+///
+/// Only responses with a compressible Content-Type (text, JSON, XML, JavaScript, SVG and the
+/// like) and a body of at least [Compression::set_min_size] bytes are compressed, streaming bodies
+/// are compressed as they are sent. Responses that already have a Content-Encoding, partial
+/// content and responses marked `Cache-Control: no-transform` are left alone. Compressible
+/// responses get `Vary: Accept-Encoding` whether they were compressed or not, and a strong ETag
+/// is made weak
+///
+/// Request bodies that fail to decompress are answered with a 400, those that decompress to more
+/// than [Compression::set_max_request_size] bytes with a 413
+pub struct Compression {
+    level: u32,
+    min_size: u64,
+    max_request_size: u64,
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self {
+            level: compression::DEFAULT_LEVEL,
+            min_size: 1024,
+            max_request_size: 64 * 1024 * 1024,
+        }
+    }
+
+    /// From 0 for no compression to 9 for the smallest output, defaults to 6
+    pub fn set_level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Smaller bodies aren't worth compressing, defaults to 1 KiB
+    pub fn set_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// The largest decompressed request body, defaults to 64 MiB
+    pub fn set_max_request_size(mut self, max_request_size: u64) -> Self {
+        self.max_request_size = max_request_size;
+        self
+    }
+
+    fn is_compressible(&self, response: &HttpResponse) -> bool {
+        let headers = response.get_headers();
+        let status_code = response.get_status_code();
+
+        if status_code.is_informational()
+            || status_code == StatusCode::NO_CONTENT
+            || status_code == StatusCode::NOT_MODIFIED
+            || status_code == StatusCode::PARTIAL_CONTENT
+            || headers.contains("Content-Encoding")
+            || headers.contains("Content-Range")
+            || headers.contains_token("Cache-Control", "no-transform")
+        {
+            return false;
+        }
+
+        if response
+            .get_body_length()
+            .is_some_and(|len| len < self.min_size)
+        {
+            return false;
+        }
+
+        headers
+            .get("Content-Type")
+            .is_some_and(is_compressible_type)
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse {
+        let mut response = match content_coding(request.get_headers()) {
+            Some(_) => {
+                let mut request = request.clone();
+                if let Err(err) = request.decode_body(self.max_request_size) {
+                    let status_code = match ParseError::from_io(&err) {
+                        Some(err) => err.status_code(),
+                        None => StatusCode::BAD_REQUEST,
+                    };
+                    return HttpResponse::from_status(status_code);
+                }
+
+                next.run(&request)
+            }
+            None => next.run(request),
+        };
+
+        if !self.is_compressible(&response) {
+            return response;
+        }
+
+        if !response
+            .get_headers()
+            .contains_token("Vary", "Accept-Encoding")
+        {
+            response.add_header("Vary".into(), "Accept-Encoding".into());
+        }
+
+        let Some(coding) = negotiate(request.get_header("Accept-Encoding")) else {
+            return response;
+        };
+
+        let body = response.take_body();
+        if body.is_stream() {
+            let encoder = Encoder::new(body, coding.format(), self.level);
+            response.set_body_reader(encoder, None);
+            // Streams such as static files come with the length of the uncompressed body
+            response.remove_header("Content-Length");
+        } else {
+            let body = compression::compress(body.as_bytes(), coding.format(), self.level);
+            response.set_header("Content-Length".into(), body.len().to_string());
+            response.set_body(body.into_boxed_slice());
+        }
+
+        response.set_header("Content-Encoding".into(), coding.as_str().into());
+
+        // The compressed representation isn't byte for byte the same as the original one
+        if let Some(etag) = response.get_header("ETag") {
+            if !etag.starts_with("W/") {
+                let etag = format!("W/{}", etag);
+                response.set_header("ETag".into(), etag);
+            }
+        }
+
+        response
+    }
+}
+
+fn is_compressible_type(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    // Event streams have to reach the client as they are written, not in blocks
+    if media_type == "text/event-stream" {
+        return false;
+    }
+
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(
+            media_type.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-www-form-urlencoded"
+                | "image/svg+xml"
+        )
+}
+
+/// The coding the client prefers out of those it accepts according to `accept_encoding`
+/// (RFC 9110 12.5.3), gzip wins a tie. Without the header there's no telling what the client
+/// supports, so nothing is chosen
+fn negotiate(accept_encoding: Option<&str>) -> Option<Coding> {
+    let mut gzip = None;
+    let mut deflate = None;
+    let mut any = None;
+
+    for item in accept_encoding?.split(',') {
+        let mut params = item.split(';');
+        let token = params.next().unwrap_or_default().trim();
+
+        let quality = params
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map_or(Some(1.0), |(_, value)| value.trim().parse::<f32>().ok());
+        let Some(quality) = quality else {
+            continue;
+        };
+
+        match Coding::parse(token) {
+            Some(Coding::Gzip) => gzip = Some(quality),
+            Some(Coding::Deflate) => deflate = Some(quality),
+            None if token == "*" => any = Some(quality),
+            None => {}
+        }
+    }
+
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    let deflate = deflate.or(any).unwrap_or(0.0);
+
+    if gzip > 0.0 && gzip >= deflate {
+        Some(Coding::Gzip)
+    } else if deflate > 0.0 {
+        Some(Coding::Deflate)
+    } else {
+        None
+    }
+}
+
+/// The coding of a message with a single gzip or deflate Content-Encoding
+fn content_coding(headers: &HeaderMap) -> Option<Coding> {
+    let mut codings = headers
+        .get_all("Content-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"));
+
+    match (codings.next(), codings.next()) {
+        (Some(coding), None) => Coding::parse(coding),
+        _ => None,
+    }
+}
+
+/// Removes a gzip or deflate Content-Encoding from a message, see [HttpRequest::decode_body]
+pub(crate) fn decode_body(
+    headers: &mut HeaderMap,
+    body: &mut Body,
+    max_size: u64,
+) -> std::io::Result<()> {
+    let Some(coding) = content_coding(headers) else {
+        return Ok(());
+    };
+
+    let encoded = std::mem::take(body);
+    let decoder = match coding {
+        Coding::Gzip => Decoder::new(encoded, Format::Gzip),
+        Coding::Deflate => Decoder::zlib_or_deflate(encoded),
+    };
+
+    if decoder.get_ref().is_stream() {
+        *body = Body::from_reader(DecodedBody(decoder), None);
+        headers.remove("Content-Length");
+    } else {
+        let mut decoded = Vec::new();
+        decoder
+            .take(max_size.saturating_add(1))
+            .read_to_end(&mut decoded)?;
+        if decoded.len() as u64 > max_size {
+            return Err(ParseError::BodyTooLarge.into());
+        }
+
+        headers.insert("Content-Length", decoded.len().to_string());
+        *body = decoded.into();
+    }
+
+    headers.remove("Content-Encoding");
+    Ok(())
+}
+
+/// A decompressed stream that reads the compressed body to its end once the compressed data is
+/// over, which is what hands a client connection back to its pool
+struct DecodedBody(Decoder<Body>);
+
+impl Read for DecodedBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.0.read(buf)?;
+        if len == 0 && !buf.is_empty() {
+            std::io::copy(self.0.get_mut(), &mut std::io::sink())?;
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{Handler, Method, Pipeline, Version};
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(negotiate(None), None);
+        assert_eq!(negotiate(Some("")), None);
+        assert_eq!(negotiate(Some("gzip, deflate, br")), Some(Coding::Gzip));
+        assert_eq!(negotiate(Some("deflate")), Some(Coding::Deflate));
+        assert_eq!(
+            negotiate(Some("gzip;q=0.5, deflate;q=0.8")),
+            Some(Coding::Deflate)
+        );
+        assert_eq!(negotiate(Some("*")), Some(Coding::Gzip));
+        assert_eq!(negotiate(Some("*, gzip;q=0")), Some(Coding::Deflate));
+        assert_eq!(negotiate(Some("gzip;q=0, deflate;q=0")), None);
+        assert_eq!(negotiate(Some("identity, br")), None);
+        assert_eq!(negotiate(Some("GZIP ; Q=1")), Some(Coding::Gzip));
+    }
+
+    fn json_handler(request: &HttpRequest) -> HttpResponse {
+        let mut response = HttpResponse::from_status(StatusCode::OK);
+        response.set_header("Content-Type".into(), "application/json".into());
+        response.set_header("ETag".into(), "\"v1\"".into());
+
+        let body = match request.get_url() {
+            "/small" => "[]".repeat(10),
+            "/echo" => String::from_utf8(request.get_body().to_vec()).unwrap(),
+            _ => "{\"id\": 1, \"name\": \"fp_lib\"}, ".repeat(1000),
+        };
+        response.set_body(body.into_bytes().into_boxed_slice());
+        response
+    }
+
+    fn get(url: &str, accept_encoding: Option<&str>) -> HttpRequest {
+        let mut headers = HeaderMap::new();
+        if let Some(accept_encoding) = accept_encoding {
+            headers.insert("Accept-Encoding", accept_encoding);
+        }
+
+        HttpRequest::new(
+            Method::Get,
+            url.into(),
+            Version::Http11,
+            headers,
+            Box::new([]),
+        )
+    }
+
+    #[test]
+    fn compresses_responses() {
+        let pipeline = Pipeline::new(json_handler).wrap(Compression::new());
+        let plain = json_handler(&get("/", None));
+
+        let mut response = pipeline.handle(&get("/", Some("gzip, deflate")));
+        assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.get_header("Vary"), Some("Accept-Encoding"));
+        assert_eq!(response.get_header("ETag"), Some("W/\"v1\""));
+        assert!(response.get_body().len() < plain.get_body().len() / 10);
+        assert_eq!(
+            response.get_header("Content-Length"),
+            Some(response.get_body().len().to_string().as_str())
+        );
+
+        response.decode_body(u64::MAX).unwrap();
+        assert_eq!(response.get_body(), plain.get_body());
+        assert_eq!(response.get_header("Content-Encoding"), None);
+
+        let response = pipeline.handle(&get("/", Some("deflate")));
+        assert_eq!(response.get_header("Content-Encoding"), Some("deflate"));
+        assert_eq!(
+            compression::decompress(response.get_body(), Format::Zlib).unwrap(),
+            plain.get_body()
+        );
+
+        // Not accepted, but could have been
+        let response = pipeline.handle(&get("/", None));
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert_eq!(response.get_header("Vary"), Some("Accept-Encoding"));
+        assert_eq!(response.get_body(), plain.get_body());
+
+        let response = pipeline.handle(&get("/small", Some("gzip")));
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert_eq!(response.get_header("Vary"), None);
+    }
+
+    #[test]
+    fn compresses_streams_and_decodes_requests() {
+        let pipeline = Pipeline::new(|request: &HttpRequest| {
+            let mut response = json_handler(request);
+            let body = response.take_body();
+            response.set_body_reader(body, None);
+            response
+        })
+        .wrap(Compression::new().set_max_request_size(10));
+
+        let mut response = pipeline.handle(&get("/", Some("gzip")));
+        assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+        assert!(response.is_body_streaming());
+
+        let mut compressed = Vec::new();
+        response
+            .get_body_mut()
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(
+            compression::decompress(&compressed, Format::Gzip).unwrap(),
+            json_handler(&get("/", None)).get_body()
+        );
+
+        let mut request = get("/echo", None);
+        request.set_header("Content-Encoding".into(), "gzip".into());
+        request.set_body(compression::compress(b"[1, 2, 3]", Format::Gzip, 6).into());
+        let mut response = pipeline.handle(&request);
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        let body = response.take_body().into_bytes().unwrap();
+        assert_eq!(&body[..], b"[1, 2, 3]");
+
+        // Raw DEFLATE where zlib data belongs is accepted too
+        request.set_header("Content-Encoding".into(), "deflate".into());
+        request.set_body(compression::compress(b"[4]", Format::Deflate, 6).into());
+        let mut response = pipeline.handle(&request);
+        let body = response.take_body().into_bytes().unwrap();
+        assert_eq!(&body[..], b"[4]");
+
+        request.set_header("Content-Encoding".into(), "gzip".into());
+        request.set_body(compression::compress(b"[1, 2, 3, 4]", Format::Gzip, 6).into());
+        let response = pipeline.handle(&request);
+        assert_eq!(response.get_status_code(), StatusCode::CONTENT_TOO_LARGE);
+
+        request.set_body(Box::new(*b"not gzip"));
+        let response = pipeline.handle(&request);
+        assert_eq!(response.get_status_code(), StatusCode::BAD_REQUEST);
+    }
+}
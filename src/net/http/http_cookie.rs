@@ -0,0 +1,624 @@
+use std::fmt::Display;
+use std::time::{Duration, SystemTime};
+
+use super::HttpResponse;
+use super::{format_http_date, parse_http_date};
+use crate::net::url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strict => write!(f, "Strict"),
+            Self::Lax => write!(f, "Lax"),
+            Self::None => write!(f, "None"),
+        }
+    }
+}
+
+/// An HTTP cookie (RFC 6265), as sent in a `Cookie` header or set with `Set-Cookie`
+///
+/// Only the name and value are sent back by clients, the attributes are for `Set-Cookie`. Neither
+/// is checked for characters that aren't allowed in a cookie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    expires: Option<SystemTime>,
+    max_age: Option<Duration>,
+    domain: Option<String>,
+    path: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// A cookie that makes clients delete the cookie of the same name, the domain and path have
+    /// to match the ones it was set with
+    pub fn removal(name: &str) -> Self {
+        let mut cookie = Self::new(name, "");
+        cookie
+            .set_max_age(Duration::ZERO)
+            .set_expires(SystemTime::UNIX_EPOCH);
+
+        cookie
+    }
+
+    /// Parses the `name=value` pairs of a `Cookie` request header, pairs without a name are
+    /// skipped and quotes around values are removed
+    pub fn parse_header(header: &str) -> Vec<Cookie> {
+        header
+            .split(';')
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let (name, value) = (name.trim(), value.trim());
+
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+
+                (!name.is_empty()).then(|| Self::new(name, value))
+            })
+            .collect()
+    }
+
+    /// Parses a `Set-Cookie` header value the way user agents do (RFC 6265 5.2), unknown and
+    /// malformed attributes are ignored. Returns `None` for a cookie without a name
+    pub fn parse_set_cookie(header: &str) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Self::new(name, value);
+
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+
+            match key.as_str() {
+                "expires" => cookie.expires = parse_http_date(value).or(cookie.expires),
+                "max-age" => {
+                    let digits = value.strip_prefix('-').unwrap_or(value);
+                    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                        continue;
+                    }
+
+                    // Zero and negative ages expire the cookie right away
+                    let secs = match value.starts_with('-') {
+                        true => 0,
+                        false => value.parse().unwrap_or(u64::MAX),
+                    };
+                    cookie.max_age = Some(Duration::from_secs(secs));
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.strip_prefix('.').unwrap_or(value);
+                    cookie.domain = Some(domain.to_ascii_lowercase());
+                }
+                "path" => {
+                    cookie.path = value.starts_with('/').then(|| value.to_string());
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "strict" => Some(SameSite::Strict),
+                        "lax" => Some(SameSite::Lax),
+                        "none" => Some(SameSite::None),
+                        _ => cookie.same_site,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Some(cookie)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn set_value(&mut self, value: &str) -> &mut Self {
+        self.value = value.to_string();
+        self
+    }
+
+    pub fn get_expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Sent with second precision
+    pub fn set_expires(&mut self, expires: SystemTime) -> &mut Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn get_max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Takes precedence over Expires, sent with second precision
+    pub fn set_max_age(&mut self, max_age: Duration) -> &mut Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn get_domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// Also sends the cookie to subdomains of `domain`, without a domain the cookie only goes
+    /// back to the host that set it
+    pub fn set_domain(&mut self, domain: &str) -> &mut Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn set_path(&mut self, path: &str) -> &mut Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn set_secure(&mut self, secure: bool) -> &mut Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    pub fn set_http_only(&mut self, http_only: bool) -> &mut Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn get_same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    pub fn set_same_site(&mut self, same_site: SameSite) -> &mut Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// The cookie as a `Set-Cookie` header value, use [Display] for the same
+    pub fn to_set_cookie(&self) -> String {
+        let mut header = format!("{}={}", self.name, self.value);
+
+        if let Some(expires) = self.expires {
+            header.push_str(&format!("; Expires={}", format_http_date(expires)));
+        }
+
+        if let Some(max_age) = self.max_age {
+            header.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+
+        if let Some(domain) = &self.domain {
+            header.push_str(&format!("; Domain={}", domain));
+        }
+
+        if let Some(path) = &self.path {
+            header.push_str(&format!("; Path={}", path));
+        }
+
+        if self.secure {
+            header.push_str("; Secure");
+        }
+
+        if self.http_only {
+            header.push_str("; HttpOnly");
+        }
+
+        if let Some(same_site) = self.same_site {
+            header.push_str(&format!("; SameSite={}", same_site));
+        }
+
+        header
+    }
+}
+
+/// Formats the cookie as a `Set-Cookie` header value
+impl Display for Cookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_set_cookie())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredCookie {
+    cookie: Cookie,
+    /// Lowercase, without a leading dot
+    domain: String,
+    /// Only sent back to exactly `domain`, not to its subdomains
+    host_only: bool,
+    path: String,
+    expires: Option<SystemTime>,
+    /// Orders cookies with paths of the same length
+    created: u64,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, host: &str, path: &str, secure: bool) -> bool {
+        let domain_matches = match self.host_only {
+            true => host == self.domain,
+            false => domain_matches(host, &self.domain),
+        };
+
+        domain_matches && path_matches(path, &self.path) && (secure || !self.cookie.secure)
+    }
+}
+
+/// Stores the cookies set by responses and picks the ones to send with requests
+///
+/// Follows the storage model of RFC 6265 5.3: cookies are scoped to the host that set them or
+/// the domain they name, which has to contain that host, and to a path. Later cookies with the
+/// same name, domain and path replace earlier ones. Secure cookies are only accepted from and
+/// sent to `https` and `wss` URLs
+///
+/// There is no public suffix list, so a response can set cookies for a whole top level domain
+/// such as `com` if it wants to. Only give the jar to clients that talk to hosts you trust
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<StoredCookie>,
+    next_id: u64,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores every cookie set by `response`, which is the response to a request for `url`
+    pub fn store_response(&mut self, url: &Url, response: &HttpResponse) {
+        for cookie in response.get_set_cookies() {
+            self.store(url, cookie);
+        }
+    }
+
+    /// Stores `cookie` as if it was set by the response to a request for `url`, returns whether
+    /// it was accepted. Cookies that have already expired remove the cookie they replace
+    pub fn store(&mut self, url: &Url, cookie: Cookie) -> bool {
+        let Some(host) = url.host().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+
+        if cookie.secure && !is_secure(url) {
+            return false;
+        }
+
+        let (domain, host_only) = match &cookie.domain {
+            Some(domain) => {
+                let domain = domain
+                    .strip_prefix('.')
+                    .unwrap_or(domain)
+                    .to_ascii_lowercase();
+
+                if !domain_matches(&host, &domain) {
+                    return false;
+                }
+
+                (domain, false)
+            }
+            None => (host, true),
+        };
+
+        let path = match &cookie.path {
+            Some(path) if path.starts_with('/') => path.clone(),
+            _ => default_path(url.path()),
+        };
+
+        let now = SystemTime::now();
+        let expires = match cookie.max_age {
+            Some(max_age) => Some(now.checked_add(max_age).unwrap_or(now + MAX_LIFETIME)),
+            None => cookie.expires,
+        };
+
+        let existing = self.cookies.iter().position(|stored| {
+            stored.cookie.name == cookie.name && stored.domain == domain && stored.path == path
+        });
+
+        let created = match existing {
+            Some(index) => self.cookies.remove(index).created,
+            None => {
+                self.next_id += 1;
+                self.next_id
+            }
+        };
+
+        let stored = StoredCookie {
+            cookie,
+            domain,
+            host_only,
+            path,
+            expires,
+            created,
+        };
+
+        if stored.is_expired(now) {
+            return true;
+        }
+
+        self.cookies.push(stored);
+        true
+    }
+
+    /// The cookies to send with a request for `url`, those with longer paths first
+    pub fn cookies_for(&self, url: &Url) -> Vec<&Cookie> {
+        let Some(host) = url.host().map(str::to_ascii_lowercase) else {
+            return Vec::new();
+        };
+
+        let path = match url.path() {
+            "" => "/",
+            path => path,
+        };
+        let secure = is_secure(url);
+        let now = SystemTime::now();
+
+        let mut cookies: Vec<_> = self
+            .cookies
+            .iter()
+            .filter(|stored| !stored.is_expired(now) && stored.matches(&host, path, secure))
+            .collect();
+
+        cookies.sort_by_key(|stored| (std::cmp::Reverse(stored.path.len()), stored.created));
+        cookies.into_iter().map(|stored| &stored.cookie).collect()
+    }
+
+    /// The `Cookie` header value for a request for `url`, `None` if there are no cookies to send
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let cookies = self.cookies_for(url);
+        if cookies.is_empty() {
+            return None;
+        }
+
+        let pairs: Vec<_> = cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+
+        Some(pairs.join("; "))
+    }
+
+    pub fn remove_expired(&mut self) {
+        let now = SystemTime::now();
+        self.cookies.retain(|stored| !stored.is_expired(now));
+    }
+
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    /// Number of stored cookies, including ones that have expired but were not removed yet
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.iter().map(|stored| &stored.cookie)
+    }
+}
+
+/// Used for Max-Age values too large to add to the current time
+const MAX_LIFETIME: Duration = Duration::from_secs(400 * 24 * 60 * 60);
+
+fn is_secure(url: &Url) -> bool {
+    url.scheme().is_some_and(|scheme| {
+        scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("wss")
+    })
+}
+
+/// Whether `host` is `domain` or one of its subdomains, IP addresses only match themselves
+/// (RFC 6265 5.1.3)
+fn domain_matches(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+
+    let is_ip = host.parse::<std::net::IpAddr>().is_ok();
+
+    !is_ip
+        && host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Whether the cookie path `cookie_path` covers `path` (RFC 6265 5.1.4)
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    match path.strip_prefix(cookie_path) {
+        Some(rest) => rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// The directory of the request path, used for cookies without a Path
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(pos) => path[..pos].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{HttpRequest, Method, StatusCode, Version};
+
+    #[test]
+    fn headers() {
+        let request = HttpRequest::builder()
+            .set_method(Method::Get)
+            .set_url("/".into())
+            .set_version(Version::Http11)
+            .add_header("Cookie".into(), "a=1; b=\"two\"; =x; junk".into())
+            .add_header("Cookie".into(), "c=3".into())
+            .set_body(Box::new([]))
+            .build()
+            .unwrap();
+
+        let names: Vec<_> = request
+            .get_cookies()
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.get_name(), cookie.get_value()))
+            .collect();
+        assert_eq!(names, ["a=1", "b=two", "c=3"]);
+        assert_eq!(request.get_cookie("c").unwrap().get_value(), "3");
+
+        let mut session = Cookie::new("session", "abc");
+        session
+            .set_path("/")
+            .set_domain("example.com")
+            .set_max_age(Duration::from_secs(3600))
+            .set_secure(true)
+            .set_http_only(true)
+            .set_same_site(SameSite::Lax);
+
+        let mut response = HttpResponse::from_status(StatusCode::OK);
+        response.add_cookie(&session);
+        response.add_cookie(&Cookie::removal("old"));
+
+        assert_eq!(
+            response.get_header_values("Set-Cookie"),
+            [
+                "session=abc; Max-Age=3600; Domain=example.com; Path=/; Secure; HttpOnly; SameSite=Lax",
+                "old=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0",
+            ]
+        );
+        assert_eq!(response.get_set_cookies()[0], session);
+
+        let parsed = Cookie::parse_set_cookie(
+            " id = 42 ; Domain=.Example.COM; Path=relative; Max-Age=-1; SameSite=odd; Unknown",
+        )
+        .unwrap();
+        assert_eq!((parsed.get_name(), parsed.get_value()), ("id", "42"));
+        assert_eq!(parsed.get_domain(), Some("example.com"));
+        assert_eq!(parsed.get_path(), None);
+        assert_eq!(parsed.get_max_age(), Some(Duration::ZERO));
+        assert_eq!(parsed.get_same_site(), None);
+
+        assert_eq!(Cookie::parse_set_cookie("novalue"), None);
+        assert_eq!(Cookie::parse_set_cookie("=x"), None);
+    }
+
+    #[test]
+    fn jar() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let mut jar = CookieJar::new();
+
+        let set = |jar: &mut CookieJar, from: &str, header: &str| {
+            jar.store(&url(from), Cookie::parse_set_cookie(header).unwrap())
+        };
+
+        assert!(set(&mut jar, "http://www.example.com/app/login", "host=1"));
+        assert!(set(
+            &mut jar,
+            "http://www.example.com/",
+            "wide=2; Domain=example.com; Path=/"
+        ));
+        assert!(set(
+            &mut jar,
+            "http://www.example.com/",
+            "deep=3; Path=/app/api"
+        ));
+        assert!(!set(
+            &mut jar,
+            "http://www.example.com/",
+            "other=4; Domain=example.org"
+        ));
+        assert!(!set(
+            &mut jar,
+            "http://www.example.com/",
+            "secure=5; Secure"
+        ));
+        assert!(set(
+            &mut jar,
+            "https://www.example.com/",
+            "secure=5; Secure; Path=/"
+        ));
+
+        let header = |jar: &CookieJar, to: &str| jar.cookie_header(&url(to));
+
+        assert_eq!(
+            header(&jar, "https://www.example.com/app/api/x"),
+            Some("deep=3; host=1; wide=2; secure=5".into())
+        );
+        assert_eq!(
+            header(&jar, "http://www.example.com/application"),
+            Some("wide=2".into())
+        );
+        assert_eq!(
+            header(&jar, "http://api.example.com/app"),
+            Some("wide=2".into())
+        );
+        assert_eq!(header(&jar, "http://example.org/"), None);
+
+        // Replacing and expiring
+        assert!(set(
+            &mut jar,
+            "http://a.example.com/",
+            "wide=new; Domain=example.com; Path=/"
+        ));
+        assert!(set(
+            &mut jar,
+            "http://www.example.com/app/",
+            "host=gone; Max-Age=0"
+        ));
+        assert_eq!(
+            header(&jar, "http://www.example.com/app/"),
+            Some("wide=new".into())
+        );
+        assert!(set(
+            &mut jar,
+            "http://www.example.com/",
+            "wide=x; Domain=example.com; Path=/; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        ));
+        assert_eq!(jar.len(), 2);
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats `time` as an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT` (RFC 9110 5.6.7),
+/// sub-second precision is dropped and times before 1970 are clamped to the epoch
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days);
+    let secs_of_day = secs % 86400;
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an HTTP date in any of the three formats recipients have to accept: IMF-fixdate, the
+/// obsolete RFC 850 format and asctime. The day of the week isn't checked
+pub fn parse_http_date(input: &str) -> Option<SystemTime> {
+    let input = input.trim();
+    let (_, rest) = input.split_once([',', ' '])?;
+    let parts: Vec<_> = rest.split_whitespace().collect();
+
+    let (year, month, day, time) = match parts[..] {
+        // IMF-fixdate: Sun, 06 Nov 1994 08:49:37 GMT
+        [day, month, year, time, "GMT"] if input.as_bytes()[3] == b',' => {
+            (year.parse().ok()?, month, day, time)
+        }
+        // RFC 850: Sunday, 06-Nov-94 08:49:37 GMT
+        [date, time, "GMT"] => {
+            let mut date = date.split('-');
+            let (day, month, year) = (date.next()?, date.next()?, date.next()?);
+            if year.len() != 2 {
+                return None;
+            }
+
+            // Two digit years that would be more than 50 years in the future are in the past
+            let year: u64 = year.parse().ok()?;
+            let year = if year < 70 { 2000 + year } else { 1900 + year };
+            (year, month, day, time)
+        }
+        // asctime: Sun Nov  6 08:49:37 1994
+        [month, day, time, year] => (year.parse().ok()?, month, day, time),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|&name| name == month)? as u64 + 1;
+    let day: u64 = day.parse().ok()?;
+
+    let mut time = time.split(':').map(|part| match part.len() {
+        2 => part.parse::<u64>().ok(),
+        _ => None,
+    });
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    if time.next().is_some()
+        || year < 1970
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, valid from 1970 on
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of [days_from_civil]
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = (month_index + 2) % 12 + 1;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            format_http_date(UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+
+        for input in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(parse_http_date(input), Some(time), "{}", input);
+        }
+
+        let leap = parse_http_date("Tue, 29 Feb 2000 23:59:59 GMT").unwrap();
+        assert_eq!(format_http_date(leap), "Tue, 29 Feb 2000 23:59:59 GMT");
+
+        for input in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 8:49:37 GMT",
+            "Mon, 29 Feb 2100 00:00:00 GMT",
+        ] {
+            assert_eq!(parse_http_date(input), None, "{}", input);
+        }
+    }
+}
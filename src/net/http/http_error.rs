@@ -0,0 +1,177 @@
+use std::fmt::Display;
+
+use super::ParseError;
+use super::StatusCode;
+
+/// Why receiving a message failed
+///
+/// Malformed messages are sorted by what was wrong with them, the [ParseError] they carry has
+/// the details
+#[derive(Debug)]
+pub enum HttpError {
+    Io(std::io::Error),
+    /// The peer closed the connection before sending anything
+    ConnectionClosed,
+    /// The peer closed the connection part way through a message
+    IncompleteMessage,
+    /// The read timed out, or a non-blocking stream has no more data for now. What was received
+    /// so far is kept, so receiving again picks up where this left off
+    Timeout,
+    InvalidStartLine(ParseError),
+    /// A malformed field line, including lines that aren't terminated by a CRLF or aren't UTF-8
+    InvalidHeader(ParseError),
+    InvalidStatusCode,
+    /// Content-Length, Transfer-Encoding or chunks that don't add up
+    InvalidFraming(ParseError),
+    /// The message was larger than the [super::HttpLimits] allow
+    LimitExceeded(ParseError),
+}
+
+impl HttpError {
+    /// The parse error behind a malformed message
+    pub fn parse_error(&self) -> Option<ParseError> {
+        match self {
+            Self::InvalidStartLine(err)
+            | Self::InvalidHeader(err)
+            | Self::InvalidFraming(err)
+            | Self::LimitExceeded(err) => Some(*err),
+            Self::InvalidStatusCode => Some(ParseError::InvalidStatusCode),
+            _ => None,
+        }
+    }
+
+    /// The status code to answer a malformed request with, `None` if the error is not the
+    /// peer's fault or there is nobody left to answer
+    pub fn status_code(&self) -> Option<StatusCode> {
+        self.parse_error().map(|err| err.status_code())
+    }
+
+    /// Whether the message can be sent again on a new connection, which is the case when a kept
+    /// alive connection turned out to be closed by the peer before it answered
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ConnectionClosed => true,
+            Self::Io(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::ConnectionClosed => write!(f, "Connection closed"),
+            Self::IncompleteMessage => {
+                write!(f, "Connection closed before the whole message was received")
+            }
+            Self::Timeout => write!(f, "Timed out"),
+            Self::InvalidStatusCode => write!(f, "Invalid Status Code"),
+            Self::InvalidStartLine(err)
+            | Self::InvalidHeader(err)
+            | Self::InvalidFraming(err)
+            | Self::LimitExceeded(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for HttpError {
+    fn from(err: ParseError) -> Self {
+        match err {
+            ParseError::InvalidRequestLine | ParseError::InvalidStatusLine => {
+                Self::InvalidStartLine(err)
+            }
+            ParseError::InvalidStatusCode => Self::InvalidStatusCode,
+            ParseError::InvalidHeader
+            | ParseError::ObsoleteLineFolding
+            | ParseError::BareLineFeed
+            | ParseError::InvalidUtf8 => Self::InvalidHeader(err),
+            ParseError::InvalidContentLength
+            | ParseError::ConflictingContentLength
+            | ParseError::ContentLengthWithTransferEncoding
+            | ParseError::UnsupportedTransferEncoding
+            | ParseError::InvalidChunk => Self::InvalidFraming(err),
+            ParseError::StartLineTooLong
+            | ParseError::HeaderLineTooLong
+            | ParseError::TooManyHeaders
+            | ParseError::HeadersTooLarge
+            | ParseError::BodyTooLarge => Self::LimitExceeded(err),
+        }
+    }
+}
+
+/// Sorts I/O errors, unwrapping any [HttpError] or [ParseError] they carry
+impl From<std::io::Error> for HttpError {
+    fn from(err: std::io::Error) -> Self {
+        if let Some(err) = ParseError::from_io(&err) {
+            return err.into();
+        }
+
+        if err.get_ref().is_some_and(|inner| inner.is::<Self>()) {
+            return *err.into_inner().unwrap().downcast::<Self>().unwrap();
+        }
+
+        match err.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Self::Timeout,
+            _ => Self::Io(err),
+        }
+    }
+}
+
+/// Keeps the [HttpError] inside the I/O error, converting back gives the same error
+impl From<HttpError> for std::io::Error {
+    fn from(err: HttpError) -> Self {
+        let kind = match err {
+            HttpError::Io(err) => return err,
+            HttpError::ConnectionClosed | HttpError::IncompleteMessage => {
+                std::io::ErrorKind::UnexpectedEof
+            }
+            HttpError::Timeout => std::io::ErrorKind::TimedOut,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+
+        std::io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn io_round_trip() {
+        let err: std::io::Error = HttpError::from(ParseError::TooManyHeaders).into();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let err = HttpError::from(err);
+        assert!(matches!(
+            err,
+            HttpError::LimitExceeded(ParseError::TooManyHeaders)
+        ));
+        assert_eq!(
+            err.status_code(),
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+
+        let err: std::io::Error = HttpError::ConnectionClosed.into();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(HttpError::from(err).is_retryable());
+
+        let err = std::io::Error::from(std::io::ErrorKind::WouldBlock);
+        assert!(matches!(HttpError::from(err), HttpError::Timeout));
+    }
+}
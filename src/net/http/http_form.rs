@@ -0,0 +1,751 @@
+use std::fmt::Display;
+use std::io::Read;
+
+use super::http_parser::parse_header_line;
+use super::HeaderMap;
+use crate::net::url::{decode_bytes, Query};
+
+/// Part headers larger than this are rejected
+const MAX_PART_HEAD_SIZE: usize = 16 * 1024;
+
+/// Why a form body could not be parsed
+#[derive(Debug)]
+pub enum FormError {
+    Io(std::io::Error),
+    /// The message has no Content-Type, or one that isn't a form
+    UnsupportedContentType,
+    /// A multipart Content-Type without a usable boundary parameter
+    MissingBoundary,
+    /// A urlencoded body or a part's headers that aren't UTF-8
+    InvalidUtf8,
+    /// A part without a `Content-Disposition: form-data` header naming the field
+    MissingName,
+    InvalidPartHeader,
+    PartHeadTooLarge,
+    /// The body ended before the closing boundary
+    UnexpectedEnd,
+}
+
+impl Display for FormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::UnsupportedContentType => write!(f, "Unsupported Content-Type for a form"),
+            Self::MissingBoundary => write!(f, "Missing multipart boundary"),
+            Self::InvalidUtf8 => write!(f, "Form data is not valid UTF-8"),
+            Self::MissingName => write!(f, "Multipart part without a field name"),
+            Self::InvalidPartHeader => write!(f, "Invalid multipart part header"),
+            Self::PartHeadTooLarge => write!(f, "Multipart part headers too large"),
+            Self::UnexpectedEnd => write!(f, "Multipart body ended before the closing boundary"),
+        }
+    }
+}
+
+impl std::error::Error for FormError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Unwraps a [FormError] that was passed through a [PartReader]
+impl From<std::io::Error> for FormError {
+    fn from(err: std::io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Self>()) {
+            return *err.into_inner().unwrap().downcast::<Self>().unwrap();
+        }
+
+        Self::Io(err)
+    }
+}
+
+impl From<FormError> for std::io::Error {
+    fn from(err: FormError) -> Self {
+        match err {
+            FormError::Io(err) => err,
+            FormError::UnexpectedEnd => std::io::Error::new(std::io::ErrorKind::UnexpectedEof, err),
+            err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// A single field of a form, either a plain value or an uploaded file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Part {
+    pub fn text(name: &str, value: &str) -> Self {
+        Self::new(name, None, None, value.as_bytes().to_vec())
+    }
+
+    pub fn file(name: &str, filename: &str, content_type: &str, data: Vec<u8>) -> Self {
+        Self::new(name, Some(filename), Some(content_type), data)
+    }
+
+    fn new(name: &str, filename: Option<&str>, content_type: Option<&str>, body: Vec<u8>) -> Self {
+        let mut disposition = format!("form-data; name=\"{}\"", escape_quoted(name));
+        if let Some(filename) = filename {
+            disposition.push_str(&format!("; filename=\"{}\"", escape_quoted(filename)));
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Disposition", disposition);
+        if let Some(content_type) = content_type {
+            headers.insert("Content-Type", content_type);
+        }
+
+        Self {
+            name: name.to_string(),
+            filename: filename.map(str::to_string),
+            content_type: content_type.map(str::to_string),
+            headers,
+            body,
+        }
+    }
+
+    /// Takes the field name and filename from the Content-Disposition header
+    fn from_headers(headers: HeaderMap) -> Result<Self, FormError> {
+        let (disposition, params) = headers
+            .get("Content-Disposition")
+            .map(parse_parameters)
+            .ok_or(FormError::MissingName)?;
+
+        if !disposition.eq_ignore_ascii_case("form-data") {
+            return Err(FormError::MissingName);
+        }
+
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, val)| val.clone())
+        };
+
+        let name = param("name").ok_or(FormError::MissingName)?;
+        let filename = param("filename*")
+            .and_then(|val| decode_ext_value(&val))
+            .or_else(|| param("filename"));
+
+        Ok(Self {
+            name,
+            filename,
+            content_type: headers.get("Content-Type").map(str::to_string),
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of an uploaded file, as sent by the client
+    ///
+    /// Never use it as a path as is, it may well be `../../etc/passwd`
+    pub fn get_filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn get_content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn get_body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// The body as text, `None` if it isn't UTF-8
+    pub fn as_text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+}
+
+/// The fields of a form in the order they were sent, see [HttpRequest::get_form]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Form {
+    parts: Vec<Part>,
+}
+
+impl Form {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the body of a `application/x-www-form-urlencoded` or `multipart/form-data` message
+    /// with the given Content-Type
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<Self, FormError> {
+        let (mime, _) = parse_parameters(content_type);
+
+        if mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            let body = std::str::from_utf8(body).map_err(|_| FormError::InvalidUtf8)?;
+            return Ok(Query::parse(body).into());
+        }
+
+        if mime.eq_ignore_ascii_case("multipart/form-data") {
+            let boundary = multipart_boundary(content_type).ok_or(FormError::MissingBoundary)?;
+            let mut reader = MultipartReader::new(body, &boundary);
+
+            let mut form = Self::new();
+            while let Some(part) = reader.next_part()? {
+                form.parts.push(part.into_part()?);
+            }
+
+            return Ok(form);
+        }
+
+        Err(FormError::UnsupportedContentType)
+    }
+
+    /// The first value of the field as text, files and fields that aren't UTF-8 are skipped
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).next()
+    }
+
+    /// Every value of the field as text, see [Self::get]
+    pub fn get_all<'a: 'n, 'n>(&'a self, name: &'n str) -> impl Iterator<Item = &'a str> + 'n {
+        self.parts
+            .iter()
+            .filter(move |part| part.name == name && !part.is_file())
+            .filter_map(Part::as_text)
+    }
+
+    /// The first part of the field, which includes files
+    pub fn get_part(&self, name: &str) -> Option<&Part> {
+        self.parts.iter().find(|part| part.name == name)
+    }
+
+    pub fn get_parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// Iterates over the uploaded files
+    pub fn files(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(|part| part.is_file())
+    }
+
+    pub fn append(&mut self, part: Part) -> &mut Self {
+        self.parts.push(part);
+        self
+    }
+
+    pub fn append_text(&mut self, name: &str, value: &str) -> &mut Self {
+        self.append(Part::text(name, value))
+    }
+
+    pub fn append_file(
+        &mut self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> &mut Self {
+        self.append(Part::file(name, filename, content_type, data))
+    }
+
+    /// Encodes the form as `multipart/form-data`, returning the Content-Type along with the body
+    pub fn encode_multipart(&self) -> (String, Vec<u8>) {
+        let boundary: String = crate::util::random_bytes::<16>()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let boundary = format!("----FormBoundary{}", boundary);
+
+        let mut body = Vec::new();
+        for part in &self.parts {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            part.headers.encode(&mut body);
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&part.body);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        (format!("multipart/form-data; boundary={}", boundary), body)
+    }
+
+    /// Encodes the text fields as `application/x-www-form-urlencoded`, files only keep their
+    /// filename as browsers do
+    pub fn encode_urlencoded(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match (&part.filename, part.as_text()) {
+                (Some(filename), _) => (part.name.clone(), filename.clone()),
+                (None, Some(text)) => (part.name.clone(), text.to_string()),
+                (None, None) => (
+                    part.name.clone(),
+                    String::from_utf8_lossy(&part.body).into_owned(),
+                ),
+            })
+            .collect::<Query>()
+            .to_string()
+    }
+}
+
+impl From<Query> for Form {
+    fn from(query: Query) -> Self {
+        Self {
+            parts: query
+                .iter()
+                .map(|(key, val)| Part::text(key, val))
+                .collect(),
+        }
+    }
+}
+
+/// The boundary parameter of a `multipart/*` Content-Type
+pub fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = parse_parameters(content_type);
+    if !mime.to_ascii_lowercase().starts_with("multipart/") {
+        return None;
+    }
+
+    params
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, boundary)| boundary)
+        .filter(|boundary| (1..=70).contains(&boundary.len()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the first boundary, anything there is ignored
+    Preamble,
+    /// Right after a boundary, which is followed by either `--` or the next part
+    Boundary,
+    Body,
+    Finished,
+}
+
+/// Reads the parts of a `multipart/form-data` body one by one as they arrive, so large uploads
+/// never have to be held in memory
+///
+/// Works on any reader, such as the [super::BodyReader] of a request received with
+/// [super::HttpStream::recv_request_streaming]
+pub struct MultipartReader<R: Read> {
+    rx: R,
+    /// `CRLF--boundary`, the delimiter that ends every part
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    eof: bool,
+    state: State,
+}
+
+impl<R: Read> MultipartReader<R> {
+    pub fn new(rx: R, boundary: &str) -> Self {
+        Self {
+            rx,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // The first boundary has no CRLF of its own to precede it
+            buf: b"\r\n".to_vec(),
+            eof: false,
+            state: State::Preamble,
+        }
+    }
+
+    /// Moves on to the next part, skipping whatever is left of the current one. Returns `None`
+    /// after the closing boundary
+    pub fn next_part(&mut self) -> Result<Option<PartReader<'_, R>>, FormError> {
+        loop {
+            match self.state {
+                State::Finished => return Ok(None),
+                State::Preamble => {
+                    self.skip_to_delimiter()?;
+                    self.state = State::Boundary;
+                }
+                State::Body => {
+                    std::io::copy(&mut PartReader::raw(self), &mut std::io::sink())?;
+                }
+                State::Boundary => {
+                    while self.buf.len() < 2 {
+                        self.fill()?;
+                    }
+
+                    if self.buf.starts_with(b"--") {
+                        self.state = State::Finished;
+                        return Ok(None);
+                    }
+
+                    let headers = self.read_part_head()?;
+                    let part = Part::from_headers(headers)?;
+                    self.state = State::Body;
+
+                    return Ok(Some(PartReader { rx: self, part }));
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), FormError> {
+        if self.eof {
+            return Err(FormError::UnexpectedEnd);
+        }
+
+        let mut buffer = [0_u8; 8192];
+        let bytes_read = loop {
+            match self.rx.read(&mut buffer) {
+                Ok(bytes_read) => break bytes_read,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        match bytes_read {
+            0 => self.eof = true,
+            _ => self.buf.extend_from_slice(&buffer[..bytes_read]),
+        }
+
+        Ok(())
+    }
+
+    fn skip_to_delimiter(&mut self) -> Result<(), FormError> {
+        loop {
+            if let Some(pos) = find(&self.buf, &self.delimiter) {
+                self.buf.drain(..pos + self.delimiter.len());
+                return Ok(());
+            }
+
+            // Keep what could be the start of a delimiter
+            let keep = self.delimiter.len() - 1;
+            if self.buf.len() > keep {
+                self.buf.drain(..self.buf.len() - keep);
+            }
+
+            self.fill()?;
+        }
+    }
+
+    /// Reads the rest of the boundary line and the part's header fields
+    fn read_part_head(&mut self) -> Result<HeaderMap, FormError> {
+        let end = loop {
+            if let Some(pos) = find(&self.buf, b"\r\n\r\n") {
+                break pos;
+            }
+
+            if self.buf.len() > MAX_PART_HEAD_SIZE {
+                return Err(FormError::PartHeadTooLarge);
+            }
+
+            self.fill()?;
+        };
+
+        if end > MAX_PART_HEAD_SIZE {
+            return Err(FormError::PartHeadTooLarge);
+        }
+
+        let head = std::str::from_utf8(&self.buf[..end]).map_err(|_| FormError::InvalidUtf8)?;
+        let mut lines = head.split("\r\n");
+
+        // Transport padding may follow the boundary
+        if !lines
+            .next()
+            .unwrap_or_default()
+            .trim_matches([' ', '\t'])
+            .is_empty()
+        {
+            return Err(FormError::InvalidPartHeader);
+        }
+
+        let mut headers = HeaderMap::new();
+        for line in lines {
+            let (name, value) =
+                parse_header_line(line).map_err(|_| FormError::InvalidPartHeader)?;
+            headers.append(name, value);
+        }
+
+        self.buf.drain(..end + 4);
+        Ok(headers)
+    }
+}
+
+/// The body of a single part, see [MultipartReader::next_part]
+///
+/// Reading returns 0 at the end of the part. A body that ends before the closing boundary fails
+/// with [std::io::ErrorKind::UnexpectedEof]
+pub struct PartReader<'r, R: Read> {
+    rx: &'r mut MultipartReader<R>,
+    part: Part,
+}
+
+impl<'r, R: Read> PartReader<'r, R> {
+    /// Reads the rest of a part whose headers were already taken
+    fn raw(rx: &'r mut MultipartReader<R>) -> Self {
+        Self {
+            rx,
+            part: Part::new("", None, None, Vec::new()),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.part.get_name()
+    }
+
+    /// See [Part::get_filename]
+    pub fn get_filename(&self) -> Option<&str> {
+        self.part.get_filename()
+    }
+
+    pub fn get_content_type(&self) -> Option<&str> {
+        self.part.get_content_type()
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
+        self.part.get_headers()
+    }
+
+    /// Reads the rest of the body into memory
+    pub fn into_part(mut self) -> std::io::Result<Part> {
+        let mut body = Vec::new();
+        self.read_to_end(&mut body)?;
+
+        self.part.body = body;
+        Ok(self.part)
+    }
+}
+
+impl<R: Read> Read for PartReader<'_, R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        let rx = &mut *self.rx;
+        if rx.state != State::Body || out.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let (available, found) = match find(&rx.buf, &rx.delimiter) {
+                Some(pos) => (pos, true),
+                None => (rx.buf.len().saturating_sub(rx.delimiter.len() - 1), false),
+            };
+
+            if available > 0 {
+                let len = available.min(out.len());
+                out[..len].copy_from_slice(&rx.buf[..len]);
+                rx.buf.drain(..len);
+
+                return Ok(len);
+            }
+
+            if found {
+                rx.buf.drain(..rx.delimiter.len());
+                rx.state = State::Boundary;
+
+                return Ok(0);
+            }
+
+            rx.fill()?;
+        }
+    }
+}
+
+/// Splits a header value such as `form-data; name="a"` into its first item and its parameters,
+/// unquoting quoted values
+pub(crate) fn parse_parameters(value: &str) -> (String, Vec<(String, String)>) {
+    let (first, mut rest) = value.split_once(';').unwrap_or((value, ""));
+    let mut params = Vec::new();
+
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            break;
+        }
+
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
+        };
+        let name = name.trim().to_string();
+        let after = after.trim_start();
+
+        let value = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = index + 1;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+
+            rest = &quoted[end..];
+            value
+        } else {
+            let (value, after) = after.split_once(';').unwrap_or((after, ""));
+            rest = after;
+            value.trim().to_string()
+        };
+
+        params.push((name, value));
+    }
+
+    (first.trim().to_string(), params)
+}
+
+/// Decodes an RFC 8187 extended value such as `UTF-8''na%C3%AFve.txt`
+fn decode_ext_value(value: &str) -> Option<String> {
+    let (charset, rest) = value.split_once('\'')?;
+    let (_, encoded) = rest.split_once('\'')?;
+
+    if !charset.eq_ignore_ascii_case("UTF-8") {
+        return None;
+    }
+
+    String::from_utf8(decode_bytes(encoded).ok()?).ok()
+}
+
+/// Names are quoted the way browsers do, with the characters that would end the quoted string or
+/// the line percent-encoded
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{HttpRequest, Method, Version};
+
+    #[test]
+    fn urlencoded() {
+        let form = Form::parse(
+            "application/x-www-form-urlencoded; charset=utf-8",
+            b"name=J%C3%BCrgen+M&tag=a&tag=b&empty",
+        )
+        .unwrap();
+
+        assert_eq!(form.get("name"), Some("Jürgen M"));
+        assert_eq!(form.get_all("tag").collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(form.get("empty"), Some(""));
+        assert_eq!(
+            form.encode_urlencoded(),
+            "name=J%C3%BCrgen+M&tag=a&tag=b&empty="
+        );
+
+        assert!(matches!(
+            Form::parse("application/x-www-form-urlencoded", b"\xFF"),
+            Err(FormError::InvalidUtf8)
+        ));
+        assert!(matches!(
+            Form::parse("text/plain", b""),
+            Err(FormError::UnsupportedContentType)
+        ));
+    }
+
+    #[test]
+    fn multipart() {
+        let body = b"preamble\r\n--XyZ \r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Hello\r\n--XyZ\r\n\
+            content-disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"; filename*=UTF-8''na%C3%AFve.txt\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line one\r\n--XyY\r\n\r\n--XyZ--\r\nepilogue";
+
+        let form = Form::parse("multipart/form-data; boundary=\"XyZ\"", body).unwrap();
+        assert_eq!(form.get("title"), Some("Hello"));
+        assert_eq!(form.get("upload"), None);
+
+        let file = form.files().next().unwrap();
+        assert_eq!(file.get_name(), "upload");
+        assert_eq!(file.get_filename(), Some("naïve.txt"));
+        assert_eq!(file.get_content_type(), Some("text/plain"));
+        assert_eq!(file.get_body(), b"line one\r\n--XyY\r\n");
+
+        // Read back one byte at a time to cross every buffer boundary
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+                let len = self.0.len().min(out.len()).min(1);
+                out[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let mut reader = MultipartReader::new(Trickle(body), "XyZ");
+        let first = reader.next_part().unwrap().unwrap();
+        assert_eq!(first.get_name(), "title");
+        // Skipped without being read
+        let mut second = reader.next_part().unwrap().unwrap();
+        let mut data = Vec::new();
+        second.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"line one\r\n--XyY\r\n");
+        assert!(reader.next_part().unwrap().is_none());
+
+        let truncated = &body[..body.len() - 20];
+        assert!(matches!(
+            Form::parse("multipart/form-data; boundary=XyZ", truncated),
+            Err(FormError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            Form::parse("multipart/form-data", body),
+            Err(FormError::MissingBoundary)
+        ));
+    }
+
+    #[test]
+    fn encode_requests() {
+        let mut form = Form::new();
+        form.append_text("say \"hi\"", "hello").append_file(
+            "file",
+            "data.bin",
+            "application/octet-stream",
+            vec![0, 1, 2],
+        );
+
+        let request = HttpRequest::builder()
+            .set_method(Method::Post)
+            .set_url("/upload".into())
+            .set_version(Version::Http11)
+            .set_multipart_body(&form)
+            .build()
+            .unwrap();
+
+        let parsed = request.get_form().unwrap();
+        assert_eq!(parsed.get("say %22hi%22"), Some("hello"));
+        assert_eq!(parsed.get_part("file").unwrap().get_body(), [0, 1, 2]);
+
+        let mut query = Query::new();
+        query.append("q", "a b&c");
+
+        let request = HttpRequest::builder()
+            .set_method(Method::Post)
+            .set_url("/search".into())
+            .set_version(Version::Http11)
+            .set_urlencoded_body(&query)
+            .build()
+            .unwrap();
+
+        assert_eq!(request.get_body(), b"q=a+b%26c");
+        assert_eq!(request.get_form().unwrap().get("q"), Some("a b&c"));
+    }
+}
@@ -0,0 +1,18 @@
+use super::HttpRequest;
+use super::HttpResponse;
+
+/// Turns a request into a response
+///
+/// Implemented for every `Fn(&HttpRequest) -> HttpResponse` that can be shared between threads
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, request: &HttpRequest) -> HttpResponse;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        self(request)
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt::Display;
+
+use super::http_method::is_token;
+
+/// A field name that isn't a token or a value with a CR, LF or NUL in it, which would let the
+/// field end early and smuggle in another one (RFC 9110 5.5)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHeader;
+
+impl Display for InvalidHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid HTTP Header Field")
+    }
+}
+
+impl std::error::Error for InvalidHeader {}
+
+/// HTTP header fields
+///
+/// Field names are matched case-insensitively but keep the casing they were inserted with, a name
+/// can hold several values (as with `Set-Cookie`) and fields keep their insertion order, which is
+/// also the order they are sent in. Values are stored without surrounding whitespace, fields
+/// that can't be sent as they are never make it into the map
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the first value of the field
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).next()
+    }
+
+    /// Returns every value of the field in order
+    pub fn get_all<'a: 'n, 'n>(&'a self, name: &'n str) -> impl Iterator<Item = &'a str> + 'n {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether a comma separated list field such as `Connection` holds `token`, ignoring case
+    pub fn contains_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|val| val.split(','))
+            .any(|val| val.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Replaces every value of the field with `value`, the field keeps the position of its first
+    /// occurrence
+    ///
+    /// An invalid field is a bug in the caller, debug builds panic on one and release builds drop
+    /// it. Use [Self::try_insert] for fields that may be invalid
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let result = self.try_insert(name, value);
+        debug_assert!(result.is_ok(), "Invalid header field");
+    }
+
+    /// Same as [Self::insert] but fails on an invalid field, leaving the map as it was
+    pub fn try_insert(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), InvalidHeader> {
+        let (name, value) = validate(name.into(), value.into())?;
+
+        match self
+            .entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(&name))
+        {
+            Some(pos) => {
+                self.entries[pos].1 = value;
+
+                let mut index = pos + 1;
+                while index < self.entries.len() {
+                    if self.entries[index].0.eq_ignore_ascii_case(&name) {
+                        self.entries.remove(index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+            None => self.entries.push((name, value)),
+        }
+
+        Ok(())
+    }
+
+    /// Adds another value to the field, keeping the existing ones
+    ///
+    /// An invalid field is a bug in the caller, debug builds panic on one and release builds drop
+    /// it. Use [Self::try_append] for fields that may be invalid
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let result = self.try_append(name, value);
+        debug_assert!(result.is_ok(), "Invalid header field");
+    }
+
+    /// Same as [Self::append] but fails on an invalid field
+    pub fn try_append(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), InvalidHeader> {
+        self.entries.push(validate(name.into(), value.into())?);
+        Ok(())
+    }
+
+    /// Removes the field, returning its values
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+
+        self.entries.retain_mut(|(key, val)| {
+            if key.eq_ignore_ascii_case(name) {
+                removed.push(std::mem::take(val));
+                false
+            } else {
+                true
+            }
+        });
+
+        removed
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&str, &str) -> bool) {
+        self.entries.retain(|(key, val)| f(key, val));
+    }
+
+    /// Number of name and value pairs
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Iterates over every name and value pair in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+    }
+
+    /// Describes a new buffered body of `len` bytes, the framing and coding fields that went with
+    /// the old body would no longer match it
+    pub(crate) fn replace_body_fields(&mut self, content_type: &str, len: usize) {
+        self.remove("Transfer-Encoding");
+        self.remove("Content-Encoding");
+        self.insert("Content-Type", content_type);
+        self.insert("Content-Length", len.to_string());
+    }
+
+    pub(crate) fn encode(&self, bytes: &mut Vec<u8>) {
+        for (key, val) in &self.entries {
+            bytes.extend_from_slice(key.as_bytes());
+            bytes.extend_from_slice(b": ");
+            bytes.extend_from_slice(val.as_bytes());
+            bytes.extend_from_slice(b"\r\n");
+        }
+    }
+}
+
+/// Trims the value and checks the field can be sent as is
+fn validate(name: String, value: String) -> Result<(String, String), InvalidHeader> {
+    let value = value.trim();
+
+    if !is_token(&name) || value.bytes().any(|b| matches!(b, b'\r' | b'\n' | b'\0')) {
+        return Err(InvalidHeader);
+    }
+
+    Ok((name, value.to_string()))
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut headers = Self::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+/// Appends every pair, see [HeaderMap::append]
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for HeaderMap {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (key, val) in iter {
+            self.append(key, val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn case_insensitive_multi_valued() {
+        let mut headers = HeaderMap::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Content-Type", " text/plain ");
+        headers.append("set-cookie", "b=2");
+
+        assert_eq!(headers.get("SET-COOKIE"), Some("a=1"));
+        assert_eq!(
+            headers.get_all("Set-Cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+        assert_eq!(headers.get("content-type"), Some("text/plain"));
+
+        headers.insert("SET-COOKIE", "c=3");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [("Set-Cookie", "c=3"), ("Content-Type", "text/plain")]
+        );
+
+        assert_eq!(headers.remove("content-TYPE"), ["text/plain"]);
+        assert!(!headers.contains("Content-Type"));
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn rejects_injection() {
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(
+            headers.try_insert("Location", "/a\r\nSet-Cookie: session=x"),
+            Err(InvalidHeader)
+        );
+        assert_eq!(headers.try_append("X-Id", "1\n2"), Err(InvalidHeader));
+        assert_eq!(headers.try_append("X-Id", "1\x002"), Err(InvalidHeader));
+        assert_eq!(
+            headers.try_append("X-Id: 1\r\nX-Other", "2"),
+            Err(InvalidHeader)
+        );
+        assert_eq!(headers.try_append("Bad Name", "1"), Err(InvalidHeader));
+        assert_eq!(headers.try_append("", "1"), Err(InvalidHeader));
+
+        headers.insert("Location", "/");
+        for (name, value) in [("Location", "/b\r\nX-Injected: 1"), ("X-Other\r\n", "1")] {
+            let inserted = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                headers.insert(name, value);
+            }));
+            assert_eq!(inserted.is_err(), cfg!(debug_assertions));
+
+            let appended = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                headers.append(name, value);
+            }));
+            assert_eq!(appended.is_err(), cfg!(debug_assertions));
+        }
+        assert_eq!(headers.iter().collect::<Vec<_>>(), [("Location", "/")]);
+
+        // Tabs and other visible characters are fine, surrounding whitespace is trimmed
+        assert_eq!(headers.try_append("X-Id", "a\tb \u{e9}\r\n"), Ok(()));
+        assert_eq!(headers.get("x-id"), Some("a\tb \u{e9}"));
+    }
+
+    #[test]
+    fn encode_keeps_order() {
+        let headers: HeaderMap = [("Host", "example.com"), ("Accept", "*/*"), ("host", "x")]
+            .into_iter()
+            .collect();
+
+        let mut bytes = Vec::new();
+        headers.encode(&mut bytes);
+
+        assert_eq!(
+            bytes,
+            b"Host: example.com\r\nAccept: */*\r\nhost: x\r\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,508 @@
+use std::time::{Duration, Instant};
+
+use super::Handler;
+use super::HttpRequest;
+use super::HttpResponse;
+use super::Method;
+use super::StatusCode;
+use crate::logging::Logger;
+
+/// Wraps a [Handler], see [Pipeline]
+///
+/// A middleware can inspect the request before passing it on through [Next::run], pass on a
+/// modified copy of it instead, change the response on its way back or skip the rest of the
+/// pipeline by not calling [Next::run] at all
+///
+/// Implemented for every `Fn(&HttpRequest, Next) -> HttpResponse` that can be shared between
+/// threads
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&HttpRequest, Next<'_>) -> HttpResponse + Send + Sync + 'static,
+{
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse {
+        self(request, next)
+    }
+}
+
+/// The rest of a [Pipeline] after the current middleware
+pub struct Next<'a> {
+    middlewares: &'a [Box<dyn Middleware>],
+    handler: &'a dyn Handler,
+}
+
+impl Next<'_> {
+    pub fn run(self, request: &HttpRequest) -> HttpResponse {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => middleware.handle(
+                request,
+                Next {
+                    middlewares: rest,
+                    handler: self.handler,
+                },
+            ),
+            None => self.handler.handle(request),
+        }
+    }
+}
+
+/// A handler wrapped in layers of [Middleware], the first middleware added is the outermost one
+/// and sees the request first and the response last
+pub struct Pipeline<H: Handler> {
+    middlewares: Vec<Box<dyn Middleware>>,
+    handler: H,
+}
+
+impl<H: Handler> Pipeline<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            middlewares: Vec::new(),
+            handler,
+        }
+    }
+
+    pub fn wrap(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+}
+
+impl<H: Handler> Handler for Pipeline<H> {
+    fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        Next {
+            middlewares: &self.middlewares,
+            handler: &self.handler,
+        }
+        .run(request)
+    }
+}
+
+/// Logs every request and the status it was answered with through [Logger] to stdout
+pub struct RequestLogger {
+    name: String,
+}
+
+impl RequestLogger {
+    /// `name` is what [Logger] prints as the thread name
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Default for RequestLogger {
+    fn default() -> Self {
+        Self::new("HttpServer")
+    }
+}
+
+impl Middleware for RequestLogger {
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse {
+        let response = next.run(request);
+
+        let msg = format!(
+            "\"{} {} {}\" {}",
+            request.get_method(),
+            request.get_url(),
+            request.get_version(),
+            response.get_status_code()
+        );
+
+        if response.get_status_code().is_server_error() {
+            Logger::err(std::io::stdout(), &self.name, &msg);
+        } else {
+            Logger::info(std::io::stdout(), &self.name, &msg);
+        }
+
+        response
+    }
+}
+
+/// Measures how long the rest of the pipeline takes and reports it in a `Server-Timing` header,
+/// requests slower than the threshold are also logged as warnings
+pub struct RequestTimer {
+    slow_threshold: Option<Duration>,
+}
+
+impl RequestTimer {
+    pub fn new() -> Self {
+        Self {
+            slow_threshold: None,
+        }
+    }
+
+    /// Logs requests that take at least `threshold` through [Logger]
+    pub fn set_slow_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_threshold = Some(threshold);
+        self
+    }
+}
+
+impl Default for RequestTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for RequestTimer {
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse {
+        let start = Instant::now();
+        let mut response = next.run(request);
+        let elapsed = start.elapsed();
+
+        response.add_header(
+            "Server-Timing".into(),
+            format!("total;dur={:.3}", elapsed.as_secs_f64() * 1000.0),
+        );
+
+        if self
+            .slow_threshold
+            .is_some_and(|threshold| elapsed >= threshold)
+        {
+            Logger::warn(
+                std::io::stdout(),
+                "RequestTimer",
+                &format!(
+                    "{} {} took {}ms",
+                    request.get_method(),
+                    request.get_url(),
+                    elapsed.as_millis()
+                ),
+            );
+        }
+
+        response
+    }
+}
+
+/// Cross-Origin Resource Sharing
+///
+/// Answers preflight requests itself and adds the `Access-Control-Allow-*` headers to the
+/// responses of requests from allowed origins. Requests from other origins are passed on
+/// unchanged, it is the browser that enforces CORS
+pub struct Cors {
+    allowed_origins: Option<Vec<String>>,
+    allowed_methods: Vec<Method>,
+    allowed_headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Allows any origin to use GET, HEAD and POST
+    pub fn new() -> Self {
+        Self {
+            allowed_origins: None,
+            allowed_methods: vec![Method::Get, Method::Head, Method::Post],
+            allowed_headers: Vec::new(),
+            exposed_headers: Vec::new(),
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Only allows the given origins, such as `https://example.com`
+    pub fn set_allowed_origins(mut self, origins: &[&str]) -> Self {
+        self.allowed_origins = Some(origins.iter().map(|origin| origin.to_string()).collect());
+        self
+    }
+
+    pub fn set_allowed_methods(mut self, methods: &[Method]) -> Self {
+        self.allowed_methods = methods.to_vec();
+        self
+    }
+
+    pub fn set_allowed_headers(mut self, headers: &[&str]) -> Self {
+        self.allowed_headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    pub fn set_exposed_headers(mut self, headers: &[&str]) -> Self {
+        self.exposed_headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Panics when allowing credentials before [Self::set_allowed_origins] was called, echoing
+    /// back any origin along with credentials would let every site act on behalf of the user
+    pub fn set_allow_credentials(mut self, allow_credentials: bool) -> Self {
+        assert!(
+            !allow_credentials || self.allowed_origins.is_some(),
+            "Credentials need an explicit list of allowed origins"
+        );
+
+        self.allow_credentials = allow_credentials;
+        self
+    }
+
+    /// How long browsers may cache a preflight response
+    pub fn set_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn is_allowed_origin(&self, origin: &str) -> bool {
+        match &self.allowed_origins {
+            Some(origins) => origins.iter().any(|allowed| allowed == origin),
+            None => true,
+        }
+    }
+
+    fn add_origin_headers(&self, origin: &str, response: &mut HttpResponse) {
+        if self.allowed_origins.is_none() {
+            response.set_header("Access-Control-Allow-Origin".into(), "*".into());
+        } else {
+            response.set_header("Access-Control-Allow-Origin".into(), origin.into());
+            add_vary_origin(response);
+        }
+
+        if self.allow_credentials {
+            response.set_header("Access-Control-Allow-Credentials".into(), "true".into());
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse {
+        let Some(origin) = request.get_header("Origin") else {
+            return next.run(request);
+        };
+
+        if !self.is_allowed_origin(origin) {
+            // Caches mustn't hand this response to an allowed origin either
+            let mut response = next.run(request);
+            add_vary_origin(&mut response);
+            return response;
+        }
+
+        let is_preflight = request.get_method() == &Method::Options
+            && request
+                .get_headers()
+                .contains("Access-Control-Request-Method");
+
+        if !is_preflight {
+            let mut response = next.run(request);
+            self.add_origin_headers(origin, &mut response);
+
+            if !self.exposed_headers.is_empty() {
+                response.set_header(
+                    "Access-Control-Expose-Headers".into(),
+                    self.exposed_headers.join(", "),
+                );
+            }
+
+            return response;
+        }
+
+        let mut response = HttpResponse::from_status(StatusCode::NO_CONTENT);
+        response.remove_header("Content-Type");
+        response.set_body(Box::new([]));
+
+        self.add_origin_headers(origin, &mut response);
+
+        let methods: Vec<_> = self
+            .allowed_methods
+            .iter()
+            .map(|method| method.as_str())
+            .collect();
+        response.set_header("Access-Control-Allow-Methods".into(), methods.join(", "));
+
+        if !self.allowed_headers.is_empty() {
+            response.set_header(
+                "Access-Control-Allow-Headers".into(),
+                self.allowed_headers.join(", "),
+            );
+        }
+
+        if let Some(max_age) = self.max_age {
+            response.set_header(
+                "Access-Control-Max-Age".into(),
+                max_age.as_secs().to_string(),
+            );
+        }
+
+        response
+    }
+}
+
+/// Responses that depend on the request's Origin are only shared between requests from the same
+/// one
+fn add_vary_origin(response: &mut HttpResponse) {
+    if !response.get_headers().contains_token("Vary", "Origin") {
+        response.add_header("Vary".into(), "Origin".into());
+    }
+}
+
+/// Turns a panicking handler into a 500 response and logs the panic message
+///
+/// Unlike the bare 500 [super::HttpServer] falls back to, the connection is kept alive and the
+/// response goes through the rest of the pipeline
+pub struct CatchPanic;
+
+impl Middleware for CatchPanic {
+    fn handle(&self, request: &HttpRequest, next: Next<'_>) -> HttpResponse {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| next.run(request)));
+
+        match result {
+            Ok(response) => response,
+            Err(payload) => {
+                let reason = payload
+                    .downcast_ref::<&str>()
+                    .map(|msg| msg.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+
+                Logger::err(
+                    std::io::stderr(),
+                    "CatchPanic",
+                    &format!(
+                        "Handler for {} {} panicked: {}",
+                        request.get_method(),
+                        request.get_url(),
+                        reason
+                    ),
+                );
+
+                HttpResponse::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::Version;
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest::builder()
+            .set_method(method)
+            .set_url("/".into())
+            .set_version(Version::Http11)
+            .set_headers(headers.iter().copied().collect())
+            .set_body(Box::new([]))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn order_and_short_circuit() {
+        let pipeline = Pipeline::new(|request: &HttpRequest| {
+            let mut response = HttpResponse::from_status(StatusCode::OK);
+            response.set_header(
+                "X-Seen".into(),
+                request.get_header("X-Added").unwrap().into(),
+            );
+            response
+        })
+        .wrap(|request: &HttpRequest, next: Next<'_>| {
+            if request.get_headers().contains("X-Block") {
+                return HttpResponse::from_status(StatusCode::FORBIDDEN);
+            }
+
+            let mut response = next.run(request);
+            response.add_header("X-Order".into(), "outer".into());
+            response
+        })
+        .wrap(|request: &HttpRequest, next: Next<'_>| {
+            let mut request = request.clone();
+            request.set_header("X-Added".into(), "yes".into());
+
+            let mut response = next.run(&request);
+            response.add_header("X-Order".into(), "inner".into());
+            response
+        });
+
+        let response = pipeline.handle(&request(Method::Get, &[]));
+        assert_eq!(response.get_header("X-Seen"), Some("yes"));
+        assert_eq!(response.get_header_values("X-Order"), ["inner", "outer"]);
+
+        let response = pipeline.handle(&request(Method::Get, &[("X-Block", "1")]));
+        assert_eq!(response.get_status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn catch_panic() {
+        let pipeline = Pipeline::new(|_: &HttpRequest| -> HttpResponse { panic!("Intentional") })
+            .wrap(CatchPanic);
+
+        let response = pipeline.handle(&request(Method::Get, &[]));
+        assert_eq!(
+            response.get_status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn cors_preflight() {
+        let pipeline = Pipeline::new(|_: &HttpRequest| HttpResponse::from_status(StatusCode::OK))
+            .wrap(
+                Cors::new()
+                    .set_allowed_origins(&["https://example.com"])
+                    .set_allowed_methods(&[Method::Get, Method::Put]),
+            );
+
+        let response = pipeline.handle(&request(
+            Method::Options,
+            &[
+                ("Origin", "https://example.com"),
+                ("Access-Control-Request-Method", "PUT"),
+            ],
+        ));
+        assert_eq!(response.get_status_code(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.get_header("Access-Control-Allow-Origin"),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            response.get_header("Access-Control-Allow-Methods"),
+            Some("GET, PUT")
+        );
+
+        let response = pipeline.handle(&request(Method::Get, &[("Origin", "https://evil.com")]));
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert!(response.get_header("Access-Control-Allow-Origin").is_none());
+        assert_eq!(response.get_header("Vary"), Some("Origin"));
+    }
+
+    #[test]
+    fn cors_credentials() {
+        let pipeline = Pipeline::new(|_: &HttpRequest| HttpResponse::from_status(StatusCode::OK))
+            .wrap(
+                Cors::new()
+                    .set_allowed_origins(&["https://example.com"])
+                    .set_allow_credentials(true),
+            );
+
+        let response = pipeline.handle(&request(Method::Get, &[("Origin", "https://example.com")]));
+        assert_eq!(
+            response.get_header("Access-Control-Allow-Origin"),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            response.get_header("Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+        assert_eq!(response.get_header_values("Vary"), ["Origin"]);
+
+        let response = pipeline.handle(&request(Method::Get, &[("Origin", "https://evil.com")]));
+        assert!(response.get_header("Access-Control-Allow-Origin").is_none());
+        assert!(response
+            .get_header("Access-Control-Allow-Credentials")
+            .is_none());
+
+        // Any origin with credentials is refused when the middleware is set up
+        let any_origin = std::panic::catch_unwind(|| Cors::new().set_allow_credentials(true));
+        assert!(any_origin.is_err());
+    }
+}
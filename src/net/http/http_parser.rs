@@ -0,0 +1,865 @@
+//! Parses HTTP/1.x messages out of a byte buffer
+//!
+//! The parsers never consume input themselves, they report how many bytes a complete message
+//! occupies or that more input is needed, which lets a caller resume parsing once more bytes
+//! have arrived without losing anything that was already received. Only a chunked body keeps
+//! track of how far it was decoded in a [ChunkedProgress], so the chunks that already arrived
+//! aren't decoded again every time more bytes do
+//!
+//! Parsing is strict: anything that lets two parties disagree about where a message ends, such as
+//! conflicting framing headers, bare LF line endings or obsolete line folding, is rejected rather
+//! than guessed at (RFC 9112 11.2)
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use super::http_chunked::{is_chunked, parse_chunk_size};
+use super::http_method::is_token;
+use super::HeaderMap;
+use super::HttpRequest;
+use super::HttpResponse;
+use super::Method;
+use super::StatusCode;
+use super::Version;
+
+/// Limits on the size of received messages, anything over a limit is rejected with a
+/// [ParseError]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpLimits {
+    /// Longest start line, field line or chunk size line in bytes, including the line ending.
+    /// Defaults to 8 KiB
+    pub max_line_length: usize,
+    /// Most header fields in a message, trailer fields are counted separately. Defaults to 100
+    pub max_header_count: usize,
+    /// Largest head of a message in bytes, including the start line. Defaults to 64 KiB
+    pub max_header_size: usize,
+    /// Largest body that is received as a whole. Bodies that are streamed, for example through
+    /// [HttpStream::recv_request_streaming](super::HttpStream::recv_request_streaming), are not
+    /// limited. Defaults to 64 MiB
+    pub max_body_size: u64,
+}
+
+impl Default for HttpLimits {
+    fn default() -> Self {
+        Self {
+            max_line_length: 8 * 1024,
+            max_header_count: 100,
+            max_header_size: 64 * 1024,
+            max_body_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Why a received message was rejected
+///
+/// Receiving a message returns these sorted into an [super::HttpError], reading a streamed body
+/// returns them as [std::io::ErrorKind::InvalidData] errors that [ParseError::from_io] gets them
+/// back out of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidRequestLine,
+    InvalidStatusLine,
+    InvalidStatusCode,
+    /// A field line without a colon, with whitespace before the colon or with characters that are
+    /// not allowed in a field name or value
+    InvalidHeader,
+    /// A field line that continues the previous one by starting with whitespace (RFC 9112 5.2)
+    ObsoleteLineFolding,
+    /// A line terminated by a LF without a CR before it
+    BareLineFeed,
+    InvalidUtf8,
+    InvalidContentLength,
+    /// Several Content-Length values that differ
+    ConflictingContentLength,
+    /// Both a Content-Length and a Transfer-Encoding, which is how requests get smuggled past
+    /// proxies that only look at one of them (RFC 9112 6.3)
+    ContentLengthWithTransferEncoding,
+    /// A request Transfer-Encoding that doesn't end in chunked, or applies chunked more than once
+    UnsupportedTransferEncoding,
+    InvalidChunk,
+    StartLineTooLong,
+    HeaderLineTooLong,
+    TooManyHeaders,
+    HeadersTooLarge,
+    BodyTooLarge,
+}
+
+impl ParseError {
+    /// The status code a request that failed to parse should be answered with
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::HeaderLineTooLong | Self::TooManyHeaders | Self::HeadersTooLarge => {
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            }
+            Self::BodyTooLarge => StatusCode::CONTENT_TOO_LARGE,
+            Self::StartLineTooLong => StatusCode::URI_TOO_LONG,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Returns the parse error an I/O error wraps, if any
+    pub fn from_io(err: &std::io::Error) -> Option<Self> {
+        err.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::InvalidRequestLine => "Invalid Request Line",
+            Self::InvalidStatusLine => "Invalid Status Line",
+            Self::InvalidStatusCode => "Invalid Status Code",
+            Self::InvalidHeader => "Invalid Header",
+            Self::ObsoleteLineFolding => "Obsolete Line Folding",
+            Self::BareLineFeed => "Line Feed without Carriage Return",
+            Self::InvalidUtf8 => "Message is not valid UTF-8",
+            Self::InvalidContentLength => "Invalid Content-Length",
+            Self::ConflictingContentLength => "Conflicting Content-Length",
+            Self::ContentLengthWithTransferEncoding => "Content-Length with Transfer-Encoding",
+            Self::UnsupportedTransferEncoding => "Unsupported Transfer-Encoding",
+            Self::InvalidChunk => "Invalid Chunk",
+            Self::StartLineTooLong => "Start Line Too Long",
+            Self::HeaderLineTooLong => "Header Line Too Long",
+            Self::TooManyHeaders => "Too Many Headers",
+            Self::HeadersTooLarge => "Headers Too Large",
+            Self::BodyTooLarge => "Body Too Large",
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for std::io::Error {
+    fn from(err: ParseError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// A start line and its header fields, along with the number of bytes they occupied
+struct Head {
+    start_line: String,
+    headers: HeaderMap,
+    len: usize,
+}
+
+/// How much of a chunked body that is received as a whole has been decoded, handed back to the
+/// parser on every attempt at the same message
+#[derive(Debug, Default)]
+pub(crate) struct ChunkedProgress {
+    /// Offset into the body of the first chunk that hasn't been decoded yet
+    pos: usize,
+    body: Vec<u8>,
+}
+
+/// How the end of a message body is found (RFC 9112 6.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// The message has no body
+    Empty,
+    Length(u64),
+    Chunked,
+    /// The body ends when the connection is closed
+    Close,
+}
+
+/// Returns the request at the start of `buf` and its length in bytes, or `None` if `buf` does not
+/// contain a complete request yet
+///
+/// `progress` has to be the same for every attempt at a request and is reset once it's complete
+pub(crate) fn parse_request(
+    buf: &[u8],
+    limits: &HttpLimits,
+    progress: &mut ChunkedProgress,
+) -> Result<Option<(HttpRequest, usize)>, ParseError> {
+    let Some((mut request, framing, head_len)) = parse_request_head(buf, limits)? else {
+        return Ok(None);
+    };
+
+    let Some((body, trailers, body_len)) = collect_body(
+        &buf[head_len..],
+        framing,
+        false,
+        limits,
+        request.get_headers_mut(),
+        progress,
+    )?
+    else {
+        return Ok(None);
+    };
+    request.set_body(body.into_boxed_slice());
+    request.set_trailers(trailers);
+
+    Ok(Some((request, head_len + body_len)))
+}
+
+/// Returns the head of the request at the start of `buf` with an empty body, how its body is
+/// framed and the length of the head in bytes, or `None` if the head is not complete yet
+pub(crate) fn parse_request_head(
+    buf: &[u8],
+    limits: &HttpLimits,
+) -> Result<Option<(HttpRequest, Framing, usize)>, ParseError> {
+    let Some(head) = parse_head(buf, limits)? else {
+        return Ok(None);
+    };
+
+    // Process Request Line
+    let words: Vec<_> = head.start_line.split(' ').collect();
+
+    let [method, url, version] = words[..] else {
+        return Err(ParseError::InvalidRequestLine);
+    };
+
+    let method = method
+        .parse::<Method>()
+        .map_err(|_| ParseError::InvalidRequestLine)?;
+    let version = version
+        .parse::<Version>()
+        .map_err(|_| ParseError::InvalidRequestLine)?;
+
+    if url.is_empty() || url.bytes().any(|b| b.is_ascii_control()) {
+        return Err(ParseError::InvalidRequestLine);
+    }
+
+    // A request without a Content-Length or Transfer-Encoding has no body (RFC 9112 6.3)
+    let headers = head.headers;
+    let framing = match transfer_coding(&headers)? {
+        Some(_) if headers.contains("Content-Length") => {
+            return Err(ParseError::ContentLengthWithTransferEncoding)
+        }
+        Some(true) => Framing::Chunked,
+        Some(false) => return Err(ParseError::UnsupportedTransferEncoding),
+        None => match content_length(&headers)? {
+            Some(len) => Framing::Length(len),
+            None => Framing::Empty,
+        },
+    };
+
+    // Build Request
+    let mut builder = HttpRequest::builder();
+
+    let request = builder
+        .set_method(method)
+        .set_url(url.to_string())
+        .set_version(version)
+        .set_body(Box::new([]))
+        .set_headers(headers)
+        .build()
+        .unwrap();
+
+    Ok(Some((request, framing, head.len)))
+}
+
+/// Returns the response at the start of `buf` and its length in bytes, or `None` if `buf` does
+/// not contain a complete response yet
+///
+/// `eof` signals that the peer has closed the connection, which is what ends a response that has
+/// neither a Content-Length nor a chunked Transfer-Encoding
+pub(crate) fn parse_response(
+    buf: &[u8],
+    eof: bool,
+    limits: &HttpLimits,
+    progress: &mut ChunkedProgress,
+) -> Result<Option<(HttpResponse, usize)>, ParseError> {
+    parse_response_to(buf, eof, limits, &Method::Get, progress)
+}
+
+/// Like [parse_response] for the response to a request made with `method`, responses to HEAD
+/// requests and successful responses to CONNECT requests never have a body
+pub(crate) fn parse_response_to(
+    buf: &[u8],
+    eof: bool,
+    limits: &HttpLimits,
+    method: &Method,
+    progress: &mut ChunkedProgress,
+) -> Result<Option<(HttpResponse, usize)>, ParseError> {
+    let Some((mut response, framing, head_len)) = parse_response_head(buf, limits, method)? else {
+        return Ok(None);
+    };
+
+    let Some((body, trailers, body_len)) = collect_body(
+        &buf[head_len..],
+        framing,
+        eof,
+        limits,
+        response.get_headers_mut(),
+        progress,
+    )?
+    else {
+        return Ok(None);
+    };
+    response.set_body(body.into_boxed_slice());
+    response.set_trailers(trailers);
+
+    Ok(Some((response, head_len + body_len)))
+}
+
+/// Returns the head of the response to a request made with `method` at the start of `buf` with an
+/// empty body, how its body is framed and the length of the head in bytes, or `None` if the head
+/// is not complete yet
+pub(crate) fn parse_response_head(
+    buf: &[u8],
+    limits: &HttpLimits,
+    method: &Method,
+) -> Result<Option<(HttpResponse, Framing, usize)>, ParseError> {
+    let Some(head) = parse_head(buf, limits)? else {
+        return Ok(None);
+    };
+
+    // Process Status Line
+    let mut words: VecDeque<_> = head.start_line.split(' ').collect();
+
+    if words.len() < 3 {
+        return Err(ParseError::InvalidStatusLine);
+    }
+
+    let version = words
+        .pop_front()
+        .unwrap()
+        .parse::<Version>()
+        .map_err(|_| ParseError::InvalidStatusLine)?;
+    let status_code = words
+        .pop_front()
+        .unwrap()
+        .parse::<StatusCode>()
+        .map_err(|_| ParseError::InvalidStatusCode)?;
+    let status_message = words.into_iter().collect::<Vec<_>>().join(" ");
+
+    // 1xx, 204 and 304 responses never have a body, otherwise a response without a
+    // Content-Length or chunked Transfer-Encoding is delimited by the server closing the
+    // connection (RFC 9112 6.3)
+    let headers = head.headers;
+    let framing = if status_code.is_informational()
+        || status_code == StatusCode::NO_CONTENT
+        || status_code == StatusCode::NOT_MODIFIED
+        || method == &Method::Head
+        || (method == &Method::Connect && status_code.is_success())
+    {
+        Framing::Empty
+    } else {
+        match transfer_coding(&headers)? {
+            Some(_) if headers.contains("Content-Length") => {
+                return Err(ParseError::ContentLengthWithTransferEncoding)
+            }
+            Some(true) => Framing::Chunked,
+            Some(false) => Framing::Close,
+            None => match content_length(&headers)? {
+                Some(len) => Framing::Length(len),
+                None => Framing::Close,
+            },
+        }
+    };
+
+    // Build Response
+    let mut builder = HttpResponse::builder();
+
+    let response = builder
+        .set_version(version)
+        .set_status_code(status_code)
+        .set_status_message(status_message)
+        .set_headers(headers)
+        .set_body(Box::new([]))
+        .build()
+        .unwrap();
+
+    Ok(Some((response, framing, head.len)))
+}
+
+/// Collects a whole body from `buf`, returning it along with its trailer fields and the number of
+/// bytes it occupied or `None` if it is not complete yet
+fn collect_body(
+    buf: &[u8],
+    framing: Framing,
+    eof: bool,
+    limits: &HttpLimits,
+    headers: &mut HeaderMap,
+    progress: &mut ChunkedProgress,
+) -> Result<Option<(Vec<u8>, HeaderMap, usize)>, ParseError> {
+    Ok(match framing {
+        Framing::Empty => Some((Vec::new(), HeaderMap::new(), 0)),
+        Framing::Length(len) if len > limits.max_body_size => Err(ParseError::BodyTooLarge)?,
+        Framing::Length(len) => match usize::try_from(len) {
+            Ok(len) if buf.len() >= len => Some((buf[..len].to_vec(), HeaderMap::new(), len)),
+            _ => None,
+        },
+        Framing::Chunked => decode_chunked_body(buf, limits, headers, progress)?,
+        Framing::Close if buf.len() as u64 > limits.max_body_size => Err(ParseError::BodyTooLarge)?,
+        Framing::Close if eof => Some((buf.to_vec(), HeaderMap::new(), buf.len())),
+        Framing::Close => None,
+    })
+}
+
+/// Reads the start line and the header fields of a message, up to and including the empty line
+/// that terminates the head
+fn parse_head(buf: &[u8], limits: &HttpLimits) -> Result<Option<Head>, ParseError> {
+    let mut pos = 0;
+
+    // Empty lines before the start line are ignored (RFC 9112 2.2), some clients send an extra
+    // CRLF after a body. They count towards the size of the head, or a peer could keep the
+    // buffer growing with nothing but line endings
+    let start_line = 'skipping_empty_lines: loop {
+        let line = next_line(buf, pos, limits, ParseError::StartLineTooLong)?;
+
+        if line.map_or(buf.len(), |(_, next)| next) > limits.max_header_size {
+            return Err(ParseError::HeadersTooLarge);
+        }
+
+        let Some((line, next)) = line else {
+            return Ok(None);
+        };
+        pos = next;
+
+        if !line.is_empty() {
+            break 'skipping_empty_lines line;
+        }
+    };
+
+    let Some((headers, len)) = parse_header_lines(buf, pos, limits)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(Head {
+        start_line: start_line.to_string(),
+        headers,
+        len,
+    }))
+}
+
+/// Reads field lines starting at `pos` up to and including the empty line that terminates them,
+/// returning the fields and the position right after the empty line
+///
+/// Everything before `pos` counts towards the size limit too
+fn parse_header_lines(
+    buf: &[u8],
+    mut pos: usize,
+    limits: &HttpLimits,
+) -> Result<Option<(HeaderMap, usize)>, ParseError> {
+    let mut headers = HeaderMap::new();
+
+    'reading_headers: loop {
+        let line = next_line(buf, pos, limits, ParseError::HeaderLineTooLong)?;
+
+        // Incomplete heads are checked as well, so a peer can't make the buffer grow forever
+        let end = line.map_or(buf.len(), |(_, next)| next);
+        if end > limits.max_header_size {
+            return Err(ParseError::HeadersTooLarge);
+        }
+
+        let Some((line, next)) = line else {
+            return Ok(None);
+        };
+        pos = next;
+
+        if line.is_empty() {
+            break 'reading_headers;
+        }
+
+        if headers.len() == limits.max_header_count {
+            return Err(ParseError::TooManyHeaders);
+        }
+
+        let (key, val) = parse_header_line(line)?;
+        headers.append(key, val);
+    }
+
+    Ok(Some((headers, pos)))
+}
+
+/// Splits a field line into its name and its value without surrounding whitespace (RFC 9112 5)
+pub(crate) fn parse_header_line(line: &str) -> Result<(&str, &str), ParseError> {
+    if line.starts_with([' ', '\t']) {
+        return Err(ParseError::ObsoleteLineFolding);
+    }
+
+    let (key, val) = line.split_once(':').ok_or(ParseError::InvalidHeader)?;
+
+    // No whitespace is allowed between the name and the colon
+    if !is_token(key) || val.bytes().any(|b| b.is_ascii_control() && b != b'\t') {
+        return Err(ParseError::InvalidHeader);
+    }
+
+    Ok((key, val.trim_matches([' ', '\t'])))
+}
+
+/// Returns the line starting at `pos` without its CRLF and the position of the following line, or
+/// `None` if the line has not been terminated yet
+///
+/// Lines longer than the line length limit are rejected with `too_long`
+pub(crate) fn next_line<'b>(
+    buf: &'b [u8],
+    pos: usize,
+    limits: &HttpLimits,
+    too_long: ParseError,
+) -> Result<Option<(&'b str, usize)>, ParseError> {
+    let window = &buf[pos..buf.len().min(pos.saturating_add(limits.max_line_length))];
+
+    let Some(offset) = window.iter().position(|b| *b == b'\n') else {
+        if window.len() == limits.max_line_length {
+            return Err(too_long);
+        }
+
+        return Ok(None);
+    };
+    let end = pos + offset + 1;
+
+    if offset == 0 || buf[end - 2] != b'\r' {
+        return Err(ParseError::BareLineFeed);
+    }
+
+    let line = std::str::from_utf8(&buf[pos..end - 2]).map_err(|_| ParseError::InvalidUtf8)?;
+
+    Ok(Some((line, end)))
+}
+
+/// Like [is_chunked], but applying chunked more than once is rejected (RFC 9112 6.1)
+fn transfer_coding(headers: &HeaderMap) -> Result<Option<bool>, ParseError> {
+    let chunked_count = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|val| val.split(','))
+        .filter(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        .count();
+
+    if chunked_count > 1 {
+        return Err(ParseError::UnsupportedTransferEncoding);
+    }
+
+    Ok(is_chunked(headers))
+}
+
+/// Several Content-Length values, as a list or as separate fields, are only accepted if they are
+/// all the same (RFC 9110 8.6)
+fn content_length(headers: &HeaderMap) -> Result<Option<u64>, ParseError> {
+    let mut len = None;
+
+    for val in headers
+        .get_all("Content-Length")
+        .flat_map(|val| val.split(','))
+    {
+        let val = val.trim();
+
+        // u64's parser would also take a leading +
+        if val.is_empty() || !val.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength);
+        }
+
+        let val = val
+            .parse::<u64>()
+            .map_err(|_| ParseError::InvalidContentLength)?;
+
+        match len {
+            Some(len) if len != val => return Err(ParseError::ConflictingContentLength),
+            _ => len = Some(val),
+        }
+    }
+
+    Ok(len)
+}
+
+/// Decodes a chunked body (RFC 9112 7.1), chunk extensions are ignored
+///
+/// Decoding picks up at the first chunk `progress` hasn't seen yet, so each chunk is only
+/// decoded once however many attempts it takes for the body to arrive
+///
+/// Afterwards the message's headers are rewritten as if it had been sent with a Content-Length:
+/// chunked is removed from the Transfer-Encoding and the Trailer header is dropped. Trailer fields
+/// are returned on their own, merging them would let a sender set framing, routing or
+/// authentication fields after the head had already been checked (RFC 9110 6.5.1)
+fn decode_chunked_body(
+    buf: &[u8],
+    limits: &HttpLimits,
+    headers: &mut HeaderMap,
+    progress: &mut ChunkedProgress,
+) -> Result<Option<(Vec<u8>, HeaderMap, usize)>, ParseError> {
+    let mut pos = progress.pos;
+
+    'reading_chunks: loop {
+        let Some((size_line, next)) = next_line(buf, pos, limits, ParseError::InvalidChunk)? else {
+            return Ok(None);
+        };
+        pos = next;
+
+        let size = parse_chunk_size(size_line)?;
+
+        if size == 0 {
+            break 'reading_chunks;
+        }
+
+        if (progress.body.len() as u64).saturating_add(size as u64) > limits.max_body_size {
+            return Err(ParseError::BodyTooLarge);
+        }
+
+        if buf.len() - pos < size {
+            return Ok(None);
+        }
+
+        let data = &buf[pos..pos + size];
+        pos += size;
+
+        // Every chunk's data is followed by a CRLF
+        let Some((terminator, next)) = next_line(buf, pos, limits, ParseError::InvalidChunk)?
+        else {
+            return Ok(None);
+        };
+        pos = next;
+
+        if !terminator.is_empty() {
+            return Err(ParseError::InvalidChunk);
+        }
+
+        // Only complete chunks count as progress
+        progress.body.extend_from_slice(data);
+        progress.pos = pos;
+    }
+
+    // Get Trailers, which are limited on their own
+    let Some((trailers, len)) = parse_header_lines(&buf[pos..], 0, limits)? else {
+        return Ok(None);
+    };
+
+    // Rewrite Headers
+    headers.remove("Trailer");
+    headers.remove("Content-Length");
+
+    let codings: Vec<_> = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|val| val.split(','))
+        .map(|coding| coding.trim())
+        .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("chunked"))
+        .map(|coding| coding.to_string())
+        .collect();
+
+    headers.remove("Transfer-Encoding");
+    if !codings.is_empty() {
+        headers.insert("Transfer-Encoding", codings.join(", "));
+    }
+
+    let body = std::mem::take(&mut progress.body);
+    progress.pos = 0;
+
+    headers.insert("Content-Length", body.len().to_string());
+
+    Ok(Some((body, trailers, pos + len)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(bytes: &[u8]) -> Result<Option<(HttpRequest, usize)>, ParseError> {
+        parse_request(
+            bytes,
+            &HttpLimits::default(),
+            &mut ChunkedProgress::default(),
+        )
+    }
+
+    fn rejection(bytes: &[u8], limits: &HttpLimits) -> ParseError {
+        parse_request(bytes, limits, &mut ChunkedProgress::default()).unwrap_err()
+    }
+
+    #[test]
+    fn incomplete_until_body_arrives() {
+        let bytes = b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET";
+
+        for end in 0..bytes.len() - 8 {
+            assert!(request(&bytes[..end]).unwrap().is_none());
+        }
+
+        let (request, len) = request(bytes).unwrap().unwrap();
+        assert_eq!(request.get_body(), b"hello");
+        assert_eq!(len, bytes.len() - 3);
+    }
+
+    #[test]
+    fn chunked_request() {
+        let bytes = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4;ext=1\r\nWiki\r\n5\r\npedia\r\n0\r\nX-Trailer: yes\r\n\r\n";
+
+        assert!(request(&bytes[..bytes.len() - 2]).unwrap().is_none());
+
+        let (request, len) = request(bytes).unwrap().unwrap();
+        assert_eq!(request.get_body(), b"Wikipedia");
+        assert_eq!(len, bytes.len());
+        assert!(request.get_header("transfer-encoding").is_none());
+        assert_eq!(request.get_header("X-Trailer"), None);
+        assert_eq!(request.get_trailers().get("X-Trailer"), Some("yes"));
+
+        // Arriving a byte at a time, with the chunks decoded so far carried over
+        let mut progress = ChunkedProgress::default();
+        let limits = HttpLimits::default();
+        for end in 0..bytes.len() {
+            assert!(parse_request(&bytes[..end], &limits, &mut progress)
+                .unwrap()
+                .is_none());
+        }
+        let (request, _) = parse_request(bytes, &limits, &mut progress)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.get_body(), b"Wikipedia");
+        assert_eq!(progress.pos, 0);
+        assert!(progress.body.is_empty());
+    }
+
+    #[test]
+    fn trailers_stay_out_of_the_headers() {
+        let bytes = b"POST / HTTP/1.1\r\nHost: example.com\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2\r\nhi\r\n0\r\nHost: evil.example\r\nTransfer-Encoding: chunked\r\n\
+            Authorization: Bearer forged\r\nContent-Length: 100\r\n\r\n";
+
+        let (request, _) = request(bytes).unwrap().unwrap();
+        assert_eq!(request.get_header_values("Host"), ["example.com"]);
+        assert_eq!(request.get_header_values("Content-Length"), ["2"]);
+        assert!(request.get_header("Transfer-Encoding").is_none());
+        assert!(request.get_header("Authorization").is_none());
+
+        let trailers = request.get_trailers();
+        assert_eq!(trailers.get("Host"), Some("evil.example"));
+        assert_eq!(trailers.get("Authorization"), Some("Bearer forged"));
+    }
+
+    #[test]
+    fn header_values_are_trimmed() {
+        let bytes = b"\r\nGET / HTTP/1.1\r\nHost:   example.com  \r\nAccept:*/*\r\n\r\n";
+
+        let (request, _) = request(bytes).unwrap().unwrap();
+        assert_eq!(request.get_header("host"), Some("example.com"));
+        assert_eq!(request.get_header("ACCEPT"), Some("*/*"));
+    }
+
+    #[test]
+    fn close_delimited_response() {
+        let bytes = b"HTTP/1.1 200 OK\r\n\r\npartial";
+        let limits = HttpLimits::default();
+
+        assert!(
+            parse_response(bytes, false, &limits, &mut ChunkedProgress::default())
+                .unwrap()
+                .is_none()
+        );
+
+        let (response, len) = parse_response(bytes, true, &limits, &mut ChunkedProgress::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.get_body(), b"partial");
+        assert_eq!(len, bytes.len());
+    }
+
+    #[test]
+    fn head_response_has_no_body() {
+        let bytes = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+
+        let (response, len) = parse_response_to(
+            bytes,
+            false,
+            &HttpLimits::default(),
+            &Method::Head,
+            &mut ChunkedProgress::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(response.get_header("Content-Length"), Some("5"));
+        assert!(response.get_body().is_empty());
+        assert_eq!(len, bytes.len());
+    }
+
+    #[test]
+    fn smuggling_patterns_are_rejected() {
+        let limits = HttpLimits::default();
+        let cases: [(&[u8], ParseError); 9] = [
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n",
+                ParseError::ConflictingContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 5, 6\r\n\r\n",
+                ParseError::ConflictingContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\n",
+                ParseError::InvalidContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n",
+                ParseError::ContentLengthWithTransferEncoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, chunked\r\n\r\n",
+                ParseError::UnsupportedTransferEncoding,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nX-Long: a\r\n b\r\n\r\n",
+                ParseError::ObsoleteLineFolding,
+            ),
+            (
+                b"GET / HTTP/1.1\nHost: example.com\r\n\r\n",
+                ParseError::BareLineFeed,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost : example.com\r\n\r\n",
+                ParseError::InvalidHeader,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\n",
+                ParseError::BareLineFeed,
+            ),
+        ];
+
+        for (bytes, expected) in cases {
+            assert_eq!(rejection(bytes, &limits), expected);
+        }
+
+        let (_, len) = request(b"POST / HTTP/1.1\r\nContent-Length: 2, 2\r\n\r\nhi")
+            .unwrap()
+            .unwrap();
+        assert_eq!(len, 43);
+    }
+
+    #[test]
+    fn limits() {
+        let limits = HttpLimits {
+            max_line_length: 32,
+            max_header_count: 2,
+            max_header_size: 64,
+            max_body_size: 4,
+        };
+
+        let error = rejection(b"GET /a-very-long-path-that-keeps-going", &limits);
+        assert_eq!(error, ParseError::StartLineTooLong);
+        assert_eq!(error.status_code(), StatusCode::URI_TOO_LONG);
+
+        let error = rejection(b"GET / HTTP/1.1\r\nX: 1\r\nY: 2\r\nZ: 3\r\n\r\n", &limits);
+        assert_eq!(error, ParseError::TooManyHeaders);
+        assert_eq!(
+            error.status_code(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+
+        let bytes = b"GET / HTTP/1.1\r\nX-A: aaaaaaaaaaaaaaaaaaaa\r\nX-B: bbbbbbbbbbbbbbbbbbbb\r\n";
+        assert_eq!(rejection(bytes, &limits), ParseError::HeadersTooLarge);
+
+        // Empty lines before the start line are skipped but still count towards the head
+        let mut bytes = b"\r\n".repeat(3);
+        bytes.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+        assert!(
+            parse_request(&bytes, &limits, &mut ChunkedProgress::default())
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(
+            rejection(&b"\r\n".repeat(33), &limits),
+            ParseError::HeadersTooLarge
+        );
+        assert_eq!(
+            rejection(&b"\r\n".repeat(1024 * 1024), &HttpLimits::default()),
+            ParseError::HeadersTooLarge
+        );
+
+        let error = rejection(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n", &limits);
+        assert_eq!(error, ParseError::BodyTooLarge);
+        assert_eq!(error.status_code(), StatusCode::CONTENT_TOO_LARGE);
+
+        let bytes = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\n";
+        assert_eq!(rejection(bytes, &limits), ParseError::BodyTooLarge);
+    }
+}
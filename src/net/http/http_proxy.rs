@@ -0,0 +1,690 @@
+use std::io::Read;
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::http_client::is_reusable;
+use super::{Handler, HeaderMap, HttpError, HttpLimits, HttpRequest, HttpResponse, HttpStream};
+use super::{Method, StatusCode, Version};
+
+type Connection = HttpStream<TcpStream, TcpStream>;
+
+/// Idle connections kept per upstream
+const MAX_IDLE_PER_UPSTREAM: usize = 8;
+
+/// How long an idle upstream connection is kept for reuse
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Headers that only apply to a single connection and are never forwarded (RFC 9110 7.6.1)
+const HOP_BY_HOP: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// How a [ReverseProxy] picks the upstream for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balancing {
+    /// Each upstream in turn
+    RoundRobin,
+    /// The upstream with the fewest requests in flight, streamed response bodies included
+    LeastConnections,
+}
+
+/// Forwards requests to a set of upstream servers and relays their responses
+///
+/// Requests keep their method, target and body. The `Host` header is set to the upstream's
+/// address unless [ReverseProxy::set_preserve_host] is set, the client's address is added to
+/// `X-Forwarded-For` and `Forwarded`, and hop-by-hop headers are dropped in both directions.
+/// Response bodies are streamed through, connections to the upstreams are kept alive and reused
+///
+/// Upstreams that fail to connect or answer are marked down after
+/// [ReverseProxy::set_max_fails] failures in a row and skipped for
+/// [ReverseProxy::set_fail_timeout]. A request that failed is tried on the next upstream if
+/// nothing was sent yet or it is idempotent, otherwise it is answered with a 502, or a 504 if
+/// the upstream timed out
+///
+/// Protocol upgrades such as WebSocket aren't forwarded
+pub struct ReverseProxy {
+    upstreams: Vec<Arc<Upstream>>,
+    balancing: Balancing,
+    next: AtomicUsize,
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_fails: u32,
+    fail_timeout: Duration,
+    preserve_host: bool,
+    limits: HttpLimits,
+}
+
+struct Upstream {
+    /// `host:port` as given
+    addr: String,
+    /// Requests in flight, until their response body has been relayed
+    active: AtomicUsize,
+    health: Mutex<Health>,
+    idle: Mutex<Vec<IdleConnection>>,
+}
+
+struct Health {
+    fails: u32,
+    down_until: Option<Instant>,
+}
+
+struct IdleConnection {
+    connection: Connection,
+    since: Instant,
+}
+
+/// Why forwarding a request to an upstream failed
+enum ForwardError {
+    /// Nothing was sent, the request can go to another upstream
+    Connect(std::io::Error),
+    Exchange(HttpError),
+}
+
+impl ReverseProxy {
+    /// Forwards to the upstreams at the given `host:port` addresses, with round-robin balancing
+    pub fn new(upstreams: &[&str]) -> Self {
+        Self {
+            upstreams: upstreams
+                .iter()
+                .map(|addr| {
+                    Arc::new(Upstream {
+                        addr: addr.to_string(),
+                        active: AtomicUsize::new(0),
+                        health: Mutex::new(Health {
+                            fails: 0,
+                            down_until: None,
+                        }),
+                        idle: Mutex::new(Vec::new()),
+                    })
+                })
+                .collect(),
+            balancing: Balancing::RoundRobin,
+            next: AtomicUsize::new(0),
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Some(Duration::from_secs(60)),
+            write_timeout: Some(Duration::from_secs(60)),
+            max_fails: 1,
+            fail_timeout: Duration::from_secs(10),
+            preserve_host: false,
+            limits: HttpLimits::default(),
+        }
+    }
+
+    pub fn set_balancing(mut self, balancing: Balancing) -> Self {
+        self.balancing = balancing;
+        self
+    }
+
+    /// Defaults to 5 seconds
+    pub fn set_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// How long an upstream may take to answer or to send the next part of a body, defaults to
+    /// 60 seconds, `None` waits forever
+    pub fn set_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Defaults to 60 seconds, `None` waits forever
+    pub fn set_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Failures in a row after which an upstream is marked down, defaults to 1, 0 never marks an
+    /// upstream down
+    pub fn set_max_fails(mut self, max_fails: u32) -> Self {
+        self.max_fails = max_fails;
+        self
+    }
+
+    /// How long an upstream that is down is skipped, defaults to 10 seconds
+    pub fn set_fail_timeout(mut self, fail_timeout: Duration) -> Self {
+        self.fail_timeout = fail_timeout;
+        self
+    }
+
+    /// Forwards the client's `Host` header instead of replacing it with the upstream's address
+    pub fn set_preserve_host(mut self, preserve_host: bool) -> Self {
+        self.preserve_host = preserve_host;
+        self
+    }
+
+    /// Limits on the responses accepted from upstreams, see [HttpLimits]
+    pub fn set_limits(mut self, limits: HttpLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Picks an upstream that hasn't been tried yet, preferring those that aren't down
+    fn pick(&self, tried: &[bool]) -> Option<usize> {
+        let now = Instant::now();
+        let untried = (0..self.upstreams.len()).filter(|index| !tried[*index]);
+        let up: Vec<_> = untried
+            .clone()
+            .filter(|index| !self.upstreams[*index].is_down(now))
+            .collect();
+
+        // When every upstream is down trying one anyway beats failing until they recover
+        let candidates = match up.is_empty() {
+            true => untried.collect(),
+            false => up,
+        };
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+
+        match self.balancing {
+            Balancing::RoundRobin => Some(candidates[start]),
+            // Starting the search at a different upstream every time spreads out the ties
+            Balancing::LeastConnections => candidates[start..]
+                .iter()
+                .chain(&candidates[..start])
+                .copied()
+                .min_by_key(|index| self.upstreams[*index].active.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// The request as it is sent upstream, without the Host header
+    fn upstream_request(&self, request: &HttpRequest) -> HttpRequest {
+        let mut upstream_request = request.clone();
+        upstream_request.set_version(Version::Http11);
+
+        let headers = upstream_request.get_headers_mut();
+        strip_hop_by_hop(headers);
+
+        // Streaming bodies are framed when they are sent, chunked if their length is unknown
+        if !request.is_body_streaming() && !headers.contains("Content-Length") {
+            let len = request.get_body().len();
+            if len > 0
+                || matches!(
+                    request.get_method(),
+                    Method::Post | Method::Put | Method::Patch
+                )
+            {
+                headers.insert("Content-Length", len.to_string());
+            }
+        }
+
+        let host = request.get_header("Host");
+        let client = request.get_peer_addr().map(|addr| addr.ip());
+
+        if let Some(client) = client {
+            let forwarded_for = match headers.get("X-Forwarded-For") {
+                Some(forwarded_for) => format!("{}, {}", forwarded_for, client),
+                None => client.to_string(),
+            };
+            headers.insert("X-Forwarded-For", forwarded_for);
+        }
+
+        if let Some(host) = host {
+            if !headers.contains("X-Forwarded-Host") {
+                headers.insert("X-Forwarded-Host", host);
+            }
+        }
+
+        if !headers.contains("X-Forwarded-Proto") {
+            headers.insert("X-Forwarded-Proto", "http");
+        }
+
+        let mut forwarded = Vec::new();
+        if let Some(client) = client {
+            forwarded.push(format!("for={}", forwarded_node(client)));
+        }
+        if let Some(host) = host {
+            forwarded.push(format!("host={}", forwarded_value(host)));
+        }
+        forwarded.push("proto=http".to_string());
+        headers.append("Forwarded", forwarded.join(";"));
+
+        upstream_request
+    }
+
+    fn forward(
+        &self,
+        upstream: &Arc<Upstream>,
+        request: &HttpRequest,
+    ) -> Result<HttpResponse, ForwardError> {
+        let mut request = request.clone();
+        if !self.preserve_host || !request.get_headers().contains("Host") {
+            request.set_header("Host".into(), upstream.addr.clone());
+        }
+
+        let active = Active::new(upstream);
+
+        let (mut connection, reused) = match upstream.checkout() {
+            Some(connection) => (connection, true),
+            None => (
+                self.connect(upstream).map_err(ForwardError::Connect)?,
+                false,
+            ),
+        };
+
+        // A reused connection may have been closed by the upstream in the meantime
+        let mut response = match round_trip(&mut connection, &request) {
+            Err(err) if reused && err.is_retryable() => {
+                connection = self.connect(upstream).map_err(ForwardError::Connect)?;
+                round_trip(&mut connection, &request)
+            }
+            res => res,
+        }
+        .map_err(ForwardError::Exchange)?;
+
+        let reusable = is_reusable(&request, &response);
+        let has_body = request.get_method() != &Method::Head
+            && !matches!(response.get_status_code().as_u16(), 204 | 304);
+
+        let len = match response.get_header("Transfer-Encoding") {
+            Some(_) => None,
+            None => response
+                .get_header("Content-Length")
+                .and_then(|len| len.parse().ok()),
+        };
+        strip_hop_by_hop(response.get_headers_mut());
+
+        if has_body {
+            let body = ProxyBody {
+                connection: Some(connection),
+                reusable,
+                _active: active,
+            };
+            response.set_body_reader(body, len);
+        } else if reusable {
+            upstream.checkin(connection);
+        }
+
+        Ok(response)
+    }
+
+    fn connect(&self, upstream: &Upstream) -> std::io::Result<Connection> {
+        let mut last_err = None;
+
+        for addr in upstream.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(self.read_timeout)?;
+                    stream.set_write_timeout(self.write_timeout)?;
+                    stream.set_nodelay(true)?;
+
+                    let mut connection = HttpStream::from_parts(stream.try_clone()?, stream);
+                    connection.set_limits(self.limits);
+
+                    return Ok(connection);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Could not resolve {}", upstream.addr),
+            )
+        }))
+    }
+}
+
+impl Handler for ReverseProxy {
+    fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let upstream_request = self.upstream_request(request);
+
+        // Sending a streamed body consumes it, so such a request can only be sent once
+        let repeatable = request.get_method().is_idempotent() && !request.is_body_streaming();
+
+        let mut tried = vec![false; self.upstreams.len()];
+        let mut timed_out = false;
+
+        while let Some(index) = self.pick(&tried) {
+            tried[index] = true;
+            let upstream = &self.upstreams[index];
+
+            match self.forward(upstream, &upstream_request) {
+                Ok(response) => {
+                    upstream.succeeded();
+                    return response;
+                }
+                Err(ForwardError::Connect(err)) => {
+                    upstream.failed(self.max_fails, self.fail_timeout);
+                    timed_out = err.kind() == std::io::ErrorKind::TimedOut;
+                }
+                Err(ForwardError::Exchange(err)) => {
+                    upstream.failed(self.max_fails, self.fail_timeout);
+                    timed_out = matches!(err, HttpError::Timeout);
+
+                    if !repeatable {
+                        break;
+                    }
+                }
+            }
+        }
+
+        HttpResponse::from_status(match timed_out {
+            true => StatusCode::GATEWAY_TIMEOUT,
+            false => StatusCode::BAD_GATEWAY,
+        })
+    }
+}
+
+impl Upstream {
+    fn is_down(&self, now: Instant) -> bool {
+        let health = self.health.lock().unwrap_or_else(|err| err.into_inner());
+        health.down_until.is_some_and(|until| now < until)
+    }
+
+    fn failed(&self, max_fails: u32, fail_timeout: Duration) {
+        let mut health = self.health.lock().unwrap_or_else(|err| err.into_inner());
+        health.fails += 1;
+
+        if max_fails > 0 && health.fails >= max_fails {
+            health.fails = 0;
+            health.down_until = Some(Instant::now() + fail_timeout);
+        }
+    }
+
+    fn succeeded(&self) {
+        let mut health = self.health.lock().unwrap_or_else(|err| err.into_inner());
+        health.fails = 0;
+        health.down_until = None;
+    }
+
+    fn checkout(&self) -> Option<Connection> {
+        let mut idle = self.idle.lock().unwrap_or_else(|err| err.into_inner());
+
+        while let Some(entry) = idle.pop() {
+            if entry.since.elapsed() >= IDLE_TIMEOUT {
+                continue;
+            }
+
+            // An idle connection has nothing to read, unless the upstream closed it
+            #[cfg(unix)]
+            if entry
+                .connection
+                .wait_readable(Some(Duration::ZERO))
+                .unwrap_or(true)
+            {
+                continue;
+            }
+
+            return Some(entry.connection);
+        }
+
+        None
+    }
+
+    fn checkin(&self, connection: Connection) {
+        let mut idle = self.idle.lock().unwrap_or_else(|err| err.into_inner());
+
+        if idle.len() < MAX_IDLE_PER_UPSTREAM {
+            idle.push(IdleConnection {
+                connection,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+/// Counts a request as in flight for as long as it lives
+struct Active(Arc<Upstream>);
+
+impl Active {
+    fn new(upstream: &Arc<Upstream>) -> Self {
+        upstream.active.fetch_add(1, Ordering::Relaxed);
+        Self(Arc::clone(upstream))
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A response body relayed from an upstream, whose connection goes back to the pool at the end
+struct ProxyBody {
+    connection: Option<Connection>,
+    reusable: bool,
+    _active: Active,
+}
+
+impl Read for ProxyBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(connection) = &mut self.connection else {
+            return Ok(0);
+        };
+
+        let bytes_read = connection.read_body(buf)?;
+
+        if bytes_read == 0 && !buf.is_empty() {
+            let connection = self.connection.take().unwrap();
+
+            if self.reusable {
+                self._active.0.checkin(connection);
+            }
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+/// Skips interim responses, the upstream may send a 100 Continue before the final response
+fn round_trip(
+    connection: &mut Connection,
+    request: &HttpRequest,
+) -> Result<HttpResponse, HttpError> {
+    connection.send_request(request)?;
+
+    loop {
+        let (response, _) = connection.recv_response_streaming(request.get_method())?;
+
+        if !response.get_status_code().is_informational() {
+            return Ok(response);
+        }
+    }
+}
+
+/// Removes the hop-by-hop headers along with those the Connection header names
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let named: Vec<String> = headers
+        .get_all("Connection")
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    for name in HOP_BY_HOP
+        .iter()
+        .copied()
+        .chain(named.iter().map(String::as_str))
+    {
+        headers.remove(name);
+    }
+}
+
+/// A node for the Forwarded header (RFC 7239 6), IPv6 addresses have to be quoted
+fn forwarded_node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+    }
+}
+
+/// A Forwarded parameter value, quoted unless it is a token
+fn forwarded_value(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+
+    match is_token {
+        true => value.to_string(),
+        false => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::TestServer;
+    use std::net::TcpListener;
+
+    /// An upstream answering with its name and the request headers it saw
+    fn upstream(name: &'static str) -> TestServer {
+        TestServer::start(move |request: &HttpRequest| {
+            let mut body = format!("{}\n", name);
+            for (key, value) in request.get_headers().iter() {
+                body.push_str(&format!("{}: {}\n", key.to_ascii_lowercase(), value));
+            }
+            body.push_str(&String::from_utf8_lossy(request.get_body()));
+
+            let mut response = HttpResponse::from_status(StatusCode::OK);
+            response.set_body(body.into_bytes().into_boxed_slice());
+            response
+        })
+        .unwrap()
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut request = HttpRequest::new(
+            method,
+            "/path?q=1".into(),
+            Version::Http11,
+            HeaderMap::new(),
+            Box::new([]),
+        );
+        request.set_peer_addr(Some("10.0.0.7:50000".parse().unwrap()));
+        for (key, value) in headers {
+            request.add_header(key.to_string(), value.to_string());
+        }
+        request
+    }
+
+    fn read_body(mut response: HttpResponse) -> String {
+        let mut body = String::new();
+        response.get_body_mut().read_to_string(&mut body).unwrap();
+        body
+    }
+
+    /// An address nothing listens on
+    fn closed_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[test]
+    fn forwards_and_rewrites_headers() {
+        let upstream_a = upstream("a");
+        let upstream_b = upstream("b");
+        let (addr_a, addr_b) = (upstream_a.get_addr(), upstream_b.get_addr());
+        let proxy = ReverseProxy::new(&[&addr_a.to_string(), &addr_b.to_string()]);
+
+        let response = proxy.handle(&request(
+            Method::Get,
+            &[
+                ("Host", "example.com"),
+                ("Connection", "keep-alive, X-Secret"),
+                ("X-Secret", "1"),
+                ("Keep-Alive", "timeout=5"),
+                ("X-Forwarded-For", "192.0.2.1"),
+                ("Accept", "*/*"),
+            ],
+        ));
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert_eq!(response.get_header("Connection"), None);
+
+        let body = read_body(response);
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(lines[0], "a");
+        assert!(lines.contains(&format!("host: {}", addr_a).as_str()));
+        assert!(lines.contains(&"x-forwarded-for: 192.0.2.1, 10.0.0.7"));
+        assert!(lines.contains(&"x-forwarded-host: example.com"));
+        assert!(lines.contains(&"forwarded: for=10.0.0.7;host=example.com;proto=http"));
+        assert!(lines.contains(&"accept: */*"));
+        assert!(!body.contains("x-secret"));
+        assert!(!body.contains("keep-alive"));
+
+        // Round-robin, and the first connection went back to the pool
+        let names: Vec<_> = (0..4)
+            .map(|_| read_body(proxy.handle(&request(Method::Get, &[]))))
+            .map(|body| body.lines().next().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["b", "a", "b", "a"]);
+        assert_eq!(proxy.upstreams[0].idle.lock().unwrap().len(), 1);
+
+        let proxy = ReverseProxy::new(&[&addr_a.to_string()]).set_preserve_host(true);
+        let mut post = request(Method::Post, &[("Host", "example.com")]);
+        post.set_body(Box::new(*b"payload"));
+        let body = read_body(proxy.handle(&post));
+        assert!(body.contains("host: example.com\n"));
+        assert!(body.ends_with("\npayload"));
+
+        upstream_a.shutdown().unwrap();
+        upstream_b.shutdown().unwrap();
+    }
+
+    #[test]
+    fn passive_health_checks() {
+        let up = upstream("up");
+        let addr = up.get_addr();
+        let dead = closed_addr();
+        let proxy = ReverseProxy::new(&[&dead, &addr.to_string()]).set_max_fails(2);
+
+        // The dead upstream is retried on the live one until it counts as down
+        for _ in 0..4 {
+            let response = proxy.handle(&request(Method::Post, &[]));
+            assert_eq!(read_body(response).lines().next(), Some("up"));
+        }
+        assert!(proxy.upstreams[0].is_down(Instant::now()));
+        assert!(!proxy.upstreams[1].is_down(Instant::now()));
+
+        let proxy = ReverseProxy::new(&[&closed_addr(), &closed_addr()]);
+        let response = proxy.handle(&request(Method::Get, &[]));
+        assert_eq!(response.get_status_code(), StatusCode::BAD_GATEWAY);
+        assert!(proxy.upstreams.iter().all(|up| up.is_down(Instant::now())));
+
+        // With everything down they are still tried
+        let proxy = ReverseProxy::new(&[&addr.to_string()]);
+        proxy.upstreams[0].failed(1, Duration::from_secs(60));
+        let response = proxy.handle(&request(Method::Get, &[]));
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert!(!proxy.upstreams[0].is_down(Instant::now()));
+
+        up.shutdown().unwrap();
+    }
+
+    #[test]
+    fn least_connections() {
+        let proxy =
+            ReverseProxy::new(&["a:1", "b:1", "c:1"]).set_balancing(Balancing::LeastConnections);
+
+        let busy_a = Active::new(&proxy.upstreams[0]);
+        let _busy_c = Active::new(&proxy.upstreams[2]);
+        let _also_c = Active::new(&proxy.upstreams[2]);
+
+        for _ in 0..3 {
+            assert_eq!(proxy.pick(&[false; 3]), Some(1));
+        }
+        assert_eq!(proxy.pick(&[false, true, false]), Some(0));
+
+        drop(busy_a);
+        let picks: Vec<_> = (0..4).map(|_| proxy.pick(&[false; 3]).unwrap()).collect();
+        assert!(picks.contains(&0) && picks.contains(&1) && !picks.contains(&2));
+        assert_eq!(proxy.pick(&[true; 3]), None);
+    }
+}
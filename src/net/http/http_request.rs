@@ -1,19 +1,69 @@
-use std::collections::HashMap;
-
-#[derive(Debug)]
+use std::fmt::Display;
+use std::net::SocketAddr;
+
+use super::http_chunked::{encode_chunked_body, is_chunked};
+use super::http_compression::decode_body;
+use super::Body;
+use super::Cookie;
+use super::HeaderMap;
+use super::Method;
+use super::Version;
+use super::{Form, FormError};
+use crate::json::{JsonError, JsonValue};
+use crate::net::url::Query;
+
+/// The part of the message that was never set on the builder, or that can't be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HttpRequestBuildError {
     MissingMethod,
     MissingUrl,
     MissingVersion,
     MissingBody,
+    /// See [InvalidUrl]
+    InvalidUrl,
+}
+
+impl Display for HttpRequestBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::MissingMethod => "Missing Method",
+            Self::MissingUrl => "Missing URL",
+            Self::MissingVersion => "Missing Version",
+            Self::MissingBody => "Missing Body",
+            Self::InvalidUrl => "Invalid URL",
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for HttpRequestBuildError {}
+
+/// An empty URL or one with whitespace or control characters in it, which would end the request
+/// line early and let the rest pass for header fields (RFC 9112 3.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidUrl;
+
+impl Display for InvalidUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid URL")
+    }
+}
+
+impl std::error::Error for InvalidUrl {}
+
+impl From<InvalidUrl> for std::io::Error {
+    fn from(err: InvalidUrl) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+    }
 }
 
 pub struct HttpRequestBuilder {
-    method: Option<String>,
+    method: Option<Method>,
     url: Option<String>,
-    version: Option<String>,
-    headers: Option<HashMap<String, String>>,
-    body: Option<Box<[u8]>>,
+    version: Option<Version>,
+    headers: Option<HeaderMap>,
+    body: Option<Body>,
 }
 
 impl HttpRequestBuilder {
@@ -22,12 +72,12 @@ impl HttpRequestBuilder {
             method: None,
             url: None,
             version: None,
-            headers: Some(HashMap::new()),
+            headers: Some(HeaderMap::new()),
             body: None,
         }
     }
 
-    pub fn set_method(&mut self, method: String) -> &mut Self {
+    pub fn set_method(&mut self, method: Method) -> &mut Self {
         self.method = Some(method);
         self
     }
@@ -37,23 +87,74 @@ impl HttpRequestBuilder {
         self
     }
 
-    pub fn set_version(&mut self, version: String) -> &mut Self {
+    pub fn set_version(&mut self, version: Version) -> &mut Self {
         self.version = Some(version);
         self
     }
 
     pub fn set_body(&mut self, body: Box<[u8]>) -> &mut Self {
-        self.body = Some(body);
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Streams the body from `reader` when the request is sent, see [Body::from_reader]
+    pub fn set_body_reader(
+        &mut self,
+        reader: impl std::io::Read + Send + 'static,
+        len: Option<u64>,
+    ) -> &mut Self {
+        self.body = Some(Body::from_reader(reader, len));
         self
     }
 
+    /// Sets the body to `query` encoded as `application/x-www-form-urlencoded`, along with the
+    /// Content-Type
+    pub fn set_urlencoded_body(&mut self, query: &Query) -> &mut Self {
+        self.set_header(
+            "Content-Type".into(),
+            "application/x-www-form-urlencoded".into(),
+        );
+        self.set_body(query.to_string().into_bytes().into_boxed_slice())
+    }
+
+    /// Sets the body to `form` encoded as `multipart/form-data`, along with the Content-Type
+    pub fn set_multipart_body(&mut self, form: &Form) -> &mut Self {
+        let (content_type, body) = form.encode_multipart();
+
+        self.set_header("Content-Type".into(), content_type);
+        self.set_body(body.into_boxed_slice())
+    }
+
+    /// Sets the body to `value` written compactly, along with an `application/json` Content-Type
+    /// and a matching Content-Length
+    pub fn set_json_body(&mut self, value: &JsonValue) -> &mut Self {
+        let body = value.to_string().into_bytes();
+
+        self.headers
+            .as_mut()
+            .unwrap()
+            .replace_body_fields("application/json", body.len());
+        self.set_body(body.into_boxed_slice())
+    }
+
+    /// Replaces any values already set for the header
+    ///
+    /// Invalid fields are treated as in [HeaderMap::insert]
     pub fn set_header(&mut self, key: String, value: String) -> &mut Self {
         self.headers.as_mut().unwrap().insert(key, value);
         self
     }
 
+    /// Adds another value for the header, keeping the ones already set
+    ///
+    /// Invalid fields are treated as in [HeaderMap::append]
+    pub fn add_header(&mut self, key: String, value: String) -> &mut Self {
+        self.headers.as_mut().unwrap().append(key, value);
+        self
+    }
+
     /// This will clear all currently set headers
-    pub fn set_headers(&mut self, headers: HashMap<String, String>) -> &mut Self {
+    pub fn set_headers(&mut self, headers: HeaderMap) -> &mut Self {
         self.headers = Some(headers);
         self
     }
@@ -63,8 +164,10 @@ impl HttpRequestBuilder {
             return Err(HttpRequestBuildError::MissingMethod);
         }
 
-        if self.url.is_none() {
-            return Err(HttpRequestBuildError::MissingUrl);
+        match &self.url {
+            None => return Err(HttpRequestBuildError::MissingUrl),
+            Some(url) if !is_valid_url(url) => return Err(HttpRequestBuildError::InvalidUrl),
+            Some(_) => {}
         }
 
         if self.version.is_none() {
@@ -80,10 +183,11 @@ impl HttpRequestBuilder {
             self.url.take().unwrap(),
             self.version.take().unwrap(),
             self.headers.take().unwrap(),
-            self.body.take().unwrap(),
-        ));
+            Box::new([]),
+        )
+        .with_body(self.body.take().unwrap()));
 
-        self.headers = Some(HashMap::new());
+        self.headers = Some(HeaderMap::new());
 
         req
     }
@@ -95,21 +199,24 @@ impl Default for HttpRequestBuilder {
     }
 }
 
-/// HTTP 1.x Request
+/// HTTP Request, the same for HTTP/1.x and HTTP/2
+#[derive(Debug, Clone)]
 pub struct HttpRequest {
-    method: String,
+    method: Method,
     url: String,
-    version: String,
-    headers: HashMap<String, String>,
-    body: Box<[u8]>,
+    version: Version,
+    headers: HeaderMap,
+    body: Body,
+    peer_addr: Option<SocketAddr>,
+    trailers: HeaderMap,
 }
 
 impl HttpRequest {
     pub fn new(
-        method: String,
+        method: Method,
         url: String,
-        version: String,
-        headers: HashMap<String, String>,
+        version: Version,
+        headers: HeaderMap,
         body: Box<[u8]>,
     ) -> Self {
         Self {
@@ -117,15 +224,17 @@ impl HttpRequest {
             url,
             version,
             headers,
-            body,
+            body: body.into(),
+            peer_addr: None,
+            trailers: HeaderMap::new(),
         }
     }
 
-    pub fn get_method(&self) -> &str {
+    pub fn get_method(&self) -> &Method {
         &self.method
     }
 
-    pub fn set_method(&mut self, method: String) {
+    pub fn set_method(&mut self, method: Method) {
         self.method = method;
     }
 
@@ -133,78 +242,236 @@ impl HttpRequest {
         &self.url
     }
 
-    pub fn set_url(&mut self, url: String) {
+    /// Fails without changing the URL if it couldn't be sent, see [InvalidUrl]
+    pub fn set_url(&mut self, url: String) -> Result<(), InvalidUrl> {
+        if !is_valid_url(&url) {
+            return Err(InvalidUrl);
+        }
+
         self.url = url;
+        Ok(())
+    }
+
+    /// The address of the client a request was received from, set by [super::HttpServer]
+    pub fn get_peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    pub fn set_peer_addr(&mut self, peer_addr: Option<SocketAddr>) {
+        self.peer_addr = peer_addr;
     }
 
-    pub fn get_version(&self) -> &str {
-        &self.version
+    pub fn get_version(&self) -> Version {
+        self.version
     }
 
-    pub fn set_version(&mut self, version: String) {
+    pub fn set_version(&mut self, version: Version) {
         self.version = version;
     }
 
-    pub fn get_header(&self, key: &String) -> Option<&String> {
+    /// Header names are case-insensitive, if the header has several values the first is returned
+    pub fn get_header(&self, key: &str) -> Option<&str> {
         self.headers.get(key)
     }
 
-    pub fn get_headers(&self) -> &HashMap<String, String> {
+    pub fn get_header_values(&self, key: &str) -> Vec<&str> {
+        self.headers.get_all(key).collect()
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
         &self.headers
     }
 
+    pub fn get_headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// The trailer fields that followed a chunked body, kept apart from the headers since a
+    /// sender can put anything in them (RFC 9110 6.5.1)
+    pub fn get_trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
+    pub fn set_trailers(&mut self, trailers: HeaderMap) {
+        self.trailers = trailers;
+    }
+
+    /// Whether the sender wants the connection kept open after this request, HTTP/1.1
+    /// connections persist unless `Connection: close` is sent while HTTP/1.0 connections need
+    /// `Connection: keep-alive` (RFC 9112 9.3)
+    pub fn is_keep_alive(&self) -> bool {
+        if self.headers.contains_token("Connection", "close") {
+            return false;
+        }
+
+        match self.version {
+            Version::Http10 => self.headers.contains_token("Connection", "keep-alive"),
+            _ => true,
+        }
+    }
+
+    /// Every cookie sent in the request's `Cookie` headers
+    pub fn get_cookies(&self) -> Vec<Cookie> {
+        self.get_header_values("Cookie")
+            .into_iter()
+            .flat_map(Cookie::parse_header)
+            .collect()
+    }
+
+    pub fn get_cookie(&self, name: &str) -> Option<Cookie> {
+        self.get_cookies()
+            .into_iter()
+            .find(|cookie| cookie.get_name() == name)
+    }
+
+    /// Parses the body as a form according to the Content-Type, see [Form::parse]
+    ///
+    /// For large uploads receive the request with
+    /// [super::HttpStream::recv_request_streaming] and read it with a [super::MultipartReader]
+    /// instead
+    pub fn get_form(&self) -> Result<Form, FormError> {
+        let content_type = self
+            .get_header("Content-Type")
+            .ok_or(FormError::UnsupportedContentType)?;
+
+        Form::parse(content_type, self.get_body())
+    }
+
+    /// Parses the buffered body as JSON, the Content-Type isn't checked since plenty of clients
+    /// leave it out or get it wrong
+    pub fn get_json(&self) -> Result<JsonValue, JsonError> {
+        JsonValue::parse(self.get_body())
+    }
+
+    /// Replaces any values already set for the header
+    ///
+    /// Invalid fields are treated as in [HeaderMap::insert]
     pub fn set_header(&mut self, key: String, val: String) {
         self.headers.insert(key, val);
     }
 
+    /// Adds another value for the header, keeping the ones already set
+    ///
+    /// Invalid fields are treated as in [HeaderMap::append]
+    pub fn add_header(&mut self, key: String, val: String) {
+        self.headers.append(key, val);
+    }
+
+    /// Returns the removed values
+    pub fn remove_header(&mut self, key: &str) -> Vec<String> {
+        self.headers.remove(key)
+    }
+
+    /// The buffered body, a streaming body returns an empty slice and has to be read through
+    /// [Self::get_body_mut] instead
     pub fn get_body(&self) -> &[u8] {
-        &self.body
+        self.body.as_bytes()
+    }
+
+    pub fn get_body_mut(&mut self) -> &mut Body {
+        &mut self.body
     }
 
     pub fn set_body(&mut self, body: Box<[u8]>) {
+        self.body = body.into();
+    }
+
+    /// Replaces the body with `value` written compactly, setting an `application/json`
+    /// Content-Type and a matching Content-Length
+    pub fn set_json_body(&mut self, value: &JsonValue) {
+        let body = value.to_string().into_bytes();
+
+        self.headers
+            .replace_body_fields("application/json", body.len());
+        self.set_body(body.into_boxed_slice());
+    }
+
+    /// Streams the body from `reader` when the request is sent, see [Body::from_reader]
+    pub fn set_body_reader(
+        &mut self,
+        reader: impl std::io::Read + Send + 'static,
+        len: Option<u64>,
+    ) {
+        self.body = Body::from_reader(reader, len);
+    }
+
+    /// Replaces the body with an empty one, returning the old body
+    pub fn take_body(&mut self) -> Body {
+        std::mem::take(&mut self.body)
+    }
+
+    pub fn is_body_streaming(&self) -> bool {
+        self.body.is_stream()
+    }
+
+    /// See [Body::content_length]
+    pub fn get_body_length(&self) -> Option<u64> {
+        self.body.content_length()
+    }
+
+    /// Removes a gzip or deflate Content-Encoding by decompressing the body, other codings are
+    /// left as they are
+    ///
+    /// A buffered body is decompressed right away and fails with
+    /// [ParseError::BodyTooLarge](super::ParseError::BodyTooLarge) if it would grow past
+    /// `max_size` bytes, a streaming body is decompressed as it is read
+    pub fn decode_body(&mut self, max_size: u64) -> std::io::Result<()> {
+        decode_body(&mut self.headers, &mut self.body, max_size)
+    }
+
+    fn with_body(mut self, body: Body) -> Self {
         self.body = body;
+        self
     }
 
     pub fn builder() -> HttpRequestBuilder {
         HttpRequestBuilder::new()
     }
 
-    pub fn as_bytes(&self) -> Box<[u8]> {
+    pub(crate) fn head_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
         // Encode Request Line
-        bytes.extend_from_slice(self.method.as_bytes());
+        bytes.extend_from_slice(self.method.as_str().as_bytes());
         bytes.push(b' ');
         bytes.extend_from_slice(self.url.as_bytes());
         bytes.push(b' ');
-        bytes.extend_from_slice(self.version.as_bytes());
+        bytes.extend_from_slice(self.version.as_str().as_bytes());
         bytes.extend_from_slice(b"\r\n");
 
         // Encode headers
-        for (key, val) in &self.headers {
-            bytes.extend_from_slice(key.as_bytes());
-            bytes.extend_from_slice(b": ");
-            bytes.extend_from_slice(val.as_bytes());
-            bytes.extend_from_slice(b"\r\n");
-        }
+        self.headers.encode(&mut bytes);
 
         bytes.extend_from_slice(b"\r\n");
 
+        bytes
+    }
+
+    /// Encodes the message, a streaming body is left out
+    pub fn as_bytes(&self) -> Box<[u8]> {
+        let mut bytes = self.head_bytes();
+
         // Encode Body
-        bytes.extend_from_slice(&self.body);
+        if is_chunked(&self.headers) == Some(true) {
+            bytes.extend_from_slice(&encode_chunked_body(self.body.as_bytes()));
+        } else {
+            bytes.extend_from_slice(self.body.as_bytes());
+        }
 
         bytes.into_boxed_slice()
     }
 
+    /// Encodes the message, a streaming body is left out
     pub fn into_bytes(self) -> Box<[u8]> {
+        let chunked = is_chunked(&self.headers) == Some(true);
         let mut bytes = Vec::new();
 
         // Encode Request Line
-        bytes.extend_from_slice(&self.method.into_bytes());
+        bytes.extend_from_slice(self.method.as_str().as_bytes());
         bytes.push(b' ');
         bytes.extend_from_slice(&self.url.into_bytes());
         bytes.push(b' ');
-        bytes.extend_from_slice(&self.version.into_bytes());
+        bytes.extend_from_slice(self.version.as_str().as_bytes());
         bytes.extend_from_slice(b"\r\n");
 
         // Encode headers
@@ -218,8 +485,16 @@ impl HttpRequest {
         bytes.extend_from_slice(b"\r\n");
 
         // Encode Body
-        bytes.extend_from_slice(&self.body);
+        if chunked {
+            bytes.extend_from_slice(&encode_chunked_body(self.body.as_bytes()));
+        } else {
+            bytes.extend_from_slice(self.body.as_bytes());
+        }
 
         bytes.into_boxed_slice()
     }
 }
+
+pub(crate) fn is_valid_url(url: &str) -> bool {
+    !url.is_empty() && !url.bytes().any(|b| b.is_ascii_control() || b == b' ')
+}
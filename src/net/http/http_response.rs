@@ -1,19 +1,64 @@
-use std::collections::HashMap;
-
-#[derive(Debug)]
+use std::fmt::Display;
+
+use super::http_chunked::{encode_chunked_body, is_chunked};
+use super::http_compression::decode_body;
+use super::Body;
+use super::Cookie;
+use super::HeaderMap;
+use super::StatusCode;
+use super::Version;
+use crate::json::{JsonError, JsonValue};
+
+/// The part of the message that was never set on the builder, or that can't be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HttpResponseBuildError {
     MissingVersion,
     MissingStatusCode,
-    MissingStatusMessage,
     MissingBody,
+    /// See [InvalidStatusMessage]
+    InvalidStatusMessage,
+}
+
+impl Display for HttpResponseBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::MissingVersion => "Missing Version",
+            Self::MissingStatusCode => "Missing Status Code",
+            Self::MissingBody => "Missing Body",
+            Self::InvalidStatusMessage => "Invalid Status Message",
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for HttpResponseBuildError {}
+
+/// A status message with control characters other than tabs in it, a CR or LF would end the
+/// status line early and let the rest pass for header fields (RFC 9112 4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidStatusMessage;
+
+impl Display for InvalidStatusMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid Status Message")
+    }
+}
+
+impl std::error::Error for InvalidStatusMessage {}
+
+impl From<InvalidStatusMessage> for std::io::Error {
+    fn from(err: InvalidStatusMessage) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+    }
 }
 
 pub struct HttpResponseBuilder {
-    version: Option<String>,
-    status_code: Option<u16>,
+    version: Option<Version>,
+    status_code: Option<StatusCode>,
     status_message: Option<String>,
-    headers: Option<HashMap<String, String>>,
-    body: Option<Box<[u8]>>,
+    headers: Option<HeaderMap>,
+    body: Option<Body>,
 }
 
 impl HttpResponseBuilder {
@@ -22,38 +67,74 @@ impl HttpResponseBuilder {
             version: None,
             status_code: None,
             status_message: None,
-            headers: Some(HashMap::new()),
+            headers: Some(HeaderMap::new()),
             body: None,
         }
     }
-    pub fn set_version(&mut self, version: String) -> &mut Self {
+    pub fn set_version(&mut self, version: Version) -> &mut Self {
         self.version = Some(version);
         self
     }
 
-    pub fn set_status_code(&mut self, status_code: u16) -> &mut Self {
+    /// The status message defaults to the code's reason phrase, see [Self::set_status_message]
+    pub fn set_status_code(&mut self, status_code: StatusCode) -> &mut Self {
         self.status_code = Some(status_code);
         self
     }
 
+    /// Defaults to the status code's canonical reason phrase, or an empty one for unregistered
+    /// status codes
     pub fn set_status_message(&mut self, status_message: String) -> &mut Self {
         self.status_message = Some(status_message);
         self
     }
 
+    /// Replaces any values already set for the header
+    ///
+    /// Invalid fields are treated as in [HeaderMap::insert]
     pub fn set_header(&mut self, key: String, value: String) -> &mut Self {
         self.headers.as_mut().unwrap().insert(key, value);
         self
     }
 
+    /// Adds another value for the header, keeping the ones already set
+    ///
+    /// Invalid fields are treated as in [HeaderMap::append]
+    pub fn add_header(&mut self, key: String, value: String) -> &mut Self {
+        self.headers.as_mut().unwrap().append(key, value);
+        self
+    }
+
     /// This will clear all currently set headers
-    pub fn set_headers(&mut self, headers: HashMap<String, String>) -> &mut Self {
+    pub fn set_headers(&mut self, headers: HeaderMap) -> &mut Self {
         self.headers = Some(headers);
         self
     }
 
     pub fn set_body(&mut self, body: Box<[u8]>) -> &mut Self {
-        self.body = Some(body);
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Sets the body to `value` written compactly, along with an `application/json` Content-Type
+    /// and a matching Content-Length
+    pub fn set_json_body(&mut self, value: &JsonValue) -> &mut Self {
+        let body = value.to_string().into_bytes();
+
+        self.headers
+            .as_mut()
+            .unwrap()
+            .replace_body_fields("application/json", body.len());
+        self.set_body(body.into_boxed_slice())
+    }
+
+    /// Streams the body from `reader` when the response is sent, see [Body::from_reader]
+    pub fn set_body_reader(
+        &mut self,
+        reader: impl std::io::Read + Send + 'static,
+        len: Option<u64>,
+    ) -> &mut Self {
+        self.body = Some(Body::from_reader(reader, len));
         self
     }
 
@@ -66,23 +147,34 @@ impl HttpResponseBuilder {
             return Err(HttpResponseBuildError::MissingStatusCode);
         }
 
-        if self.status_message.is_none() {
-            return Err(HttpResponseBuildError::MissingStatusMessage);
-        }
-
         if self.body.is_none() {
             return Err(HttpResponseBuildError::MissingBody);
         }
 
+        if let Some(status_message) = &self.status_message {
+            if !is_valid_status_message(status_message) {
+                return Err(HttpResponseBuildError::InvalidStatusMessage);
+            }
+        }
+
+        let status_code = self.status_code.take().unwrap();
+        let status_message = self.status_message.take().unwrap_or_else(|| {
+            status_code
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string()
+        });
+
         let res = Ok(HttpResponse::new(
             self.version.take().unwrap(),
-            self.status_code.take().unwrap(),
-            self.status_message.take().unwrap(),
+            status_code,
+            status_message,
             self.headers.take().unwrap(),
-            self.body.take().unwrap(),
-        ));
+            Box::new([]),
+        )
+        .with_body(self.body.take().unwrap()));
 
-        self.headers = Some(HashMap::new());
+        self.headers = Some(HeaderMap::new());
 
         res
     }
@@ -94,21 +186,23 @@ impl Default for HttpResponseBuilder {
     }
 }
 
-/// HTTP 1.x Response
+/// HTTP Response, the same for HTTP/1.x and HTTP/2
+#[derive(Debug, Clone)]
 pub struct HttpResponse {
-    version: String,
-    status_code: u16,
+    version: Version,
+    status_code: StatusCode,
     status_message: String,
-    headers: HashMap<String, String>,
-    body: Box<[u8]>,
+    headers: HeaderMap,
+    body: Body,
+    trailers: HeaderMap,
 }
 
 impl HttpResponse {
     pub fn new(
-        version: String,
-        status_code: u16,
+        version: Version,
+        status_code: StatusCode,
         status_message: String,
-        headers: HashMap<String, String>,
+        headers: HeaderMap,
         body: Box<[u8]>,
     ) -> Self {
         Self {
@@ -116,63 +210,217 @@ impl HttpResponse {
             status_code,
             status_message,
             headers,
-            body,
+            body: body.into(),
+            trailers: HeaderMap::new(),
         }
     }
 
-    pub fn get_version(&self) -> &str {
-        &self.version
+    pub fn get_version(&self) -> Version {
+        self.version
     }
 
-    pub fn set_version(&mut self, version: String) {
+    pub fn set_version(&mut self, version: Version) {
         self.version = version;
     }
 
-    pub fn get_status_code(&self) -> u16 {
+    pub fn get_status_code(&self) -> StatusCode {
         self.status_code
     }
 
-    pub fn set_status_code(&mut self, status_code: u16) {
+    /// Also sets the status message to the code's canonical reason phrase, or an empty one for
+    /// unregistered status codes, call [Self::set_status_message] afterwards for a custom one
+    pub fn set_status_code(&mut self, status_code: StatusCode) {
         self.status_code = status_code;
+        self.status_message = status_code
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string();
     }
 
     pub fn get_status_message(&self) -> &str {
         &self.status_message
     }
 
-    pub fn set_status_message(&mut self, status_message: String) {
+    /// Fails without changing the status message if it couldn't be sent, see
+    /// [InvalidStatusMessage]
+    pub fn set_status_message(
+        &mut self,
+        status_message: String,
+    ) -> Result<(), InvalidStatusMessage> {
+        if !is_valid_status_message(&status_message) {
+            return Err(InvalidStatusMessage);
+        }
+
         self.status_message = status_message;
+        Ok(())
     }
 
-    pub fn get_header(&self, key: &String) -> Option<&String> {
+    /// Header names are case-insensitive, if the header has several values the first is returned
+    pub fn get_header(&self, key: &str) -> Option<&str> {
         self.headers.get(key)
     }
 
-    pub fn get_headers(&self) -> &HashMap<String, String> {
+    pub fn get_header_values(&self, key: &str) -> Vec<&str> {
+        self.headers.get_all(key).collect()
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
         &self.headers
     }
 
+    pub fn get_headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// The trailer fields that followed a chunked body, kept apart from the headers since a
+    /// sender can put anything in them (RFC 9110 6.5.1)
+    pub fn get_trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
+    pub fn set_trailers(&mut self, trailers: HeaderMap) {
+        self.trailers = trailers;
+    }
+
+    /// Whether the sender wants the connection kept open after this response, HTTP/1.1
+    /// connections persist unless `Connection: close` is sent while HTTP/1.0 connections need
+    /// `Connection: keep-alive` (RFC 9112 9.3)
+    pub fn is_keep_alive(&self) -> bool {
+        if self.headers.contains_token("Connection", "close") {
+            return false;
+        }
+
+        match self.version {
+            Version::Http10 => self.headers.contains_token("Connection", "keep-alive"),
+            _ => true,
+        }
+    }
+
+    /// Replaces any values already set for the header
+    ///
+    /// Invalid fields are treated as in [HeaderMap::insert]
     pub fn set_header(&mut self, key: String, val: String) {
         self.headers.insert(key, val);
     }
 
+    /// Adds another value for the header, keeping the ones already set
+    ///
+    /// Invalid fields are treated as in [HeaderMap::append]
+    pub fn add_header(&mut self, key: String, val: String) {
+        self.headers.append(key, val);
+    }
+
+    /// Adds a `Set-Cookie` header for `cookie`, keeping the ones already set
+    pub fn add_cookie(&mut self, cookie: &Cookie) {
+        self.add_header("Set-Cookie".into(), cookie.to_set_cookie());
+    }
+
+    /// Every cookie set by the response's `Set-Cookie` headers, malformed ones are skipped
+    pub fn get_set_cookies(&self) -> Vec<Cookie> {
+        self.get_header_values("Set-Cookie")
+            .into_iter()
+            .filter_map(Cookie::parse_set_cookie)
+            .collect()
+    }
+
+    /// Returns the removed values
+    pub fn remove_header(&mut self, key: &str) -> Vec<String> {
+        self.headers.remove(key)
+    }
+
+    /// The buffered body, a streaming body returns an empty slice and has to be read through
+    /// [Self::get_body_mut] instead
     pub fn get_body(&self) -> &[u8] {
-        &self.body
+        self.body.as_bytes()
+    }
+
+    pub fn get_body_mut(&mut self) -> &mut Body {
+        &mut self.body
     }
 
     pub fn set_body(&mut self, body: Box<[u8]>) {
+        self.body = body.into();
+    }
+
+    /// Replaces the body with `value` written compactly, setting an `application/json`
+    /// Content-Type and a matching Content-Length
+    pub fn set_json_body(&mut self, value: &JsonValue) {
+        let body = value.to_string().into_bytes();
+
+        self.headers
+            .replace_body_fields("application/json", body.len());
+        self.set_body(body.into_boxed_slice());
+    }
+
+    /// Parses the buffered body as JSON regardless of the Content-Type
+    pub fn get_json(&self) -> Result<JsonValue, JsonError> {
+        JsonValue::parse(self.get_body())
+    }
+
+    /// Streams the body from `reader` when the response is sent, see [Body::from_reader]
+    pub fn set_body_reader(
+        &mut self,
+        reader: impl std::io::Read + Send + 'static,
+        len: Option<u64>,
+    ) {
+        self.body = Body::from_reader(reader, len);
+    }
+
+    /// Replaces the body with an empty one, returning the old body
+    pub fn take_body(&mut self) -> Body {
+        std::mem::take(&mut self.body)
+    }
+
+    pub fn is_body_streaming(&self) -> bool {
+        self.body.is_stream()
+    }
+
+    /// See [Body::content_length]
+    pub fn get_body_length(&self) -> Option<u64> {
+        self.body.content_length()
+    }
+
+    /// Removes a gzip or deflate Content-Encoding by decompressing the body, other codings are
+    /// left as they are
+    ///
+    /// A buffered body is decompressed right away and fails with
+    /// [ParseError::BodyTooLarge](super::ParseError::BodyTooLarge) if it would grow past
+    /// `max_size` bytes, a streaming body is decompressed as it is read
+    pub fn decode_body(&mut self, max_size: u64) -> std::io::Result<()> {
+        decode_body(&mut self.headers, &mut self.body, max_size)
+    }
+
+    fn with_body(mut self, body: Body) -> Self {
         self.body = body;
+        self
     }
 
     pub fn builder() -> HttpResponseBuilder {
         HttpResponseBuilder::new()
     }
 
-    pub fn as_bytes(&self) -> Box<[u8]> {
+    /// An HTTP/1.1 response with the status code's reason phrase as a plain text body, meant for
+    /// error responses
+    pub fn from_status(status_code: StatusCode) -> Self {
+        let body = status_code
+            .canonical_reason()
+            .unwrap_or_default()
+            .as_bytes();
+
+        Self::builder()
+            .set_version(Version::Http11)
+            .set_status_code(status_code)
+            .set_header("Content-Type".into(), "text/plain".into())
+            .set_body(body.into())
+            .build()
+            .unwrap()
+    }
+
+    pub(crate) fn head_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
         // Encode Status Line
-        bytes.extend_from_slice(self.version.as_bytes());
+        bytes.extend_from_slice(self.version.as_str().as_bytes());
         bytes.push(b' ');
         bytes.extend_from_slice(self.status_code.to_string().as_bytes());
         bytes.push(b' ');
@@ -180,25 +428,33 @@ impl HttpResponse {
         bytes.extend_from_slice(b"\r\n");
 
         // Encode Headers
-        for (key, val) in &self.headers {
-            bytes.extend_from_slice(key.as_bytes());
-            bytes.extend_from_slice(b": ");
-            bytes.extend_from_slice(val.as_bytes());
-            bytes.extend_from_slice(b"\r\n");
-        }
+        self.headers.encode(&mut bytes);
         bytes.extend_from_slice(b"\r\n");
 
-        // Encode body
-        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    /// Encodes the message, a streaming body is left out
+    pub fn as_bytes(&self) -> Box<[u8]> {
+        let mut bytes = self.head_bytes();
+
+        // Encode Body
+        if is_chunked(&self.headers) == Some(true) {
+            bytes.extend_from_slice(&encode_chunked_body(self.body.as_bytes()));
+        } else {
+            bytes.extend_from_slice(self.body.as_bytes());
+        }
 
         bytes.into_boxed_slice()
     }
 
+    /// Encodes the message, a streaming body is left out
     pub fn into_bytes(self) -> Box<[u8]> {
+        let chunked = is_chunked(&self.headers) == Some(true);
         let mut bytes = Vec::new();
 
         // Encode Status Line
-        bytes.extend_from_slice(&self.version.into_bytes());
+        bytes.extend_from_slice(self.version.as_str().as_bytes());
         bytes.push(b' ');
         bytes.extend_from_slice(&self.status_code.to_string().into_bytes());
         bytes.push(b' ');
@@ -215,8 +471,56 @@ impl HttpResponse {
         bytes.extend_from_slice(b"\r\n");
 
         // Encode body
-        bytes.extend_from_slice(&self.body);
+        if chunked {
+            bytes.extend_from_slice(&encode_chunked_body(self.body.as_bytes()));
+        } else {
+            bytes.extend_from_slice(self.body.as_bytes());
+        }
 
         bytes.into_boxed_slice()
     }
 }
+
+pub(crate) fn is_valid_status_message(status_message: &str) -> bool {
+    !status_message
+        .bytes()
+        .any(|b| b.is_ascii_control() && b != b'\t')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{HttpRequest, TestClient};
+
+    #[test]
+    fn status_code_sets_reason_phrase() {
+        let mut response = HttpResponse::from_status(StatusCode::OK);
+
+        response.set_status_code(StatusCode::BAD_REQUEST);
+        assert_eq!(response.get_status_message(), "Bad Request");
+        assert!(response
+            .head_bytes()
+            .starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+
+        response.set_status_message("Nope".into()).unwrap();
+        assert!(response.head_bytes().starts_with(b"HTTP/1.1 400 Nope\r\n"));
+
+        response.set_status_code(StatusCode::new(599).unwrap());
+        assert_eq!(response.get_status_message(), "");
+    }
+
+    #[test]
+    fn status_body_can_be_replaced() {
+        let client = TestClient::new(|_: &HttpRequest| {
+            let mut response = HttpResponse::from_status(StatusCode::OK);
+            response.set_body(b"a body that's longer than OK"[..].into());
+            response
+        });
+
+        client
+            .get("/")
+            .assert_status(StatusCode::OK)
+            .assert_header("Content-Length", "28")
+            .assert_body("a body that's longer than OK");
+    }
+}
@@ -0,0 +1,377 @@
+use std::str::FromStr;
+
+use super::Handler;
+use super::HttpRequest;
+use super::HttpResponse;
+use super::Method;
+use super::StatusCode;
+use crate::net::url::{decode, remove_dot_segments, Url, UrlError};
+
+type RouteHandler = dyn Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static;
+
+/// Values captured from the request path by a route's `:name` and `*name` segments
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathParams {
+    params: Vec<(String, String)>,
+}
+
+impl PathParams {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, val)| val.as_str())
+    }
+
+    /// Returns `None` if the parameter is missing or does not parse as a `T`
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name)?.parse().ok()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    /// Matches the rest of the path, including no segments at all
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Static segments win over parameters, which win over wildcards
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Static(_) => 0,
+            Self::Param(_) => 1,
+            Self::Wildcard(_) => 2,
+        }
+    }
+}
+
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Box<RouteHandler>,
+}
+
+impl Route {
+    fn matches(&self, path: &[&str]) -> Option<PathParams> {
+        let mut params = PathParams::default();
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(expected) => {
+                    if path.get(index) != Some(&expected.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => match path.get(index) {
+                    Some(val) if !val.is_empty() => {
+                        params.params.push((name.clone(), val.to_string()))
+                    }
+                    _ => return None,
+                },
+                Segment::Wildcard(name) => {
+                    let rest = path.get(index..).unwrap_or_default();
+
+                    // A decoded `/` would be indistinguishable from a separator once the
+                    // segments are joined, letting `a%2F..%2Fsecret` pass for `a/../secret`
+                    if rest.iter().any(|segment| segment.contains('/')) {
+                        return None;
+                    }
+
+                    params.params.push((name.clone(), rest.join("/")));
+
+                    return Some(params);
+                }
+            }
+        }
+
+        (path.len() == self.segments.len()).then_some(params)
+    }
+
+    fn rank(&self) -> Vec<u8> {
+        self.segments.iter().map(Segment::rank).collect()
+    }
+}
+
+/// Dispatches requests to handlers by method and path
+///
+/// Path patterns are made of `/` separated segments, which are either matched literally,
+/// captured as a parameter with `:name` or, as the last segment, capture the rest of the path
+/// with `*name`. Request paths are matched segment by segment after percent-decoding, so
+/// captured values are decoded as well. Wildcards don't match paths with an encoded `/` in the
+/// part they would capture, since it couldn't be told apart from a separator. When several
+/// routes match, literal segments are preferred over parameters and parameters over wildcards,
+/// from left to right
+///
+/// Requests that match no route's path get a 404, requests that match a path but not its method
+/// get a 405 with an `Allow` header. HEAD requests fall back to the GET route of a path
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<Box<dyn Handler>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            not_found: None,
+        }
+    }
+
+    /// Panics if `pattern` doesn't start with a `/`, a parameter has no name or a wildcard isn't
+    /// the last segment
+    pub fn route(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: impl Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.routes.push(Route {
+            method,
+            segments: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    pub fn patch(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&HttpRequest, &PathParams) -> HttpResponse + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.route(Method::Patch, pattern, handler)
+    }
+
+    /// Replaces the default 404 response
+    pub fn set_not_found(&mut self, handler: impl Handler) -> &mut Self {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    fn best_match<'a>(&'a self, method: &Method, path: &[&str]) -> Option<(&'a Route, PathParams)> {
+        self.routes
+            .iter()
+            .filter(|route| &route.method == method)
+            .filter_map(|route| route.matches(path).map(|params| (route, params)))
+            .min_by_key(|(route, _)| route.rank())
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let Ok(segments) = request_segments(request.get_url()) else {
+            return HttpResponse::from_status(StatusCode::BAD_REQUEST);
+        };
+        let segments: Vec<_> = segments.iter().map(String::as_str).collect();
+
+        let found = self
+            .best_match(request.get_method(), &segments)
+            .or_else(|| match request.get_method() {
+                Method::Head => self.best_match(&Method::Get, &segments),
+                _ => None,
+            });
+
+        if let Some((route, params)) = found {
+            return (route.handler)(request, &params);
+        }
+
+        // Collect the methods that would have matched the path
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            if route.matches(&segments).is_some() && !allowed.contains(&route.method.as_str()) {
+                allowed.push(route.method.as_str());
+            }
+        }
+
+        if allowed.contains(&"GET") && !allowed.contains(&"HEAD") {
+            allowed.push("HEAD");
+        }
+
+        if !allowed.is_empty() {
+            let mut response = HttpResponse::from_status(StatusCode::METHOD_NOT_ALLOWED);
+            response.set_header("Allow".into(), allowed.join(", "));
+            return response;
+        }
+
+        match &self.not_found {
+            Some(handler) => handler.handle(request),
+            None => HttpResponse::from_status(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+/// Splits the path of a request target into percent-decoded segments after removing any dot
+/// segments, absolute-form targets are reduced to their path
+fn request_segments(target: &str) -> Result<Vec<String>, UrlError> {
+    let url = Url::parse(target)?;
+    let path = remove_dot_segments(url.path());
+
+    path.split('/').skip(1).map(decode).collect()
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    assert!(
+        pattern.starts_with('/'),
+        "Route patterns must start with a /"
+    );
+
+    let parts: Vec<_> = pattern.split('/').skip(1).collect();
+    let mut segments = Vec::new();
+
+    for (index, part) in parts.iter().enumerate() {
+        let segment = if let Some(name) = part.strip_prefix(':') {
+            assert!(!name.is_empty(), "Route parameters need a name");
+            Segment::Param(name.to_string())
+        } else if let Some(name) = part.strip_prefix('*') {
+            assert!(!name.is_empty(), "Route wildcards need a name");
+            assert!(
+                index == parts.len() - 1,
+                "Route wildcards must be the last segment"
+            );
+            Segment::Wildcard(name.to_string())
+        } else {
+            Segment::Static(part.to_string())
+        };
+
+        segments.push(segment);
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::Version;
+
+    fn request(method: Method, url: &str) -> HttpRequest {
+        HttpRequest::builder()
+            .set_method(method)
+            .set_url(url.into())
+            .set_version(Version::Http11)
+            .set_body(Box::new([]))
+            .build()
+            .unwrap()
+    }
+
+    fn text(body: String) -> HttpResponse {
+        HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::OK)
+            .set_body(body.into_bytes().into_boxed_slice())
+            .build()
+            .unwrap()
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+
+        router
+            .get("/users/:id", |_, params| {
+                let id: u64 = params.get_as("id").unwrap();
+                text(format!("user {}", id))
+            })
+            .get("/users/me", |_, _| text("me".into()))
+            .delete("/users/:id", |_, params| {
+                text(format!("deleted {}", params.get("id").unwrap()))
+            })
+            .get("/static/*rest", |_, params| {
+                text(format!("file {}", params.get("rest").unwrap()))
+            });
+
+        router
+    }
+
+    #[test]
+    fn dispatch() {
+        let router = router();
+
+        let response = router.handle(&request(Method::Get, "/users/42?verbose=1"));
+        assert_eq!(response.get_body(), b"user 42");
+
+        let response = router.handle(&request(Method::Get, "/users/me"));
+        assert_eq!(response.get_body(), b"me");
+
+        let response = router.handle(&request(Method::Delete, "/users/7"));
+        assert_eq!(response.get_body(), b"deleted 7");
+
+        let response = router.handle(&request(Method::Get, "/static/css/site.css"));
+        assert_eq!(response.get_body(), b"file css/site.css");
+
+        let response = router.handle(&request(Method::Get, "/static/../users/a%2Fb/../me"));
+        assert_eq!(response.get_body(), b"me");
+
+        let response = router.handle(&request(Method::Delete, "/users/a%20b"));
+        assert_eq!(response.get_body(), b"deleted a b");
+
+        let response = router.handle(&request(Method::Get, "http://example.com/users/me"));
+        assert_eq!(response.get_body(), b"me");
+
+        let response = router.handle(&request(Method::Head, "/users/me"));
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+    }
+
+    #[test]
+    fn not_found_and_not_allowed() {
+        let router = router();
+
+        let response = router.handle(&request(Method::Get, "/nothing"));
+        assert_eq!(response.get_status_code(), StatusCode::NOT_FOUND);
+
+        let response = router.handle(&request(Method::Get, "/users/"));
+        assert_eq!(response.get_status_code(), StatusCode::NOT_FOUND);
+
+        let response = router.handle(&request(Method::Get, "/static/a%2F..%2Fsecret"));
+        assert_eq!(response.get_status_code(), StatusCode::NOT_FOUND);
+
+        let response = router.handle(&request(Method::Post, "/users/42"));
+        assert_eq!(response.get_status_code(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.get_header("Allow"), Some("GET, DELETE, HEAD"));
+    }
+}
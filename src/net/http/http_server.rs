@@ -0,0 +1,641 @@
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::Handler;
+use super::HttpError;
+use super::HttpLimits;
+use super::HttpRequest;
+use super::HttpResponse;
+use super::HttpStream;
+use super::Method;
+use super::StatusCode;
+use super::Version;
+use crate::concurrency::{ThreadPool, ThreadPoolError};
+use crate::logging::Logger;
+use crate::net::http2;
+
+const LOG_NAME: &str = "HttpServer";
+
+/// How often an idle connection checks whether the server is shutting down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A multi-threaded HTTP/1.x server, which also speaks cleartext HTTP/2 once enabled with
+/// [HttpServer::set_http2]
+///
+/// Every connection is handled by one of the [ThreadPool]'s workers for as long as the connection
+/// is kept alive, so the worker count is also the maximum number of connections served at once.
+/// Further connections wait until a worker frees up
+pub struct HttpServer {
+    listener: TcpListener,
+    worker_count: usize,
+    keep_alive_timeout: Duration,
+    request_timeout: Duration,
+    write_timeout: Option<Duration>,
+    limits: HttpLimits,
+    logging: bool,
+    http2: bool,
+    shutdown: Arc<AtomicBool>,
+}
+
+/// Stops an [HttpServer] from another thread, see [HttpServer::shutdown_handle]
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting connections, requests that are already being handled still get their
+    /// response before [HttpServer::serve] returns
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        // Wake up the accept loop, the connection itself is dropped straight away
+        let _ = TcpStream::connect_timeout(&self.addr, Duration::from_secs(1));
+    }
+}
+
+impl HttpServer {
+    /// Panics if `worker_count` is 0, just like [ThreadPool::new]
+    pub fn bind(addr: impl ToSocketAddrs, worker_count: usize) -> std::io::Result<Self> {
+        assert!(worker_count > 0);
+
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            worker_count,
+            keep_alive_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(30),
+            write_timeout: Some(Duration::from_secs(30)),
+            limits: HttpLimits::default(),
+            logging: true,
+            http2: false,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// How long an idle keep-alive connection is held open, defaults to 5 seconds
+    ///
+    /// Once part of a request has arrived the connection is no longer idle, see
+    /// [Self::set_request_timeout]
+    pub fn set_keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// How long a partly received request may go without any more of it arriving before it is
+    /// answered with a 408 and the connection is closed, defaults to 30 seconds
+    pub fn set_request_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Defaults to 30 seconds, `None` waits forever
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Requests over a limit are rejected with a 400, 413, 414 or 431 response, see
+    /// [HttpError::status_code]
+    pub fn set_limits(&mut self, limits: HttpLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Requests and errors are logged through [Logger] to stdout and stderr unless disabled
+    pub fn set_logging(&mut self, logging: bool) -> &mut Self {
+        self.logging = logging;
+        self
+    }
+
+    /// Whether connections can switch to HTTP/2 (h2c), either by starting with the HTTP/2
+    /// connection preface or by upgrading a request with `Upgrade: h2c`. Off by default
+    ///
+    /// The streams of an HTTP/2 connection are handled on threads of their own, so the worker
+    /// count limits connections but not requests in flight, see [crate::net::http2]
+    pub fn set_http2(&mut self, http2: bool) -> &mut Self {
+        self.http2 = http2;
+        self
+    }
+
+    pub fn shutdown_handle(&self) -> std::io::Result<ShutdownHandle> {
+        let mut addr = self.local_addr()?;
+
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+
+        Ok(ShutdownHandle {
+            addr,
+            shutdown: Arc::clone(&self.shutdown),
+        })
+    }
+
+    /// Accepts connections and answers their requests with `handler` until shut down through a
+    /// [ShutdownHandle]
+    ///
+    /// Returns once every request that was in flight at the time of the shutdown has been
+    /// answered
+    pub fn serve(self, handler: impl Handler) -> std::io::Result<()> {
+        let handler = Arc::new(handler);
+        let mut pool = ThreadPool::new(self.worker_count);
+
+        if self.logging {
+            Logger::info(
+                std::io::stdout(),
+                LOG_NAME,
+                &format!("Listening on {}", self.local_addr()?),
+            );
+        }
+
+        'accepting: loop {
+            let accepted = self.listener.accept();
+
+            if self.shutdown.load(Ordering::SeqCst) {
+                break 'accepting;
+            }
+
+            let (stream, peer) = match accepted {
+                Ok(val) => val,
+                Err(err) => {
+                    if self.logging {
+                        Logger::warn(
+                            std::io::stderr(),
+                            LOG_NAME,
+                            &format!("Failed to accept connection: {}", err),
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            let connection = Connection {
+                peer,
+                handler: Arc::clone(&handler),
+                keep_alive_timeout: self.keep_alive_timeout,
+                request_timeout: self.request_timeout,
+                write_timeout: self.write_timeout,
+                limits: self.limits,
+                logging: self.logging,
+                http2: self.http2,
+                shutdown: Arc::clone(&self.shutdown),
+            };
+
+            // A panic that makes it to the worker would poison the pool and leave the server
+            // accepting connections it can't serve
+            let job = move || {
+                let _ = std::panic::catch_unwind(AssertUnwindSafe(|| connection.run(stream)));
+            };
+
+            if let Err(err) = pool.execute(job) {
+                if self.logging {
+                    let reason = match err {
+                        ThreadPoolError::Poisoned => "a handler panicked",
+                        ThreadPoolError::JobSendFailure => "the workers are gone",
+                    };
+
+                    Logger::err(
+                        std::io::stderr(),
+                        LOG_NAME,
+                        &format!("Dropped connection from {}, {}", peer, reason),
+                    );
+                }
+            }
+        }
+
+        if self.logging {
+            Logger::info(std::io::stdout(), LOG_NAME, "Shutting down");
+        }
+
+        // Dropping the pool lets the workers finish the connections they were given first
+        drop(pool);
+
+        Ok(())
+    }
+}
+
+struct Connection<H: Handler> {
+    peer: SocketAddr,
+    handler: Arc<H>,
+    keep_alive_timeout: Duration,
+    request_timeout: Duration,
+    write_timeout: Option<Duration>,
+    limits: HttpLimits,
+    logging: bool,
+    http2: bool,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl<H: Handler> Connection<H> {
+    fn run(self, stream: TcpStream) {
+        if let Err(err) = self.serve(&stream) {
+            if self.logging {
+                Logger::warn(
+                    std::io::stderr(),
+                    LOG_NAME,
+                    &format!("Connection to {} failed: {}", self.peer, err),
+                );
+            }
+        }
+
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+    }
+
+    fn serve(&self, stream: &TcpStream) -> std::io::Result<()> {
+        let mut http =
+            HttpStream::with_timeouts(stream, Some(SHUTDOWN_POLL_INTERVAL), self.write_timeout)?;
+        http.set_limits(self.limits);
+
+        'serving: loop {
+            let idle_since = Instant::now();
+            let mut received = 0;
+            let mut last_received = idle_since;
+
+            let request = 'receiving: loop {
+                match http.recv_request() {
+                    Ok(mut request) => {
+                        request.set_peer_addr(Some(self.peer));
+                        break 'receiving request;
+                    }
+                    Err(HttpError::Timeout) => {
+                        // Partially received requests stay buffered in the stream, so timing
+                        // out here only gives a chance to notice a shutdown or a stalled peer
+                        if self.shutdown.load(Ordering::SeqCst) {
+                            break 'serving;
+                        }
+
+                        // The keep-alive timeout is for idle connections, a request that is
+                        // still arriving gets as long as it keeps making progress
+                        let partial = http.partial_message_len();
+                        if partial == 0 {
+                            if idle_since.elapsed() >= self.keep_alive_timeout {
+                                break 'serving;
+                            }
+                        } else if partial != received {
+                            received = partial;
+                            last_received = Instant::now();
+                        } else if last_received.elapsed() >= self.request_timeout {
+                            let mut response =
+                                HttpResponse::from_status(StatusCode::REQUEST_TIMEOUT);
+                            response.set_header("Connection".into(), "close".into());
+                            http.send_response(&response)?;
+                            break 'serving;
+                        }
+                    }
+                    Err(HttpError::ConnectionClosed | HttpError::IncompleteMessage) => {
+                        break 'serving;
+                    }
+                    Err(HttpError::Io(err)) => return Err(err),
+                    Err(err) => {
+                        http.send_response(&error_response(&err))?;
+
+                        return Err(err.into());
+                    }
+                }
+            };
+
+            if self.http2 && is_http2_preface(&request) {
+                // The preface's first line and the empty line after it were consumed as the head
+                // of a request, put them back in front of whatever arrived after them
+                let mut received = http2::PREFACE[..18].to_vec();
+                let (reader, writer, leftover) = http.into_upgraded()?;
+                received.extend_from_slice(&leftover);
+
+                return self.serve_http2(reader, writer, received, None);
+            }
+
+            if self.http2 && request.get_headers().contains_token("Upgrade", "h2c") {
+                if let Some(params) = http2::upgrade_settings(&request) {
+                    let response = HttpResponse::builder()
+                        .set_version(Version::Http11)
+                        .set_status_code(StatusCode::SWITCHING_PROTOCOLS)
+                        .set_header("Connection".into(), "Upgrade".into())
+                        .set_header("Upgrade".into(), "h2c".into())
+                        .set_body(Box::new([]))
+                        .build()
+                        .unwrap();
+                    http.send_response(&response)?;
+
+                    let (reader, writer, received) = http.into_upgraded()?;
+                    return self.serve_http2(reader, writer, received, Some((request, params)));
+                }
+            }
+
+            let keep_alive = request.is_keep_alive() && !self.shutdown.load(Ordering::SeqCst);
+
+            // A panicking handler gets a 500 and the connection closed, whatever state it left
+            // behind isn't trusted with the next request
+            let (mut response, keep_alive) = match std::panic::catch_unwind(AssertUnwindSafe(
+                || self.handler.handle(&request),
+            )) {
+                Ok(response) => (response, keep_alive),
+                Err(_) => {
+                    if self.logging {
+                        Logger::err(
+                            std::io::stderr(),
+                            LOG_NAME,
+                            &format!(
+                                "Handler for {} {} panicked",
+                                request.get_method(),
+                                request.get_url()
+                            ),
+                        );
+                    }
+
+                    (
+                        HttpResponse::from_status(StatusCode::INTERNAL_SERVER_ERROR),
+                        false,
+                    )
+                }
+            };
+            let keep_alive = prepare_response(&request, &mut response, keep_alive);
+
+            if self.logging {
+                Logger::info(
+                    std::io::stdout(),
+                    LOG_NAME,
+                    &format!(
+                        "{} \"{} {} {}\" {}",
+                        self.peer,
+                        request.get_method(),
+                        request.get_url(),
+                        request.get_version(),
+                        response.get_status_code()
+                    ),
+                );
+            }
+
+            http.send_response(&response)?;
+
+            if !keep_alive || !http.is_keep_alive() {
+                break 'serving;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn serve_http2(
+        &self,
+        reader: &TcpStream,
+        writer: &TcpStream,
+        received: Vec<u8>,
+        upgrade: Option<(HttpRequest, Vec<(u16, u32)>)>,
+    ) -> std::io::Result<()> {
+        let options = http2::ServerOptions {
+            peer: self.peer,
+            limits: self.limits,
+            keep_alive_timeout: self.keep_alive_timeout,
+            logging: self.logging,
+            shutdown: &self.shutdown,
+        };
+
+        http2::serve(reader, writer, received, upgrade, &*self.handler, &options)?;
+        Ok(())
+    }
+}
+
+/// Whether a request is really the first line of the HTTP/2 connection preface, which a client
+/// with prior knowledge starts with (RFC 9113 3.3)
+fn is_http2_preface(request: &HttpRequest) -> bool {
+    request.get_method() == &Method::Extension("PRI".into())
+        && request.get_url() == "*"
+        && request.get_version() == Version::Http2
+        && request.get_headers().is_empty()
+}
+
+/// The response to a request that could not be received, the connection is closed after it
+pub(crate) fn error_response(err: &HttpError) -> HttpResponse {
+    let status_code = err.status_code().unwrap_or(StatusCode::BAD_REQUEST);
+
+    let mut response = HttpResponse::from_status(status_code);
+    response.set_header("Connection".into(), "close".into());
+    response
+}
+
+/// Fills in the framing and connection headers and drops the body of responses to HEAD requests,
+/// returning whether the connection can still be kept alive
+///
+/// Streaming bodies of unknown length are chunked, except for HTTP/1.0 clients, where the end of
+/// the body has to be signalled by closing the connection
+pub(crate) fn prepare_response(
+    request: &HttpRequest,
+    response: &mut HttpResponse,
+    keep_alive: bool,
+) -> bool {
+    let headers = response.get_headers();
+    let status_code = response.get_status_code();
+    let has_body = !(status_code.is_informational()
+        || status_code == StatusCode::NO_CONTENT
+        || status_code == StatusCode::NOT_MODIFIED);
+
+    let mut keep_alive = keep_alive;
+
+    if has_body && !headers.contains("Content-Length") && !headers.contains("Transfer-Encoding") {
+        match response.get_body_length() {
+            Some(len) => response.set_header("Content-Length".into(), len.to_string()),
+            None if request.get_version() == Version::Http10 => {
+                response.set_version(Version::Http10);
+                keep_alive = false;
+            }
+            None => response.set_header("Transfer-Encoding".into(), "chunked".into()),
+        }
+    }
+
+    if !keep_alive {
+        response.set_header("Connection".into(), "close".into());
+    } else if request.get_version() == Version::Http10 {
+        response.set_header("Connection".into(), "keep-alive".into());
+    }
+
+    if request.get_method() == &Method::Head {
+        response.set_body(Box::new([]));
+        // The chunked encoding of an empty body would still add the last chunk
+        response.remove_header("Transfer-Encoding");
+    }
+
+    keep_alive
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn keep_alive_and_shutdown() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 2).unwrap();
+        server.set_logging(false);
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let server = std::thread::spawn(move || {
+            server.serve(|request: &HttpRequest| {
+                HttpResponse::builder()
+                    .set_version(Version::Http11)
+                    .set_status_code(StatusCode::OK)
+                    .set_body(request.get_url().as_bytes().into())
+                    .build()
+                    .unwrap()
+            })
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut http = HttpStream::new(&stream).unwrap();
+
+        for url in ["/first", "/second"] {
+            let request = HttpRequest::builder()
+                .set_method(Method::Get)
+                .set_url(url.into())
+                .set_version(Version::Http11)
+                .set_body(Box::new([]))
+                .build()
+                .unwrap();
+            http.send_request(&request).unwrap();
+
+            let response = http.recv_response().unwrap();
+            assert_eq!(response.get_status_code(), StatusCode::OK);
+            assert_eq!(response.get_body(), url.as_bytes());
+        }
+
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 1).unwrap();
+        server.set_logging(false).set_limits(HttpLimits {
+            max_header_count: 1,
+            ..HttpLimits::default()
+        });
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let server = std::thread::spawn(move || {
+            server.serve(|_: &HttpRequest| HttpResponse::from_status(StatusCode::OK))
+        });
+
+        let cases: [(&[u8], StatusCode); 3] = [
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nAccept: */*\r\n\r\n",
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 1, 2\r\n\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (b"GET / HTTP/1.1\r\n\r\n", StatusCode::OK),
+        ];
+
+        for (bytes, status_code) in cases {
+            let stream = TcpStream::connect(addr).unwrap();
+            (&stream).write_all(bytes).unwrap();
+
+            let mut http = HttpStream::new(&stream).unwrap();
+            let response = http.recv_response().unwrap();
+            assert_eq!(response.get_status_code(), status_code);
+        }
+
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn slow_and_stalled_requests() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 2).unwrap();
+        server
+            .set_logging(false)
+            .set_keep_alive_timeout(Duration::from_millis(200))
+            .set_request_timeout(Duration::from_millis(600));
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let server = std::thread::spawn(move || {
+            server.serve(|request: &HttpRequest| {
+                let mut response = HttpResponse::from_status(StatusCode::OK);
+                response.set_body(request.get_body().into());
+                response
+            })
+        });
+
+        // Pauses longer than the keep-alive timeout don't cut off a request that keeps arriving
+        let stream = TcpStream::connect(addr).unwrap();
+        (&stream)
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\na")
+            .unwrap();
+        for part in [b"b", b"c"] {
+            std::thread::sleep(Duration::from_millis(400));
+            (&stream).write_all(part).unwrap();
+        }
+
+        let mut http = HttpStream::new(&stream).unwrap();
+        let response = http.recv_response().unwrap();
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert_eq!(response.get_body(), b"abc");
+
+        // One that stops arriving gets a 408
+        let stream = TcpStream::connect(addr).unwrap();
+        (&stream).write_all(b"GET / HTTP/1.1\r\nHost: a").unwrap();
+
+        let mut http = HttpStream::new(&stream).unwrap();
+        let response = http.recv_response().unwrap();
+        assert_eq!(response.get_status_code(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(response.get_header("Connection"), Some("close"));
+
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn panicking_handlers_do_not_stop_the_server() {
+        let mut server = HttpServer::bind("127.0.0.1:0", 1).unwrap();
+        server.set_logging(false);
+
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+
+        let server = std::thread::spawn(move || {
+            server.serve(|request: &HttpRequest| {
+                if request.get_url() == "/panic" {
+                    panic!("Intentional");
+                }
+                HttpResponse::from_status(StatusCode::OK)
+            })
+        });
+
+        for (url, status_code) in [
+            ("/panic", StatusCode::INTERNAL_SERVER_ERROR),
+            ("/panic", StatusCode::INTERNAL_SERVER_ERROR),
+            ("/", StatusCode::OK),
+        ] {
+            let stream = TcpStream::connect(addr).unwrap();
+            (&stream)
+                .write_all(format!("GET {} HTTP/1.1\r\n\r\n", url).as_bytes())
+                .unwrap();
+
+            let mut http = HttpStream::new(&stream).unwrap();
+            let response = http.recv_response().unwrap();
+            assert_eq!(response.get_status_code(), status_code);
+            if status_code != StatusCode::OK {
+                assert_eq!(response.get_header("Connection"), Some("close"));
+            }
+        }
+
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+    }
+}
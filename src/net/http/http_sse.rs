@@ -0,0 +1,488 @@
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use super::{HttpError, HttpReceiver, HttpResponse, HttpTransmitter, Method};
+
+/// A single Server-Sent Event
+///
+/// `data` may span several lines. Line breaks in `id` and `event` can't be sent and are dropped
+/// when writing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    pub id: Option<String>,
+    /// The event type, clients treat events without one as `message`
+    pub event: Option<String>,
+    pub data: String,
+    /// Asks the client to wait this long before reconnecting
+    pub retry: Option<Duration>,
+}
+
+impl Event {
+    pub fn new(data: &str) -> Self {
+        Self {
+            data: data.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn set_id(&mut self, id: &str) -> &mut Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn set_event(&mut self, event: &str) -> &mut Self {
+        self.event = Some(event.to_string());
+        self
+    }
+
+    pub fn set_retry(&mut self, retry: Duration) -> &mut Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// The event in the `text/event-stream` format, including the blank line that ends it
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+
+        if let Some(event) = &self.event {
+            text.push_str(&format!("event: {}\n", single_line(event)));
+        }
+
+        if let Some(id) = &self.id {
+            text.push_str(&format!("id: {}\n", single_line(id)));
+        }
+
+        if let Some(retry) = self.retry {
+            text.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+
+        for line in self
+            .data
+            .split("\r\n")
+            .flat_map(|line| line.split(['\r', '\n']))
+        {
+            text.push_str(&format!("data: {}\n", line));
+        }
+
+        text.push('\n');
+        text
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !matches!(c, '\r' | '\n'))
+        .collect()
+}
+
+/// Streams Server-Sent Events as the body of a response
+///
+/// The writer takes over an [HttpTransmitter], sends the response head and then sends every
+/// event as a chunk of the body. Idle streams are kept open with comments, see
+/// [EventWriter::keep_alive_if_idle]
+///
+/// A client that goes away is only noticed once a write fails, after which
+/// [EventWriter::is_disconnected] returns true and all further sends fail. Since a TCP write
+/// may still succeed right after the peer closed, the keep-alive comments are also what
+/// eventually uncovers a disconnected client
+pub struct EventWriter<W: Write> {
+    tx: HttpTransmitter<W>,
+    keep_alive_interval: Duration,
+    last_write: Instant,
+    disconnected: bool,
+}
+
+impl<W: Write> EventWriter<W> {
+    /// Sends the head of `response`, adding `Content-Type: text/event-stream` and
+    /// `Cache-Control: no-cache` unless it sets them itself. The response's body, if any, is sent
+    /// before the first event
+    pub fn new(mut tx: HttpTransmitter<W>, response: &HttpResponse) -> std::io::Result<Self> {
+        let mut response = response.clone();
+        let headers = response.get_headers_mut();
+
+        if headers.get("Content-Type").is_none() {
+            headers.insert("Content-Type", "text/event-stream");
+        }
+
+        if headers.get("Cache-Control").is_none() {
+            headers.insert("Cache-Control", "no-cache");
+        }
+
+        tx.send_response_chunked(&response)?;
+
+        Ok(Self {
+            tx,
+            keep_alive_interval: Duration::from_secs(15),
+            last_write: Instant::now(),
+            disconnected: false,
+        })
+    }
+
+    /// How long the stream may be idle before [Self::keep_alive_if_idle] sends a comment,
+    /// defaults to 15 seconds
+    pub fn set_keep_alive_interval(&mut self, interval: Duration) -> &mut Self {
+        self.keep_alive_interval = interval;
+        self
+    }
+
+    pub fn send(&mut self, event: &Event) -> std::io::Result<()> {
+        self.write(event.encode().as_bytes())
+    }
+
+    /// Sends a `message` event without an id
+    pub fn send_data(&mut self, data: &str) -> std::io::Result<()> {
+        self.send(&Event::new(data))
+    }
+
+    /// Sends a comment, which clients ignore
+    pub fn send_comment(&mut self, comment: &str) -> std::io::Result<()> {
+        let mut text = String::new();
+        for line in comment
+            .split("\r\n")
+            .flat_map(|line| line.split(['\r', '\n']))
+        {
+            text.push_str(&format!(": {}\n", line));
+        }
+
+        self.write(text.as_bytes())
+    }
+
+    /// Sends an empty comment if nothing was sent for the keep-alive interval, returns whether
+    /// one was sent
+    pub fn keep_alive_if_idle(&mut self) -> std::io::Result<bool> {
+        if !self.time_until_keep_alive().is_zero() {
+            return Ok(false);
+        }
+
+        self.write(b":\n")?;
+        Ok(true)
+    }
+
+    /// How long until the next keep-alive comment is due, meant as the timeout for waiting on the
+    /// next event to send
+    pub fn time_until_keep_alive(&self) -> Duration {
+        self.keep_alive_interval
+            .saturating_sub(self.last_write.elapsed())
+    }
+
+    /// Whether a write failed because the client is gone
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    /// Ends the response, after which the transmitter can be used for the next one
+    pub fn finish(mut self) -> std::io::Result<HttpTransmitter<W>> {
+        self.tx.chunked_writer().finish()?;
+        Ok(self.tx)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.disconnected {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+
+        let written = self.tx.chunked_writer().send_chunk(bytes);
+        match &written {
+            Ok(()) => self.last_write = Instant::now(),
+            // The rest is queued on a non-blocking stream
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                self.last_write = Instant::now()
+            }
+            Err(_) => self.disconnected = true,
+        }
+
+        written
+    }
+}
+
+/// Parses a `text/event-stream` as it arrives, independent of where the bytes come from
+///
+/// Follows the HTML Living Standard's event stream interpretation: lines end in CRLF, LF or CR,
+/// lines starting with a colon are comments, unknown fields are ignored and events without data
+/// are never dispatched
+#[derive(Debug, Default)]
+pub struct EventParser {
+    buf: Vec<u8>,
+    started: bool,
+    data: Option<String>,
+    event: Option<String>,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl EventParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The next complete event from what was pushed so far. Set `eof` once the stream has ended
+    /// so a final CR can be taken as a line break, an event that was never ended by a blank line
+    /// is dropped as the standard asks
+    pub fn next_event(&mut self, eof: bool) -> Option<Event> {
+        if !self.started {
+            if self.buf.len() < 3 && b"\xEF\xBB\xBF".starts_with(&self.buf) && !eof {
+                return None;
+            }
+
+            if self.buf.starts_with(b"\xEF\xBB\xBF") {
+                self.buf.drain(..3);
+            }
+            self.started = true;
+        }
+
+        while let Some(line) = self.take_line(eof) {
+            if line.is_empty() {
+                if let Some(event) = self.dispatch() {
+                    return Some(event);
+                }
+                continue;
+            }
+
+            self.process_line(&line);
+        }
+
+        None
+    }
+
+    /// The id of the last event, which is sent back in the `Last-Event-ID` header on reconnect
+    pub fn get_last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// The reconnection time last asked for by the server
+    pub fn get_retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    fn take_line(&mut self, eof: bool) -> Option<String> {
+        let end = self.buf.iter().position(|&b| b == b'\r' || b == b'\n')?;
+
+        let len = match (self.buf[end], self.buf.get(end + 1)) {
+            (b'\r', Some(b'\n')) => 2,
+            // The LF may still be on its way
+            (b'\r', None) if !eof => return None,
+            _ => 1,
+        };
+
+        let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+        self.buf.drain(..end + len);
+
+        Some(line)
+    }
+
+    fn process_line(&mut self, line: &str) {
+        let (field, value) = match line.split_once(':') {
+            Some(("", _)) => return,
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "data" => {
+                let data = self.data.get_or_insert_with(String::new);
+                data.push_str(value);
+                data.push('\n');
+            }
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take().filter(|event| !event.is_empty());
+        let mut data = self.data.take()?;
+        data.pop();
+
+        Some(Event {
+            id: self.last_event_id.clone().filter(|id| !id.is_empty()),
+            event,
+            data,
+            retry: None,
+        })
+    }
+}
+
+/// Receives Server-Sent Events from the body of a response
+pub struct EventReader<R: Read> {
+    rx: HttpReceiver<R>,
+    parser: EventParser,
+    eof: bool,
+}
+
+impl<R: Read> EventReader<R> {
+    /// Receives the head of the response to a GET request that was already sent, events are then
+    /// read from its body
+    ///
+    /// The response is returned as is, checking its status and Content-Type is up to the caller
+    pub fn open(mut rx: HttpReceiver<R>) -> Result<(HttpResponse, Self), HttpError> {
+        let (response, _) = rx.recv_response_streaming(&Method::Get)?;
+
+        let reader = Self {
+            rx,
+            parser: EventParser::new(),
+            eof: false,
+        };
+
+        Ok((response, reader))
+    }
+
+    /// Waits for the next event, `None` once the stream has ended
+    ///
+    /// A read timeout returns [HttpError::Timeout] and keeps whatever was received so far
+    pub fn next_event(&mut self) -> Result<Option<Event>, HttpError> {
+        let mut buffer = [0_u8; 4096];
+
+        loop {
+            if let Some(event) = self.parser.next_event(self.eof) {
+                return Ok(Some(event));
+            }
+
+            if self.eof {
+                return Ok(None);
+            }
+
+            match self.rx.read_body(&mut buffer)? {
+                0 => self.eof = true,
+                bytes_read => self.parser.push(&buffer[..bytes_read]),
+            }
+        }
+    }
+
+    pub fn get_last_event_id(&self) -> Option<&str> {
+        self.parser.get_last_event_id()
+    }
+
+    pub fn get_retry(&self) -> Option<Duration> {
+        self.parser.get_retry()
+    }
+
+    /// Gives the receiver back, any part of the body that wasn't read is skipped by the next
+    /// receive
+    pub fn into_inner(self) -> HttpReceiver<R> {
+        self.rx
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::IntoSplit;
+    use crate::net::http::{HttpStream, StatusCode, Version};
+
+    #[test]
+    fn parse_event_stream() {
+        let mut parser = EventParser::new();
+        parser.push(b"\xEF\xBB\xBF: comment\r\nretry: 3000\ndata: one\ndata:two\r");
+
+        assert_eq!(parser.next_event(false), None);
+        assert_eq!(parser.get_retry(), Some(Duration::from_secs(3)));
+
+        parser.push(b"\n\revent: update\nid: 7\ndata\n\nid\nevent: ignored\n\ndata: tail");
+
+        let event = parser.next_event(false).unwrap();
+        assert_eq!(event.data, "one\ntwo");
+        assert_eq!((event.id, event.event), (None, None));
+
+        let event = parser.next_event(false).unwrap();
+        assert_eq!(event.data, "");
+        assert_eq!(event.id.as_deref(), Some("7"));
+        assert_eq!(event.event.as_deref(), Some("update"));
+
+        // An empty id resets the last event id, an event without data isn't dispatched
+        assert_eq!(parser.next_event(true), None);
+        assert_eq!(parser.get_last_event_id(), Some(""));
+    }
+
+    #[test]
+    fn write_and_read_events() {
+        let mut output = Vec::new();
+
+        let (_, tx) = HttpStream::from_parts(&[][..], &mut output).into_split();
+        let response = HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::OK)
+            .set_body(Box::new([]))
+            .build()
+            .unwrap();
+
+        let mut writer = EventWriter::new(tx, &response).unwrap();
+        writer
+            .send(
+                Event::new("first\nsecond")
+                    .set_id("1")
+                    .set_event("greeting")
+                    .set_retry(Duration::from_millis(500)),
+            )
+            .unwrap();
+        writer.send_comment("still here").unwrap();
+        writer.set_keep_alive_interval(Duration::ZERO);
+        assert!(writer.keep_alive_if_idle().unwrap());
+        writer.send_data("last").unwrap();
+        writer.finish().unwrap();
+
+        let (rx, _) = HttpStream::from_parts(&output[..], std::io::sink()).into_split();
+        let (response, mut reader) = EventReader::open(rx).unwrap();
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("text/event-stream")
+        );
+
+        let event = reader.next_event().unwrap().unwrap();
+        assert_eq!(event.data, "first\nsecond");
+        assert_eq!(event.id.as_deref(), Some("1"));
+        assert_eq!(event.event.as_deref(), Some("greeting"));
+        assert_eq!(reader.get_retry(), Some(Duration::from_millis(500)));
+
+        let event = reader.next_event().unwrap().unwrap();
+        assert_eq!(event.data, "last");
+        assert_eq!(event.id.as_deref(), Some("1"));
+
+        assert_eq!(reader.next_event().unwrap(), None);
+    }
+
+    #[test]
+    fn failed_writes_mark_the_client_disconnected() {
+        struct Client(std::rc::Rc<std::cell::Cell<bool>>);
+
+        impl Write for Client {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                match self.0.get() {
+                    true => Err(std::io::ErrorKind::BrokenPipe.into()),
+                    false => Ok(buf.len()),
+                }
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let gone = std::rc::Rc::new(std::cell::Cell::new(false));
+        let (_, tx) = HttpStream::from_parts(&[][..], Client(gone.clone())).into_split();
+        let response = HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::OK)
+            .set_body(Box::new([]))
+            .build()
+            .unwrap();
+
+        let mut writer = EventWriter::new(tx, &response).unwrap();
+        writer.send_data("hello").unwrap();
+        assert!(!writer.is_disconnected());
+
+        gone.set(true);
+        assert!(writer.send_data("anyone?").is_err());
+        assert!(writer.is_disconnected());
+    }
+}
@@ -0,0 +1,779 @@
+use std::fs::{File, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{format_http_date, parse_http_date};
+use super::{Handler, HttpRequest, HttpResponse, Method, StatusCode, Version};
+use crate::net::url::{decode, encode, Url};
+
+/// Requests for more ranges than this are answered with the whole file
+const MAX_RANGES: usize = 32;
+
+/// Serves the files below a directory
+///
+/// Only GET and HEAD are allowed. Request paths are percent-decoded and resolved inside the root
+/// directory, paths that would leave it, including through symbolic links, get a 404 just like
+/// files that don't exist. So do hidden files, whose name starts with a dot, unless they are
+/// allowed with [StaticFiles::set_serve_hidden]
+///
+/// Responses carry `Last-Modified` and `ETag`, conditional requests are answered with a 304 and
+/// `Range` requests with a 206 for a single range or a `multipart/byteranges` body for several
+///
+/// Requests for a directory are redirected to the path with a trailing slash, then answered with
+/// its index file or, if enabled, a listing of its entries
+pub struct StaticFiles {
+    root: PathBuf,
+    prefix: String,
+    index_file: Option<String>,
+    directory_listing: bool,
+    serve_hidden: bool,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            prefix: String::new(),
+            index_file: Some("index.html".into()),
+            directory_listing: false,
+            serve_hidden: false,
+        }
+    }
+
+    /// Strips `prefix` from request paths, so `/static/app.js` maps to `app.js` in the root with
+    /// a prefix of `/static`. Requests outside of the prefix get a 404
+    pub fn set_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The file served for a directory, defaults to `index.html`
+    pub fn set_index_file(mut self, index_file: Option<&str>) -> Self {
+        self.index_file = index_file.map(str::to_string);
+        self
+    }
+
+    /// Whether directories without an index file are answered with a listing, off by default
+    pub fn set_directory_listing(mut self, directory_listing: bool) -> Self {
+        self.directory_listing = directory_listing;
+        self
+    }
+
+    pub fn set_serve_hidden(mut self, serve_hidden: bool) -> Self {
+        self.serve_hidden = serve_hidden;
+        self
+    }
+
+    /// Serves `path`, relative to the root and already percent-decoded, such as the rest of the
+    /// path captured by a [super::Router] wildcard. The prefix isn't applied
+    pub fn serve(&self, request: &HttpRequest, path: &str) -> HttpResponse {
+        if !matches!(request.get_method(), Method::Get | Method::Head) {
+            let mut response = HttpResponse::from_status(StatusCode::METHOD_NOT_ALLOWED);
+            response.set_header("Allow".into(), "GET, HEAD".into());
+            return response;
+        }
+
+        let Some(segments) = self.segments(path) else {
+            return HttpResponse::from_status(StatusCode::NOT_FOUND);
+        };
+
+        let Some(file_path) = self.resolve(&segments) else {
+            return HttpResponse::from_status(StatusCode::NOT_FOUND);
+        };
+
+        let Ok(metadata) = std::fs::metadata(&file_path) else {
+            return HttpResponse::from_status(StatusCode::NOT_FOUND);
+        };
+
+        if !metadata.is_dir() {
+            return match path.ends_with('/') {
+                true => HttpResponse::from_status(StatusCode::NOT_FOUND),
+                false => serve_file(request, &file_path, &metadata),
+            };
+        }
+
+        if !path.is_empty() && !path.ends_with('/') {
+            let location = match Url::parse(request.get_url()) {
+                Ok(url) => {
+                    let mut location = format!("{}/", url.path());
+                    if let Some(query) = url.query() {
+                        location.push_str(&format!("?{}", query));
+                    }
+                    location
+                }
+                Err(_) => return HttpResponse::from_status(StatusCode::BAD_REQUEST),
+            };
+
+            let mut response = HttpResponse::from_status(StatusCode::MOVED_PERMANENTLY);
+            response.set_header("Location".into(), location);
+            return response;
+        }
+
+        if let Some(index_file) = &self.index_file {
+            let index_path = file_path.join(index_file);
+            if let Ok(metadata) = std::fs::metadata(&index_path) {
+                if metadata.is_file() {
+                    return serve_file(request, &index_path, &metadata);
+                }
+            }
+        }
+
+        if !self.directory_listing {
+            return HttpResponse::from_status(StatusCode::NOT_FOUND);
+        }
+
+        match self.listing(&file_path, &segments) {
+            Ok(response) => response,
+            Err(_) => HttpResponse::from_status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    /// Splits `path` into its segments, `None` if any of them is not allowed
+    fn segments(&self, path: &str) -> Option<Vec<String>> {
+        let mut segments = Vec::new();
+
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            // Dot segments and other separators would step outside of the path as requested
+            if segment == "."
+                || segment == ".."
+                || segment.contains(['\\', '\0'])
+                || (segment.starts_with('.') && !self.serve_hidden)
+            {
+                return None;
+            }
+
+            segments.push(segment.to_string());
+        }
+
+        Some(segments)
+    }
+
+    /// Joins `segments` onto the root, `None` if the result isn't inside the root once symbolic
+    /// links are resolved
+    fn resolve(&self, segments: &[String]) -> Option<PathBuf> {
+        let root = self.root.canonicalize().ok()?;
+        let path = segments
+            .iter()
+            .fold(root.clone(), |path, segment| path.join(segment))
+            .canonicalize()
+            .ok()?;
+
+        path.starts_with(&root).then_some(path)
+    }
+
+    fn listing(&self, dir: &Path, segments: &[String]) -> std::io::Result<HttpResponse> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if name.starts_with('.') && !self.serve_hidden {
+                continue;
+            }
+
+            let is_dir = entry.metadata().is_ok_and(|metadata| metadata.is_dir());
+            entries.push((!is_dir, name));
+        }
+        entries.sort();
+
+        let title = escape_html(&format!("/{}", segments.join("/")));
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n<body>\n<h1>Index of {title}</h1>\n<ul>\n"
+        );
+
+        if !segments.is_empty() {
+            html.push_str("<li><a href=\"../\">../</a></li>\n");
+        }
+
+        for (is_file, name) in entries {
+            let slash = if is_file { "" } else { "/" };
+            html.push_str(&format!(
+                "<li><a href=\"{}{}\">{}{}</a></li>\n",
+                encode(&name),
+                slash,
+                escape_html(&name),
+                slash
+            ));
+        }
+
+        html.push_str("</ul>\n</body>\n</html>\n");
+
+        Ok(HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::OK)
+            .set_header("Content-Type".into(), "text/html; charset=utf-8".into())
+            .set_body(html.into_bytes().into_boxed_slice())
+            .build()
+            .unwrap())
+    }
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let Ok(url) = Url::parse(request.get_url()) else {
+            return HttpResponse::from_status(StatusCode::BAD_REQUEST);
+        };
+
+        // The root of a prefix is a directory like any other
+        if !self.prefix.is_empty() && url.path() == self.prefix {
+            let mut response = HttpResponse::from_status(StatusCode::MOVED_PERMANENTLY);
+            response.set_header("Location".into(), format!("{}/", self.prefix));
+            return response;
+        }
+
+        let Some(path) = url
+            .path()
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|path| path.strip_prefix('/'))
+        else {
+            return HttpResponse::from_status(StatusCode::NOT_FOUND);
+        };
+
+        // Decoded segment by segment, an encoded separator doesn't separate anything
+        let segments: Option<Vec<_>> = path
+            .split('/')
+            .map(|segment| {
+                decode(segment)
+                    .ok()
+                    .filter(|segment| !segment.contains('/'))
+            })
+            .collect();
+
+        match segments {
+            Some(segments) => self.serve(request, &segments.join("/")),
+            None => HttpResponse::from_status(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+/// Guesses the MIME type of a file from its extension, `application/octet-stream` if unknown.
+/// Text types are assumed to be UTF-8
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" | "log" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+fn serve_file(request: &HttpRequest, path: &Path, metadata: &Metadata) -> HttpResponse {
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+    let etag = entity_tag(len, modified);
+
+    let mut response = match conditional(request, &etag, modified) {
+        Some(StatusCode::NOT_MODIFIED) => not_modified(),
+        Some(status_code) => HttpResponse::from_status(status_code),
+        None => match file_response(request, path, len, &etag, modified) {
+            Ok(response) => response,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return HttpResponse::from_status(StatusCode::NOT_FOUND);
+            }
+            Err(_) => return HttpResponse::from_status(StatusCode::INTERNAL_SERVER_ERROR),
+        },
+    };
+
+    response.set_header("ETag".into(), etag);
+    if let Some(modified) = modified {
+        response.set_header("Last-Modified".into(), format_http_date(modified));
+    }
+
+    response
+}
+
+/// A strong validator made from the size and modification time of the file
+fn entity_tag(len: u64, modified: Option<SystemTime>) -> String {
+    let modified = modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    format!(
+        "\"{:x}-{:x}-{:x}\"",
+        len,
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )
+}
+
+/// The status code to answer a conditional request with instead of the file, following the
+/// order of evaluation in RFC 9110 13.2.2
+fn conditional(
+    request: &HttpRequest,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> Option<StatusCode> {
+    let modified_secs = modified.map(truncate_to_secs);
+
+    if let Some(if_match) = request.get_header("If-Match") {
+        if !matches_any(if_match, etag, false) {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    } else if let Some(since) = request
+        .get_header("If-Unmodified-Since")
+        .and_then(parse_http_date)
+    {
+        if modified_secs.is_some_and(|modified| modified > since) {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+
+    if let Some(if_none_match) = request.get_header("If-None-Match") {
+        if matches_any(if_none_match, etag, true) {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+    } else if let Some(since) = request
+        .get_header("If-Modified-Since")
+        .and_then(parse_http_date)
+    {
+        if modified_secs.is_some_and(|modified| modified <= since) {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+    }
+
+    None
+}
+
+/// Whether a list of entity tags such as `"a", W/"b"` or `*` contains `etag`, weak tags only
+/// match in a weak comparison
+fn matches_any(list: &str, etag: &str, weak: bool) -> bool {
+    list.split(',').map(str::trim).any(|candidate| {
+        if candidate == "*" {
+            return true;
+        }
+
+        match candidate.strip_prefix("W/") {
+            Some(candidate) => weak && candidate == etag,
+            None => candidate == etag,
+        }
+    })
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    UNIX_EPOCH + std::time::Duration::from_secs(secs)
+}
+
+fn file_response(
+    request: &HttpRequest,
+    path: &Path,
+    len: u64,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> std::io::Result<HttpResponse> {
+    let content_type = mime_type(path);
+
+    let ranges = request
+        .get_header("Range")
+        .filter(|_| if_range_matches(request, etag, modified))
+        .and_then(|range| parse_range(range, len));
+
+    let ranges = match ranges {
+        None => {
+            let file = File::open(path)?;
+
+            let mut response = HttpResponse::builder()
+                .set_version(Version::Http11)
+                .set_status_code(StatusCode::OK)
+                .set_header("Content-Type".into(), content_type.into())
+                .set_header("Accept-Ranges".into(), "bytes".into())
+                .set_body_reader(file, Some(len))
+                .build()
+                .unwrap();
+
+            response.set_header("Content-Length".into(), len.to_string());
+            return Ok(response);
+        }
+        Some(ranges) if ranges.is_empty() => {
+            let mut response = HttpResponse::from_status(StatusCode::RANGE_NOT_SATISFIABLE);
+            response.set_header("Content-Range".into(), format!("bytes */{}", len));
+            return Ok(response);
+        }
+        Some(ranges) => ranges,
+    };
+
+    let mut builder = HttpResponse::builder();
+    builder
+        .set_version(Version::Http11)
+        .set_status_code(StatusCode::PARTIAL_CONTENT)
+        .set_header("Accept-Ranges".into(), "bytes".into());
+
+    if let [(start, end)] = ranges[..] {
+        let body_len = end - start + 1;
+
+        builder
+            .set_header("Content-Type".into(), content_type.into())
+            .set_header(
+                "Content-Range".into(),
+                format!("bytes {}-{}/{}", start, end, len),
+            )
+            .set_header("Content-Length".into(), body_len.to_string())
+            .set_body_reader(file_range(path, start, body_len)?, Some(body_len));
+
+        return Ok(builder.build().unwrap());
+    }
+
+    let boundary: String = crate::util::random_bytes::<12>()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let mut body: Box<dyn Read + Send> = Box::new(std::io::empty());
+    let mut body_len = 0;
+
+    for (start, end) in ranges {
+        let part_head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary, content_type, start, end, len
+        );
+        let part_len = end - start + 1;
+
+        body_len += part_head.len() as u64 + part_len;
+        body = Box::new(
+            body.chain(std::io::Cursor::new(part_head))
+                .chain(file_range(path, start, part_len)?),
+        );
+    }
+
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    body_len += tail.len() as u64;
+    body = Box::new(body.chain(std::io::Cursor::new(tail)));
+
+    builder
+        .set_header(
+            "Content-Type".into(),
+            format!("multipart/byteranges; boundary={}", boundary),
+        )
+        .set_header("Content-Length".into(), body_len.to_string())
+        .set_body_reader(body, Some(body_len));
+
+    Ok(builder.build().unwrap())
+}
+
+fn file_range(path: &Path, start: u64, len: u64) -> std::io::Result<impl Read + Send> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+
+    Ok(file.take(len))
+}
+
+/// A Range is only honoured if an If-Range validator still matches the file, otherwise the whole
+/// file is sent (RFC 9110 13.1.5)
+fn if_range_matches(request: &HttpRequest, etag: &str, modified: Option<SystemTime>) -> bool {
+    let Some(if_range) = request.get_header("If-Range") else {
+        return true;
+    };
+
+    if if_range.starts_with('"') {
+        return if_range == etag;
+    }
+
+    match (parse_http_date(if_range), modified) {
+        (Some(date), Some(modified)) => truncate_to_secs(modified) == date,
+        _ => false,
+    }
+}
+
+/// Parses a `bytes` Range header into inclusive ranges within a file of `len` bytes
+///
+/// `None` means the header should be ignored because it is malformed, uses another unit or asks
+/// for too many ranges. An empty list means none of the ranges can be satisfied
+fn parse_range(range: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+    let (unit, specs) = range.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+
+    for spec in specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+    {
+        let (first, last) = spec.split_once('-')?;
+
+        let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+        let range = match (first, last) {
+            ("", suffix) if is_digits(suffix) => {
+                let suffix: u64 = suffix.parse().ok()?;
+                (suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len - 1))
+            }
+            (first, last) if is_digits(first) && (last.is_empty() || is_digits(last)) => {
+                let first: u64 = first.parse().ok()?;
+                let last = match last {
+                    "" => u64::MAX,
+                    last => last.parse().ok()?,
+                };
+
+                if last < first {
+                    return None;
+                }
+
+                (first < len).then(|| (first, last.min(len - 1)))
+            }
+            _ => return None,
+        };
+
+        ranges.extend(range);
+    }
+
+    if ranges.len() > MAX_RANGES {
+        return None;
+    }
+
+    Some(ranges)
+}
+
+fn not_modified() -> HttpResponse {
+    HttpResponse::builder()
+        .set_version(Version::Http11)
+        .set_status_code(StatusCode::NOT_MODIFIED)
+        .set_body(Box::new([]))
+        .build()
+        .unwrap()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::Router;
+
+    fn request(url: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut builder = HttpRequest::builder();
+        builder
+            .set_method(Method::Get)
+            .set_url(url.into())
+            .set_version(Version::Http11)
+            .set_body(Box::new([]));
+
+        for (key, val) in headers {
+            builder.set_header(key.to_string(), val.to_string());
+        }
+
+        builder.build().unwrap()
+    }
+
+    fn body(mut response: HttpResponse) -> Vec<u8> {
+        response.take_body().into_bytes().unwrap().into_vec()
+    }
+
+    /// A fresh directory with an index, a text file, a hidden file and a subdirectory
+    fn root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("fp_lib_static_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("index.html"), "<h1>hi</h1>").unwrap();
+        std::fs::write(root.join("docs/a b.txt"), "0123456789").unwrap();
+        std::fs::write(root.join(".secret"), "hidden").unwrap();
+
+        root
+    }
+
+    #[test]
+    fn files_directories_and_traversal() {
+        let root = root("files");
+        let files = StaticFiles::new(&root)
+            .set_prefix("/static")
+            .set_directory_listing(true);
+
+        let response = files.handle(&request("/static/", &[]));
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(body(response), b"<h1>hi</h1>");
+
+        let response = files.handle(&request("/static/docs/a%20b.txt", &[]));
+        assert_eq!(response.get_header("Content-Length"), Some("10"));
+        assert!(response.get_header("ETag").is_some());
+        assert!(response.get_header("Last-Modified").is_some());
+        assert_eq!(body(response), b"0123456789");
+
+        let response = files.handle(&request("/static/docs?x=1", &[]));
+        assert_eq!(response.get_status_code(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.get_header("Location"), Some("/static/docs/?x=1"));
+
+        let response = files.handle(&request("/static/docs/", &[]));
+        let listing = String::from_utf8(body(response)).unwrap();
+        assert!(listing.contains("<a href=\"a%20b.txt\">a b.txt</a>"));
+
+        for url in [
+            "/static/../Cargo.toml",
+            "/static/docs/%2e%2e/index.html",
+            "/static/docs%2f..%2findex.html",
+            "/static/.secret",
+            "/static/missing",
+            "/static/docs/a%20b.txt/",
+            "/other/index.html",
+        ] {
+            let response = files.handle(&request(url, &[]));
+            assert_eq!(response.get_status_code(), StatusCode::NOT_FOUND, "{}", url);
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn served_through_a_router() {
+        let root = root("router");
+        std::fs::write(root.join("100%.txt"), "percent").unwrap();
+        std::fs::write(root.join("a%20b.txt"), "literal").unwrap();
+
+        let files = StaticFiles::new(&root);
+        let mut router = Router::new();
+        router.get("/static/*rest", move |request, params| {
+            files.serve(request, params.get("rest").unwrap())
+        });
+
+        for (url, expected) in [
+            ("/static/100%25.txt", &b"percent"[..]),
+            ("/static/a%2520b.txt", b"literal"),
+            ("/static/docs/a%20b.txt", b"0123456789"),
+        ] {
+            let response = router.handle(&request(url, &[]));
+            assert_eq!(response.get_status_code(), StatusCode::OK, "{}", url);
+            assert_eq!(body(response), expected);
+        }
+
+        for url in [
+            "/static/docs/%2e%2e/%2e%2e/Cargo.toml",
+            "/static/docs%2Fa%20b.txt",
+            "/static/.secret",
+        ] {
+            let response = router.handle(&request(url, &[]));
+            assert_eq!(response.get_status_code(), StatusCode::NOT_FOUND, "{}", url);
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn conditional_and_range_requests() {
+        let root = root("ranges");
+        let files = StaticFiles::new(&root);
+
+        let response = files.handle(&request("/docs/a%20b.txt", &[]));
+        let etag = response.get_header("ETag").unwrap().to_string();
+        let modified = response.get_header("Last-Modified").unwrap().to_string();
+
+        let response = files.handle(&request("/docs/a%20b.txt", &[("If-None-Match", &etag)]));
+        assert_eq!(response.get_status_code(), StatusCode::NOT_MODIFIED);
+        assert!(body(response).is_empty());
+
+        let response = files.handle(&request(
+            "/docs/a%20b.txt",
+            &[("If-Modified-Since", &modified)],
+        ));
+        assert_eq!(response.get_status_code(), StatusCode::NOT_MODIFIED);
+
+        let response = files.handle(&request("/docs/a%20b.txt", &[("Range", "bytes=2-4")]));
+        assert_eq!(response.get_status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.get_header("Content-Range"), Some("bytes 2-4/10"));
+        assert_eq!(body(response), b"234");
+
+        let response = files.handle(&request(
+            "/docs/a%20b.txt",
+            &[("Range", "bytes=-3"), ("If-Range", "\"stale\"")],
+        ));
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+
+        let response = files.handle(&request("/docs/a%20b.txt", &[("Range", "bytes=20-")]));
+        assert_eq!(
+            response.get_status_code(),
+            StatusCode::RANGE_NOT_SATISFIABLE
+        );
+        assert_eq!(response.get_header("Content-Range"), Some("bytes */10"));
+
+        let response = files.handle(&request("/docs/a%20b.txt", &[("Range", "bytes=0-1, -2")]));
+        let content_type = response.get_header("Content-Type").unwrap().to_string();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let length: usize = response
+            .get_header("Content-Length")
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        let body = body(response);
+        assert_eq!(body.len(), length);
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn range_parsing() {
+        assert_eq!(parse_range("bytes=0-0", 10), Some(vec![(0, 0)]));
+        assert_eq!(parse_range("bytes=5-100", 10), Some(vec![(5, 9)]));
+        assert_eq!(parse_range("bytes=-100", 10), Some(vec![(0, 9)]));
+        assert_eq!(parse_range("bytes=10-, -0", 10), Some(vec![]));
+        assert_eq!(parse_range("bytes=5-1", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// HTTP response status code, always a three digit number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u16);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidStatusCode;
+
+impl Display for InvalidStatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid HTTP Status Code")
+    }
+}
+
+impl std::error::Error for InvalidStatusCode {}
+
+macro_rules! status_codes {
+    ($(($code:literal, $name:ident, $reason:literal),)*) => {
+        impl StatusCode {
+            $(
+                pub const $name: StatusCode = StatusCode($code);
+            )*
+
+            /// The reason phrase registered for the status code, `None` for unregistered codes
+            pub fn canonical_reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $(
+                        $code => Some($reason),
+                    )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    (100, CONTINUE, "Continue"),
+    (101, SWITCHING_PROTOCOLS, "Switching Protocols"),
+    (200, OK, "OK"),
+    (201, CREATED, "Created"),
+    (202, ACCEPTED, "Accepted"),
+    (203, NON_AUTHORITATIVE_INFORMATION, "Non-Authoritative Information"),
+    (204, NO_CONTENT, "No Content"),
+    (205, RESET_CONTENT, "Reset Content"),
+    (206, PARTIAL_CONTENT, "Partial Content"),
+    (300, MULTIPLE_CHOICES, "Multiple Choices"),
+    (301, MOVED_PERMANENTLY, "Moved Permanently"),
+    (302, FOUND, "Found"),
+    (303, SEE_OTHER, "See Other"),
+    (304, NOT_MODIFIED, "Not Modified"),
+    (305, USE_PROXY, "Use Proxy"),
+    (307, TEMPORARY_REDIRECT, "Temporary Redirect"),
+    (308, PERMANENT_REDIRECT, "Permanent Redirect"),
+    (400, BAD_REQUEST, "Bad Request"),
+    (401, UNAUTHORIZED, "Unauthorized"),
+    (402, PAYMENT_REQUIRED, "Payment Required"),
+    (403, FORBIDDEN, "Forbidden"),
+    (404, NOT_FOUND, "Not Found"),
+    (405, METHOD_NOT_ALLOWED, "Method Not Allowed"),
+    (406, NOT_ACCEPTABLE, "Not Acceptable"),
+    (407, PROXY_AUTHENTICATION_REQUIRED, "Proxy Authentication Required"),
+    (408, REQUEST_TIMEOUT, "Request Timeout"),
+    (409, CONFLICT, "Conflict"),
+    (410, GONE, "Gone"),
+    (411, LENGTH_REQUIRED, "Length Required"),
+    (412, PRECONDITION_FAILED, "Precondition Failed"),
+    (413, CONTENT_TOO_LARGE, "Content Too Large"),
+    (414, URI_TOO_LONG, "URI Too Long"),
+    (415, UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type"),
+    (416, RANGE_NOT_SATISFIABLE, "Range Not Satisfiable"),
+    (417, EXPECTATION_FAILED, "Expectation Failed"),
+    (421, MISDIRECTED_REQUEST, "Misdirected Request"),
+    (422, UNPROCESSABLE_CONTENT, "Unprocessable Content"),
+    (426, UPGRADE_REQUIRED, "Upgrade Required"),
+    (428, PRECONDITION_REQUIRED, "Precondition Required"),
+    (429, TOO_MANY_REQUESTS, "Too Many Requests"),
+    (431, REQUEST_HEADER_FIELDS_TOO_LARGE, "Request Header Fields Too Large"),
+    (451, UNAVAILABLE_FOR_LEGAL_REASONS, "Unavailable For Legal Reasons"),
+    (500, INTERNAL_SERVER_ERROR, "Internal Server Error"),
+    (501, NOT_IMPLEMENTED, "Not Implemented"),
+    (502, BAD_GATEWAY, "Bad Gateway"),
+    (503, SERVICE_UNAVAILABLE, "Service Unavailable"),
+    (504, GATEWAY_TIMEOUT, "Gateway Timeout"),
+    (505, HTTP_VERSION_NOT_SUPPORTED, "HTTP Version Not Supported"),
+    (511, NETWORK_AUTHENTICATION_REQUIRED, "Network Authentication Required"),
+}
+
+impl StatusCode {
+    /// Fails unless `code` is in 100..=999
+    pub fn new(code: u16) -> Result<Self, InvalidStatusCode> {
+        if (100..1000).contains(&code) {
+            Ok(Self(code))
+        } else {
+            Err(InvalidStatusCode)
+        }
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    /// 3xx
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl TryFrom<u16> for StatusCode {
+    type Error = InvalidStatusCode;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Self::new(code)
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status_code: StatusCode) -> Self {
+        status_code.0
+    }
+}
+
+impl FromStr for StatusCode {
+    type Err = InvalidStatusCode;
+
+    /// status-code = 3DIGIT (RFC 9112 4)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 3 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(InvalidStatusCode);
+        }
+
+        Self::new(s.parse().map_err(|_| InvalidStatusCode)?)
+    }
+}
+
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_classify() {
+        let status: StatusCode = "404".parse().unwrap();
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(status.is_client_error());
+        assert!(!status.is_success());
+        assert_eq!(status.canonical_reason(), Some("Not Found"));
+
+        assert!("4044".parse::<StatusCode>().is_err());
+        assert!("+20".parse::<StatusCode>().is_err());
+        assert!("099".parse::<StatusCode>().is_err());
+        assert_eq!(StatusCode::new(799).unwrap().canonical_reason(), None);
+    }
+}
@@ -1,132 +1,546 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    io::{BufRead, BufReader, BufWriter, Read, Write},
+    io::{Read, Write},
     net::TcpStream,
+    time::Duration,
 };
 
+use super::http_chunked::{encode_chunk, is_chunked, parse_chunk_size};
+use super::http_parser::{
+    next_line, parse_header_line, parse_request, parse_request_head, parse_response,
+    parse_response_head, parse_response_to, ChunkedProgress, Framing, HttpLimits, ParseError,
+};
+use super::http_request::{is_valid_url, InvalidUrl};
+use super::http_response::{is_valid_status_message, InvalidStatusMessage};
+use super::Body;
+use super::ChunkedWriter;
+use super::HeaderMap;
+use super::HttpError;
 use super::HttpRequest;
 use super::HttpResponse;
+use super::Method;
+use super::Version;
 pub use crate::io::{IntoSplit, SplitMut};
 
-pub struct HttpReceiverMut<'http, 'tcp: 'http> {
-    rx: &'http mut BufReader<&'tcp TcpStream>,
+pub struct HttpReceiverMut<'http, R: Read> {
+    rx: &'http mut RecvBuffer<R>,
 }
 
-impl<'http, 'tcp: 'http> HttpReceiverMut<'http, 'tcp> {
-    pub(crate) fn new(rx: &'http mut BufReader<&'tcp TcpStream>) -> Self {
+impl<'http, R: Read> HttpReceiverMut<'http, R> {
+    pub(crate) fn new(rx: &'http mut RecvBuffer<R>) -> Self {
         Self { rx }
     }
 
-    pub fn recv_request(&mut self) -> std::io::Result<HttpRequest> {
-        receive_http_request(self.rx)
+    pub fn get_limits(&self) -> &HttpLimits {
+        &self.rx.limits
+    }
+
+    /// Sets the limits received messages are checked against, see [HttpLimits]
+    pub fn set_limits(&mut self, limits: HttpLimits) {
+        self.rx.limits = limits;
+    }
+
+    pub fn recv_request(&mut self) -> Result<HttpRequest, HttpError> {
+        self.rx
+            .recv_with(|buf, _, limits, progress| parse_request(buf, limits, progress))
+    }
+
+    pub fn recv_response(&mut self) -> Result<HttpResponse, HttpError> {
+        self.rx.recv_with(parse_response)
     }
 
-    pub fn recv_response(&mut self) -> std::io::Result<HttpResponse> {
-        receive_http_response(self.rx)
+    /// Receives the response to a request made with `method`, which unlike [Self::recv_response]
+    /// knows that responses to HEAD requests have no body
+    pub fn recv_response_to(&mut self, method: &Method) -> Result<HttpResponse, HttpError> {
+        self.rx.recv_with(|buf, eof, limits, progress| {
+            parse_response_to(buf, eof, limits, method, progress)
+        })
+    }
+
+    /// Receives the head of a request and returns it along with a reader for its body, the
+    /// request's own body is left empty
+    ///
+    /// Whatever is left of the body when the next message is received is skipped
+    pub fn recv_request_streaming(
+        &mut self,
+    ) -> Result<(HttpRequest, BodyReader<'_, R>), HttpError> {
+        let request = self
+            .rx
+            .recv_head_with(|buf, _, limits| parse_request_head(buf, limits))?;
+        Ok((request, BodyReader::new(self.rx)))
+    }
+
+    /// Receives the head of the response to a request made with `method` and returns it along
+    /// with a reader for its body, see [Self::recv_request_streaming]
+    pub fn recv_response_streaming(
+        &mut self,
+        method: &Method,
+    ) -> Result<(HttpResponse, BodyReader<'_, R>), HttpError> {
+        let response = self
+            .rx
+            .recv_head_with(|buf, _, limits| parse_response_head(buf, limits, method))?;
+        Ok((response, BodyReader::new(self.rx)))
     }
 }
 
-pub struct HttpTransmitterMut<'http, 'tcp: 'http> {
-    tx: &'http mut BufWriter<&'tcp std::net::TcpStream>,
+pub struct HttpTransmitterMut<'http, W: Write> {
+    tx: &'http mut SendBuffer<W>,
 }
 
-impl<'http, 'tcp: 'http> HttpTransmitterMut<'http, 'tcp> {
-    pub(crate) fn new(tx: &'http mut BufWriter<&'tcp TcpStream>) -> Self {
+impl<'http, W: Write> HttpTransmitterMut<'http, W> {
+    pub(crate) fn new(tx: &'http mut SendBuffer<W>) -> Self {
         Self { tx }
     }
 
     pub fn send_request(&mut self, request: &HttpRequest) -> Result<(), std::io::Error> {
-        send_http_request(self.tx, request)
+        send_http_request(self.tx, request).map(|_| ())
     }
 
     pub fn send_response(&mut self, response: &HttpResponse) -> Result<(), std::io::Error> {
-        send_http_response(self.tx, response)
+        send_http_response(self.tx, response).map(|_| ())
+    }
+
+    /// Sends the head of `response` with `Transfer-Encoding: chunked`, the rest of the body is
+    /// then streamed through the returned [ChunkedWriter]
+    pub fn send_response_chunked(
+        &mut self,
+        response: &HttpResponse,
+    ) -> std::io::Result<ChunkedWriter<'_>> {
+        send_http_response_chunked(self.tx, response)
+    }
+
+    /// Sends any data that is still queued from a send that returned
+    /// [std::io::ErrorKind::WouldBlock]
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.tx.flush()
     }
 }
 
-pub struct HttpReceiver<'tcp> {
-    rx: BufReader<&'tcp TcpStream>,
+pub struct HttpReceiver<R: Read> {
+    rx: RecvBuffer<R>,
 }
 
-impl<'tcp> HttpReceiver<'tcp> {
-    pub(crate) fn new(rx: BufReader<&'tcp TcpStream>) -> Self {
+impl<R: Read> HttpReceiver<R> {
+    pub(crate) fn new(rx: RecvBuffer<R>) -> Self {
         Self { rx }
     }
 
-    pub fn recv_request(&mut self) -> std::io::Result<HttpRequest> {
-        receive_http_request(&mut self.rx)
+    pub fn get_limits(&self) -> &HttpLimits {
+        &self.rx.limits
+    }
+
+    /// Sets the limits received messages are checked against, see [HttpLimits]
+    pub fn set_limits(&mut self, limits: HttpLimits) {
+        self.rx.limits = limits;
+    }
+
+    pub fn recv_request(&mut self) -> Result<HttpRequest, HttpError> {
+        self.rx
+            .recv_with(|buf, _, limits, progress| parse_request(buf, limits, progress))
+    }
+
+    pub fn recv_response(&mut self) -> Result<HttpResponse, HttpError> {
+        self.rx.recv_with(parse_response)
+    }
+
+    /// Receives the response to a request made with `method`, which unlike [Self::recv_response]
+    /// knows that responses to HEAD requests have no body
+    pub fn recv_response_to(&mut self, method: &Method) -> Result<HttpResponse, HttpError> {
+        self.rx.recv_with(|buf, eof, limits, progress| {
+            parse_response_to(buf, eof, limits, method, progress)
+        })
+    }
+
+    /// Receives the head of a request and returns it along with a reader for its body, the
+    /// request's own body is left empty
+    ///
+    /// Whatever is left of the body when the next message is received is skipped
+    pub fn recv_request_streaming(
+        &mut self,
+    ) -> Result<(HttpRequest, BodyReader<'_, R>), HttpError> {
+        let request = self
+            .rx
+            .recv_head_with(|buf, _, limits| parse_request_head(buf, limits))?;
+        Ok((request, BodyReader::new(&mut self.rx)))
+    }
+
+    /// Receives the head of the response to a request made with `method` and returns it along
+    /// with a reader for its body, see [Self::recv_request_streaming]
+    pub fn recv_response_streaming(
+        &mut self,
+        method: &Method,
+    ) -> Result<(HttpResponse, BodyReader<'_, R>), HttpError> {
+        let response = self
+            .rx
+            .recv_head_with(|buf, _, limits| parse_response_head(buf, limits, method))?;
+        Ok((response, BodyReader::new(&mut self.rx)))
     }
 
-    pub fn recv_response(&mut self) -> Result<HttpResponse, std::io::Error> {
-        receive_http_response(&mut self.rx)
+    /// Reads from the body of the last message received through
+    /// [Self::recv_request_streaming] or [Self::recv_response_streaming], like [BodyReader] but
+    /// without borrowing the receiver in between
+    pub(crate) fn read_body(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.rx.read_body(buf)
     }
 }
 
-pub struct HttpTransmitter<'tcp> {
-    tx: BufWriter<&'tcp std::net::TcpStream>,
+pub struct HttpTransmitter<W: Write> {
+    tx: SendBuffer<W>,
 }
 
-impl<'tcp> HttpTransmitter<'tcp> {
-    pub(crate) fn new(tx: BufWriter<&'tcp TcpStream>) -> Self {
+impl<W: Write> HttpTransmitter<W> {
+    pub(crate) fn new(tx: SendBuffer<W>) -> Self {
         Self { tx }
     }
 
     pub fn send_request(&mut self, request: &HttpRequest) -> Result<(), std::io::Error> {
-        send_http_request(&mut self.tx, request)
+        send_http_request(&mut self.tx, request).map(|_| ())
     }
 
     pub fn send_response(&mut self, response: &HttpResponse) -> Result<(), std::io::Error> {
-        send_http_response(&mut self.tx, response)
+        send_http_response(&mut self.tx, response).map(|_| ())
+    }
+
+    /// Sends the head of `response` with `Transfer-Encoding: chunked`, the rest of the body is
+    /// then streamed through the returned [ChunkedWriter]
+    pub fn send_response_chunked(
+        &mut self,
+        response: &HttpResponse,
+    ) -> std::io::Result<ChunkedWriter<'_>> {
+        send_http_response_chunked(&mut self.tx, response)
+    }
+
+    /// Sends any data that is still queued from a send that returned
+    /// [std::io::ErrorKind::WouldBlock]
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.tx.flush()
+    }
+
+    /// Continues the body of the response last sent with [Self::send_response_chunked]
+    pub(crate) fn chunked_writer(&mut self) -> ChunkedWriter<'_> {
+        ChunkedWriter::new(&mut self.tx)
     }
 }
 
-pub struct HttpStream<'tcp> {
-    rx: BufReader<&'tcp TcpStream>,
-    tx: BufWriter<&'tcp TcpStream>,
+/// Reads the body of a message received by [HttpStream::recv_request_streaming] or
+/// [HttpStream::recv_response_streaming] straight from the connection
+///
+/// The body's framing is removed, reading returns 0 at the end of the body
+pub struct BodyReader<'http, R: Read> {
+    rx: &'http mut RecvBuffer<R>,
 }
 
-impl<'tcp> HttpStream<'tcp> {
+impl<'http, R: Read> BodyReader<'http, R> {
+    fn new(rx: &'http mut RecvBuffer<R>) -> Self {
+        Self { rx }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rx.body.is_none()
+    }
+
+    /// The trailer fields of a chunked body, empty until the whole body has been read
+    pub fn get_trailers(&self) -> &HeaderMap {
+        &self.rx.trailers
+    }
+}
+
+impl<R: Read> Read for BodyReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.rx.read_body(buf)
+    }
+}
+
+/// An HTTP/1.x connection over any reader and writer pair, such as the two halves of a
+/// [TcpStream], a `UnixStream`, pipes to a child process or in-memory buffers
+///
+/// Over a [TcpStream] the stream can run in blocking mode (see [HttpStream::new] and
+/// [HttpStream::with_timeouts]), where sends and receives wait until they are done. A receive
+/// that runs into the read timeout returns a [std::io::ErrorKind::WouldBlock] or
+/// [std::io::ErrorKind::TimedOut] error.
+///
+/// In non-blocking mode (see [HttpStream::new_nonblocking]) sends and receives never wait and
+/// instead return a [std::io::ErrorKind::WouldBlock] error when they cannot finish yet, use
+/// [HttpStream::wait_readable] and [HttpStream::wait_writable] or [crate::net::poll] to find out
+/// when to try again.
+///
+/// In both modes data that was received or queued before a WouldBlock error is kept, so calling
+/// the same method again later picks up where it left off.
+///
+/// Messages are read back to back, so pipelined requests are received one after the other
+/// without any bytes of one ending up in the next. Whether the connection can carry another
+/// message is tracked from the messages sent and received, see [HttpStream::is_keep_alive], and a
+/// peer that closes the connection between messages makes receiving return
+/// [HttpError::ConnectionClosed]
+pub struct HttpStream<R: Read, W: Write> {
+    rx: RecvBuffer<R>,
+    tx: SendBuffer<W>,
+    /// Cleared once either side asks to close the connection or a message fails
+    keep_alive: bool,
+}
+
+/// An [HttpStream] over a borrowed [TcpStream]
+pub type TcpHttpStream<'tcp> = HttpStream<&'tcp TcpStream, &'tcp TcpStream>;
+
+impl<'tcp> HttpStream<&'tcp TcpStream, &'tcp TcpStream> {
+    /// Creates a blocking stream without timeouts
+    ///
     /// Using the TcpStream passed into this function after it is called can lead to data loss and
     /// as such is inadvisable, just like when using the underlying reader of a BufReader
-    pub fn new(
-        stream: &'tcp TcpStream, /*, read_timeout: std::time::Duration*/
+    pub fn new(stream: &'tcp TcpStream) -> std::io::Result<Self> {
+        Self::with_timeouts(stream, None, None)
+    }
+
+    /// Creates a blocking stream, a timeout of `None` waits forever
+    pub fn with_timeouts(
+        stream: &'tcp TcpStream,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
     ) -> std::io::Result<Self> {
-        //debug_assert!(!read_timeout.is_zero());
-        //let _ = stream.set_read_timeout(Some(read_timeout));
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(read_timeout)?;
+        stream.set_write_timeout(write_timeout)?;
+
+        Ok(Self::from_parts(stream, stream))
+    }
+
+    /// Creates a non-blocking stream, meant to be driven by readiness notifications
+    pub fn new_nonblocking(stream: &'tcp TcpStream) -> std::io::Result<Self> {
         stream.set_nonblocking(true)?;
-        let rx = BufReader::new(stream);
-        let tx = BufWriter::new(stream);
 
-        Ok(Self { rx, tx })
+        Ok(Self::from_parts(stream, stream))
+    }
+
+    /// Like [TcpStream::set_read_timeout] this fails if `timeout` is `Some(Duration::ZERO)`
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.rx.rx.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.tx.tx.set_write_timeout(timeout)
+    }
+}
+
+impl<R: Read, W: Write> HttpStream<R, W> {
+    /// Messages are received from `reader` and sent through `writer`
+    ///
+    /// As with [HttpStream::new], reading from `reader` behind the stream's back can lose data
+    pub fn from_parts(reader: R, writer: W) -> Self {
+        Self {
+            rx: RecvBuffer::new(reader),
+            tx: SendBuffer::new(writer),
+            keep_alive: true,
+        }
+    }
+
+    pub fn get_reader(&self) -> &R {
+        &self.rx.rx
+    }
+
+    pub fn get_writer(&self) -> &W {
+        &self.tx.tx
+    }
+
+    /// Returns the reader and writer, anything that was received but not parsed yet or queued but
+    /// not sent yet is lost
+    pub fn into_parts(self) -> (R, W) {
+        (self.rx.rx, self.tx.tx)
+    }
+
+    /// Sends whatever is still queued and returns the reader, the writer and the bytes that were
+    /// received after the last message, for switching to another protocol after a 101 response
+    pub fn into_upgraded(mut self) -> std::io::Result<(R, W, Vec<u8>)> {
+        self.tx.flush()?;
+        Ok((self.rx.rx, self.tx.tx, self.rx.buf))
     }
 
     pub fn send_request(&mut self, request: &HttpRequest) -> Result<(), std::io::Error> {
-        send_http_request(&mut self.tx, request)
+        self.keep_alive &= request.is_keep_alive();
+
+        let sent = send_http_request(&mut self.tx, request);
+        self.track_send(sent)
+    }
+
+    pub fn get_limits(&self) -> &HttpLimits {
+        &self.rx.limits
     }
 
-    pub fn recv_request(&mut self) -> std::io::Result<HttpRequest> {
-        receive_http_request(&mut self.rx)
+    /// Sets the limits received messages are checked against, see [HttpLimits]
+    pub fn set_limits(&mut self, limits: HttpLimits) {
+        self.rx.limits = limits;
+    }
+
+    /// Returns [HttpError::ConnectionClosed] if the peer closed the connection cleanly instead of
+    /// sending another request
+    pub fn recv_request(&mut self) -> Result<HttpRequest, HttpError> {
+        let received = self
+            .rx
+            .recv_with(|buf, _, limits, progress| parse_request(buf, limits, progress));
+        self.track_recv(received, HttpRequest::is_keep_alive)
     }
 
     pub fn send_response(&mut self, response: &HttpResponse) -> Result<(), std::io::Error> {
-        send_http_response(&mut self.tx, response)
+        self.keep_alive &= response.is_keep_alive();
+
+        let sent = send_http_response(&mut self.tx, response);
+        self.track_send(sent)
+    }
+
+    /// Sends the head of `response` with `Transfer-Encoding: chunked`, the rest of the body is
+    /// then streamed through the returned [ChunkedWriter]
+    ///
+    /// A non-empty body on `response` is sent as the first chunk
+    pub fn send_response_chunked(
+        &mut self,
+        response: &HttpResponse,
+    ) -> std::io::Result<ChunkedWriter<'_>> {
+        self.keep_alive &= response.is_keep_alive();
+        send_http_response_chunked(&mut self.tx, response)
+    }
+
+    pub fn recv_response(&mut self) -> Result<HttpResponse, HttpError> {
+        let received = self.rx.recv_with(parse_response);
+        self.track_recv(received, HttpResponse::is_keep_alive)
     }
 
-    pub fn recv_response(&mut self) -> std::io::Result<HttpResponse> {
-        receive_http_response(&mut self.rx)
+    /// Receives the response to a request made with `method`, which unlike [Self::recv_response]
+    /// knows that responses to HEAD requests have no body
+    pub fn recv_response_to(&mut self, method: &Method) -> Result<HttpResponse, HttpError> {
+        let received = self.rx.recv_with(|buf, eof, limits, progress| {
+            parse_response_to(buf, eof, limits, method, progress)
+        });
+        self.track_recv(received, HttpResponse::is_keep_alive)
+    }
+
+    /// Receives the head of a request and returns it along with a reader for its body, the
+    /// request's own body is left empty
+    ///
+    /// Whatever is left of the body when the next message is received is skipped
+    pub fn recv_request_streaming(
+        &mut self,
+    ) -> Result<(HttpRequest, BodyReader<'_, R>), HttpError> {
+        let received = self
+            .rx
+            .recv_head_with(|buf, _, limits| parse_request_head(buf, limits));
+        let request = self.track_recv(received, HttpRequest::is_keep_alive)?;
+
+        Ok((request, BodyReader::new(&mut self.rx)))
+    }
+
+    /// Receives the head of the response to a request made with `method` and returns it along
+    /// with a reader for its body, see [Self::recv_request_streaming]
+    pub fn recv_response_streaming(
+        &mut self,
+        method: &Method,
+    ) -> Result<(HttpResponse, BodyReader<'_, R>), HttpError> {
+        let received = self
+            .rx
+            .recv_head_with(|buf, _, limits| parse_response_head(buf, limits, method));
+        let response = self.track_recv(received, HttpResponse::is_keep_alive)?;
+
+        Ok((response, BodyReader::new(&mut self.rx)))
+    }
+
+    /// Whether another message can follow on the connection: neither side asked to close it (see
+    /// [HttpRequest::is_keep_alive]), no body was delimited by closing the connection, no message
+    /// failed to send or parse and the peer has not closed its end with nothing left to receive
+    pub fn is_keep_alive(&self) -> bool {
+        self.keep_alive
+            && self.rx.body != Some(BodyState::Close)
+            && !(self.rx.eof && self.rx.buf.is_empty())
+    }
+
+    /// How many bytes of a message that hasn't been received completely are buffered, empty
+    /// lines in front of a message don't count
+    pub(crate) fn partial_message_len(&self) -> usize {
+        match self.rx.buf.iter().position(|b| !matches!(b, b'\r' | b'\n')) {
+            Some(start) => self.rx.buf.len() - start,
+            None => 0,
+        }
+    }
+
+    /// Sends any data that is still queued from a send that returned
+    /// [std::io::ErrorKind::WouldBlock]
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.tx.flush()
+    }
+
+    /// Reads from the body of the last message received through
+    /// [Self::recv_request_streaming] or [Self::recv_response_streaming], like [BodyReader] but
+    /// without borrowing the stream in between
+    pub(crate) fn read_body(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.rx.read_body(buf)
+    }
+
+    /// A message that failed part way leaves the connection in an unknown state, only timeouts
+    /// can be recovered from
+    fn track_recv<T>(
+        &mut self,
+        received: Result<T, HttpError>,
+        keep_alive: impl FnOnce(&T) -> bool,
+    ) -> Result<T, HttpError> {
+        match &received {
+            Ok(message) => self.keep_alive &= keep_alive(message),
+            Err(HttpError::Timeout) => {}
+            Err(_) => self.keep_alive = false,
+        }
+
+        received
+    }
+
+    fn track_send(&mut self, sent: std::io::Result<bool>) -> std::io::Result<()> {
+        match sent {
+            Ok(close_delimited) => {
+                self.keep_alive &= !close_delimited;
+                Ok(())
+            }
+            // The rest of the message is queued
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Err(err),
+            Err(err) => {
+                self.keep_alive = false;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<R: Read + std::os::fd::AsFd, W: Write + std::os::fd::AsFd> HttpStream<R, W> {
+    /// Returns true once there is something to receive, which includes already buffered
+    /// pipelined data that the socket itself would not report as readable
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> std::io::Result<bool> {
+        if self.rx.has_unparsed_data() {
+            return Ok(true);
+        }
+
+        let readiness =
+            crate::net::poll::wait(&self.rx.rx, crate::net::poll::Interest::Readable, timeout)?;
+        Ok(readiness.readable || readiness.closed)
+    }
+
+    /// Returns true once queued data can be sent, or right away if nothing is queued
+    pub fn wait_writable(&self, timeout: Option<Duration>) -> std::io::Result<bool> {
+        if !self.tx.has_queued_data() {
+            return Ok(true);
+        }
+
+        let readiness =
+            crate::net::poll::wait(&self.tx.tx, crate::net::poll::Interest::Writable, timeout)?;
+        Ok(readiness.writable || readiness.closed)
+    }
+}
+
+/// Borrows the reader's file descriptor
+#[cfg(unix)]
+impl<R: Read + std::os::fd::AsFd, W: Write> std::os::fd::AsFd for HttpStream<R, W> {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.rx.rx.as_fd()
     }
 }
 
-impl<'http, 'tcp: 'http>
-    SplitMut<'http, HttpReceiverMut<'http, 'tcp>, HttpTransmitterMut<'http, 'tcp>>
-    for HttpStream<'tcp>
+impl<'http, R: Read + 'http, W: Write + 'http>
+    SplitMut<'http, HttpReceiverMut<'http, R>, HttpTransmitterMut<'http, W>> for HttpStream<R, W>
 {
-    fn split_mut(
-        &'http mut self,
-    ) -> (
-        HttpReceiverMut<'http, 'tcp>,
-        HttpTransmitterMut<'http, 'tcp>,
-    ) {
+    fn split_mut(&'http mut self) -> (HttpReceiverMut<'http, R>, HttpTransmitterMut<'http, W>) {
         (
             HttpReceiverMut::new(&mut self.rx),
             HttpTransmitterMut::new(&mut self.tx),
@@ -134,224 +548,850 @@ impl<'http, 'tcp: 'http>
     }
 }
 
-impl<'tcp> IntoSplit<HttpReceiver<'tcp>, HttpTransmitter<'tcp>> for HttpStream<'tcp> {
-    fn into_split(self) -> (HttpReceiver<'tcp>, HttpTransmitter<'tcp>) {
+impl<R: Read, W: Write> IntoSplit<HttpReceiver<R>, HttpTransmitter<W>> for HttpStream<R, W> {
+    fn into_split(self) -> (HttpReceiver<R>, HttpTransmitter<W>) {
         (HttpReceiver::new(self.rx), HttpTransmitter::new(self.tx))
     }
 }
 
-fn receive_http_request(rx: &mut BufReader<&TcpStream>) -> std::io::Result<HttpRequest> {
-    // Get Request line
-    let mut request_line_buf = String::new();
-    'reading_request_line: loop {
-        match rx.read_line(&mut request_line_buf) {
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::WouldBlock => continue,
-                _ => {
-                    return Err(err);
-                }
-            },
-            _ => break 'reading_request_line,
-        };
+/// Returns whether the body was delimited by closing the connection
+fn send_http_request(tx: &mut impl Write, request: &HttpRequest) -> std::io::Result<bool> {
+    // Requests made with HttpRequest::new haven't been checked yet
+    if !is_valid_url(request.get_url()) {
+        return Err(InvalidUrl.into());
     }
 
-    // Get Headers
-    let mut header_strings = Vec::new();
+    if !request.is_body_streaming() {
+        tx.write_all(&request.as_bytes())?;
+        tx.flush()?;
 
-    'reading_headers: loop {
-        let mut buf = String::new();
-        if let Err(err) = rx.read_line(&mut buf) {
-            match err.kind() {
-                std::io::ErrorKind::WouldBlock => continue,
-                _ => {
-                    return Err(err);
-                }
-            };
-        }
+        return Ok(false);
+    }
 
-        let line = buf.trim();
+    let mut request = request.clone();
+    let (len, version) = (request.get_body_length(), request.get_version());
+    let framing = frame_stream(request.get_headers_mut(), len, version)?;
 
-        if line.is_empty() {
-            break 'reading_headers;
-        }
+    tx.write_all(&request.head_bytes())?;
+    send_body(tx, request.get_body_mut(), framing)?;
+
+    Ok(framing == Framing::Close)
+}
 
-        header_strings.push(line.to_string());
+/// Returns whether the body was delimited by closing the connection
+fn send_http_response(tx: &mut impl Write, response: &HttpResponse) -> std::io::Result<bool> {
+    // Responses made with HttpResponse::new haven't been checked yet
+    if !is_valid_status_message(response.get_status_message()) {
+        return Err(InvalidStatusMessage.into());
     }
 
-    // Get Body
-    let mut bytes = Vec::new();
+    if !response.is_body_streaming() {
+        tx.write_all(&response.as_bytes())?;
+        tx.flush()?;
 
-    const BUFFER_SIZE: usize = 512;
-    let mut buffer = [0_u8; BUFFER_SIZE];
-    'reading_body: loop {
-        let bytes_read = match rx.read(&mut buffer) {
-            Ok(val) => val,
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::WouldBlock => 0,
-                _ => {
-                    return Err(err);
-                }
-            },
+        return Ok(false);
+    }
+
+    let mut response = response.clone();
+    let (len, version) = (response.get_body_length(), response.get_version());
+    let framing = frame_stream(response.get_headers_mut(), len, version)?;
+
+    tx.write_all(&response.head_bytes())?;
+    send_body(tx, response.get_body_mut(), framing)?;
+
+    Ok(framing == Framing::Close)
+}
+
+/// Picks the framing for a streaming body, framing headers that are already set are kept,
+/// otherwise a body of known length gets a Content-Length and any other body is chunked
+///
+/// HTTP/1.0 has no chunked coding, so a body of unknown length is delimited by closing the
+/// connection instead
+fn frame_stream(
+    headers: &mut HeaderMap,
+    len: Option<u64>,
+    version: Version,
+) -> std::io::Result<Framing> {
+    if let Some(chunked) = is_chunked(headers) {
+        return Ok(match chunked {
+            true => Framing::Chunked,
+            false => Framing::Close,
+        });
+    }
+
+    if let Some(val) = headers.get("Content-Length") {
+        let len = val
+            .parse()
+            .map_err(|_| std::io::Error::other("Invalid Content-Length"))?;
+        return Ok(Framing::Length(len));
+    }
+
+    Ok(match len {
+        Some(len) => {
+            headers.insert("Content-Length", len.to_string());
+            Framing::Length(len)
+        }
+        None if version == Version::Http10 => Framing::Close,
+        None => {
+            headers.insert("Transfer-Encoding", "chunked");
+            Framing::Chunked
+        }
+    })
+}
+
+/// Copies a streaming body into `tx`, flushing after every block so that only one block is ever
+/// queued at a time
+fn send_body(tx: &mut impl Write, body: &mut Body, framing: Framing) -> std::io::Result<()> {
+    const BLOCK_SIZE: usize = 16384;
+    let mut block = vec![0_u8; BLOCK_SIZE];
+
+    let mut remaining = match framing {
+        Framing::Length(len) => len,
+        _ => u64::MAX,
+    };
+
+    while remaining > 0 {
+        let max = BLOCK_SIZE.min(usize::try_from(remaining).unwrap_or(BLOCK_SIZE));
+
+        let bytes_read = match body.read(&mut block[..max]) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
         };
 
-        bytes.extend_from_slice(&buffer[..bytes_read]);
+        match framing {
+            Framing::Chunked => tx.write_all(&encode_chunk(&block[..bytes_read]))?,
+            _ => tx.write_all(&block[..bytes_read])?,
+        }
+        tx.flush()?;
 
-        if bytes_read < BUFFER_SIZE {
-            break 'reading_body;
+        if let Framing::Length(_) = framing {
+            remaining -= bytes_read as u64;
         }
     }
 
-    // Process Request Line
-    let request_line = request_line_buf.trim().to_string();
-    let mut words: Vec<_> = request_line.split(' ').collect();
+    match framing {
+        Framing::Length(_) if remaining > 0 => Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "Body ended before its Content-Length",
+        )),
+        Framing::Chunked => {
+            tx.write_all(b"0\r\n\r\n")?;
+            tx.flush()
+        }
+        _ => Ok(()),
+    }
+}
 
-    if words.len() != 3 {
-        Err(std::io::Error::other("Invalid Request Line"))?;
+fn send_http_response_chunked<'w>(
+    tx: &'w mut impl Write,
+    response: &HttpResponse,
+) -> std::io::Result<ChunkedWriter<'w>> {
+    if !is_valid_status_message(response.get_status_message()) {
+        return Err(InvalidStatusMessage.into());
     }
 
-    let version = words.pop().unwrap().to_string();
-    let url = words.pop().unwrap().to_string();
-    let method = words.pop().unwrap().to_string();
+    // The body length is unknown so any framing headers on the response are replaced
+    let mut headers = response.get_headers().clone();
+    headers.remove("Content-Length");
+    headers.insert("Transfer-Encoding", "chunked");
 
-    // Process Headers
-    let headers: HashMap<String, String> = header_strings
-        .into_iter()
-        .filter_map(|line| {
-            line.split_once(':')
-                .map(|(key, val)| (key.to_string(), val.to_string()))
-        })
-        .collect();
-
-    // Process Body
-    let body = bytes.into_boxed_slice();
-
-    // Build Request
-    let mut builder = HttpRequest::builder();
-
-    Ok(builder
-        .set_method(method)
-        .set_url(url)
-        .set_version(version)
-        .set_body(body)
-        .set_headers(headers)
-        .build()
-        .unwrap())
+    let head = HttpResponse::new(
+        response.get_version(),
+        response.get_status_code(),
+        response.get_status_message().to_string(),
+        headers,
+        Box::new([]),
+    );
+
+    tx.write_all(&head.head_bytes())?;
+
+    let mut writer = ChunkedWriter::new(tx);
+    writer.send_chunk(response.get_body())?;
+    writer.flush()?;
+
+    Ok(writer)
 }
 
-fn send_http_request(tx: &mut BufWriter<&TcpStream>, request: &HttpRequest) -> std::io::Result<()> {
-    tx.write_all(&request.as_bytes())?;
-    tx.flush()
+/// Received bytes that have not been turned into messages yet
+pub(crate) struct RecvBuffer<R: Read> {
+    rx: R,
+    buf: Vec<u8>,
+    eof: bool,
+    /// Set when bytes were left over after the last message, for example because the peer
+    /// pipelined its requests
+    unparsed: bool,
+    /// Where a body that is being streamed is at, `None` once it has been read completely
+    body: Option<BodyState>,
+    /// How far a chunked body received as a whole got, until the message is complete
+    chunked: ChunkedProgress,
+    trailers: HeaderMap,
+    limits: HttpLimits,
 }
 
-fn receive_http_response(rx: &mut BufReader<&TcpStream>) -> std::io::Result<HttpResponse> {
-    // Get Status line
-    let mut status_line_buf = String::new();
+/// Progress through a streamed body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyState {
+    Length(u64),
+    Close,
+    ChunkSize,
+    ChunkData(u64),
+    /// The CRLF after a chunk's data
+    ChunkEnd,
+    Trailers,
+}
 
-    'reading_status_line: loop {
-        match rx.read_line(&mut status_line_buf) {
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::WouldBlock => continue,
-                _ => {
-                    return Err(err);
-                }
-            },
-            _ => break 'reading_status_line,
+impl<R: Read> RecvBuffer<R> {
+    fn new(rx: R) -> Self {
+        Self {
+            rx,
+            buf: Vec::new(),
+            eof: false,
+            unparsed: false,
+            body: None,
+            chunked: ChunkedProgress::default(),
+            trailers: HeaderMap::new(),
+            limits: HttpLimits::default(),
+        }
+    }
+
+    fn has_unparsed_data(&self) -> bool {
+        self.unparsed || self.eof
+    }
+
+    /// Runs `parse` over the buffered bytes and the EOF flag, reading more whenever it needs more
+    ///
+    /// If the underlying stream returns an error the bytes received so far stay buffered for the
+    /// next call
+    fn recv_with<T>(
+        &mut self,
+        parse: impl Fn(
+            &[u8],
+            bool,
+            &HttpLimits,
+            &mut ChunkedProgress,
+        ) -> Result<Option<(T, usize)>, ParseError>,
+    ) -> Result<T, HttpError> {
+        self.skip_body()?;
+
+        'receiving: loop {
+            let parsed = parse(&self.buf, self.eof, &self.limits, &mut self.chunked);
+
+            // Whatever was decoded belongs to the message that just ended one way or another
+            if !matches!(parsed, Ok(None)) {
+                self.chunked = ChunkedProgress::default();
+            }
+
+            if let Some((message, len)) = parsed? {
+                self.buf.drain(..len);
+                self.unparsed = !self.buf.is_empty();
+
+                return Ok(message);
+            }
+            self.unparsed = false;
+
+            if self.eof {
+                break 'receiving;
+            }
+
+            self.fill()?;
+        }
+
+        if self.buf.is_empty() {
+            Err(HttpError::ConnectionClosed)
+        } else {
+            Err(HttpError::IncompleteMessage)
+        }
+    }
+
+    /// Like [Self::recv_with] for a parser that only parses the head of a message, the body is
+    /// then streamed through [Self::read_body]
+    fn recv_head_with<T>(
+        &mut self,
+        parse: impl Fn(&[u8], bool, &HttpLimits) -> Result<Option<(T, Framing, usize)>, ParseError>,
+    ) -> Result<T, HttpError> {
+        let (message, framing) = self.recv_with(|buf, eof, limits, _| {
+            Ok(parse(buf, eof, limits)?.map(|(message, framing, len)| ((message, framing), len)))
+        })?;
+
+        self.trailers.clear();
+        self.body = match framing {
+            Framing::Empty => None,
+            Framing::Length(len) => Some(BodyState::Length(len)),
+            Framing::Chunked => Some(BodyState::ChunkSize),
+            Framing::Close => Some(BodyState::Close),
         };
+
+        Ok(message)
     }
 
-    // Get Headers
-    let mut header_strings = Vec::new();
+    /// Reads from the body that is being streamed, returning 0 at its end
+    ///
+    /// As with [Self::recv_with] no progress is lost if the underlying stream returns an error
+    fn read_body(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
 
-    'reading_headers: loop {
-        let mut buf = String::new();
+        while let Some(state) = self.body {
+            match state {
+                BodyState::Length(0) => self.body = None,
+                BodyState::Length(remaining) => {
+                    let bytes_read = self.read_raw(out, remaining)?;
+                    self.body = Some(BodyState::Length(remaining - bytes_read as u64));
 
-        if let Err(err) = rx.read_line(&mut buf) {
-            match err.kind() {
-                std::io::ErrorKind::WouldBlock => continue,
-                _ => {
-                    return Err(err);
+                    return Ok(bytes_read);
+                }
+                BodyState::Close => {
+                    let bytes_read = match self.read_raw(out, u64::MAX) {
+                        Ok(bytes_read) => bytes_read,
+                        // The body ends at EOF, so this is not unexpected at all
+                        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+                        Err(err) => return Err(err),
+                    };
+
+                    if bytes_read == 0 {
+                        self.body = None;
+                    }
+
+                    return Ok(bytes_read);
                 }
-            };
+                BodyState::ChunkSize => {
+                    let size = parse_chunk_size(&self.take_line(ParseError::InvalidChunk)?)?;
+
+                    self.body = Some(match size {
+                        0 => BodyState::Trailers,
+                        size => BodyState::ChunkData(size as u64),
+                    });
+                }
+                BodyState::ChunkData(remaining) => {
+                    let bytes_read = self.read_raw(out, remaining)?;
+
+                    self.body = Some(match remaining - bytes_read as u64 {
+                        0 => BodyState::ChunkEnd,
+                        remaining => BodyState::ChunkData(remaining),
+                    });
+
+                    return Ok(bytes_read);
+                }
+                BodyState::ChunkEnd => {
+                    if !self.take_line(ParseError::InvalidChunk)?.is_empty() {
+                        return Err(ParseError::InvalidChunk.into());
+                    }
+
+                    self.body = Some(BodyState::ChunkSize);
+                }
+                BodyState::Trailers => {
+                    let line = self.take_line(ParseError::HeaderLineTooLong)?;
+
+                    if line.is_empty() {
+                        self.body = None;
+                    } else if self.trailers.len() == self.limits.max_header_count {
+                        return Err(ParseError::TooManyHeaders.into());
+                    } else {
+                        let (key, val) = parse_header_line(&line)?;
+                        self.trailers.append(key, val);
+                    }
+                }
+            }
         }
 
-        let line = buf.trim();
+        Ok(0)
+    }
+
+    /// Reads and discards the rest of the body that is being streamed
+    fn skip_body(&mut self) -> std::io::Result<()> {
+        let mut buffer = [0_u8; 8192];
+        while self.read_body(&mut buffer)? > 0 {}
+
+        Ok(())
+    }
+
+    /// Reads up to `limit` bytes of body data, buffered bytes first
+    fn read_raw(&mut self, out: &mut [u8], limit: u64) -> std::io::Result<usize> {
+        let max = out.len().min(usize::try_from(limit).unwrap_or(usize::MAX));
+
+        if !self.buf.is_empty() {
+            let len = max.min(self.buf.len());
+            out[..len].copy_from_slice(&self.buf[..len]);
+            self.buf.drain(..len);
+            self.unparsed = !self.buf.is_empty();
 
-        if line.is_empty() {
-            break 'reading_headers;
+            return Ok(len);
         }
 
-        header_strings.push(line.to_string());
+        if !self.eof {
+            // Read straight into `out` rather than going through the buffer
+            loop {
+                match self.rx.read(&mut out[..max]) {
+                    Ok(0) => {
+                        self.eof = true;
+                        break;
+                    }
+                    Ok(bytes_read) => return Ok(bytes_read),
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Err(HttpError::IncompleteMessage.into())
     }
 
-    // Get Body
-    let mut bytes = Vec::new();
+    /// Takes the next line out of the buffer without its CRLF, reading more until it is complete
+    fn take_line(&mut self, too_long: ParseError) -> std::io::Result<String> {
+        loop {
+            if let Some((line, len)) = next_line(&self.buf, 0, &self.limits, too_long)? {
+                let line = line.to_string();
+                self.buf.drain(..len);
+                self.unparsed = !self.buf.is_empty();
+
+                return Ok(line);
+            }
+
+            if self.eof {
+                return Err(HttpError::IncompleteMessage.into());
+            }
+
+            self.fill()?;
+        }
+    }
 
-    const BUFFER_SIZE: usize = 512;
-    let mut buffer = [0_u8; BUFFER_SIZE];
-    'reading_body: loop {
-        let bytes_read = match rx.read(&mut buffer) {
-            Ok(val) => val,
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::WouldBlock => 0,
-                _ => {
-                    return Err(err);
+    fn fill(&mut self) -> std::io::Result<()> {
+        const BUFFER_SIZE: usize = 8192;
+        let mut buffer = [0_u8; BUFFER_SIZE];
+
+        loop {
+            match self.rx.read(&mut buffer) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(());
                 }
-            },
-        };
+                Ok(bytes_read) => {
+                    self.buf.extend_from_slice(&buffer[..bytes_read]);
+                    return Ok(());
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
 
-        bytes.extend_from_slice(&buffer[..bytes_read]);
+/// Bytes queued for sending
+///
+/// Writing only queues the bytes, flushing sends them. A flush that fails part way keeps
+/// whatever was not sent yet so a later flush can finish the job
+pub(crate) struct SendBuffer<W: Write> {
+    tx: W,
+    buf: Vec<u8>,
+    written: usize,
+}
 
-        if bytes_read < BUFFER_SIZE {
-            break 'reading_body;
+impl<W: Write> SendBuffer<W> {
+    fn new(tx: W) -> Self {
+        Self {
+            tx,
+            buf: Vec::new(),
+            written: 0,
         }
     }
 
-    // Process Status Line
-    let status_line = status_line_buf.trim().to_string();
-    let mut words: VecDeque<_> = status_line.split(' ').collect();
+    fn has_queued_data(&self) -> bool {
+        self.written < self.buf.len()
+    }
+}
 
-    if words.len() < 3 {
-        Err(std::io::Error::other("Invalid Status Line"))?;
+impl<W: Write> Write for SendBuffer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
     }
 
-    let version = words.pop_front().unwrap().to_string();
-    let status_code = words
-        .pop_front()
-        .unwrap()
-        .to_string()
-        .parse::<u16>()
-        .map_err(|_| std::io::Error::other("Failed to parse status code"))?;
-    let status_message = words.into_iter().collect::<Vec<_>>().join(" ");
+    fn flush(&mut self) -> std::io::Result<()> {
+        while self.written < self.buf.len() {
+            match self.tx.write(&self.buf[self.written..]) {
+                Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(bytes_written) => self.written += bytes_written,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
 
-    // Process Headers
-    let headers: HashMap<String, String> = header_strings
-        .into_iter()
-        .filter_map(|line| {
-            line.split_once(':')
-                .map(|(key, val)| (key.to_string(), val.to_string()))
-        })
-        .collect();
-
-    // Process Body
-    let body = bytes.into_boxed_slice();
-
-    // Build Request
-    let mut builder = HttpResponse::builder();
-
-    Ok(builder
-        .set_version(version)
-        .set_status_code(status_code)
-        .set_status_message(status_message)
-        .set_headers(headers)
-        .set_body(body)
-        .build()
-        .unwrap())
+        self.buf.clear();
+        self.written = 0;
+
+        self.tx.flush()
+    }
 }
 
-fn send_http_response(
-    tx: &mut BufWriter<&TcpStream>,
-    response: &HttpResponse,
-) -> std::io::Result<()> {
-    tx.write_all(&response.as_bytes())?;
-    tx.flush()
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{
+        HeaderMap, HttpRequestBuildError, HttpResponseBuildError, Method, StatusCode, Version,
+    };
+    use std::net::TcpListener;
+
+    #[test]
+    fn body_split_across_segments() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+
+            stream
+                .write_all(b"POST /upload HTTP/1.1\r\ncontent-length: 1000\r\n\r\n")
+                .unwrap();
+
+            for chunk in [0_u8; 1000].chunks(100) {
+                stream.write_all(chunk).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut http = HttpStream::new(&stream).unwrap();
+        let request = http.recv_request().unwrap();
+
+        assert_eq!(request.get_url(), "/upload");
+        assert_eq!(request.get_body().len(), 1000);
+
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn chunked_response_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut http = HttpStream::new(&stream).unwrap();
+
+            let head = HttpResponse::builder()
+                .set_version(Version::Http11)
+                .set_status_code(StatusCode::OK)
+                .set_header("Trailer".into(), "X-Checksum".into())
+                .set_body(b"first ".to_vec().into_boxed_slice())
+                .build()
+                .unwrap();
+
+            let mut writer = http.send_response_chunked(&head).unwrap();
+            writer.send_chunk(b"second ").unwrap();
+            writer.send_chunk(b"third").unwrap();
+
+            let mut trailers = HeaderMap::new();
+            trailers.insert("X-Checksum", "abc");
+            writer.finish_with_trailers(&trailers).unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut http = HttpStream::new(&stream).unwrap();
+        let response = http.recv_response().unwrap();
+
+        assert_eq!(response.get_body(), b"first second third");
+        assert_eq!(response.get_trailers().get("X-Checksum"), Some("abc"));
+        assert_eq!(response.get_header("Content-Length"), Some("18"));
+        assert!(response.get_header("Transfer-Encoding").is_none());
+        assert!(response.get_header("Trailer").is_none());
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn response_without_length_reads_until_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            stream.write_all(b"hello world").unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut http = HttpStream::new(&stream).unwrap();
+        let response = http.recv_response().unwrap();
+
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert_eq!(response.get_body(), b"hello world");
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn nonblocking_keeps_partial_progress() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut http = HttpStream::new_nonblocking(&stream).unwrap();
+
+        let err = http.recv_request().unwrap_err();
+        assert!(matches!(err, HttpError::Timeout));
+
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nab")
+            .unwrap();
+        assert!(http.wait_readable(Some(Duration::from_secs(5))).unwrap());
+
+        let err = http.recv_request().unwrap_err();
+        assert!(matches!(err, HttpError::Timeout));
+
+        client.write_all(b"cdGET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(http.wait_readable(Some(Duration::from_secs(5))).unwrap());
+
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_body(), b"abcd");
+
+        // The pipelined request is already buffered
+        assert!(http.wait_readable(Some(Duration::ZERO)).unwrap());
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_method(), &Method::Get);
+    }
+
+    #[test]
+    fn read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = TcpStream::connect(addr).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut http =
+            HttpStream::with_timeouts(&stream, Some(Duration::from_millis(20)), None).unwrap();
+
+        let err = http.recv_request().unwrap_err();
+        assert!(matches!(err, HttpError::Timeout));
+    }
+
+    #[test]
+    fn in_memory_transport() {
+        let input: &[u8] = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+
+        assert_eq!(http.recv_request().unwrap().get_url(), "/a");
+        assert_eq!(http.recv_request().unwrap().get_url(), "/b");
+        assert!(matches!(
+            http.recv_request().unwrap_err(),
+            HttpError::ConnectionClosed
+        ));
+
+        let response = HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::NO_CONTENT)
+            .set_body(Box::new([]))
+            .build()
+            .unwrap();
+        http.send_response(&response).unwrap();
+
+        let (_, output) = http.into_parts();
+        assert_eq!(output, b"HTTP/1.1 204 No Content\r\n\r\n");
+    }
+
+    #[test]
+    fn start_line_injection() {
+        let forged = "/ HTTP/1.1\r\nX-Forged: yes\r\n\r\nGET /admin";
+
+        let mut request = HttpRequest::builder();
+        request
+            .set_method(Method::Get)
+            .set_url(forged.into())
+            .set_version(Version::Http11)
+            .set_body(Box::new([]));
+        assert_eq!(
+            request.build().unwrap_err(),
+            HttpRequestBuildError::InvalidUrl
+        );
+
+        let mut request = HttpRequest::new(
+            Method::Get,
+            "/".into(),
+            Version::Http11,
+            HeaderMap::new(),
+            Box::new([]),
+        );
+        assert!(request.set_url("/a b".into()).is_err());
+        assert_eq!(request.get_url(), "/");
+
+        let mut response = HttpResponse::builder();
+        response
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::OK)
+            .set_status_message("OK\r\nX-Forged: yes".into())
+            .set_body(Box::new([]));
+        assert_eq!(
+            response.build().unwrap_err(),
+            HttpResponseBuildError::InvalidStatusMessage
+        );
+
+        let mut response = HttpResponse::from_status(StatusCode::OK);
+        assert!(response.set_status_message("OK\0".into()).is_err());
+        assert!(response.set_status_message("Fine\tthanks".into()).is_ok());
+
+        // Messages made with new are checked when they are sent
+        let mut http = HttpStream::from_parts(&b""[..], Vec::new());
+        let request = HttpRequest::new(
+            Method::Get,
+            forged.into(),
+            Version::Http11,
+            HeaderMap::new(),
+            Box::new([]),
+        );
+        assert!(http.send_request(&request).is_err());
+
+        let response = HttpResponse::new(
+            Version::Http11,
+            StatusCode::OK,
+            "OK\nX-Forged: yes".into(),
+            HeaderMap::new(),
+            Box::new([]),
+        );
+        assert!(http.send_response(&response).is_err());
+        assert!(http.send_response_chunked(&response).is_err());
+
+        let (_, output) = http.into_parts();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn pipelining_and_keep_alive() {
+        let input: &[u8] = b"POST /a HTTP/1.1\r\nContent-Length: 19\r\n\r\nGET /x HTTP/1.1\r\n\r\n\
+            POST /b HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\n0\r\n\r\n0\r\n\r\n\
+            GET /c HTTP/1.0\r\nConnection: keep-alive\r\n\r\n\
+            GET /d HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_body(), b"GET /x HTTP/1.1\r\n\r\n");
+        assert!(http.is_keep_alive());
+
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_url(), "/b");
+        assert_eq!(request.get_body(), b"0\r\n");
+
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_url(), "/c");
+        assert!(http.is_keep_alive());
+
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_url(), "/d");
+        assert!(!http.is_keep_alive());
+
+        assert!(matches!(
+            http.recv_request().unwrap_err(),
+            HttpError::ConnectionClosed
+        ));
+
+        // HTTP/1.0 closes by default and so do responses delimited by closing the connection
+        let input: &[u8] = b"GET / HTTP/1.0\r\n\r\n";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+        http.recv_request().unwrap();
+        assert!(!http.is_keep_alive());
+
+        let input: &[u8] = b"HTTP/1.1 200 OK\r\n\r\nuntil the end";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+        let (_, mut body) = http.recv_response_streaming(&Method::Get).unwrap();
+        body.read_exact(&mut [0; 5]).unwrap();
+        assert!(!http.is_keep_alive());
+    }
+
+    #[test]
+    fn streaming_bodies() {
+        let input: &[u8] = b"POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n6\r\n world\r\n0\r\nX-Sum: 11\r\n\r\n\
+            POST /b HTTP/1.1\r\nContent-Length: 9\r\n\r\nunwantedGET /c HTTP/1.1\r\n\r\n";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+
+        let (request, mut body) = http.recv_request_streaming().unwrap();
+        assert_eq!(request.get_url(), "/a");
+
+        let mut text = String::new();
+        body.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "hello world");
+        assert_eq!(body.get_trailers().get("X-Sum"), Some("11"));
+
+        // The unread body of the second request is skipped
+        let (request, mut body) = http.recv_request_streaming().unwrap();
+        assert_eq!(request.get_url(), "/b");
+        assert_eq!(body.read(&mut [0; 2]).unwrap(), 2);
+        assert_eq!(http.recv_request().unwrap().get_url(), "/c");
+
+        let mut response = HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::OK)
+            .set_body_reader(&b"streamed"[..], None)
+            .build()
+            .unwrap();
+        http.send_response(&response).unwrap();
+
+        response.set_body_reader(&b"sized"[..], Some(5));
+        http.send_response(&response).unwrap();
+
+        let (_, output) = http.into_parts();
+        assert_eq!(
+            output,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n\
+            HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nsized"
+        );
+    }
+
+    #[test]
+    fn limits_and_head_responses() {
+        let input: &[u8] = b"POST / HTTP/1.1\r\nContent-Length: 6\r\n\r\ntoo big";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+        http.set_limits(HttpLimits {
+            max_body_size: 5,
+            ..HttpLimits::default()
+        });
+
+        let err = http.recv_request().unwrap_err();
+        assert!(matches!(
+            err,
+            HttpError::LimitExceeded(ParseError::BodyTooLarge)
+        ));
+        assert!(!http.is_keep_alive());
+
+        // Without the method a response to HEAD would swallow the next response as its body
+        let input: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n\
+            HTTP/1.1 204 No Content\r\n\r\n";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+
+        let response = http.recv_response_to(&Method::Head).unwrap();
+        assert_eq!(response.get_header("Content-Length"), Some("5"));
+        assert!(response.get_body().is_empty());
+
+        let response = http.recv_response_to(&Method::Get).unwrap();
+        assert_eq!(response.get_status_code(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn upgrade_keeps_early_data() {
+        let input: &[u8] =
+            b"GET /chat HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n\x81\x00";
+        let mut http = HttpStream::from_parts(input, Vec::new());
+
+        let request = http.recv_request().unwrap();
+        assert_eq!(request.get_header("Upgrade"), Some("websocket"));
+
+        let response = HttpResponse::builder()
+            .set_version(Version::Http11)
+            .set_status_code(StatusCode::SWITCHING_PROTOCOLS)
+            .set_header("Connection".into(), "Upgrade".into())
+            .set_header("Upgrade".into(), "websocket".into())
+            .set_body(Box::new([]))
+            .build()
+            .unwrap();
+        http.send_response(&response).unwrap();
+
+        let (_, output, early) = http.into_upgraded().unwrap();
+        assert!(output.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+        assert_eq!(early, b"\x81\x00");
+    }
 }
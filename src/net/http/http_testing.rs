@@ -0,0 +1,421 @@
+use std::net::{SocketAddr, TcpStream};
+use std::thread::JoinHandle;
+
+use super::http_server::{error_response, prepare_response};
+use super::{Handler, HttpError, HttpLimits, HttpRequest, HttpResponse, HttpServer, HttpStream};
+use super::{Method, ShutdownHandle, StatusCode, Version};
+
+/// Feeds requests to a [Handler] the way [HttpServer] would, but through in-memory buffers
+/// instead of sockets
+///
+/// Requests are written out and parsed again and the responses go through the same framing as
+/// the server's, so parse errors, limits, HEAD requests and keep-alive behave like they do over a
+/// real connection. Requests appear to come from `127.0.0.1`, see [TestClient::set_peer_addr]
+pub struct TestClient<H: Handler> {
+    handler: H,
+    peer_addr: SocketAddr,
+    limits: HttpLimits,
+}
+
+/// A response received through a [TestClient] or from a [TestServer], with assertions that
+/// can be chained
+///
+/// The assertions panic with the whole response in the message, and report the caller's
+/// location rather than their own
+#[derive(Debug)]
+pub struct TestResponse {
+    response: HttpResponse,
+}
+
+/// An [HttpServer] on an ephemeral loopback port, running on its own thread until it is shut
+/// down or dropped
+pub struct TestServer {
+    addr: SocketAddr,
+    handle: ShutdownHandle,
+    thread: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl<H: Handler> TestClient<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            peer_addr: SocketAddr::from(([127, 0, 0, 1], 50000)),
+            limits: HttpLimits::default(),
+        }
+    }
+
+    pub fn get_handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// The address requests appear to come from, see [HttpRequest::get_peer_addr]
+    pub fn set_peer_addr(&mut self, peer_addr: SocketAddr) -> &mut Self {
+        self.peer_addr = peer_addr;
+        self
+    }
+
+    /// Limits requests are checked against, see [HttpServer::set_limits]
+    pub fn set_limits(&mut self, limits: HttpLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sends `request` and returns the response, a `Content-Length` is added to requests with a
+    /// body that don't have one
+    pub fn send(&self, request: &HttpRequest) -> TestResponse {
+        let mut http = HttpStream::from_parts(std::io::empty(), Vec::new());
+        http.send_request(&with_length(request))
+            .expect("the request body could not be read");
+
+        let (_, bytes) = http.into_parts();
+        self.send_raw(&bytes)
+            .into_iter()
+            .next()
+            .expect("the request got no response")
+    }
+
+    /// Sends a GET request for `url`
+    pub fn get(&self, url: &str) -> TestResponse {
+        self.send(&request(Method::Get, url, Box::new([])))
+    }
+
+    /// Sends a POST request for `url` with `body`
+    pub fn post(&self, url: &str, body: impl Into<Box<[u8]>>) -> TestResponse {
+        self.send(&request(Method::Post, url, body.into()))
+    }
+
+    /// Handles `bytes` as everything a client sent over one connection and returns the
+    /// responses in order
+    ///
+    /// Like the server this stops after a request that closes the connection, a request that
+    /// can't be parsed, which is answered with an error response, or an incomplete request,
+    /// which isn't answered at all
+    pub fn send_raw(&self, bytes: &[u8]) -> Vec<TestResponse> {
+        let mut http = HttpStream::from_parts(bytes, Vec::new());
+        http.set_limits(self.limits);
+
+        let mut methods = Vec::new();
+
+        loop {
+            let mut request = match http.recv_request() {
+                Ok(request) => request,
+                Err(HttpError::ConnectionClosed | HttpError::IncompleteMessage) => break,
+                Err(err) => {
+                    send(&mut http, &error_response(&err));
+                    methods.push(Method::Get);
+                    break;
+                }
+            };
+            request.set_peer_addr(Some(self.peer_addr));
+
+            let mut response = self.handler.handle(&request);
+            let keep_alive = prepare_response(&request, &mut response, request.is_keep_alive());
+
+            send(&mut http, &response);
+            methods.push(request.get_method().clone());
+
+            if !keep_alive || !http.is_keep_alive() {
+                break;
+            }
+        }
+
+        let (_, output) = http.into_parts();
+        let mut http = HttpStream::from_parts(&output[..], std::io::sink());
+
+        methods
+            .iter()
+            .map(|method| {
+                let response = http
+                    .recv_response_to(method)
+                    .unwrap_or_else(|err| panic!("the handler sent an invalid response: {}", err));
+                TestResponse::new(response)
+            })
+            .collect()
+    }
+}
+
+impl TestResponse {
+    pub fn new(response: HttpResponse) -> Self {
+        Self { response }
+    }
+
+    pub fn get_response(&self) -> &HttpResponse {
+        &self.response
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        self.response
+    }
+
+    pub fn get_status_code(&self) -> StatusCode {
+        self.response.get_status_code()
+    }
+
+    pub fn get_header(&self, key: &str) -> Option<&str> {
+        self.response.get_header(key)
+    }
+
+    pub fn get_body(&self) -> &[u8] {
+        self.response.get_body()
+    }
+
+    /// The body as text, with invalid UTF-8 replaced
+    pub fn get_text(&self) -> String {
+        String::from_utf8_lossy(self.get_body()).into_owned()
+    }
+
+    #[track_caller]
+    pub fn assert_status(&self, status_code: StatusCode) -> &Self {
+        if self.get_status_code() != status_code {
+            self.fail(&format!("expected status {}", status_code));
+        }
+        self
+    }
+
+    /// Compares the first value of the header
+    #[track_caller]
+    pub fn assert_header(&self, key: &str, value: &str) -> &Self {
+        if self.get_header(key) != Some(value) {
+            self.fail(&format!("expected header {}: {}", key, value));
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_no_header(&self, key: &str) -> &Self {
+        if self.response.get_headers().contains(key) {
+            self.fail(&format!("expected no {} header", key));
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_body(&self, body: impl AsRef<[u8]>) -> &Self {
+        if self.get_body() != body.as_ref() {
+            let expected = String::from_utf8_lossy(body.as_ref()).into_owned();
+            self.fail(&format!("expected body {:?}", expected));
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_body_contains(&self, text: &str) -> &Self {
+        if !self.get_text().contains(text) {
+            self.fail(&format!("expected the body to contain {:?}", text));
+        }
+        self
+    }
+
+    #[track_caller]
+    fn fail(&self, expectation: &str) -> ! {
+        let head = String::from_utf8_lossy(&self.response.head_bytes()).into_owned();
+        panic!(
+            "{}, got:\n{}{}",
+            expectation,
+            head,
+            String::from_utf8_lossy(self.get_body())
+        );
+    }
+}
+
+impl TestServer {
+    /// Serves `handler` on `127.0.0.1` with logging turned off
+    pub fn start(handler: impl Handler) -> std::io::Result<Self> {
+        let mut server = HttpServer::bind("127.0.0.1:0", 4)?;
+        server.set_logging(false);
+
+        Self::from_server(server, handler)
+    }
+
+    /// Runs an already configured server, which should be bound to a loopback address
+    pub fn from_server(server: HttpServer, handler: impl Handler) -> std::io::Result<Self> {
+        let addr = server.local_addr()?;
+        let handle = server.shutdown_handle()?;
+        let thread = std::thread::spawn(move || server.serve(handler));
+
+        Ok(Self {
+            addr,
+            handle,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn get_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// An `http://` URL for `path` on the server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn connect(&self) -> std::io::Result<TcpStream> {
+        TcpStream::connect(self.addr)
+    }
+
+    /// Sends `request` over a new connection and returns the response, see [TestClient::send]
+    pub fn send(&self, request: &HttpRequest) -> Result<TestResponse, HttpError> {
+        let stream = self.connect()?;
+        let mut http = HttpStream::new(&stream)?;
+
+        http.send_request(&with_length(request))?;
+        let response = http.recv_response_to(request.get_method())?;
+
+        Ok(TestResponse::new(response))
+    }
+
+    /// Stops the server and waits for it, a panic on the server thread is passed on
+    pub fn shutdown(mut self) -> std::io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> std::io::Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+
+        self.handle.shutdown();
+
+        match thread.join() {
+            Ok(res) => res,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        // A panic while already unwinding would abort the test run
+        if std::thread::panicking() {
+            self.handle.shutdown();
+        } else {
+            let _ = self.stop();
+        }
+    }
+}
+
+fn request(method: Method, url: &str, body: Box<[u8]>) -> HttpRequest {
+    let mut headers = super::HeaderMap::new();
+    headers.insert("Host", "localhost");
+
+    HttpRequest::new(method, url.to_string(), Version::Http11, headers, body)
+}
+
+/// Adds the `Content-Length` that a request built in code is easily missing
+fn with_length(request: &HttpRequest) -> HttpRequest {
+    let mut request = request.clone();
+    let headers = request.get_headers();
+
+    if !request.is_body_streaming()
+        && !request.get_body().is_empty()
+        && !headers.contains("Content-Length")
+        && !headers.contains("Transfer-Encoding")
+    {
+        let len = request.get_body().len();
+        request.set_header("Content-Length".into(), len.to_string());
+    }
+
+    request
+}
+
+fn send(http: &mut HttpStream<&[u8], Vec<u8>>, response: &HttpResponse) {
+    http.send_response(response)
+        .unwrap_or_else(|err| panic!("the response body could not be read: {}", err));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{HttpClient, Router};
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router.get("/hello/:name", |_, params| {
+            let body = format!("Hello {}", params.get("name").unwrap());
+
+            HttpResponse::builder()
+                .set_version(Version::Http11)
+                .set_status_code(StatusCode::OK)
+                .set_header("Content-Type".into(), "text/plain".into())
+                .set_body(body.into_bytes().into())
+                .build()
+                .unwrap()
+        });
+        router.post("/echo", |request, _| {
+            let peer = request.get_peer_addr().unwrap();
+            let body = std::io::Cursor::new(request.get_body().to_vec());
+
+            HttpResponse::builder()
+                .set_version(Version::Http11)
+                .set_status_code(StatusCode::OK)
+                .set_header("X-Peer".into(), peer.ip().to_string())
+                .set_body_reader(body, None)
+                .build()
+                .unwrap()
+        });
+        router
+    }
+
+    #[test]
+    fn in_process_requests() {
+        let client = TestClient::new(router());
+
+        client
+            .get("/hello/world")
+            .assert_status(StatusCode::OK)
+            .assert_header("Content-Type", "text/plain")
+            .assert_header("Content-Length", "11")
+            .assert_body("Hello world");
+
+        client
+            .post("/echo", &b"ping"[..])
+            .assert_header("X-Peer", "127.0.0.1")
+            .assert_body("ping");
+
+        client.get("/missing").assert_status(StatusCode::NOT_FOUND);
+
+        let responses = client.send_raw(
+            b"HEAD /hello/a HTTP/1.1\r\n\r\n\
+            GET /hello/b HTTP/1.1\r\nConnection: close\r\n\r\n\
+            GET /hello/c HTTP/1.1\r\n\r\n",
+        );
+        assert_eq!(responses.len(), 2);
+        responses[0]
+            .assert_header("Content-Length", "7")
+            .assert_body("");
+        responses[1]
+            .assert_header("Connection", "close")
+            .assert_body("Hello b");
+
+        let responses = client.send_raw(b"GET /hello/a HTTP/1.1\r\nBad Header\r\n\r\n");
+        responses[0]
+            .assert_status(StatusCode::BAD_REQUEST)
+            .assert_header("Connection", "close");
+        assert!(client.send_raw(b"GET /hello/a HTTP/1.1\r\n").is_empty());
+
+        let response = client.get("/hello/world");
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            response.assert_body("Hello there");
+        }))
+        .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("expected body \"Hello there\", got:\nHTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn ephemeral_server() {
+        let server = TestServer::start(router()).unwrap();
+        assert!(server.get_addr().ip().is_loopback());
+
+        let response = HttpClient::new().get(&server.url("/hello/net")).unwrap();
+        TestResponse::new(response).assert_body("Hello net");
+
+        server
+            .send(&request(Method::Post, "/echo", Box::new(*b"pong")))
+            .unwrap()
+            .assert_header("X-Peer", "127.0.0.1")
+            .assert_body("pong");
+
+        server.shutdown().unwrap();
+    }
+}
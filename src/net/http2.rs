@@ -0,0 +1,325 @@
+//! HTTP/2 over cleartext TCP (h2c) as described by RFC 9113, with HPACK header compression as
+//! described by RFC 7541
+//!
+//! [HttpServer](crate::net::http::HttpServer) serves HTTP/2 once enabled with
+//! [HttpServer::set_http2](crate::net::http::HttpServer::set_http2), both to clients that start
+//! with the connection preface right away and to those that upgrade an HTTP/1.1 request with
+//! `Upgrade: h2c`. Every stream is handled on its own thread, so handlers see the same
+//! [HttpRequest](crate::net::http::HttpRequest)s as over HTTP/1.x, just with
+//! [Version::Http2](crate::net::http::Version::Http2), and their responses are sent back with
+//! flow control as they finish. [Http2Client] sends requests from any number of threads over a
+//! single multiplexed connection
+//!
+//! Server push and stream priorities are not supported, pushes are disabled through SETTINGS and
+//! priority signals are ignored
+
+mod http2_client;
+mod http2_connection;
+mod http2_frame;
+mod http2_hpack;
+mod http2_huffman;
+mod http2_server;
+
+use std::fmt::Display;
+
+pub use http2_client::Http2Client;
+pub use http2_frame::{Frame, FRAME_HEADER_LEN};
+pub use http2_hpack::{HeaderFields, HpackDecoder, HpackEncoder, HpackError};
+pub(crate) use http2_server::{serve, upgrade_settings, ServerOptions};
+
+use crate::net::http::HttpError;
+
+/// What a client sends first on every HTTP/2 connection (RFC 9113 3.4)
+pub const PREFACE: &[u8; 24] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+#[derive(Debug)]
+pub enum Http2Error {
+    Io(std::io::Error),
+    /// The connection was closed before the exchange finished
+    ConnectionClosed,
+    /// The peer broke the protocol in the given way, the connection is closed with a GOAWAY
+    /// carrying the code
+    Protocol(ErrorCode, &'static str),
+    /// The peer closed the connection with a GOAWAY carrying the code before it processed the
+    /// stream, so the request can safely be retried
+    GoAway(ErrorCode),
+    /// The peer reset the stream with the code
+    StreamReset(ErrorCode),
+    /// Received headers that don't make up a valid request or response
+    Malformed(&'static str),
+}
+
+impl Display for Http2Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::ConnectionClosed => write!(f, "HTTP/2 Connection Closed"),
+            Self::Protocol(code, reason) => write!(f, "HTTP/2 {}: {}", code, reason),
+            Self::GoAway(code) => write!(f, "HTTP/2 Connection Going Away: {}", code),
+            Self::StreamReset(code) => write!(f, "HTTP/2 Stream Reset: {}", code),
+            Self::Malformed(reason) => write!(f, "Malformed HTTP/2 Message: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Http2Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Http2Error {
+    fn from(err: std::io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Self>()) {
+            return *err.into_inner().unwrap().downcast::<Self>().unwrap();
+        }
+
+        Self::Io(err)
+    }
+}
+
+impl From<HttpError> for Http2Error {
+    fn from(err: HttpError) -> Self {
+        Self::Io(err.into())
+    }
+}
+
+impl From<HpackError> for Http2Error {
+    fn from(_: HpackError) -> Self {
+        Self::Protocol(ErrorCode::COMPRESSION_ERROR, "Invalid header block")
+    }
+}
+
+impl From<Http2Error> for std::io::Error {
+    fn from(err: Http2Error) -> Self {
+        match err {
+            Http2Error::Io(err) => err,
+            Http2Error::ConnectionClosed => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, err)
+            }
+            err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Why a stream was reset or a connection closed (RFC 9113 7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorCode(u32);
+
+impl ErrorCode {
+    pub const NO_ERROR: Self = Self(0x0);
+    pub const PROTOCOL_ERROR: Self = Self(0x1);
+    pub const INTERNAL_ERROR: Self = Self(0x2);
+    pub const FLOW_CONTROL_ERROR: Self = Self(0x3);
+    pub const SETTINGS_TIMEOUT: Self = Self(0x4);
+    pub const STREAM_CLOSED: Self = Self(0x5);
+    pub const FRAME_SIZE_ERROR: Self = Self(0x6);
+    pub const REFUSED_STREAM: Self = Self(0x7);
+    pub const CANCEL: Self = Self(0x8);
+    pub const COMPRESSION_ERROR: Self = Self(0x9);
+    pub const CONNECT_ERROR: Self = Self(0xA);
+    pub const ENHANCE_YOUR_CALM: Self = Self(0xB);
+    pub const INADEQUATE_SECURITY: Self = Self(0xC);
+    pub const HTTP_1_1_REQUIRED: Self = Self(0xD);
+
+    pub fn new(code: u32) -> Self {
+        Self(code)
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+
+    /// The name the RFC gives the code, `None` for unknown codes
+    pub fn name(&self) -> Option<&'static str> {
+        const NAMES: [&str; 14] = [
+            "NO_ERROR",
+            "PROTOCOL_ERROR",
+            "INTERNAL_ERROR",
+            "FLOW_CONTROL_ERROR",
+            "SETTINGS_TIMEOUT",
+            "STREAM_CLOSED",
+            "FRAME_SIZE_ERROR",
+            "REFUSED_STREAM",
+            "CANCEL",
+            "COMPRESSION_ERROR",
+            "CONNECT_ERROR",
+            "ENHANCE_YOUR_CALM",
+            "INADEQUATE_SECURITY",
+            "HTTP_1_1_REQUIRED",
+        ];
+
+        NAMES.get(self.0 as usize).copied()
+    }
+}
+
+impl From<u32> for ErrorCode {
+    fn from(code: u32) -> Self {
+        Self(code)
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "Error Code {:#x}", self.0),
+        }
+    }
+}
+
+/// The parameters one end of a connection announces in its SETTINGS frames (RFC 9113 6.5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Largest HPACK dynamic table the sender's decoder allows. Defaults to 4096
+    pub header_table_size: u32,
+    /// Defaults to true, though neither side here ever pushes
+    pub enable_push: bool,
+    /// Most streams the sender lets its peer open at once. Defaults to unlimited
+    pub max_concurrent_streams: Option<u32>,
+    /// Flow control window of new streams for data the sender receives. Defaults to 65535
+    pub initial_window_size: u32,
+    /// Largest frame payload the sender accepts. Defaults to 16384
+    pub max_frame_size: u32,
+    /// Largest uncompressed header list the sender accepts, counting 32 bytes of overhead per
+    /// field. Defaults to unlimited
+    pub max_header_list_size: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            header_table_size: 4096,
+            enable_push: true,
+            max_concurrent_streams: None,
+            initial_window_size: 65535,
+            max_frame_size: 16384,
+            max_header_list_size: None,
+        }
+    }
+}
+
+impl Settings {
+    const HEADER_TABLE_SIZE: u16 = 0x1;
+    const ENABLE_PUSH: u16 = 0x2;
+    const MAX_CONCURRENT_STREAMS: u16 = 0x3;
+    const INITIAL_WINDOW_SIZE: u16 = 0x4;
+    const MAX_FRAME_SIZE: u16 = 0x5;
+    const MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+    /// Applies the parameters of a SETTINGS frame, unknown parameters are ignored
+    pub fn apply(&mut self, params: &[(u16, u32)]) -> Result<(), Http2Error> {
+        for (id, value) in params.iter().copied() {
+            match id {
+                Self::HEADER_TABLE_SIZE => self.header_table_size = value,
+                Self::ENABLE_PUSH => {
+                    self.enable_push = match value {
+                        0 => false,
+                        1 => true,
+                        _ => {
+                            return Err(Http2Error::Protocol(
+                                ErrorCode::PROTOCOL_ERROR,
+                                "Invalid SETTINGS_ENABLE_PUSH",
+                            ))
+                        }
+                    }
+                }
+                Self::MAX_CONCURRENT_STREAMS => self.max_concurrent_streams = Some(value),
+                Self::INITIAL_WINDOW_SIZE => {
+                    if value > MAX_WINDOW_SIZE {
+                        return Err(Http2Error::Protocol(
+                            ErrorCode::FLOW_CONTROL_ERROR,
+                            "Invalid SETTINGS_INITIAL_WINDOW_SIZE",
+                        ));
+                    }
+                    self.initial_window_size = value;
+                }
+                Self::MAX_FRAME_SIZE => {
+                    if !(MIN_MAX_FRAME_SIZE..=MAX_MAX_FRAME_SIZE).contains(&value) {
+                        return Err(Http2Error::Protocol(
+                            ErrorCode::PROTOCOL_ERROR,
+                            "Invalid SETTINGS_MAX_FRAME_SIZE",
+                        ));
+                    }
+                    self.max_frame_size = value;
+                }
+                Self::MAX_HEADER_LIST_SIZE => self.max_header_list_size = Some(value),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The parameters that differ from the defaults, for a SETTINGS frame
+    pub fn to_params(&self) -> Vec<(u16, u32)> {
+        let default = Self::default();
+        let mut params = Vec::new();
+
+        if self.header_table_size != default.header_table_size {
+            params.push((Self::HEADER_TABLE_SIZE, self.header_table_size));
+        }
+        if self.enable_push != default.enable_push {
+            params.push((Self::ENABLE_PUSH, self.enable_push as u32));
+        }
+        if let Some(max) = self.max_concurrent_streams {
+            params.push((Self::MAX_CONCURRENT_STREAMS, max));
+        }
+        if self.initial_window_size != default.initial_window_size {
+            params.push((Self::INITIAL_WINDOW_SIZE, self.initial_window_size));
+        }
+        if self.max_frame_size != default.max_frame_size {
+            params.push((Self::MAX_FRAME_SIZE, self.max_frame_size));
+        }
+        if let Some(max) = self.max_header_list_size {
+            params.push((Self::MAX_HEADER_LIST_SIZE, max));
+        }
+
+        params
+    }
+}
+
+/// Largest flow control window (RFC 9113 6.9.1)
+pub(crate) const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+
+/// Smallest value SETTINGS_MAX_FRAME_SIZE can have, and so the largest frame always allowed
+pub(crate) const MIN_MAX_FRAME_SIZE: u32 = 1 << 14;
+
+pub(crate) const MAX_MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        let mut settings = Settings::default();
+        settings
+            .apply(&[(0x2, 0), (0x4, 1 << 20), (0x5, 1 << 16), (0x99, 7)])
+            .unwrap();
+        assert!(!settings.enable_push);
+        assert_eq!(settings.initial_window_size, 1 << 20);
+        assert_eq!(
+            settings.to_params(),
+            [(0x2, 0), (0x4, 1 << 20), (0x5, 1 << 16)]
+        );
+
+        for (param, code) in [
+            ((0x2, 2), ErrorCode::PROTOCOL_ERROR),
+            ((0x4, 1 << 31), ErrorCode::FLOW_CONTROL_ERROR),
+            ((0x5, 100), ErrorCode::PROTOCOL_ERROR),
+        ] {
+            let err = Settings::default().apply(&[param]).unwrap_err();
+            assert!(matches!(err, Http2Error::Protocol(err_code, _) if err_code == code));
+        }
+
+        assert_eq!(
+            ErrorCode::FLOW_CONTROL_ERROR.to_string(),
+            "FLOW_CONTROL_ERROR"
+        );
+        assert_eq!(ErrorCode::new(0x42).to_string(), "Error Code 0x42");
+    }
+}
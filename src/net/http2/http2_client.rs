@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use super::http2_connection::{is_connection_specific, regular_field};
+use super::http2_connection::{Event, Input, Output, RecvWindow};
+use super::{ErrorCode, Frame, HeaderFields, HpackError, Http2Error, Settings, PREFACE};
+use crate::net::http::{HeaderMap, HttpError, HttpLimits, HttpRequest, HttpResponse};
+use crate::net::http::{Method, ParseError, StatusCode, Version};
+use crate::net::url::Url;
+
+/// How much of a streaming request body is read and sent at a time
+const BODY_CHUNK_SIZE: usize = 16 * 1024;
+
+/// A blocking HTTP/2 client for a single server, which it talks to over cleartext TCP with prior
+/// knowledge
+///
+/// Every request is sent on its own stream of the one connection, so the client can be shared
+/// between any number of threads whose requests are then multiplexed. Responses are received by a
+/// background thread and buffered in full. Unlike [HttpClient](crate::net::http::HttpClient) it
+/// doesn't follow redirects or decompress bodies
+pub struct Http2Client {
+    shared: Arc<Shared>,
+    stream: TcpStream,
+    /// Used for requests that neither have an absolute URL nor a `Host` header
+    authority: String,
+    reader: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    output: Output<TcpStream>,
+    state: Mutex<ClientState>,
+    /// Signalled whenever a response completes or the connection goes away
+    received: Condvar,
+}
+
+#[derive(Default)]
+struct ClientState {
+    exchanges: HashMap<u32, Exchange>,
+    /// The last stream the server processes and why, once it sent a GOAWAY
+    going_away: Option<(u32, ErrorCode)>,
+    /// Why no more responses arrive, once the connection failed or was closed
+    closed: Option<Http2Error>,
+}
+
+/// The response to a request that is still being received
+struct Exchange {
+    response: Option<HttpResponse>,
+    body: Vec<u8>,
+    window: RecvWindow,
+    done: Option<Result<(), Http2Error>>,
+}
+
+impl Exchange {
+    fn new() -> Self {
+        Self {
+            response: None,
+            body: Vec::new(),
+            window: RecvWindow::new(),
+            done: None,
+        }
+    }
+}
+
+impl Http2Client {
+    /// Connects to an HTTP/2 server, the connection preface and SETTINGS are sent right away
+    pub fn connect(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let peer: SocketAddr = stream.peer_addr()?;
+
+        let output = Output::new(stream.try_clone()?);
+        let limits = HttpLimits::default();
+
+        let settings = Settings {
+            enable_push: false,
+            max_header_list_size: Some(limits.max_header_size as u32),
+            ..Settings::default()
+        };
+        let mut preface = PREFACE.to_vec();
+        Frame::Settings {
+            ack: false,
+            params: settings.to_params(),
+        }
+        .encode(&mut preface);
+        {
+            use std::io::Write;
+            (&stream).write_all(&preface)?;
+        }
+
+        let shared = Arc::new(Shared {
+            output,
+            state: Mutex::new(ClientState::default()),
+            received: Condvar::new(),
+        });
+
+        let input = Input::new(stream.try_clone()?, Vec::new(), limits.max_header_size);
+        let reader = {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || shared.receive(input, limits))
+        };
+
+        Ok(Self {
+            shared,
+            stream,
+            authority: peer.to_string(),
+            reader: Some(reader),
+        })
+    }
+
+    pub fn get(&self, url: &str) -> std::io::Result<HttpResponse> {
+        self.send(request(Method::Get, url, Box::new([])))
+    }
+
+    /// Sends `request` on a new stream and waits for its response
+    ///
+    /// The URL can be absolute (`http://host:port/path?query`) or just the path, in which case
+    /// the `Host` header or else the address connected to is sent as the authority. Requests the
+    /// server refused with a GOAWAY before processing them fail with
+    /// [Http2Error::GoAway] wrapped in an [std::io::Error] and can be retried on a new connection
+    pub fn send(&self, mut request: HttpRequest) -> std::io::Result<HttpResponse> {
+        let (authority, path) = self.target(&request)?;
+
+        let method = request.get_method().clone();
+        let mut body = request.take_body();
+
+        let mut headers: Vec<(String, &str)> = request
+            .get_headers()
+            .iter()
+            .filter(|(name, _)| !is_connection_specific(name) && !name.eq_ignore_ascii_case("host"))
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+
+        let content_length = body.content_length();
+        let needs_length = content_length.is_some_and(|len| len > 0)
+            || matches!(method, Method::Post | Method::Put | Method::Patch);
+        let length = content_length.map(|len| len.to_string());
+        if let Some(length) = &length {
+            if needs_length && !request.get_headers().contains("Content-Length") {
+                headers.push(("content-length".into(), length));
+            }
+        }
+
+        let pseudo = [
+            (":method", method.as_str()),
+            (":scheme", "http"),
+            (":authority", authority.as_str()),
+            (":path", path.as_str()),
+        ];
+        let fields = pseudo
+            .into_iter()
+            .chain(headers.iter().map(|(name, value)| (name.as_str(), *value)));
+
+        if let Some(err) = self.shared.unavailable() {
+            return Err(err.into());
+        }
+
+        let end_stream = content_length == Some(0);
+        let stream_id = self.shared.output.open_request(fields, end_stream)?;
+        self.shared
+            .lock()
+            .exchanges
+            .entry(stream_id)
+            .or_insert_with(Exchange::new);
+
+        let sent = match end_stream {
+            true => Ok(()),
+            false => self.send_body(stream_id, &mut body),
+        };
+
+        let received = self.shared.wait_for(stream_id);
+        self.shared.output.finish_stream(stream_id);
+
+        match (received, sent) {
+            (Ok(response), _) => Ok(response),
+            // A server that answered before the whole body was sent may stop it with a reset
+            (Err(err), Err(Http2Error::StreamReset(_))) | (Err(err), Ok(())) => Err(err.into()),
+            (Err(_), Err(err)) => Err(err.into()),
+        }
+    }
+
+    fn send_body(
+        &self,
+        stream_id: u32,
+        body: &mut crate::net::http::Body,
+    ) -> Result<(), Http2Error> {
+        let output = &self.shared.output;
+
+        if !body.is_stream() {
+            return output.send_data(stream_id, body.as_bytes(), true);
+        }
+
+        let mut buf = vec![0; BODY_CHUNK_SIZE];
+        loop {
+            match body.read(&mut buf) {
+                Ok(0) => return output.send_data(stream_id, &[], true),
+                Ok(len) => output.send_data(stream_id, &buf[..len], false)?,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    let _ = output.reset_stream(stream_id, ErrorCode::CANCEL, false);
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+
+    /// The authority and the path of a request, from its URL or else the `Host` header
+    fn target(&self, request: &HttpRequest) -> std::io::Result<(String, String)> {
+        let url = request.get_url();
+        let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+
+        if url.starts_with('/') || url == "*" {
+            let authority = request.get_header("Host").unwrap_or(&self.authority);
+            return Ok((authority.to_string(), url.to_string()));
+        }
+
+        let url = Url::parse(url).map_err(|_| invalid("Invalid request URL"))?;
+        match url.scheme() {
+            Some(scheme) if scheme.eq_ignore_ascii_case("http") => {}
+            _ => return Err(invalid("Only http URLs can be sent over cleartext HTTP/2")),
+        }
+
+        let authority = url.authority().ok_or_else(|| invalid("URL has no host"))?;
+        Ok((authority, url.path_and_query()))
+    }
+}
+
+impl Drop for Http2Client {
+    fn drop(&mut self) {
+        let _ = self.shared.output.go_away(0, ErrorCode::NO_ERROR, "");
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, ClientState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Why no new request can be sent, if the connection is going away or gone
+    fn unavailable(&self) -> Option<Http2Error> {
+        let state = self.lock();
+
+        match (&state.closed, state.going_away) {
+            (Some(err), _) => Some(duplicate(err)),
+            (None, Some((_, error_code))) => Some(Http2Error::GoAway(error_code)),
+            (None, None) => None,
+        }
+    }
+
+    fn wait_for(&self, stream_id: u32) -> Result<HttpResponse, Http2Error> {
+        let mut state = self.lock();
+
+        loop {
+            let done = state
+                .exchanges
+                .get(&stream_id)
+                .is_some_and(|e| e.done.is_some());
+            if done {
+                let exchange = state.exchanges.remove(&stream_id).unwrap();
+                exchange.done.unwrap()?;
+
+                let mut response = exchange.response.unwrap();
+                response.set_body(exchange.body.into_boxed_slice());
+                return Ok(response);
+            }
+
+            if let Some(err) = &state.closed {
+                let err = duplicate(err);
+                state.exchanges.remove(&stream_id);
+                return Err(err);
+            }
+
+            state = self
+                .received
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// Receives responses until the connection fails or is closed
+    fn receive(&self, mut input: Input<TcpStream>, limits: HttpLimits) {
+        let err = loop {
+            let event = match input.next(&self.output) {
+                Ok(event) => event,
+                Err(err) => break err,
+            };
+
+            let mut state = self.lock();
+            let res = match event {
+                Event::Headers {
+                    stream_id,
+                    fields,
+                    end_stream,
+                } => self.headers(&mut state, stream_id, fields, end_stream),
+                Event::Data {
+                    stream_id,
+                    data,
+                    len,
+                    end_stream,
+                } => self.data(&mut state, stream_id, data, len, end_stream, &limits),
+                Event::Reset {
+                    stream_id,
+                    error_code,
+                } => {
+                    if let Some(exchange) = state.exchanges.get_mut(&stream_id) {
+                        exchange
+                            .done
+                            .get_or_insert(Err(Http2Error::StreamReset(error_code)));
+                    }
+                    Ok(())
+                }
+                Event::GoAway {
+                    last_stream_id,
+                    error_code,
+                } => {
+                    // Streams after the last one were never seen by the server
+                    for (stream_id, exchange) in state.exchanges.iter_mut() {
+                        if *stream_id > last_stream_id {
+                            exchange
+                                .done
+                                .get_or_insert(Err(Http2Error::GoAway(error_code)));
+                        }
+                    }
+                    state.going_away = Some((last_stream_id, error_code));
+                    Ok(())
+                }
+            };
+
+            self.received.notify_all();
+            if let Err(err) = res {
+                break err;
+            }
+        };
+
+        if let Http2Error::Protocol(error_code, reason) = &err {
+            let _ = self.output.go_away(0, *error_code, reason);
+        }
+        self.output.close();
+
+        let mut state = self.lock();
+        for exchange in state.exchanges.values_mut() {
+            exchange.done.get_or_insert_with(|| Err(duplicate(&err)));
+        }
+        state.closed = Some(err);
+        self.received.notify_all();
+    }
+
+    /// The exchange of a stream the client opened, which it might not have registered yet
+    fn exchange<'s>(&self, state: &'s mut ClientState, stream_id: u32) -> Option<&'s mut Exchange> {
+        if self.output.is_open(stream_id) {
+            return Some(
+                state
+                    .exchanges
+                    .entry(stream_id)
+                    .or_insert_with(Exchange::new),
+            );
+        }
+
+        state.exchanges.get_mut(&stream_id)
+    }
+
+    fn headers(
+        &self,
+        state: &mut ClientState,
+        stream_id: u32,
+        fields: Result<HeaderFields, HpackError>,
+        end_stream: bool,
+    ) -> Result<(), Http2Error> {
+        let Some(exchange) = self.exchange(state, stream_id) else {
+            if stream_id.is_multiple_of(2) {
+                return Err(protocol_error("Server opened a stream"));
+            }
+            return Ok(());
+        };
+        if exchange.done.is_some() {
+            return Ok(());
+        }
+
+        let res = fields
+            .map_err(|_| Http2Error::Malformed("Response header list too large"))
+            .and_then(|fields| match &mut exchange.response {
+                None => to_response(fields).map(|response| {
+                    // Interim responses are skipped
+                    if !response.get_status_code().is_informational() {
+                        exchange.response = Some(response);
+                    }
+                }),
+                Some(response) if end_stream => add_trailers(response, fields),
+                Some(_) => Err(Http2Error::Malformed("Trailers without END_STREAM")),
+            });
+
+        match res {
+            Ok(()) if end_stream => self.complete(exchange, stream_id),
+            Ok(()) => Ok(()),
+            Err(err) => self.fail(exchange, stream_id, err),
+        }
+    }
+
+    fn data(
+        &self,
+        state: &mut ClientState,
+        stream_id: u32,
+        data: Vec<u8>,
+        len: u32,
+        end_stream: bool,
+        limits: &HttpLimits,
+    ) -> Result<(), Http2Error> {
+        let Some(exchange) = self.exchange(state, stream_id) else {
+            return Ok(());
+        };
+        if exchange.done.is_some() {
+            return Ok(());
+        }
+
+        if exchange.response.is_none() {
+            return self.fail(
+                exchange,
+                stream_id,
+                Http2Error::Malformed("DATA before HEADERS"),
+            );
+        }
+
+        match exchange.window.consume(len) {
+            Ok(Some(increment)) if !end_stream => {
+                self.output.send_frame(&Frame::WindowUpdate {
+                    stream_id,
+                    increment,
+                })?;
+            }
+            Ok(_) => {}
+            Err(err) => return self.fail(exchange, stream_id, err),
+        }
+
+        if (exchange.body.len() + data.len()) as u64 > limits.max_body_size {
+            let err = HttpError::LimitExceeded(ParseError::BodyTooLarge);
+            return self.fail(exchange, stream_id, err.into());
+        }
+        exchange.body.extend_from_slice(&data);
+
+        match end_stream {
+            true => self.complete(exchange, stream_id),
+            false => Ok(()),
+        }
+    }
+
+    /// Finishes a response once the server ended the stream
+    fn complete(&self, exchange: &mut Exchange, stream_id: u32) -> Result<(), Http2Error> {
+        let Some(response) = &exchange.response else {
+            let err = Http2Error::Malformed("Stream ended without a response");
+            return self.fail(exchange, stream_id, err);
+        };
+
+        let expected_length = response
+            .get_header("Content-Length")
+            .map(|len| len.parse::<u64>());
+        let has_body = !(response.get_status_code() == StatusCode::NO_CONTENT
+            || response.get_status_code() == StatusCode::NOT_MODIFIED);
+
+        // Responses to HEAD requests keep the Content-Length of the body they would have had
+        let length_matches = match expected_length {
+            Some(Ok(len)) => len == exchange.body.len() as u64 || exchange.body.is_empty(),
+            Some(Err(_)) => false,
+            None => true,
+        };
+        if !length_matches || (!has_body && !exchange.body.is_empty()) {
+            let err = Http2Error::Malformed("Body doesn't match its Content-Length");
+            return self.fail(exchange, stream_id, err);
+        }
+
+        exchange.done = Some(Ok(()));
+        Ok(())
+    }
+
+    /// Fails a single stream, resetting it unless the server already ended it
+    fn fail(
+        &self,
+        exchange: &mut Exchange,
+        stream_id: u32,
+        err: Http2Error,
+    ) -> Result<(), Http2Error> {
+        let error_code = match &err {
+            Http2Error::Protocol(error_code, _) => *error_code,
+            Http2Error::Malformed(_) => ErrorCode::PROTOCOL_ERROR,
+            _ => ErrorCode::CANCEL,
+        };
+
+        exchange.done = Some(Err(err));
+        self.output.reset_stream(stream_id, error_code, false)
+    }
+}
+
+fn request(method: Method, url: &str, body: Box<[u8]>) -> HttpRequest {
+    HttpRequest::new(method, url.into(), Version::Http2, HeaderMap::new(), body)
+}
+
+/// Builds a response from the fields of its header block (RFC 9113 8.3.2)
+fn to_response(fields: HeaderFields) -> Result<HttpResponse, Http2Error> {
+    let mut fields = fields.into_iter();
+
+    let status_code = match fields.next() {
+        Some((name, value)) if name == b":status" => std::str::from_utf8(&value)
+            .ok()
+            .filter(|status| status.len() == 3)
+            .and_then(|status| status.parse::<u16>().ok())
+            .and_then(|status| StatusCode::new(status).ok())
+            .ok_or(Http2Error::Malformed("Invalid :status"))?,
+        _ => return Err(Http2Error::Malformed("Missing :status")),
+    };
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in fields {
+        if name.starts_with(b":") {
+            return Err(Http2Error::Malformed("Unexpected pseudo-header"));
+        }
+        let (name, value) = regular_field(name, value)?;
+        headers.append(name, value);
+    }
+
+    Ok(HttpResponse::new(
+        Version::Http2,
+        status_code,
+        status_code
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string(),
+        headers,
+        Box::new([]),
+    ))
+}
+
+/// Trailers are kept apart from the response's headers, just like those of a chunked HTTP/1.1
+/// body
+fn add_trailers(response: &mut HttpResponse, fields: HeaderFields) -> Result<(), Http2Error> {
+    let mut trailers = HeaderMap::new();
+
+    for (name, value) in fields {
+        if name.starts_with(b":") {
+            return Err(Http2Error::Malformed("Pseudo-header in trailers"));
+        }
+        let (name, value) = regular_field(name, value)?;
+        trailers.append(name, value);
+    }
+
+    response.set_trailers(trailers);
+    Ok(())
+}
+
+/// A copy of an error to hand to every stream a connection failure affects
+fn duplicate(err: &Http2Error) -> Http2Error {
+    match err {
+        Http2Error::Io(err) => Http2Error::Io(std::io::Error::new(err.kind(), err.to_string())),
+        Http2Error::ConnectionClosed => Http2Error::ConnectionClosed,
+        Http2Error::Protocol(error_code, reason) => Http2Error::Protocol(*error_code, reason),
+        Http2Error::GoAway(error_code) => Http2Error::GoAway(*error_code),
+        Http2Error::StreamReset(error_code) => Http2Error::StreamReset(*error_code),
+        Http2Error::Malformed(reason) => Http2Error::Malformed(reason),
+    }
+}
+
+fn protocol_error(reason: &'static str) -> Http2Error {
+    Http2Error::Protocol(ErrorCode::PROTOCOL_ERROR, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::http::{HttpClient, HttpServer, TestServer};
+    use std::time::{Duration, Instant};
+
+    fn server() -> TestServer {
+        let mut server = HttpServer::bind("127.0.0.1:0", 2).unwrap();
+        server.set_logging(false).set_http2(true);
+
+        TestServer::from_server(server, |request: &HttpRequest| {
+            let mut response = HttpResponse::new(
+                Version::Http11,
+                StatusCode::OK,
+                "OK".into(),
+                HeaderMap::new(),
+                request.get_body().into(),
+            );
+            response.set_header("X-Version".into(), request.get_version().to_string());
+            response.set_header("X-Url".into(), request.get_url().into());
+
+            match request.get_url() {
+                "/slow" => std::thread::sleep(Duration::from_millis(200)),
+                "/stream" => {
+                    let body = std::io::Cursor::new(vec![b'x'; 100_000]);
+                    response.set_body_reader(body, None);
+                }
+                _ => {}
+            }
+
+            response
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn requests_over_one_connection() {
+        let server = server();
+        let client = Http2Client::connect(server.get_addr()).unwrap();
+
+        let response = client.get(&server.url("/hello?x=1")).unwrap();
+        assert_eq!(response.get_version(), Version::Http2);
+        assert_eq!(response.get_status_code(), StatusCode::OK);
+        assert_eq!(response.get_header("X-Version"), Some("HTTP/2"));
+        assert_eq!(response.get_header("X-Url"), Some("/hello?x=1"));
+
+        // Larger than every window in both directions
+        let body: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+        let post = request(Method::Post, "/echo", body.clone().into_boxed_slice());
+        let response = client.send(post).unwrap();
+        assert_eq!(response.get_body(), &body[..]);
+        assert_eq!(response.get_header("Content-Length"), Some("300000"));
+
+        let response = client.get("/stream").unwrap();
+        assert_eq!(response.get_body().len(), 100_000);
+
+        let response = client.send(request(Method::Head, "/echo", Box::new([])));
+        assert!(response.unwrap().get_body().is_empty());
+
+        // The same server still speaks HTTP/1.1
+        let response = HttpClient::new().get(&server.url("/plain")).unwrap();
+        assert_eq!(response.get_header("X-Version"), Some("HTTP/1.1"));
+
+        drop(client);
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn requests_are_multiplexed() {
+        let server = server();
+        let client = Http2Client::connect(server.get_addr()).unwrap();
+        let start = Instant::now();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let response = client.get("/slow").unwrap();
+                    assert_eq!(response.get_header("X-Url"), Some("/slow"));
+                });
+            }
+        });
+
+        // Eight requests on a server with two workers, which would take 800ms one at a time
+        assert!(start.elapsed() < Duration::from_millis(600));
+        drop(client);
+        server.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use super::http2_frame::FrameReader;
+use super::{ErrorCode, Frame, HeaderFields, HpackDecoder, HpackEncoder, HpackError};
+use super::{Http2Error, Settings};
+use super::{MAX_WINDOW_SIZE, MIN_MAX_FRAME_SIZE};
+
+/// The window both ends start out with, for the connection and for every stream
+const DEFAULT_WINDOW_SIZE: u32 = 65535;
+
+/// Fields that only make sense for a single HTTP/1.x connection and so are not allowed in
+/// HTTP/2 messages (RFC 9113 8.2.2)
+const CONNECTION_SPECIFIC: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub(crate) fn is_connection_specific(name: &str) -> bool {
+    CONNECTION_SPECIFIC
+        .iter()
+        .any(|field| field.eq_ignore_ascii_case(name))
+}
+
+/// Checks a decoded field that isn't a pseudo-header and turns it into strings (RFC 9113 8.2.1)
+pub(crate) fn regular_field(name: Vec<u8>, value: Vec<u8>) -> Result<(String, String), Http2Error> {
+    let valid_name = !name.is_empty()
+        && name.iter().all(|b| {
+            b.is_ascii_graphic() && !b.is_ascii_uppercase() && !b"\"(),/:;<=>?@[\\]{}".contains(b)
+        });
+    if !valid_name {
+        return Err(Http2Error::Malformed("Invalid field name"));
+    }
+
+    let valid_value = !value.iter().any(|b| matches!(b, b'\0' | b'\r' | b'\n'))
+        && !value.first().is_some_and(|b| matches!(b, b' ' | b'\t'))
+        && !value.last().is_some_and(|b| matches!(b, b' ' | b'\t'));
+    if !valid_value {
+        return Err(Http2Error::Malformed("Invalid field value"));
+    }
+
+    let name = String::from_utf8(name).unwrap();
+    if is_connection_specific(&name) {
+        return Err(Http2Error::Malformed("Connection-specific field"));
+    }
+
+    match String::from_utf8(value) {
+        Ok(value) => Ok((name, value)),
+        Err(_) => Err(Http2Error::Malformed("Field value is not UTF-8")),
+    }
+}
+
+/// Whether a receive returned because the read timeout ran out rather than because it failed
+pub(crate) fn is_timeout(err: &Http2Error) -> bool {
+    matches!(err, Http2Error::Io(err) if matches!(
+        err.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    ))
+}
+
+/// How much more data the peer may send on the connection or a stream
+///
+/// Everything received is buffered as a whole, so the window is topped up again as soon as half
+/// of it is used up rather than when the data is consumed
+#[derive(Debug)]
+pub(crate) struct RecvWindow(i64);
+
+impl RecvWindow {
+    pub(crate) fn new() -> Self {
+        Self(DEFAULT_WINDOW_SIZE as i64)
+    }
+
+    /// Takes `len` bytes off the window and returns the increment to send in a WINDOW_UPDATE if
+    /// it is due
+    pub(crate) fn consume(&mut self, len: u32) -> Result<Option<u32>, Http2Error> {
+        self.0 -= len as i64;
+
+        if self.0 < 0 {
+            return Err(Http2Error::Protocol(
+                ErrorCode::FLOW_CONTROL_ERROR,
+                "Data beyond the flow control window",
+            ));
+        }
+
+        if self.0 > DEFAULT_WINDOW_SIZE as i64 / 2 {
+            return Ok(None);
+        }
+
+        let increment = DEFAULT_WINDOW_SIZE - self.0 as u32;
+        self.0 = DEFAULT_WINDOW_SIZE as i64;
+        Ok(Some(increment))
+    }
+}
+
+/// The sending half of a connection, shared by every thread that sends on one of its streams
+///
+/// Frames are written whole under a lock, header blocks together with their CONTINUATION frames,
+/// and DATA waits for both the connection's and the stream's flow control window
+pub(crate) struct Output<W: Write> {
+    state: Mutex<OutputState<W>>,
+    /// Signalled whenever a window grows, a stream finishes or is reset or the connection closes
+    changed: Condvar,
+}
+
+struct OutputState<W: Write> {
+    writer: W,
+    encoder: HpackEncoder,
+    peer: Settings,
+    window: i64,
+    streams: HashMap<u32, StreamOutput>,
+    /// The stream the next request goes out on, clients only
+    next_stream_id: u32,
+    /// Set once the connection failed or was closed, nothing is sent from then on
+    closed: bool,
+}
+
+struct StreamOutput {
+    window: i64,
+    reset: Option<ErrorCode>,
+}
+
+impl<W: Write> Output<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            state: Mutex::new(OutputState {
+                writer,
+                encoder: HpackEncoder::new(),
+                peer: Settings::default(),
+                window: DEFAULT_WINDOW_SIZE as i64,
+                streams: HashMap::new(),
+                next_stream_id: 1,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, OutputState<W>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn wait<'s>(&self, state: MutexGuard<'s, OutputState<W>>) -> MutexGuard<'s, OutputState<W>> {
+        self.changed
+            .wait(state)
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn send_frame(&self, frame: &Frame) -> Result<(), Http2Error> {
+        let mut bytes = Vec::new();
+        frame.encode(&mut bytes);
+        self.write(&mut self.lock(), &bytes)
+    }
+
+    fn write(&self, state: &mut OutputState<W>, bytes: &[u8]) -> Result<(), Http2Error> {
+        if state.closed {
+            return Err(Http2Error::ConnectionClosed);
+        }
+
+        let res = state
+            .writer
+            .write_all(bytes)
+            .and_then(|_| state.writer.flush());
+        if let Err(err) = res {
+            state.closed = true;
+            self.changed.notify_all();
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    /// Starts sending on a stream the peer opened
+    pub(crate) fn open_stream(&self, stream_id: u32) {
+        let mut state = self.lock();
+        let window = state.peer.initial_window_size as i64;
+        state.streams.insert(
+            stream_id,
+            StreamOutput {
+                window,
+                reset: None,
+            },
+        );
+    }
+
+    /// Opens the next stream and sends a request's header block on it, waiting while the peer's
+    /// SETTINGS_MAX_CONCURRENT_STREAMS are in use
+    pub(crate) fn open_request<'f>(
+        &self,
+        fields: impl IntoIterator<Item = (&'f str, &'f str)>,
+        end_stream: bool,
+    ) -> Result<u32, Http2Error> {
+        let mut state = self.lock();
+
+        while !state.closed
+            && state
+                .peer
+                .max_concurrent_streams
+                .is_some_and(|max| state.streams.len() >= max as usize)
+        {
+            state = self.wait(state);
+        }
+
+        // Out of stream identifiers, which takes a new connection just like a GOAWAY would
+        if state.next_stream_id > MAX_WINDOW_SIZE {
+            return Err(Http2Error::GoAway(ErrorCode::NO_ERROR));
+        }
+
+        let stream_id = state.next_stream_id;
+        state.next_stream_id += 2;
+
+        let window = state.peer.initial_window_size as i64;
+        state.streams.insert(
+            stream_id,
+            StreamOutput {
+                window,
+                reset: None,
+            },
+        );
+
+        self.write_headers(&mut state, stream_id, fields, end_stream)?;
+        Ok(stream_id)
+    }
+
+    /// Whether the stream was opened and hasn't finished yet
+    pub(crate) fn is_open(&self, stream_id: u32) -> bool {
+        self.lock().streams.contains_key(&stream_id)
+    }
+
+    pub(crate) fn open_count(&self) -> usize {
+        self.lock().streams.len()
+    }
+
+    /// Forgets a stream once nothing more is sent on it
+    pub(crate) fn finish_stream(&self, stream_id: u32) {
+        self.lock().streams.remove(&stream_id);
+        self.changed.notify_all();
+    }
+
+    /// Stops sending on a stream, with a RST_STREAM unless the peer reset it
+    pub(crate) fn reset_stream(
+        &self,
+        stream_id: u32,
+        error_code: ErrorCode,
+        by_peer: bool,
+    ) -> Result<(), Http2Error> {
+        let mut state = self.lock();
+
+        if let Some(stream) = state.streams.get_mut(&stream_id) {
+            stream.reset = Some(error_code);
+            self.changed.notify_all();
+        }
+
+        if by_peer {
+            return Ok(());
+        }
+
+        let mut bytes = Vec::new();
+        Frame::RstStream {
+            stream_id,
+            error_code,
+        }
+        .encode(&mut bytes);
+        self.write(&mut state, &bytes)
+    }
+
+    pub(crate) fn send_headers<'f>(
+        &self,
+        stream_id: u32,
+        fields: impl IntoIterator<Item = (&'f str, &'f str)>,
+        end_stream: bool,
+    ) -> Result<(), Http2Error> {
+        let mut state = self.lock();
+
+        if let Some(error_code) = state.streams.get(&stream_id).and_then(|s| s.reset) {
+            return Err(Http2Error::StreamReset(error_code));
+        }
+
+        self.write_headers(&mut state, stream_id, fields, end_stream)
+    }
+
+    /// Encodes and writes a header block in one go, since no other frame may come between its
+    /// HEADERS and CONTINUATION frames and the encoder's table has to stay in the order the peer
+    /// decodes in
+    fn write_headers<'f>(
+        &self,
+        state: &mut OutputState<W>,
+        stream_id: u32,
+        fields: impl IntoIterator<Item = (&'f str, &'f str)>,
+        end_stream: bool,
+    ) -> Result<(), Http2Error> {
+        let mut block = Vec::new();
+        state.encoder.encode(fields, &mut block);
+
+        let max_frame_size = state.peer.max_frame_size as usize;
+        let mut fragments = block.chunks(max_frame_size).peekable();
+        let mut bytes = Vec::new();
+
+        Frame::Headers {
+            stream_id,
+            block: fragments.next().unwrap_or_default().to_vec(),
+            end_stream,
+            end_headers: fragments.peek().is_none(),
+        }
+        .encode(&mut bytes);
+
+        while let Some(fragment) = fragments.next() {
+            Frame::Continuation {
+                stream_id,
+                block: fragment.to_vec(),
+                end_headers: fragments.peek().is_none(),
+            }
+            .encode(&mut bytes);
+        }
+
+        self.write(state, &bytes)
+    }
+
+    /// Sends `data` in as many frames as the windows and the peer's maximum frame size call for,
+    /// the last of them with END_STREAM if `end_stream` is set
+    pub(crate) fn send_data(
+        &self,
+        stream_id: u32,
+        data: &[u8],
+        end_stream: bool,
+    ) -> Result<(), Http2Error> {
+        let mut data = data;
+
+        loop {
+            let mut state = self.lock();
+
+            let len = loop {
+                if state.closed {
+                    return Err(Http2Error::ConnectionClosed);
+                }
+
+                let Some(stream) = state.streams.get(&stream_id) else {
+                    return Err(Http2Error::StreamReset(ErrorCode::STREAM_CLOSED));
+                };
+                if let Some(error_code) = stream.reset {
+                    return Err(Http2Error::StreamReset(error_code));
+                }
+
+                // Empty frames don't need any window
+                let available = state.window.min(stream.window).max(0) as usize;
+                if data.is_empty() || available > 0 {
+                    break data
+                        .len()
+                        .min(available)
+                        .min(state.peer.max_frame_size as usize);
+                }
+
+                state = self.wait(state);
+            };
+
+            state.window -= len as i64;
+            state.streams.get_mut(&stream_id).unwrap().window -= len as i64;
+
+            let (chunk, rest) = data.split_at(len);
+            let mut bytes = Vec::with_capacity(len + super::FRAME_HEADER_LEN);
+            Frame::Data {
+                stream_id,
+                data: chunk.to_vec(),
+                end_stream: end_stream && rest.is_empty(),
+            }
+            .encode(&mut bytes);
+            self.write(&mut state, &bytes)?;
+
+            data = rest;
+            if data.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Applies the peer's SETTINGS and acknowledges them unless they came in an `HTTP2-Settings`
+    /// header, which is acknowledged implicitly by the upgrade
+    pub(crate) fn apply_settings(
+        &self,
+        params: &[(u16, u32)],
+        ack: bool,
+    ) -> Result<(), Http2Error> {
+        let mut state = self.lock();
+
+        let initial_window_size = state.peer.initial_window_size;
+        state.peer.apply(params)?;
+
+        // A new initial window size changes the window of every open stream by the difference
+        // (RFC 9113 6.9.2)
+        let delta = state.peer.initial_window_size as i64 - initial_window_size as i64;
+        for stream in state.streams.values_mut() {
+            stream.window += delta;
+            if stream.window > MAX_WINDOW_SIZE as i64 {
+                return Err(Http2Error::Protocol(
+                    ErrorCode::FLOW_CONTROL_ERROR,
+                    "SETTINGS_INITIAL_WINDOW_SIZE overflowed a stream window",
+                ));
+            }
+        }
+
+        let header_table_size = state.peer.header_table_size as usize;
+        state.encoder.set_max_table_size(header_table_size);
+        self.changed.notify_all();
+
+        if !ack {
+            return Ok(());
+        }
+
+        let mut bytes = Vec::new();
+        Frame::Settings {
+            ack: true,
+            params: Vec::new(),
+        }
+        .encode(&mut bytes);
+        self.write(&mut state, &bytes)
+    }
+
+    /// Grows the window of the connection or of a stream, a stream whose window overflows is
+    /// reset
+    pub(crate) fn window_update(&self, stream_id: u32, increment: u32) -> Result<(), Http2Error> {
+        let mut state = self.lock();
+
+        if stream_id == 0 {
+            state.window += increment as i64;
+            if state.window > MAX_WINDOW_SIZE as i64 {
+                return Err(Http2Error::Protocol(
+                    ErrorCode::FLOW_CONTROL_ERROR,
+                    "Connection window overflowed",
+                ));
+            }
+        } else if let Some(stream) = state.streams.get_mut(&stream_id) {
+            stream.window += increment as i64;
+            if stream.window > MAX_WINDOW_SIZE as i64 {
+                drop(state);
+                return self.reset_stream(stream_id, ErrorCode::FLOW_CONTROL_ERROR, false);
+            }
+        }
+
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Sends a GOAWAY, `reason` ends up in its debug data
+    pub(crate) fn go_away(
+        &self,
+        last_stream_id: u32,
+        error_code: ErrorCode,
+        reason: &str,
+    ) -> Result<(), Http2Error> {
+        self.send_frame(&Frame::GoAway {
+            last_stream_id,
+            error_code,
+            debug_data: reason.as_bytes().to_vec(),
+        })
+    }
+
+    /// Makes every send fail from now on, waking up those waiting for a window
+    pub(crate) fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// What the peer sent on a stream, or the GOAWAY it sent for the connection
+#[derive(Debug)]
+pub(crate) enum Event {
+    /// A complete header block. It failed to decode only if it was larger than the limit, see
+    /// [HpackDecoder::set_max_header_list_size]
+    Headers {
+        stream_id: u32,
+        fields: Result<HeaderFields, HpackError>,
+        end_stream: bool,
+    },
+    /// `len` is what the frame counts against the stream's window, padding included
+    Data {
+        stream_id: u32,
+        data: Vec<u8>,
+        len: u32,
+        end_stream: bool,
+    },
+    Reset {
+        stream_id: u32,
+        error_code: ErrorCode,
+    },
+    GoAway {
+        last_stream_id: u32,
+        error_code: ErrorCode,
+    },
+}
+
+/// The receiving half of a connection, which answers the frames that concern the connection as a
+/// whole and turns the rest into [Event]s
+pub(crate) struct Input<R: Read> {
+    frames: FrameReader<R>,
+    decoder: HpackDecoder,
+    window: RecvWindow,
+    /// A header block waiting for CONTINUATION frames: its stream, what arrived so far and
+    /// whether it ends the stream
+    continuing: Option<(u32, Vec<u8>, bool)>,
+    /// Blocks are assembled in memory before they can be decoded, so they are limited as well
+    max_block_size: usize,
+}
+
+impl<R: Read> Input<R> {
+    /// `received` are bytes that were already read from `rx`, and `max_header_list_size` is the
+    /// SETTINGS_MAX_HEADER_LIST_SIZE sent to the peer
+    pub(crate) fn new(rx: R, received: Vec<u8>, max_header_list_size: usize) -> Self {
+        let mut decoder = HpackDecoder::new();
+        decoder.set_max_header_list_size(max_header_list_size);
+
+        Self {
+            frames: FrameReader::new(rx, received),
+            decoder,
+            window: RecvWindow::new(),
+            continuing: None,
+            max_block_size: max_header_list_size
+                .saturating_mul(2)
+                .max(MIN_MAX_FRAME_SIZE as usize),
+        }
+    }
+
+    pub(crate) fn read_preface(&mut self) -> Result<(), Http2Error> {
+        self.frames.read_preface()
+    }
+
+    /// Receives frames until one concerns a stream or the connection is going away
+    ///
+    /// A read that times out can be retried, frames that were only partially received stay
+    /// buffered
+    pub(crate) fn next<W: Write>(&mut self, output: &Output<W>) -> Result<Event, Http2Error> {
+        loop {
+            let (frame, len) = self.frames.read_frame(MIN_MAX_FRAME_SIZE)?;
+
+            if let Some((stream_id, block, _)) = &mut self.continuing {
+                let Frame::Continuation {
+                    stream_id: continued,
+                    block: fragment,
+                    end_headers,
+                } = frame
+                else {
+                    return Err(protocol_error("Header block interrupted"));
+                };
+                if continued != *stream_id {
+                    return Err(protocol_error("Header block interrupted"));
+                }
+
+                block.extend_from_slice(&fragment);
+                if block.len() > self.max_block_size {
+                    return Err(Http2Error::Protocol(
+                        ErrorCode::ENHANCE_YOUR_CALM,
+                        "Header block too large",
+                    ));
+                }
+
+                if end_headers {
+                    let (stream_id, block, end_stream) = self.continuing.take().unwrap();
+                    return self.headers(stream_id, &block, end_stream);
+                }
+                continue;
+            }
+
+            match frame {
+                Frame::Data {
+                    stream_id,
+                    data,
+                    end_stream,
+                } => {
+                    if let Some(increment) = self.window.consume(len)? {
+                        output.send_frame(&Frame::WindowUpdate {
+                            stream_id: 0,
+                            increment,
+                        })?;
+                    }
+
+                    return Ok(Event::Data {
+                        stream_id,
+                        data,
+                        len,
+                        end_stream,
+                    });
+                }
+                Frame::Headers {
+                    stream_id,
+                    block,
+                    end_stream,
+                    end_headers: true,
+                } => return self.headers(stream_id, &block, end_stream),
+                Frame::Headers {
+                    stream_id,
+                    block,
+                    end_stream,
+                    end_headers: false,
+                } => self.continuing = Some((stream_id, block, end_stream)),
+                Frame::RstStream {
+                    stream_id,
+                    error_code,
+                } => {
+                    output.reset_stream(stream_id, error_code, true)?;
+                    return Ok(Event::Reset {
+                        stream_id,
+                        error_code,
+                    });
+                }
+                Frame::Settings { ack: false, params } => output.apply_settings(&params, true)?,
+                Frame::Ping { ack: false, data } => {
+                    output.send_frame(&Frame::Ping { ack: true, data })?
+                }
+                Frame::GoAway {
+                    last_stream_id,
+                    error_code,
+                    ..
+                } => {
+                    return Ok(Event::GoAway {
+                        last_stream_id,
+                        error_code,
+                    })
+                }
+                Frame::WindowUpdate {
+                    stream_id: 0,
+                    increment: 0,
+                } => return Err(protocol_error("WINDOW_UPDATE of 0")),
+                Frame::WindowUpdate {
+                    stream_id,
+                    increment: 0,
+                } => output.reset_stream(stream_id, ErrorCode::PROTOCOL_ERROR, false)?,
+                Frame::WindowUpdate {
+                    stream_id,
+                    increment,
+                } => output.window_update(stream_id, increment)?,
+                Frame::PushPromise { .. } => {
+                    return Err(protocol_error("PUSH_PROMISE with push disabled"))
+                }
+                Frame::Continuation { .. } => {
+                    return Err(protocol_error("Unexpected CONTINUATION"))
+                }
+                Frame::Settings { ack: true, .. }
+                | Frame::Ping { ack: true, .. }
+                | Frame::Priority { .. }
+                | Frame::Unknown { .. } => {}
+            }
+        }
+    }
+
+    fn headers(
+        &mut self,
+        stream_id: u32,
+        block: &[u8],
+        end_stream: bool,
+    ) -> Result<Event, Http2Error> {
+        let fields = match self.decoder.decode(block) {
+            Err(HpackError::HeaderListTooLarge) => Err(HpackError::HeaderListTooLarge),
+            fields => Ok(fields?),
+        };
+
+        Ok(Event::Headers {
+            stream_id,
+            fields,
+            end_stream,
+        })
+    }
+}
+
+fn protocol_error(reason: &'static str) -> Http2Error {
+    Http2Error::Protocol(ErrorCode::PROTOCOL_ERROR, reason)
+}
@@ -0,0 +1,583 @@
+use std::io::Read;
+
+use super::{ErrorCode, Http2Error};
+
+/// Every frame starts with a 9 byte header: a 24 bit length, the type, the flags and a 31 bit
+/// stream identifier (RFC 9113 4.1)
+pub const FRAME_HEADER_LEN: usize = 9;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+/// A single HTTP/2 frame (RFC 9113 6) with its padding removed
+///
+/// Header blocks are carried as HPACK encoded fragments, which [super::HpackDecoder] turns into
+/// fields once the block is complete
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Data {
+        stream_id: u32,
+        data: Vec<u8>,
+        end_stream: bool,
+    },
+    /// Starts a header block, which continues in [Frame::Continuation] frames unless
+    /// `end_headers` is set
+    Headers {
+        stream_id: u32,
+        block: Vec<u8>,
+        end_stream: bool,
+        end_headers: bool,
+    },
+    /// Priority signals are deprecated (RFC 9113 5.3.2), so only the stream is kept
+    Priority {
+        stream_id: u32,
+    },
+    RstStream {
+        stream_id: u32,
+        error_code: ErrorCode,
+    },
+    Settings {
+        ack: bool,
+        params: Vec<(u16, u32)>,
+    },
+    PushPromise {
+        stream_id: u32,
+        promised_stream_id: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+    },
+    Ping {
+        ack: bool,
+        data: [u8; 8],
+    },
+    GoAway {
+        last_stream_id: u32,
+        error_code: ErrorCode,
+        debug_data: Vec<u8>,
+    },
+    /// An increment of 0 is only an error for the receiver to deal with
+    WindowUpdate {
+        stream_id: u32,
+        increment: u32,
+    },
+    Continuation {
+        stream_id: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+    },
+    /// A frame of an unknown type, which has to be ignored (RFC 9113 5.5)
+    Unknown {
+        kind: u8,
+        stream_id: u32,
+    },
+}
+
+impl Frame {
+    /// 0 for frames that apply to the whole connection
+    pub fn stream_id(&self) -> u32 {
+        match self {
+            Self::Data { stream_id, .. }
+            | Self::Headers { stream_id, .. }
+            | Self::Priority { stream_id }
+            | Self::RstStream { stream_id, .. }
+            | Self::PushPromise { stream_id, .. }
+            | Self::WindowUpdate { stream_id, .. }
+            | Self::Continuation { stream_id, .. }
+            | Self::Unknown { stream_id, .. } => *stream_id,
+            Self::Settings { .. } | Self::Ping { .. } | Self::GoAway { .. } => 0,
+        }
+    }
+
+    /// Appends the encoded frame to `out`, nothing is ever padded
+    ///
+    /// Checking the payload against the peer's SETTINGS_MAX_FRAME_SIZE is up to the caller
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&[0; FRAME_HEADER_LEN]);
+
+        let (kind, flags) = match self {
+            Self::Data {
+                data, end_stream, ..
+            } => {
+                out.extend_from_slice(data);
+                (DATA, flag(*end_stream, END_STREAM))
+            }
+            Self::Headers {
+                block,
+                end_stream,
+                end_headers,
+                ..
+            } => {
+                out.extend_from_slice(block);
+                let flags = flag(*end_stream, END_STREAM) | flag(*end_headers, END_HEADERS);
+                (HEADERS, flags)
+            }
+            Self::Priority { .. } => {
+                // No dependency and the default weight
+                out.extend_from_slice(&[0, 0, 0, 0, 15]);
+                (PRIORITY, 0)
+            }
+            Self::RstStream { error_code, .. } => {
+                out.extend_from_slice(&error_code.as_u32().to_be_bytes());
+                (RST_STREAM, 0)
+            }
+            Self::Settings { ack, params } => {
+                for (id, value) in params {
+                    out.extend_from_slice(&id.to_be_bytes());
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+                (SETTINGS, flag(*ack, ACK))
+            }
+            Self::PushPromise {
+                promised_stream_id,
+                block,
+                end_headers,
+                ..
+            } => {
+                out.extend_from_slice(&promised_stream_id.to_be_bytes());
+                out.extend_from_slice(block);
+                (PUSH_PROMISE, flag(*end_headers, END_HEADERS))
+            }
+            Self::Ping { ack, data } => {
+                out.extend_from_slice(data);
+                (PING, flag(*ack, ACK))
+            }
+            Self::GoAway {
+                last_stream_id,
+                error_code,
+                debug_data,
+            } => {
+                out.extend_from_slice(&last_stream_id.to_be_bytes());
+                out.extend_from_slice(&error_code.as_u32().to_be_bytes());
+                out.extend_from_slice(debug_data);
+                (GOAWAY, 0)
+            }
+            Self::WindowUpdate { increment, .. } => {
+                out.extend_from_slice(&increment.to_be_bytes());
+                (WINDOW_UPDATE, 0)
+            }
+            Self::Continuation {
+                block, end_headers, ..
+            } => {
+                out.extend_from_slice(block);
+                (CONTINUATION, flag(*end_headers, END_HEADERS))
+            }
+            Self::Unknown { kind, .. } => (*kind, 0),
+        };
+
+        let len = (out.len() - start - FRAME_HEADER_LEN) as u32;
+        out[start..start + 3].copy_from_slice(&len.to_be_bytes()[1..]);
+        out[start + 3] = kind;
+        out[start + 4] = flags;
+        out[start + 5..start + 9].copy_from_slice(&self.stream_id().to_be_bytes());
+    }
+}
+
+fn flag(set: bool, flag: u8) -> u8 {
+    match set {
+        true => flag,
+        false => 0,
+    }
+}
+
+fn protocol_error(reason: &'static str) -> Http2Error {
+    Http2Error::Protocol(ErrorCode::PROTOCOL_ERROR, reason)
+}
+
+fn frame_size_error(reason: &'static str) -> Http2Error {
+    Http2Error::Protocol(ErrorCode::FRAME_SIZE_ERROR, reason)
+}
+
+/// Returns the frame at the start of `buf` along with its length in bytes, or `None` if the frame
+/// is not complete yet
+///
+/// `max_frame_size` is the SETTINGS_MAX_FRAME_SIZE sent to the peer. Frames that can't be valid
+/// no matter the state of the connection are connection errors
+pub(crate) fn parse_frame(
+    buf: &[u8],
+    max_frame_size: u32,
+) -> Result<Option<(Frame, usize)>, Http2Error> {
+    let Some(header) = buf.get(..FRAME_HEADER_LEN) else {
+        return Ok(None);
+    };
+
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]);
+    let (kind, flags) = (header[3], header[4]);
+    // The reserved bit is ignored
+    let stream_id = u32::from_be_bytes(header[5..9].try_into().unwrap()) & 0x7FFF_FFFF;
+
+    if len > max_frame_size {
+        return Err(frame_size_error(
+            "Frame larger than SETTINGS_MAX_FRAME_SIZE",
+        ));
+    }
+
+    let frame_len = FRAME_HEADER_LEN + len as usize;
+    let Some(payload) = buf.get(FRAME_HEADER_LEN..frame_len) else {
+        return Ok(None);
+    };
+
+    let on_stream = |stream_id: u32| match stream_id {
+        0 => Err(protocol_error("Stream frame on stream 0")),
+        stream_id => Ok(stream_id),
+    };
+    let on_connection = || match stream_id {
+        0 => Ok(()),
+        _ => Err(protocol_error("Connection frame on a stream")),
+    };
+
+    let frame = match kind {
+        DATA => Frame::Data {
+            stream_id: on_stream(stream_id)?,
+            data: unpad(payload, flags)?.to_vec(),
+            end_stream: flags & END_STREAM != 0,
+        },
+        HEADERS => {
+            let mut block = unpad(payload, flags)?;
+            if flags & PRIORITY_FLAG != 0 {
+                block = block
+                    .get(5..)
+                    .ok_or(frame_size_error("HEADERS too short for its priority"))?;
+            }
+
+            Frame::Headers {
+                stream_id: on_stream(stream_id)?,
+                block: block.to_vec(),
+                end_stream: flags & END_STREAM != 0,
+                end_headers: flags & END_HEADERS != 0,
+            }
+        }
+        PRIORITY => {
+            if len != 5 {
+                return Err(frame_size_error("PRIORITY must be 5 bytes"));
+            }
+            Frame::Priority {
+                stream_id: on_stream(stream_id)?,
+            }
+        }
+        RST_STREAM => {
+            if len != 4 {
+                return Err(frame_size_error("RST_STREAM must be 4 bytes"));
+            }
+            Frame::RstStream {
+                stream_id: on_stream(stream_id)?,
+                error_code: ErrorCode::new(u32::from_be_bytes(payload.try_into().unwrap())),
+            }
+        }
+        SETTINGS => {
+            on_connection()?;
+
+            let ack = flags & ACK != 0;
+            if ack && len != 0 {
+                return Err(frame_size_error("SETTINGS acknowledgement with a payload"));
+            }
+            if !len.is_multiple_of(6) {
+                return Err(frame_size_error("SETTINGS length not a multiple of 6"));
+            }
+
+            let params = payload
+                .chunks_exact(6)
+                .map(|param| {
+                    let id = u16::from_be_bytes([param[0], param[1]]);
+                    (id, u32::from_be_bytes(param[2..6].try_into().unwrap()))
+                })
+                .collect();
+
+            Frame::Settings { ack, params }
+        }
+        PUSH_PROMISE => {
+            let payload = unpad(payload, flags)?;
+            let Some((promised, block)) = payload.split_first_chunk::<4>() else {
+                return Err(frame_size_error("PUSH_PROMISE too short"));
+            };
+
+            Frame::PushPromise {
+                stream_id: on_stream(stream_id)?,
+                promised_stream_id: u32::from_be_bytes(*promised) & 0x7FFF_FFFF,
+                block: block.to_vec(),
+                end_headers: flags & END_HEADERS != 0,
+            }
+        }
+        PING => {
+            on_connection()?;
+            let data = payload
+                .try_into()
+                .map_err(|_| frame_size_error("PING must be 8 bytes"))?;
+
+            Frame::Ping {
+                ack: flags & ACK != 0,
+                data,
+            }
+        }
+        GOAWAY => {
+            on_connection()?;
+            if len < 8 {
+                return Err(frame_size_error("GOAWAY too short"));
+            }
+
+            Frame::GoAway {
+                last_stream_id: u32::from_be_bytes(payload[..4].try_into().unwrap()) & 0x7FFF_FFFF,
+                error_code: ErrorCode::new(u32::from_be_bytes(payload[4..8].try_into().unwrap())),
+                debug_data: payload[8..].to_vec(),
+            }
+        }
+        WINDOW_UPDATE => {
+            if len != 4 {
+                return Err(frame_size_error("WINDOW_UPDATE must be 4 bytes"));
+            }
+
+            Frame::WindowUpdate {
+                stream_id,
+                increment: u32::from_be_bytes(payload.try_into().unwrap()) & 0x7FFF_FFFF,
+            }
+        }
+        CONTINUATION => Frame::Continuation {
+            stream_id: on_stream(stream_id)?,
+            block: payload.to_vec(),
+            end_headers: flags & END_HEADERS != 0,
+        },
+        kind => Frame::Unknown { kind, stream_id },
+    };
+
+    Ok(Some((frame, frame_len)))
+}
+
+/// Strips the padding of a frame that has the PADDED flag set
+fn unpad(payload: &[u8], flags: u8) -> Result<&[u8], Http2Error> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+
+    let Some((pad_len, rest)) = payload.split_first() else {
+        return Err(frame_size_error("Padded frame without a pad length"));
+    };
+
+    rest.len()
+        .checked_sub(*pad_len as usize)
+        .map(|len| &rest[..len])
+        .ok_or(protocol_error("Padding longer than the frame"))
+}
+
+/// Reads frames from a byte stream, keeping partial frames buffered across timeouts
+pub(crate) struct FrameReader<R: Read> {
+    rx: R,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// `received` are bytes that were already read from `rx`
+    pub(crate) fn new(rx: R, received: Vec<u8>) -> Self {
+        Self { rx, buf: received }
+    }
+
+    /// Reads the client connection preface, which a server expects before any frame
+    pub(crate) fn read_preface(&mut self) -> Result<(), Http2Error> {
+        while self.buf.len() < super::PREFACE.len() {
+            if !super::PREFACE.starts_with(&self.buf) {
+                break;
+            }
+            self.fill()?;
+        }
+
+        match self.buf.starts_with(super::PREFACE) {
+            true => {
+                self.buf.drain(..super::PREFACE.len());
+                Ok(())
+            }
+            false => Err(protocol_error("Invalid connection preface")),
+        }
+    }
+
+    /// Returns the next frame and the length of its payload, which flow control counts padding
+    /// included
+    pub(crate) fn read_frame(&mut self, max_frame_size: u32) -> Result<(Frame, u32), Http2Error> {
+        loop {
+            if let Some((frame, len)) = parse_frame(&self.buf, max_frame_size)? {
+                self.buf.drain(..len);
+                return Ok((frame, (len - FRAME_HEADER_LEN) as u32));
+            }
+
+            self.fill()?;
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), Http2Error> {
+        let mut buffer = [0_u8; 16384];
+
+        loop {
+            match self.rx.read(&mut buffer) {
+                Ok(0) => return Err(Http2Error::ConnectionClosed),
+                Ok(bytes_read) => {
+                    self.buf.extend_from_slice(&buffer[..bytes_read]);
+                    return Ok(());
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(frame: Frame) {
+        let mut bytes = Vec::new();
+        frame.encode(&mut bytes);
+        assert_eq!(
+            parse_frame(&bytes, 16384).unwrap(),
+            Some((frame.clone(), bytes.len()))
+        );
+        assert_eq!(parse_frame(&bytes[..bytes.len() - 1], 16384).unwrap(), None);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        round_trip(Frame::Data {
+            stream_id: 1,
+            data: b"hello".to_vec(),
+            end_stream: true,
+        });
+        round_trip(Frame::Headers {
+            stream_id: 3,
+            block: vec![0x82, 0x86],
+            end_stream: false,
+            end_headers: true,
+        });
+        round_trip(Frame::RstStream {
+            stream_id: 5,
+            error_code: ErrorCode::CANCEL,
+        });
+        round_trip(Frame::Settings {
+            ack: false,
+            params: vec![(0x3, 100), (0x4, 1 << 20)],
+        });
+        round_trip(Frame::Ping {
+            ack: true,
+            data: *b"12345678",
+        });
+        round_trip(Frame::GoAway {
+            last_stream_id: 7,
+            error_code: ErrorCode::PROTOCOL_ERROR,
+            debug_data: b"bye".to_vec(),
+        });
+        round_trip(Frame::WindowUpdate {
+            stream_id: 0,
+            increment: 1 << 30,
+        });
+        round_trip(Frame::Continuation {
+            stream_id: 3,
+            block: vec![0x84],
+            end_headers: true,
+        });
+    }
+
+    #[test]
+    fn padding_and_priority() {
+        // HEADERS with PADDED, PRIORITY and END_HEADERS: pad length 2, 5 bytes of priority, the
+        // block and the padding
+        let bytes = [
+            0, 0, 10, 0x1, 0x2C, 0, 0, 0, 1, 2, 0x80, 0, 0, 3, 200, 0x82, 0x84, 0, 0,
+        ];
+        let (frame, len) = parse_frame(&bytes, 16384).unwrap().unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(
+            frame,
+            Frame::Headers {
+                stream_id: 1,
+                block: vec![0x82, 0x84],
+                end_stream: false,
+                end_headers: true,
+            }
+        );
+
+        let padded_data = [0, 0, 4, 0x0, 0x8, 0, 0, 0, 1, 5, b'a', b'b', b'c'];
+        assert!(matches!(
+            parse_frame(&padded_data, 16384),
+            Err(Http2Error::Protocol(ErrorCode::PROTOCOL_ERROR, _))
+        ));
+
+        // A frame of an unknown type is kept so that it can be skipped
+        let unknown = [0, 0, 1, 0xFA, 0xFF, 0, 0, 0, 9, 0];
+        assert_eq!(
+            parse_frame(&unknown, 16384).unwrap(),
+            Some((
+                Frame::Unknown {
+                    kind: 0xFA,
+                    stream_id: 9
+                },
+                10
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let cases: [(&[u8], ErrorCode); 5] = [
+            // Larger than the maximum frame size
+            (
+                &[0, 0x40, 1, 0x0, 0, 0, 0, 0, 1],
+                ErrorCode::FRAME_SIZE_ERROR,
+            ),
+            // DATA on stream 0
+            (&[0, 0, 0, 0x0, 0, 0, 0, 0, 0], ErrorCode::PROTOCOL_ERROR),
+            // SETTINGS on a stream
+            (&[0, 0, 0, 0x4, 0, 0, 0, 0, 1], ErrorCode::PROTOCOL_ERROR),
+            // A PING that is too short
+            (
+                &[0, 0, 1, 0x6, 0, 0, 0, 0, 0, 0],
+                ErrorCode::FRAME_SIZE_ERROR,
+            ),
+            // A SETTINGS acknowledgement with a payload
+            (
+                &[0, 0, 6, 0x4, 0x1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0],
+                ErrorCode::FRAME_SIZE_ERROR,
+            ),
+        ];
+
+        for (bytes, code) in cases {
+            match parse_frame(bytes, 16384) {
+                Err(Http2Error::Protocol(err_code, _)) => assert_eq!(err_code, code),
+                res => panic!("{:?} parsed as {:?}", bytes, res),
+            }
+        }
+    }
+
+    #[test]
+    fn reader_keeps_partial_frames() {
+        let mut bytes = super::super::PREFACE.to_vec();
+        Frame::Ping {
+            ack: false,
+            data: [7; 8],
+        }
+        .encode(&mut bytes);
+
+        let mut reader = FrameReader::new(&bytes[20..], bytes[..20].to_vec());
+        reader.read_preface().unwrap();
+        let (frame, len) = reader.read_frame(16384).unwrap();
+        assert_eq!(frame.stream_id(), 0);
+        assert_eq!(len, 8);
+        assert!(matches!(
+            reader.read_frame(16384),
+            Err(Http2Error::ConnectionClosed)
+        ));
+
+        let mut reader = FrameReader::new(&b"GET / HTTP/1.1\r\n\r\n"[..], Vec::new());
+        assert!(reader.read_preface().is_err());
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use super::http2_huffman;
+
+/// RFC 7541 Appendix A, index 1 is the first entry
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Every table entry counts 32 bytes on top of its name and value (RFC 7541 4.1)
+const ENTRY_OVERHEAD: usize = 32;
+
+/// The table size both ends start out with
+pub(crate) const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// Fields whose values are too valuable to an attacker to risk compressing them along with
+/// values the attacker controls (RFC 7541 7.1.3)
+const SENSITIVE: [&str; 3] = ["authorization", "proxy-authorization", "set-cookie"];
+
+/// Fields whose values are rarely repeated, so they would only push useful entries out of the
+/// table
+const NOT_INDEXED: [&str; 8] = [
+    ":path",
+    "content-length",
+    "content-range",
+    "date",
+    "etag",
+    "age",
+    "last-modified",
+    "location",
+];
+
+/// Decoded fields as names and values, in the order they were encoded in
+pub type HeaderFields = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpackError {
+    /// An index past the end of the static and dynamic tables, or index 0
+    InvalidIndex,
+    /// An integer that doesn't fit into 32 bits
+    IntegerOverflow,
+    InvalidHuffman,
+    /// A table size update that is too large or comes after the first field of a block
+    InvalidTableSizeUpdate,
+    /// The decoded fields are larger than the decoder's limit, see
+    /// [HpackDecoder::set_max_header_list_size]
+    HeaderListTooLarge,
+    UnexpectedEnd,
+}
+
+impl Display for HpackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidIndex => write!(f, "Invalid HPACK Table Index"),
+            Self::IntegerOverflow => write!(f, "HPACK Integer Overflow"),
+            Self::InvalidHuffman => write!(f, "Invalid Huffman Encoded String"),
+            Self::InvalidTableSizeUpdate => write!(f, "Invalid Dynamic Table Size Update"),
+            Self::HeaderListTooLarge => write!(f, "Header List Too Large"),
+            Self::UnexpectedEnd => write!(f, "Unexpected End of Header Block"),
+        }
+    }
+}
+
+impl std::error::Error for HpackError {}
+
+/// The entries added while coding header blocks, newest first (RFC 7541 2.3.2)
+#[derive(Debug, Clone)]
+struct DynamicTable {
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl DynamicTable {
+    fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        match index {
+            0 => None,
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Some((name.as_bytes(), value.as_bytes()))
+            }
+            _ => self
+                .entries
+                .get(index - 62)
+                .map(|(name, value)| (name.as_slice(), value.as_slice())),
+        }
+    }
+
+    /// An entry larger than the whole table empties it without being added
+    fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+        self.evict(self.max_size.saturating_sub(size));
+
+        if size <= self.max_size {
+            self.size += size;
+            self.entries.push_front((name, value));
+        }
+    }
+
+    fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(max_size);
+    }
+
+    fn evict(&mut self, max_size: usize) {
+        while self.size > max_size {
+            let (name, value) = self.entries.pop_back().unwrap();
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+
+    /// The index of an entry with both the name and the value, or else of one with the name
+    fn find(&self, name: &str, value: &str) -> Option<(usize, bool)> {
+        let mut name_match = None;
+
+        let static_entries = STATIC_TABLE
+            .iter()
+            .map(|(n, v)| (n.as_bytes(), v.as_bytes()));
+        let dynamic_entries = self.entries.iter().map(|(n, v)| (&n[..], &v[..]));
+
+        for (index, (entry_name, entry_value)) in static_entries.chain(dynamic_entries).enumerate()
+        {
+            if entry_name == name.as_bytes() {
+                if entry_value == value.as_bytes() {
+                    return Some((index + 1, true));
+                }
+                name_match.get_or_insert(index + 1);
+            }
+        }
+
+        name_match.map(|index| (index, false))
+    }
+}
+
+/// Decodes header blocks, keeping the dynamic table in sync with the peer's encoder
+///
+/// Every block received on a connection has to go through the same decoder in order, even those
+/// of streams that are going to be refused
+#[derive(Debug, Clone)]
+pub struct HpackDecoder {
+    table: DynamicTable,
+    /// The SETTINGS_HEADER_TABLE_SIZE sent to the peer, which its size updates can't exceed
+    max_table_size: usize,
+    max_header_list_size: usize,
+}
+
+impl HpackDecoder {
+    pub fn new() -> Self {
+        Self {
+            table: DynamicTable::new(DEFAULT_TABLE_SIZE),
+            max_table_size: DEFAULT_TABLE_SIZE,
+            max_header_list_size: usize::MAX,
+        }
+    }
+
+    /// The most the table size updates of the peer can ask for, as sent to it in
+    /// SETTINGS_HEADER_TABLE_SIZE
+    pub fn set_max_table_size(&mut self, max_table_size: usize) {
+        self.max_table_size = max_table_size;
+    }
+
+    /// Blocks that decode to more than this, counting 32 bytes of overhead per field, fail with
+    /// [HpackError::HeaderListTooLarge]. Unlimited by default
+    pub fn set_max_header_list_size(&mut self, max_header_list_size: usize) {
+        self.max_header_list_size = max_header_list_size;
+    }
+
+    /// Decodes a complete header block into its fields, in order
+    ///
+    /// A block that is too large is still decoded in full before failing, so that the table
+    /// stays usable for the next one
+    pub fn decode(&mut self, block: &[u8]) -> Result<HeaderFields, HpackError> {
+        let mut fields = Vec::new();
+        let mut list_size = 0_usize;
+        let mut pos = 0;
+
+        while pos < block.len() {
+            let first = block[pos];
+
+            let (name, value) = if first & 0x80 != 0 {
+                // Indexed field
+                let index = decode_int(block, &mut pos, 7)?;
+                let (name, value) = self.table.get(index).ok_or(HpackError::InvalidIndex)?;
+                (name.to_vec(), value.to_vec())
+            } else if first & 0xE0 == 0x20 {
+                // Dynamic table size update, only allowed before the first field
+                let size = decode_int(block, &mut pos, 5)?;
+                if !fields.is_empty() || list_size > 0 || size > self.max_table_size {
+                    return Err(HpackError::InvalidTableSizeUpdate);
+                }
+                self.table.set_max_size(size);
+                continue;
+            } else {
+                // Literal with incremental indexing, without indexing or never indexed
+                let indexing = first & 0xC0 == 0x40;
+                let prefix = match indexing {
+                    true => 6,
+                    false => 4,
+                };
+
+                let name = match decode_int(block, &mut pos, prefix)? {
+                    0 => decode_string(block, &mut pos)?,
+                    index => {
+                        let (name, _) = self.table.get(index).ok_or(HpackError::InvalidIndex)?;
+                        name.to_vec()
+                    }
+                };
+                let value = decode_string(block, &mut pos)?;
+
+                if indexing {
+                    self.table.insert(name.clone(), value.clone());
+                }
+                (name, value)
+            };
+
+            list_size = list_size.saturating_add(name.len() + value.len() + ENTRY_OVERHEAD);
+            if list_size <= self.max_header_list_size {
+                fields.push((name, value));
+            }
+        }
+
+        match list_size > self.max_header_list_size {
+            true => Err(HpackError::HeaderListTooLarge),
+            false => Ok(fields),
+        }
+    }
+}
+
+impl Default for HpackDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encodes header blocks, indexing fields that are likely to be repeated and Huffman coding
+/// strings whenever that makes them shorter
+///
+/// Names have to be lowercase already, as HTTP/2 requires
+#[derive(Debug, Clone)]
+pub struct HpackEncoder {
+    table: DynamicTable,
+    /// The smallest size the table had since the last block, for the size updates that have to
+    /// start the next one
+    pending_update: Option<(usize, usize)>,
+}
+
+impl HpackEncoder {
+    pub fn new() -> Self {
+        Self {
+            table: DynamicTable::new(DEFAULT_TABLE_SIZE),
+            pending_update: None,
+        }
+    }
+
+    /// Follows the peer's SETTINGS_HEADER_TABLE_SIZE, the table never grows past the size both
+    /// ends start out with
+    pub fn set_max_table_size(&mut self, max_table_size: usize) {
+        let size = max_table_size.min(DEFAULT_TABLE_SIZE);
+        if size == self.table.max_size && self.pending_update.is_none() {
+            return;
+        }
+
+        let smallest = match self.pending_update {
+            Some((smallest, _)) => smallest.min(size),
+            None => size.min(self.table.max_size),
+        };
+        self.pending_update = Some((smallest, size));
+        self.table.set_max_size(size);
+    }
+
+    /// Appends the encoded block to `out`
+    pub fn encode<'f>(
+        &mut self,
+        fields: impl IntoIterator<Item = (&'f str, &'f str)>,
+        out: &mut Vec<u8>,
+    ) {
+        // The peer has to see the smallest size first so that it evicts the same entries
+        if let Some((smallest, size)) = self.pending_update.take() {
+            encode_int(smallest, 5, 0x20, out);
+            if size != smallest {
+                encode_int(size, 5, 0x20, out);
+            }
+        }
+
+        for (name, value) in fields {
+            let found = self.table.find(name, value);
+
+            if let Some((index, true)) = found {
+                if !SENSITIVE.contains(&name) {
+                    encode_int(index, 7, 0x80, out);
+                    continue;
+                }
+            }
+
+            let name_index = found.map_or(0, |(index, _)| index);
+
+            if SENSITIVE.contains(&name) {
+                encode_int(name_index, 4, 0x10, out);
+            } else if NOT_INDEXED.contains(&name) {
+                encode_int(name_index, 4, 0x00, out);
+            } else {
+                encode_int(name_index, 6, 0x40, out);
+                self.table
+                    .insert(name.as_bytes().to_vec(), value.as_bytes().to_vec());
+            }
+
+            if name_index == 0 {
+                encode_string(name.as_bytes(), out);
+            }
+            encode_string(value.as_bytes(), out);
+        }
+    }
+}
+
+impl Default for HpackEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encodes an integer with an N-bit prefix (RFC 7541 5.1), `flags` fills the bits above it
+fn encode_int(value: usize, prefix: u8, flags: u8, out: &mut Vec<u8>) {
+    let max = (1_usize << prefix) - 1;
+
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+
+    out.push(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        out.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.push(rest as u8);
+}
+
+fn decode_int(block: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, HpackError> {
+    let max = (1_usize << prefix) - 1;
+    let first = *block.get(*pos).ok_or(HpackError::UnexpectedEnd)?;
+    *pos += 1;
+
+    let mut value = (first as usize) & max;
+    if value < max {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+    loop {
+        let byte = *block.get(*pos).ok_or(HpackError::UnexpectedEnd)?;
+        *pos += 1;
+
+        let part = ((byte & 0x7F) as u64) << shift;
+        value = value
+            .checked_add(part as usize)
+            .filter(|value| *value <= u32::MAX as usize && shift <= 28)
+            .ok_or(HpackError::IntegerOverflow)?;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn encode_string(data: &[u8], out: &mut Vec<u8>) {
+    let huffman_len = http2_huffman::encoded_len(data);
+
+    if huffman_len < data.len() {
+        encode_int(huffman_len, 7, 0x80, out);
+        http2_huffman::encode(data, out);
+    } else {
+        encode_int(data.len(), 7, 0x00, out);
+        out.extend_from_slice(data);
+    }
+}
+
+fn decode_string(block: &[u8], pos: &mut usize) -> Result<Vec<u8>, HpackError> {
+    let huffman = *block.get(*pos).ok_or(HpackError::UnexpectedEnd)? & 0x80 != 0;
+    let len = decode_int(block, pos, 7)?;
+
+    let data = block
+        .get(*pos..pos.saturating_add(len))
+        .ok_or(HpackError::UnexpectedEnd)?;
+    *pos += len;
+
+    match huffman {
+        true => http2_huffman::decode(data),
+        false => Ok(data.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(u8::is_ascii_hexdigit).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn fields(decoded: HeaderFields) -> Vec<(String, String)> {
+        decoded
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    String::from_utf8(name).unwrap(),
+                    String::from_utf8(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn integers() {
+        // RFC 7541 C.1
+        for (value, prefix, encoded) in [
+            (10, 5, vec![0x0A]),
+            (1337, 5, vec![0x1F, 0x9A, 0x0A]),
+            (42, 8, vec![0x2A]),
+        ] {
+            let mut out = Vec::new();
+            encode_int(value, prefix, 0, &mut out);
+            assert_eq!(out, encoded);
+            assert_eq!(decode_int(&encoded, &mut 0, prefix), Ok(value));
+        }
+
+        let overflow = [0x1F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F];
+        assert_eq!(
+            decode_int(&overflow, &mut 0, 5),
+            Err(HpackError::IntegerOverflow)
+        );
+        assert_eq!(
+            decode_int(&[0x1F, 0x9A], &mut 0, 5),
+            Err(HpackError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn rfc_request_examples() {
+        // RFC 7541 C.4, three requests on one connection with Huffman coding
+        let blocks = [
+            "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff",
+            "8286 84be 5886 a8eb 1064 9cbf",
+            "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+        ];
+        let expected = [
+            vec![
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ],
+            vec![
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ],
+            vec![
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ],
+        ];
+
+        let mut decoder = HpackDecoder::new();
+        let mut encoder = HpackEncoder::new();
+
+        for (block, expected) in blocks.iter().zip(&expected) {
+            let decoded = fields(decoder.decode(&hex(block)).unwrap());
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            assert_eq!(decoded, expected);
+
+            // The encoder makes the same choices as the RFC for these
+            let mut out = Vec::new();
+            encoder.encode(
+                expected.iter().map(|(n, v)| (n.as_str(), v.as_str())),
+                &mut out,
+            );
+            assert_eq!(out, hex(block));
+        }
+
+        assert_eq!(decoder.table.size, 164);
+    }
+
+    #[test]
+    fn rfc_response_examples() {
+        // RFC 7541 C.5, responses with a table of 256 bytes that evicts entries
+        let blocks = [
+            "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 \
+             2032 303a 3133 3a32 3120 474d 546e 1768 7474 7073 3a2f 2f77 7777 2e65 7861 6d70 \
+             6c65 2e63 6f6d",
+            "4803 3330 37c1 c0bf",
+            "88c1 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 2032 303a 3133 3a32 3220 474d \
+             54c0 5a04 677a 6970 7738 666f 6f3d 4153 444a 4b48 514b 425a 584f 5157 454f 5049 \
+             5541 5851 5745 4f49 553b 206d 6178 2d61 6765 3d33 3630 303b 2076 6572 7369 6f6e \
+             3d31",
+        ];
+
+        let mut decoder = HpackDecoder::new();
+        decoder.table.set_max_size(256);
+
+        let decoded = fields(decoder.decode(&hex(blocks[0])).unwrap());
+        assert_eq!(decoded[0], (":status".into(), "302".into()));
+        assert_eq!(
+            decoded[3],
+            ("location".into(), "https://www.example.com".into())
+        );
+
+        let decoded = fields(decoder.decode(&hex(blocks[1])).unwrap());
+        assert_eq!(decoded[0], (":status".into(), "307".into()));
+        assert_eq!(decoded[1], ("cache-control".into(), "private".into()));
+
+        let decoded = fields(decoder.decode(&hex(blocks[2])).unwrap());
+        assert_eq!(decoded.len(), 6);
+        assert_eq!(decoded[4], ("content-encoding".into(), "gzip".into()));
+        assert_eq!(
+            decoded[5].1,
+            "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"
+        );
+        assert_eq!(decoder.table.entries.len(), 3);
+        assert_eq!(decoder.table.size, 215);
+    }
+
+    #[test]
+    fn table_size_updates_and_limits() {
+        let mut encoder = HpackEncoder::new();
+        let mut decoder = HpackDecoder::new();
+        let fields = [("x-custom", "value"), ("authorization", "secret")];
+
+        let mut block = Vec::new();
+        encoder.encode(fields, &mut block);
+        decoder.decode(&block).unwrap();
+        assert_eq!(decoder.table.entries.len(), 1);
+
+        // Shrinking the table to nothing and back has to be signalled in the next block
+        encoder.set_max_table_size(0);
+        encoder.set_max_table_size(100);
+        let mut block = Vec::new();
+        encoder.encode(fields, &mut block);
+        assert_eq!(&block[..2], &[0x20, 0x3F]);
+        assert_eq!(fields_of(decoder.decode(&block)), owned(&fields));
+        assert_eq!(decoder.table.size, 8 + 5 + 32);
+
+        // Updates past the announced maximum or after the first field are errors
+        decoder.set_max_table_size(100);
+        assert_eq!(
+            decoder.decode(&[0x3F, 0x62]),
+            Err(HpackError::InvalidTableSizeUpdate)
+        );
+        assert_eq!(
+            decoder.decode(&[0x82, 0x20]),
+            Err(HpackError::InvalidTableSizeUpdate)
+        );
+        assert_eq!(decoder.decode(&[0xFF, 0x00]), Err(HpackError::InvalidIndex));
+
+        // A list over the limit is decoded in full, so the table stays in sync
+        decoder.set_max_header_list_size(40);
+        let mut block = Vec::new();
+        encoder.encode([("x-one", "1"), ("x-two", "2")], &mut block);
+        assert_eq!(decoder.decode(&block), Err(HpackError::HeaderListTooLarge));
+        decoder.set_max_header_list_size(usize::MAX);
+
+        let mut block = Vec::new();
+        encoder.encode([("x-two", "2")], &mut block);
+        assert_eq!(block, [0xBE]);
+        assert_eq!(fields_of(decoder.decode(&block)), owned(&[("x-two", "2")]));
+    }
+
+    fn fields_of(decoded: Result<HeaderFields, HpackError>) -> Vec<(String, String)> {
+        fields(decoded.unwrap())
+    }
+
+    fn owned(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+}
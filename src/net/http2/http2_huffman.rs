@@ -0,0 +1,203 @@
+//! The static Huffman code HPACK uses for string literals (RFC 7541 Appendix B)
+//!
+//! The code is canonical, so the code lengths are all it takes to rebuild it: codes of the same
+//! length are consecutive in symbol order and shorter codes come first
+
+use super::HpackError;
+
+/// Symbol 256 is the end-of-string marker, which is never encoded
+const EOS: usize = 256;
+
+const MAX_CODE_LEN: usize = 30;
+
+const CODE_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, //
+    28, 28, 28, 28, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 28, //
+    6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, //
+    5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, //
+    13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, //
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, //
+    15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6, 6, 5, //
+    6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, //
+    20, 22, 20, 20, 22, 22, 22, 23, 22, 23, 23, 23, 23, 23, 24, 23, //
+    24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, //
+    22, 21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, //
+    21, 21, 22, 21, 23, 22, 23, 23, 20, 22, 22, 22, 23, 22, 22, 23, //
+    26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, //
+    19, 21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, //
+    20, 24, 20, 21, 22, 21, 21, 23, 22, 22, 25, 25, 24, 24, 26, 23, //
+    26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, //
+    30,
+];
+
+/// Symbols ordered by code, with where each code length starts and its first code
+struct Canonical {
+    codes: [u32; 257],
+    symbols: [u16; 257],
+    /// Index into `symbols` of the first symbol with each code length
+    offsets: [u16; MAX_CODE_LEN + 1],
+    first_codes: [u32; MAX_CODE_LEN + 1],
+    counts: [u16; MAX_CODE_LEN + 1],
+}
+
+const CANONICAL: Canonical = canonical();
+
+const fn canonical() -> Canonical {
+    let mut canonical = Canonical {
+        codes: [0; 257],
+        symbols: [0; 257],
+        offsets: [0; MAX_CODE_LEN + 1],
+        first_codes: [0; MAX_CODE_LEN + 1],
+        counts: [0; MAX_CODE_LEN + 1],
+    };
+
+    let mut code = 0_u32;
+    let mut index = 0;
+    let mut len = 1;
+
+    while len <= MAX_CODE_LEN {
+        canonical.offsets[len] = index as u16;
+        canonical.first_codes[len] = code;
+
+        let mut symbol = 0;
+        while symbol < CODE_LENGTHS.len() {
+            if CODE_LENGTHS[symbol] as usize == len {
+                canonical.codes[symbol] = code;
+                canonical.symbols[index] = symbol as u16;
+                canonical.counts[len] += 1;
+                code += 1;
+                index += 1;
+            }
+            symbol += 1;
+        }
+
+        code <<= 1;
+        len += 1;
+    }
+
+    canonical
+}
+
+/// How many bytes `data` takes up once encoded
+pub(crate) fn encoded_len(data: &[u8]) -> usize {
+    let bits: usize = data
+        .iter()
+        .map(|b| CODE_LENGTHS[*b as usize] as usize)
+        .sum();
+    bits.div_ceil(8)
+}
+
+/// Appends the encoded `data` to `out`, padded with the most significant bits of EOS
+pub(crate) fn encode(data: &[u8], out: &mut Vec<u8>) {
+    let mut bits = 0_u64;
+    let mut bit_count = 0;
+
+    for b in data {
+        let len = CODE_LENGTHS[*b as usize] as u32;
+        bits = (bits << len) | CANONICAL.codes[*b as usize] as u64;
+        bit_count += len;
+
+        while bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+    }
+
+    if bit_count > 0 {
+        let padding = 8 - bit_count;
+        out.push(((bits << padding) as u8) | ((1 << padding) - 1) as u8);
+    }
+}
+
+/// Decodes `data`, which has to end in at most 7 bits of padding that match the start of EOS
+pub(crate) fn decode(data: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let mut decoded = Vec::with_capacity(data.len() * 8 / 5);
+    let mut code = 0_u32;
+    let mut len = 0;
+
+    for byte in data {
+        for shift in (0..8).rev() {
+            code = (code << 1) | ((byte >> shift) & 1) as u32;
+            len += 1;
+
+            let offset = code.wrapping_sub(CANONICAL.first_codes[len]);
+            if offset < CANONICAL.counts[len] as u32 {
+                let symbol = CANONICAL.symbols[CANONICAL.offsets[len] as usize + offset as usize];
+                if symbol as usize == EOS {
+                    return Err(HpackError::InvalidHuffman);
+                }
+
+                decoded.push(symbol as u8);
+                code = 0;
+                len = 0;
+            } else if len == MAX_CODE_LEN {
+                return Err(HpackError::InvalidHuffman);
+            }
+        }
+    }
+
+    // Whatever is left has to be padding, which is all ones and shorter than a byte
+    if len > 7 || code != (1 << len) - 1 {
+        return Err(HpackError::InvalidHuffman);
+    }
+
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rfc_examples() {
+        // RFC 7541 C.4.1 and C.6.1
+        let cases: [(&str, &[u8]); 3] = [
+            (
+                "www.example.com",
+                &[
+                    0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff,
+                ],
+            ),
+            ("no-cache", &[0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf]),
+            (
+                "Mon, 21 Oct 2013 20:13:21 GMT",
+                &[
+                    0xd0, 0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95, 0x04,
+                    0x0b, 0x81, 0x66, 0xe0, 0x82, 0xa6, 0x2d, 0x1b, 0xff,
+                ],
+            ),
+        ];
+
+        for (text, encoded) in cases {
+            let mut out = Vec::new();
+            encode(text.as_bytes(), &mut out);
+            assert_eq!(out, encoded);
+            assert_eq!(encoded_len(text.as_bytes()), encoded.len());
+            assert_eq!(decode(encoded).unwrap(), text.as_bytes());
+        }
+
+        // Known codes from both ends of the table
+        assert_eq!(CANONICAL.codes[b'0' as usize], 0x0);
+        assert_eq!(CANONICAL.codes[0], 0x1ff8);
+        assert_eq!(CANONICAL.codes[EOS], 0x3fffffff);
+
+        let all: Vec<u8> = (0..=255).collect();
+        let mut out = Vec::new();
+        encode(&all, &mut out);
+        assert_eq!(decode(&out).unwrap(), all);
+    }
+
+    #[test]
+    fn invalid_padding() {
+        // "a" is 00011, padded with 0s instead of 1s
+        assert_eq!(decode(&[0x18]), Err(HpackError::InvalidHuffman));
+        // A whole byte of padding
+        assert_eq!(decode(&[0x1F, 0xFF]), Err(HpackError::InvalidHuffman));
+        // EOS itself
+        assert_eq!(
+            decode(&[0xFF, 0xFF, 0xFF, 0xFF]),
+            Err(HpackError::InvalidHuffman)
+        );
+        assert_eq!(decode(&[0x1F]).unwrap(), b"a");
+    }
+}
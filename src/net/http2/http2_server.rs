@@ -0,0 +1,774 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::Scope;
+use std::time::{Duration, Instant};
+
+use super::http2_connection::{is_connection_specific, is_timeout, regular_field};
+use super::http2_connection::{Event, Input, Output, RecvWindow};
+use super::{ErrorCode, Frame, HeaderFields, HpackError, Http2Error, Settings};
+use crate::encoding::base64;
+use crate::logging::Logger;
+use crate::net::http::{prepare_response, Handler, HeaderMap, HttpLimits, HttpRequest};
+use crate::net::http::{HttpResponse, Method, StatusCode, Version};
+
+const LOG_NAME: &str = "HttpServer";
+
+/// Streams a client may have open at once, each of them takes a thread while it is handled
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+/// How much of a streaming response body is read and sent at a time
+const BODY_CHUNK_SIZE: usize = 16 * 1024;
+
+/// How an [HttpServer](crate::net::http::HttpServer) connection is served once it switched to
+/// HTTP/2
+pub(crate) struct ServerOptions<'s> {
+    pub(crate) peer: SocketAddr,
+    pub(crate) limits: HttpLimits,
+    /// How long the connection is held open without any streams
+    pub(crate) keep_alive_timeout: Duration,
+    pub(crate) logging: bool,
+    pub(crate) shutdown: &'s AtomicBool,
+}
+
+/// Decodes the `HTTP2-Settings` header of an `Upgrade: h2c` request, `None` if it is missing or
+/// invalid (RFC 7540 3.2.1)
+pub(crate) fn upgrade_settings(request: &HttpRequest) -> Option<Vec<(u16, u32)>> {
+    let mut values = request.get_header_values("HTTP2-Settings").into_iter();
+    let value = values.next()?;
+    if values.next().is_some() {
+        return None;
+    }
+
+    let payload = base64::decode_url_safe(value.trim_end_matches('=')).ok()?;
+    if payload.len() % 6 != 0 {
+        return None;
+    }
+
+    let params = payload
+        .chunks_exact(6)
+        .map(|param| {
+            let id = u16::from_be_bytes([param[0], param[1]]);
+            (id, u32::from_be_bytes(param[2..6].try_into().unwrap()))
+        })
+        .collect();
+
+    Some(params)
+}
+
+/// Serves an HTTP/2 connection until the client closes it, it has been idle for too long or the
+/// server shuts down, handling every stream on its own thread
+///
+/// `received` are bytes already read from `reader`, starting with whatever is left of the
+/// connection preface. A connection upgraded from HTTP/1.1 passes the request that asked for the
+/// upgrade, which is answered on stream 1, along with the settings from its `HTTP2-Settings`
+pub(crate) fn serve<R: Read, W: Write + Send>(
+    reader: R,
+    writer: W,
+    received: Vec<u8>,
+    upgrade: Option<(HttpRequest, Vec<(u16, u32)>)>,
+    handler: &impl Handler,
+    options: &ServerOptions,
+) -> Result<(), Http2Error> {
+    let output = Output::new(writer);
+    let max_header_list_size = options.limits.max_header_size.min(u32::MAX as usize);
+
+    let settings = Settings {
+        enable_push: false,
+        max_concurrent_streams: Some(MAX_CONCURRENT_STREAMS),
+        max_header_list_size: Some(max_header_list_size as u32),
+        ..Settings::default()
+    };
+    // Servers must not announce SETTINGS_ENABLE_PUSH
+    let params: Vec<_> = settings
+        .to_params()
+        .into_iter()
+        .filter(|(id, _)| *id != 0x2)
+        .collect();
+    output.send_frame(&Frame::Settings { ack: false, params })?;
+
+    let mut input = Input::new(reader, received, max_header_list_size);
+
+    std::thread::scope(|scope| {
+        let mut server = Server {
+            scope,
+            output: &output,
+            handler,
+            options,
+            incoming: HashMap::new(),
+            last_stream_id: 0,
+            going_away: false,
+        };
+
+        let result = server.run(&mut input, upgrade);
+
+        if let Err(Http2Error::Protocol(error_code, reason)) = &result {
+            let _ = output.go_away(server.last_stream_id, *error_code, reason);
+        }
+
+        // Handlers still waiting for a window give up, the scope waits for them to return
+        output.close();
+        result
+    })
+}
+
+/// A request whose body is still being received
+struct Incoming {
+    request: HttpRequest,
+    body: Vec<u8>,
+    content_length: Option<u64>,
+    window: RecvWindow,
+}
+
+struct Server<'scope, 'env, W: Write + Send, H: Handler> {
+    scope: &'scope Scope<'scope, 'env>,
+    output: &'env Output<W>,
+    handler: &'env H,
+    options: &'env ServerOptions<'env>,
+    incoming: HashMap<u32, Incoming>,
+    /// The highest stream the client opened
+    last_stream_id: u32,
+    /// Set once a GOAWAY went out, the connection closes when its last stream finishes
+    going_away: bool,
+}
+
+impl<'scope, 'env, W: Write + Send, H: Handler> Server<'scope, 'env, W, H> {
+    fn run<R: Read>(
+        &mut self,
+        input: &mut Input<R>,
+        upgrade: Option<(HttpRequest, Vec<(u16, u32)>)>,
+    ) -> Result<(), Http2Error> {
+        if let Some((mut request, params)) = upgrade {
+            self.output.apply_settings(&params, false)?;
+
+            // The upgraded request was received in full and is the only one on stream 1
+            request.set_version(Version::Http2);
+            for name in ["Connection", "Upgrade", "HTTP2-Settings"] {
+                request.remove_header(name);
+            }
+
+            self.last_stream_id = 1;
+            self.output.open_stream(1);
+            self.dispatch(1, request);
+        }
+
+        let mut idle_since = Instant::now();
+
+        // An upgraded client only sends the preface once it received the 101 response
+        loop {
+            match input.read_preface() {
+                Ok(()) => break,
+                Err(err) if is_timeout(&err) => {
+                    if self.options.shutdown.load(Ordering::SeqCst)
+                        || idle_since.elapsed() >= self.options.keep_alive_timeout
+                    {
+                        return Ok(());
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        loop {
+            if self.output.open_count() > 0 {
+                idle_since = Instant::now();
+            } else if self.going_away {
+                return Ok(());
+            }
+
+            let event = match input.next(self.output) {
+                Ok(event) => event,
+                Err(err) if is_timeout(&err) => {
+                    // Partially received frames stay buffered, so timing out here only gives a
+                    // chance to notice a shutdown or an idle client
+                    if !self.going_away
+                        && (self.options.shutdown.load(Ordering::SeqCst)
+                            || idle_since.elapsed() >= self.options.keep_alive_timeout)
+                    {
+                        self.go_away()?;
+                    }
+                    continue;
+                }
+                Err(Http2Error::ConnectionClosed) => return Ok(()),
+                Err(err) => return Err(err),
+            };
+
+            match event {
+                Event::Headers {
+                    stream_id,
+                    fields,
+                    end_stream,
+                } => self.headers(stream_id, fields, end_stream)?,
+                Event::Data {
+                    stream_id,
+                    data,
+                    len,
+                    end_stream,
+                } => self.data(stream_id, data, len, end_stream)?,
+                Event::Reset { stream_id, .. } => {
+                    self.incoming.remove(&stream_id);
+                    self.output.finish_stream(stream_id);
+                }
+                Event::GoAway { .. } => {
+                    // The client won't open any more streams, the ones it did are still answered
+                    if !self.going_away {
+                        self.go_away()?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn go_away(&mut self) -> Result<(), Http2Error> {
+        self.going_away = true;
+        self.output
+            .go_away(self.last_stream_id, ErrorCode::NO_ERROR, "")
+    }
+
+    fn headers(
+        &mut self,
+        stream_id: u32,
+        fields: Result<HeaderFields, HpackError>,
+        end_stream: bool,
+    ) -> Result<(), Http2Error> {
+        if let Some(mut incoming) = self.incoming.remove(&stream_id) {
+            // Trailers, which end the stream and are kept apart from the request's headers
+            let trailers = match fields {
+                Ok(fields) if end_stream => trailers(fields),
+                Ok(_) => Err(Http2Error::Malformed("Trailers without END_STREAM")),
+                Err(_) => {
+                    return self.reject(
+                        stream_id,
+                        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                        true,
+                    )
+                }
+            };
+
+            match trailers {
+                Ok(trailers) => {
+                    incoming.request.set_trailers(trailers);
+                    return self.finish(stream_id, incoming);
+                }
+                Err(_) => return self.reset(stream_id, ErrorCode::PROTOCOL_ERROR),
+            }
+        }
+
+        if stream_id.is_multiple_of(2) {
+            return Err(protocol_error("Client opened an even stream"));
+        }
+        if stream_id <= self.last_stream_id {
+            return match self.output.is_open(stream_id) {
+                true => self.reset(stream_id, ErrorCode::STREAM_CLOSED),
+                false => Err(Http2Error::Protocol(
+                    ErrorCode::STREAM_CLOSED,
+                    "HEADERS on a closed stream",
+                )),
+            };
+        }
+
+        if self.going_away {
+            // Streams after the GOAWAY's last stream are ignored, the client retries them
+            return Ok(());
+        }
+        self.last_stream_id = stream_id;
+
+        if self.output.open_count() >= MAX_CONCURRENT_STREAMS as usize {
+            return self.reset(stream_id, ErrorCode::REFUSED_STREAM);
+        }
+
+        let limits = &self.options.limits;
+        let fields = match fields {
+            Ok(fields) if fields.len() <= limits.max_header_count => fields,
+            _ => {
+                return self.reject(
+                    stream_id,
+                    StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                    !end_stream,
+                )
+            }
+        };
+
+        let mut request = match to_request(fields) {
+            Ok(request) => request,
+            Err(_) => return self.reset(stream_id, ErrorCode::PROTOCOL_ERROR),
+        };
+        request.set_peer_addr(Some(self.options.peer));
+
+        let content_length = match request.get_header("content-length") {
+            Some(len) => match len.parse::<u64>() {
+                Ok(len) => Some(len),
+                Err(_) => return self.reset(stream_id, ErrorCode::PROTOCOL_ERROR),
+            },
+            None => None,
+        };
+        if content_length.is_some_and(|len| len > limits.max_body_size) {
+            return self.reject(stream_id, StatusCode::CONTENT_TOO_LARGE, !end_stream);
+        }
+
+        self.output.open_stream(stream_id);
+
+        let incoming = Incoming {
+            request,
+            body: Vec::new(),
+            content_length,
+            window: RecvWindow::new(),
+        };
+
+        match end_stream {
+            true => self.finish(stream_id, incoming),
+            false => {
+                self.incoming.insert(stream_id, incoming);
+                Ok(())
+            }
+        }
+    }
+
+    fn data(
+        &mut self,
+        stream_id: u32,
+        data: Vec<u8>,
+        len: u32,
+        end_stream: bool,
+    ) -> Result<(), Http2Error> {
+        if stream_id > self.last_stream_id {
+            return Err(protocol_error("DATA on an idle stream"));
+        }
+
+        let Some(incoming) = self.incoming.get_mut(&stream_id) else {
+            // Either the request was complete already or the stream was reset, in which case
+            // whatever was still on its way is ignored
+            return match self.output.is_open(stream_id) {
+                true => self.reset(stream_id, ErrorCode::STREAM_CLOSED),
+                false => Ok(()),
+            };
+        };
+
+        match incoming.window.consume(len) {
+            Ok(Some(increment)) if !end_stream => {
+                self.output.send_frame(&Frame::WindowUpdate {
+                    stream_id,
+                    increment,
+                })?;
+            }
+            Ok(_) => {}
+            Err(_) => {
+                self.incoming.remove(&stream_id);
+                return self.reset(stream_id, ErrorCode::FLOW_CONTROL_ERROR);
+            }
+        }
+
+        if (incoming.body.len() + data.len()) as u64 > self.options.limits.max_body_size {
+            self.incoming.remove(&stream_id);
+            self.output.finish_stream(stream_id);
+            return self.reject(stream_id, StatusCode::CONTENT_TOO_LARGE, !end_stream);
+        }
+        incoming.body.extend_from_slice(&data);
+
+        if end_stream {
+            let incoming = self.incoming.remove(&stream_id).unwrap();
+            return self.finish(stream_id, incoming);
+        }
+
+        Ok(())
+    }
+
+    /// Hands a request whose body is complete to the handler
+    fn finish(&mut self, stream_id: u32, incoming: Incoming) -> Result<(), Http2Error> {
+        let Incoming {
+            mut request,
+            body,
+            content_length,
+            ..
+        } = incoming;
+
+        if content_length.is_some_and(|len| len != body.len() as u64) {
+            return self.reset(stream_id, ErrorCode::PROTOCOL_ERROR);
+        }
+
+        request.set_body(body.into_boxed_slice());
+        self.dispatch(stream_id, request);
+        Ok(())
+    }
+
+    fn dispatch(&self, stream_id: u32, request: HttpRequest) {
+        let output = self.output;
+        let handler = self.handler;
+        let options = self.options;
+
+        self.scope
+            .spawn(move || respond(output, handler, options, stream_id, request));
+    }
+
+    /// Answers a request that is rejected before it reaches the handler, telling the client to
+    /// stop sending its body if `unfinished`
+    fn reject(
+        &self,
+        stream_id: u32,
+        status_code: StatusCode,
+        unfinished: bool,
+    ) -> Result<(), Http2Error> {
+        let status = status_code.as_u16().to_string();
+        let fields = [(":status", status.as_str()), ("content-length", "0")];
+
+        self.output.open_stream(stream_id);
+        let res = self.output.send_headers(stream_id, fields, true);
+        self.output.finish_stream(stream_id);
+        res?;
+
+        match unfinished {
+            true => self.reset(stream_id, ErrorCode::NO_ERROR),
+            false => Ok(()),
+        }
+    }
+
+    fn reset(&self, stream_id: u32, error_code: ErrorCode) -> Result<(), Http2Error> {
+        let res = self.output.reset_stream(stream_id, error_code, false);
+        self.output.finish_stream(stream_id);
+        res
+    }
+}
+
+/// Runs the handler for a stream's request and sends back its response
+fn respond<W: Write>(
+    output: &Output<W>,
+    handler: &impl Handler,
+    options: &ServerOptions,
+    stream_id: u32,
+    request: HttpRequest,
+) {
+    // A panicking handler only takes its own stream down
+    let response = std::panic::catch_unwind(AssertUnwindSafe(|| handler.handle(&request)));
+
+    let res = match response {
+        Ok(mut response) => {
+            prepare_response(&request, &mut response, true);
+
+            if options.logging {
+                Logger::info(
+                    std::io::stdout(),
+                    LOG_NAME,
+                    &format!(
+                        "{} \"{} {} {}\" {}",
+                        options.peer,
+                        request.get_method(),
+                        request.get_url(),
+                        request.get_version(),
+                        response.get_status_code()
+                    ),
+                );
+            }
+
+            send_response(output, stream_id, response)
+        }
+        Err(_) => Err(Http2Error::StreamReset(ErrorCode::INTERNAL_ERROR)),
+    };
+
+    match res {
+        Ok(()) | Err(Http2Error::ConnectionClosed) => {}
+        Err(Http2Error::StreamReset(error_code)) if output.is_open(stream_id) => {
+            let _ = output.reset_stream(stream_id, error_code, false);
+        }
+        Err(Http2Error::StreamReset(_)) => {}
+        Err(err) => {
+            if options.logging {
+                Logger::warn(
+                    std::io::stderr(),
+                    LOG_NAME,
+                    &format!("Stream {} to {} failed: {}", stream_id, options.peer, err),
+                );
+            }
+            let _ = output.reset_stream(stream_id, ErrorCode::INTERNAL_ERROR, false);
+        }
+    }
+
+    output.finish_stream(stream_id);
+}
+
+fn send_response<W: Write>(
+    output: &Output<W>,
+    stream_id: u32,
+    mut response: HttpResponse,
+) -> Result<(), Http2Error> {
+    let status_code = response.get_status_code();
+    let has_body = !(status_code.is_informational()
+        || status_code == StatusCode::NO_CONTENT
+        || status_code == StatusCode::NOT_MODIFIED);
+
+    let mut body = response.take_body();
+    let end_stream = !has_body || body.content_length() == Some(0);
+
+    let status = status_code.as_u16().to_string();
+    let headers: Vec<(String, &str)> = response
+        .get_headers()
+        .iter()
+        .filter(|(name, _)| !is_connection_specific(name))
+        .map(|(name, value)| (name.to_ascii_lowercase(), value))
+        .collect();
+
+    let fields = headers.iter().map(|(name, value)| (name.as_str(), *value));
+    output.send_headers(
+        stream_id,
+        [(":status", status.as_str())].into_iter().chain(fields),
+        end_stream,
+    )?;
+
+    if end_stream {
+        return Ok(());
+    }
+
+    if !body.is_stream() {
+        return output.send_data(stream_id, body.as_bytes(), true);
+    }
+
+    let mut buf = vec![0; BODY_CHUNK_SIZE];
+    loop {
+        match body.read(&mut buf) {
+            Ok(0) => return output.send_data(stream_id, &[], true),
+            Ok(len) => output.send_data(stream_id, &buf[..len], false)?,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Builds a request from the fields of its header block (RFC 9113 8.3.1)
+fn to_request(fields: HeaderFields) -> Result<HttpRequest, Http2Error> {
+    let mut method = None;
+    let mut scheme = None;
+    let mut path = None;
+    let mut authority = None;
+    let mut headers = HeaderMap::new();
+    let mut cookies = Vec::new();
+
+    for (name, value) in fields {
+        if name.starts_with(b":") {
+            if !headers.is_empty() || !cookies.is_empty() {
+                return Err(Http2Error::Malformed("Pseudo-header after a regular field"));
+            }
+
+            let slot = match &name[..] {
+                b":method" => &mut method,
+                b":scheme" => &mut scheme,
+                b":path" => &mut path,
+                b":authority" => &mut authority,
+                _ => return Err(Http2Error::Malformed("Unknown pseudo-header")),
+            };
+            if slot.is_some() {
+                return Err(Http2Error::Malformed("Repeated pseudo-header"));
+            }
+
+            let value = String::from_utf8(value)
+                .map_err(|_| Http2Error::Malformed("Pseudo-header is not UTF-8"))?;
+            *slot = Some(value);
+            continue;
+        }
+
+        let (name, value) = regular_field(name, value)?;
+        match name.as_str() {
+            "te" if value != "trailers" => {
+                return Err(Http2Error::Malformed("TE other than trailers"))
+            }
+            // Split cookies are joined back into one field (RFC 9113 8.2.3)
+            "cookie" => cookies.push(value),
+            _ => headers.append(name, value),
+        }
+    }
+
+    let method: Method = method
+        .ok_or(Http2Error::Malformed("Missing :method"))?
+        .parse()
+        .map_err(|_| Http2Error::Malformed("Invalid :method"))?;
+
+    let url = match (&method, scheme, path) {
+        (Method::Connect, None, None) => authority
+            .clone()
+            .ok_or(Http2Error::Malformed("CONNECT without :authority"))?,
+        (Method::Connect, _, _) => {
+            return Err(Http2Error::Malformed("CONNECT with :scheme or :path"))
+        }
+        (_, Some(_), Some(path)) if !path.is_empty() => path,
+        _ => return Err(Http2Error::Malformed("Missing :scheme or :path")),
+    };
+
+    // The target ends up in an HTTP/1.1 request line when proxied, anything but an origin-form
+    // path or `*` for OPTIONS could forge one (RFC 9113 8.3.1)
+    let valid_form = method == Method::Connect
+        || url.starts_with('/')
+        || (url == "*" && method == Method::Options);
+    if !valid_form || url.bytes().any(|b| b.is_ascii_control() || b == b' ') {
+        return Err(Http2Error::Malformed("Invalid :path"));
+    }
+
+    if let Some(authority) = authority {
+        // It stands in for the Host header, so it has to be something a Host header could hold
+        if authority.bytes().any(|b| b.is_ascii_control() || b == b' ') {
+            return Err(Http2Error::Malformed("Invalid :authority"));
+        }
+        if !headers.contains("host") {
+            headers.insert("host", authority);
+        }
+    }
+    if !cookies.is_empty() {
+        headers.append("cookie", cookies.join("; "));
+    }
+
+    Ok(HttpRequest::new(
+        method,
+        url,
+        Version::Http2,
+        headers,
+        Box::new([]),
+    ))
+}
+
+/// The fields of a trailer block, which can't have pseudo-headers
+fn trailers(fields: HeaderFields) -> Result<HeaderMap, Http2Error> {
+    let mut trailers = HeaderMap::new();
+
+    for (name, value) in fields {
+        if name.starts_with(b":") {
+            return Err(Http2Error::Malformed("Pseudo-header in trailers"));
+        }
+        let (name, value) = regular_field(name, value)?;
+        trailers.append(name, value);
+    }
+
+    Ok(trailers)
+}
+
+fn protocol_error(reason: &'static str) -> Http2Error {
+    Http2Error::Protocol(ErrorCode::PROTOCOL_ERROR, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields(fields: &[(&str, &str)]) -> HeaderFields {
+        fields
+            .iter()
+            .map(|(name, value)| (name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn requests_from_fields() {
+        let request = to_request(fields(&[
+            (":method", "POST"),
+            (":scheme", "http"),
+            (":authority", "example.com"),
+            (":path", "/submit?x=1"),
+            ("cookie", "a=1"),
+            ("content-type", "text/plain"),
+            ("cookie", "b=2"),
+        ]))
+        .unwrap();
+
+        assert_eq!(request.get_method(), &Method::Post);
+        assert_eq!(request.get_url(), "/submit?x=1");
+        assert_eq!(request.get_version(), Version::Http2);
+        assert_eq!(request.get_header("Host"), Some("example.com"));
+        assert_eq!(request.get_header("Cookie"), Some("a=1; b=2"));
+
+        let malformed: [&[(&str, &str)]; 8] = [
+            &[(":method", "GET"), (":path", "/")],
+            &[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":authority", "example.com\r\nX-Forged: yes"),
+                (":path", "/"),
+            ],
+            &[(":method", "GET"), (":scheme", "http"), (":path", "")],
+            &[
+                (":scheme", "http"),
+                (":path", "/"),
+                (":method", "GET"),
+                (":method", "GET"),
+            ],
+            &[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":status", "200"),
+            ],
+            &[
+                (":method", "GET"),
+                ("accept", "*/*"),
+                (":scheme", "http"),
+                (":path", "/"),
+            ],
+            &[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                ("Accept", "*/*"),
+            ],
+            &[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                ("connection", "close"),
+            ],
+        ];
+        for case in malformed {
+            assert!(
+                matches!(to_request(fields(case)), Err(Http2Error::Malformed(_))),
+                "{:?}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn paths_that_would_forge_a_request_line() {
+        let request = |method: &str, path: &str| {
+            to_request(fields(&[
+                (":method", method),
+                (":scheme", "http"),
+                (":path", path),
+            ]))
+        };
+
+        assert!(request("GET", "/a?b=c%20d").is_ok());
+        assert!(request("OPTIONS", "*").is_ok());
+
+        for (method, path) in [
+            ("GET", "/ HTTP/1.1\r\nHost: internal\r\n\r\nGET /"),
+            ("GET", "/a b"),
+            ("GET", "/a\tb"),
+            ("GET", "/a\0"),
+            ("GET", "/\x7f"),
+            ("GET", "*"),
+            ("GET", "a/b"),
+            ("GET", "http://example.com/"),
+        ] {
+            assert!(
+                matches!(request(method, path), Err(Http2Error::Malformed(_))),
+                "{:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn upgrade_settings_header() {
+        let mut request = HttpRequest::new(
+            Method::Get,
+            "/".into(),
+            Version::Http11,
+            HeaderMap::new(),
+            Box::new([]),
+        );
+        request.set_header("HTTP2-Settings".into(), "AAMAAABkAAQAAP__".into());
+        assert_eq!(
+            upgrade_settings(&request),
+            Some(vec![(0x3, 100), (0x4, 65535)])
+        );
+
+        request.set_header("HTTP2-Settings".into(), "AAMAAAB".into());
+        assert_eq!(upgrade_settings(&request), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interest {
+    Readable,
+    Writable,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Readiness {
+    pub readable: bool,
+    pub writable: bool,
+    /// The peer hung up or the socket is in an error state, reading will return the error or EOF
+    pub closed: bool,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.readable || self.writable || self.closed
+    }
+}
+
+/// Blocks until at least one of `sources` is ready for the I/O it is interested in or `timeout`
+/// runs out, a timeout of `None` waits forever
+///
+/// The readiness of each source is returned in the same order as `sources`
+pub fn poll(
+    sources: &[(RawFd, Interest)],
+    timeout: Option<Duration>,
+) -> std::io::Result<Vec<Readiness>> {
+    let mut fds: Vec<_> = sources
+        .iter()
+        .map(|(fd, interest)| sys::PollFd {
+            fd: *fd,
+            events: match interest {
+                Interest::Readable => sys::POLLIN,
+                Interest::Writable => sys::POLLOUT,
+                Interest::Both => sys::POLLIN | sys::POLLOUT,
+            },
+            revents: 0,
+        })
+        .collect();
+
+    let timeout_ms = match timeout {
+        // Round up so a tiny timeout doesn't turn into a non-blocking poll
+        Some(timeout) => timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+        None => -1,
+    };
+
+    'polling: loop {
+        // SAFETY: fds is a valid, exclusively borrowed array of fds.len() pollfd structs
+        let res = unsafe { sys::poll(fds.as_mut_ptr(), fds.len() as sys::NfdsT, timeout_ms) };
+
+        if res >= 0 {
+            break 'polling;
+        }
+
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+
+    Ok(fds
+        .into_iter()
+        .map(|fd| Readiness {
+            readable: fd.revents & sys::POLLIN != 0,
+            writable: fd.revents & sys::POLLOUT != 0,
+            closed: fd.revents & (sys::POLLHUP | sys::POLLERR | sys::POLLNVAL) != 0,
+        })
+        .collect())
+}
+
+/// Waits on a single source, see [poll]
+pub fn wait(
+    source: &impl AsFd,
+    interest: Interest,
+    timeout: Option<Duration>,
+) -> std::io::Result<Readiness> {
+    Ok(poll(&[(source.as_fd().as_raw_fd(), interest)], timeout)?[0])
+}
+
+mod sys {
+    use std::os::raw::{c_int, c_short};
+
+    #[cfg(target_os = "linux")]
+    pub type NfdsT = std::os::raw::c_ulong;
+    #[cfg(not(target_os = "linux"))]
+    pub type NfdsT = std::os::raw::c_uint;
+
+    pub const POLLIN: c_short = 0x001;
+    pub const POLLOUT: c_short = 0x004;
+    pub const POLLERR: c_short = 0x008;
+    pub const POLLHUP: c_short = 0x010;
+    pub const POLLNVAL: c_short = 0x020;
+
+    #[repr(C)]
+    pub struct PollFd {
+        pub fd: c_int,
+        pub events: c_short,
+        pub revents: c_short,
+    }
+
+    extern "C" {
+        pub fn poll(fds: *mut PollFd, nfds: NfdsT, timeout: c_int) -> c_int;
+    }
+}
@@ -0,0 +1,609 @@
+//! URIs and URLs as described by RFC 3986
+//!
+//! A [Url] is split into its components when it is parsed, which keep the percent-encoding they
+//! were written with. [Url::join] resolves relative references such as `../style.css` or a
+//! redirect's `Location` against a base URL
+
+mod url_encoding;
+mod url_query;
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use url_encoding::is_unreserved;
+pub use url_encoding::{decode, decode_bytes, encode, encode_path};
+pub use url_query::Query;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlError {
+    /// The scheme contains characters other than letters, digits, `+`, `-` and `.`, or a
+    /// relative path has a `:` in its first segment
+    InvalidScheme,
+    /// An IPv6 host is missing its closing `]`
+    InvalidHost,
+    InvalidPort,
+    /// A `%` that isn't followed by two hex digits, or that decodes to invalid UTF-8
+    InvalidPercentEncoding,
+    /// Whitespace, control characters and the like have to be percent-encoded
+    InvalidCharacter,
+    /// References can only be resolved against a URL that has a scheme
+    RelativeBase,
+}
+
+impl Display for UrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidScheme => write!(f, "Invalid URL Scheme"),
+            Self::InvalidHost => write!(f, "Invalid URL Host"),
+            Self::InvalidPort => write!(f, "Invalid URL Port"),
+            Self::InvalidPercentEncoding => write!(f, "Invalid Percent-Encoding"),
+            Self::InvalidCharacter => write!(f, "Invalid Character in URL"),
+            Self::RelativeBase => write!(f, "Base URL is not absolute"),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+/// A URI reference, either absolute (`http://example.com/a?b#c`) or relative (`/a?b`, `../c`)
+///
+/// Every component is optional except for the path, which may be empty
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Url {
+    scheme: Option<String>,
+    userinfo: Option<String>,
+    /// Without the brackets around IPv6 addresses
+    host: Option<String>,
+    port: Option<u16>,
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+impl Url {
+    /// Splits `input` into its components following RFC 3986 3
+    ///
+    /// Nothing is decoded or normalized, see [Url::normalize]
+    pub fn parse(input: &str) -> Result<Self, UrlError> {
+        if input
+            .bytes()
+            .any(|byte| byte <= b' ' || byte == 0x7F || matches!(byte, b'"' | b'<' | b'>'))
+        {
+            return Err(UrlError::InvalidCharacter);
+        }
+
+        let mut url = Self::default();
+        let mut rest = input;
+
+        // Fragment
+        if let Some((before, fragment)) = rest.split_once('#') {
+            url.fragment = Some(check_encoding(fragment)?.to_string());
+            rest = before;
+        }
+
+        // Query
+        if let Some((before, query)) = rest.split_once('?') {
+            url.query = Some(check_encoding(query)?.to_string());
+            rest = before;
+        }
+
+        // Scheme, a colon before the first slash either ends the scheme or is an error
+        let first_segment = rest.split('/').next().unwrap_or_default();
+        if let Some((scheme, after)) = first_segment
+            .split_once(':')
+            .map(|(scheme, _)| (scheme, &rest[scheme.len() + 1..]))
+        {
+            if !is_scheme(scheme) {
+                return Err(UrlError::InvalidScheme);
+            }
+
+            url.scheme = Some(scheme.to_string());
+            rest = after;
+        }
+
+        // Authority
+        if let Some(after) = rest.strip_prefix("//") {
+            let end = after.find('/').unwrap_or(after.len());
+            url.parse_authority(&after[..end])?;
+            rest = &after[end..];
+        }
+
+        url.path = check_encoding(rest)?.to_string();
+
+        Ok(url)
+    }
+
+    fn parse_authority(&mut self, authority: &str) -> Result<(), UrlError> {
+        let host_port = match authority.rsplit_once('@') {
+            Some((userinfo, host_port)) => {
+                self.userinfo = Some(check_encoding(userinfo)?.to_string());
+                host_port
+            }
+            None => authority,
+        };
+
+        let (host, port) = if let Some(ipv6) = host_port.strip_prefix('[') {
+            let (host, after) = ipv6.split_once(']').ok_or(UrlError::InvalidHost)?;
+
+            match after {
+                "" => (host, None),
+                _ => (
+                    host,
+                    Some(after.strip_prefix(':').ok_or(UrlError::InvalidHost)?),
+                ),
+            }
+        } else {
+            match host_port.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            }
+        };
+
+        self.host = Some(check_encoding(host)?.to_string());
+        self.port = match port {
+            // An empty port is allowed and means the scheme's default
+            Some("") | None => None,
+            Some(port) if port.bytes().all(|byte| byte.is_ascii_digit()) => {
+                Some(port.parse().map_err(|_| UrlError::InvalidPort)?)
+            }
+            Some(_) => return Err(UrlError::InvalidPort),
+        };
+
+        Ok(())
+    }
+
+    /// A URL is absolute if it has a scheme
+    pub fn is_absolute(&self) -> bool {
+        self.scheme.is_some()
+    }
+
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    pub fn set_scheme(&mut self, scheme: Option<String>) {
+        self.scheme = scheme;
+    }
+
+    /// Everything before the `@` of the authority, such as `user:password`
+    pub fn userinfo(&self) -> Option<&str> {
+        self.userinfo.as_deref()
+    }
+
+    pub fn set_userinfo(&mut self, userinfo: Option<String>) {
+        self.userinfo = userinfo;
+    }
+
+    /// IPv6 addresses are returned without their brackets
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    pub fn set_host(&mut self, host: Option<String>) {
+        self.host = host;
+    }
+
+    /// The port given in the URL, see [Url::port_or_default]
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    pub fn set_port(&mut self, port: Option<u16>) {
+        self.port = port;
+    }
+
+    /// The port given in the URL, or else the default port of a known scheme
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or_else(|| default_port(self.scheme.as_deref()?))
+    }
+
+    /// `host:port` with the port left out if there is none, not including the userinfo
+    pub fn authority(&self) -> Option<String> {
+        let host = self.host.as_deref()?;
+
+        let mut authority = match host.contains(':') {
+            true => format!("[{}]", host),
+            false => host.to_string(),
+        };
+
+        if let Some(port) = self.port {
+            authority.push_str(&format!(":{}", port));
+        }
+
+        Some(authority)
+    }
+
+    /// The path as it was written, still percent-encoded
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    /// The percent-decoded path segments, a path of `/a/b%2Fc/` has the segments `a`, `b/c`
+    /// and an empty one
+    pub fn path_segments(&self) -> Result<Vec<String>, UrlError> {
+        let path = self.path.strip_prefix('/').unwrap_or(&self.path);
+
+        if path.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        path.split('/').map(decode).collect()
+    }
+
+    /// The query without its leading `?`, still percent-encoded
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn set_query(&mut self, query: Option<String>) {
+        self.query = query;
+    }
+
+    /// The query's key and value pairs, empty if there is no query
+    pub fn query_pairs(&self) -> Query {
+        self.query.as_deref().map(Query::parse).unwrap_or_default()
+    }
+
+    /// Replaces the query with the encoded `pairs`, an empty [Query] removes the query
+    pub fn set_query_pairs(&mut self, pairs: &Query) {
+        self.query = match pairs.is_empty() {
+            true => None,
+            false => Some(pairs.to_string()),
+        };
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    pub fn set_fragment(&mut self, fragment: Option<String>) {
+        self.fragment = fragment;
+    }
+
+    /// The path and query as sent in an HTTP request line, an empty path becomes `/`
+    pub fn path_and_query(&self) -> String {
+        let mut target = match self.path.is_empty() {
+            true => "/".to_string(),
+            false => self.path.clone(),
+        };
+
+        if let Some(query) = &self.query {
+            target.push('?');
+            target.push_str(query);
+        }
+
+        target
+    }
+
+    /// Resolves `reference` against this URL (RFC 3986 5.2)
+    ///
+    /// ```
+    /// # use fp_lib::net::url::Url;
+    /// let base = Url::parse("http://a/b/c/d;p?q").unwrap();
+    /// assert_eq!(base.join("../g").unwrap().to_string(), "http://a/b/g");
+    /// ```
+    pub fn join(&self, reference: &str) -> Result<Url, UrlError> {
+        if !self.is_absolute() {
+            return Err(UrlError::RelativeBase);
+        }
+
+        let reference = Url::parse(reference)?;
+        let mut target = reference.clone();
+
+        if reference.scheme.is_some() {
+            target.path = remove_dot_segments(&reference.path);
+            return Ok(target);
+        }
+
+        target.scheme = self.scheme.clone();
+
+        if reference.host.is_some() {
+            target.path = remove_dot_segments(&reference.path);
+            return Ok(target);
+        }
+
+        target.userinfo = self.userinfo.clone();
+        target.host = self.host.clone();
+        target.port = self.port;
+
+        if reference.path.is_empty() {
+            target.path = self.path.clone();
+            target.query = reference.query.or_else(|| self.query.clone());
+        } else if reference.path.starts_with('/') {
+            target.path = remove_dot_segments(&reference.path);
+        } else {
+            target.path = remove_dot_segments(&self.merge(&reference.path));
+        }
+
+        Ok(target)
+    }
+
+    /// Appends a relative path to everything up to the last `/` of this URL's path
+    /// (RFC 3986 5.2.3)
+    fn merge(&self, path: &str) -> String {
+        if self.host.is_some() && self.path.is_empty() {
+            return format!("/{}", path);
+        }
+
+        match self.path.rfind('/') {
+            Some(end) => format!("{}{}", &self.path[..=end], path),
+            None => path.to_string(),
+        }
+    }
+
+    /// Brings the URL into its normal form (RFC 3986 6.2.2): the scheme and host are lowercased,
+    /// the default port is removed, dot segments are removed from the path, percent-encoded
+    /// unreserved characters are decoded and all other percent-encodings are uppercased
+    pub fn normalize(&mut self) {
+        if let Some(scheme) = &mut self.scheme {
+            scheme.make_ascii_lowercase();
+        }
+
+        if let Some(host) = &mut self.host {
+            *host = normalize_encoding(host).to_ascii_lowercase();
+        }
+
+        if self.port.is_some() && self.port == self.scheme.as_deref().and_then(default_port) {
+            self.port = None;
+        }
+
+        self.path = remove_dot_segments(&normalize_encoding(&self.path));
+
+        if self.path.is_empty() && self.host.is_some() {
+            self.path = "/".to_string();
+        }
+
+        for component in [&mut self.userinfo, &mut self.query, &mut self.fragment]
+            .into_iter()
+            .flatten()
+        {
+            *component = normalize_encoding(component);
+        }
+    }
+}
+
+impl FromStr for Url {
+    type Err = UrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Recomposes the URL from its components (RFC 3986 5.3)
+impl Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{}:", scheme)?;
+        }
+
+        if let Some(authority) = self.authority() {
+            write!(f, "//")?;
+
+            if let Some(userinfo) = &self.userinfo {
+                write!(f, "{}@", userinfo)?;
+            }
+
+            write!(f, "{}", authority)?;
+        }
+
+        write!(f, "{}", self.path)?;
+
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes `.` and `..` segments from a path (RFC 3986 5.2.4), `..` never goes above the root
+///
+/// ```
+/// # use fp_lib::net::url::remove_dot_segments;
+/// assert_eq!(remove_dot_segments("/a/b/c/./../../g"), "/a/g");
+/// assert_eq!(remove_dot_segments("/../secret"), "/secret");
+/// ```
+pub fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let segments: Vec<_> = path.split('/').skip(absolute as usize).collect();
+
+    let mut output: Vec<&str> = Vec::new();
+    let mut ends_in_dir = false;
+
+    for (index, segment) in segments.iter().enumerate() {
+        let is_last = index == segments.len() - 1;
+
+        match *segment {
+            "." => ends_in_dir = is_last,
+            ".." => {
+                output.pop();
+                ends_in_dir = is_last;
+            }
+            segment => output.push(segment),
+        }
+    }
+
+    let mut normalized = output.join("/");
+
+    if ends_in_dir && !normalized.is_empty() {
+        normalized.push('/');
+    }
+
+    match absolute {
+        true => format!("/{}", normalized),
+        false => normalized,
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme.to_ascii_lowercase().as_str() {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+/// `ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )` (RFC 3986 3.1)
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'-' | b'.'))
+}
+
+/// Makes sure every `%` starts a valid percent-encoding
+fn check_encoding(component: &str) -> Result<&str, UrlError> {
+    let bytes = component.as_bytes();
+
+    for (pos, byte) in bytes.iter().enumerate() {
+        if *byte == b'%'
+            && !bytes
+                .get(pos + 1..pos + 3)
+                .is_some_and(|pair| pair.iter().all(u8::is_ascii_hexdigit))
+        {
+            return Err(UrlError::InvalidPercentEncoding);
+        }
+    }
+
+    Ok(component)
+}
+
+/// Decodes percent-encoded unreserved characters and uppercases the hex digits of the rest
+fn normalize_encoding(component: &str) -> String {
+    let mut normalized = String::with_capacity(component.len());
+    let mut rest = component;
+
+    while let Some(start) = rest.find('%') {
+        normalized.push_str(&rest[..start]);
+
+        let Some(encoded) = rest
+            .get(start..start + 3)
+            .filter(|val| check_encoding(val).is_ok())
+        else {
+            normalized.push('%');
+            rest = &rest[start + 1..];
+            continue;
+        };
+
+        match decode_bytes(encoded) {
+            Ok(byte) if is_unreserved(byte[0]) => normalized.push(byte[0] as char),
+            _ => normalized.push_str(&encoded.to_ascii_uppercase()),
+        }
+
+        rest = &rest[start + 3..];
+    }
+
+    normalized.push_str(rest);
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn components() {
+        let url = Url::parse("HTTP://user:pw@Example.com:8080/a%20b/c?x=1&y=%32#frag").unwrap();
+
+        assert_eq!(url.scheme(), Some("HTTP"));
+        assert_eq!(url.userinfo(), Some("user:pw"));
+        assert_eq!(url.host(), Some("Example.com"));
+        assert_eq!(url.port(), Some(8080));
+        assert_eq!(url.path(), "/a%20b/c");
+        assert_eq!(url.path_segments().unwrap(), ["a b", "c"]);
+        assert_eq!(url.query(), Some("x=1&y=%32"));
+        assert_eq!(url.query_pairs().get("y"), Some("2"));
+        assert_eq!(url.fragment(), Some("frag"));
+        assert_eq!(url.path_and_query(), "/a%20b/c?x=1&y=%32");
+        assert_eq!(
+            url.to_string(),
+            "HTTP://user:pw@Example.com:8080/a%20b/c?x=1&y=%32#frag"
+        );
+
+        let url = Url::parse("http://[::1]/").unwrap();
+        assert_eq!(url.host(), Some("::1"));
+        assert_eq!(url.port_or_default(), Some(80));
+        assert_eq!(url.authority().unwrap(), "[::1]");
+
+        let url = Url::parse("/search?q=a+b").unwrap();
+        assert!(!url.is_absolute());
+        assert_eq!(url.query_pairs().get("q"), Some("a b"));
+
+        assert_eq!(Url::parse("http://a:port/"), Err(UrlError::InvalidPort));
+        assert_eq!(Url::parse("http://[::1/"), Err(UrlError::InvalidHost));
+        assert_eq!(Url::parse("1a:b"), Err(UrlError::InvalidScheme));
+        assert_eq!(Url::parse("/a b"), Err(UrlError::InvalidCharacter));
+        assert_eq!(Url::parse("/a%2"), Err(UrlError::InvalidPercentEncoding));
+    }
+
+    #[test]
+    fn join() {
+        // RFC 3986 5.4
+        let base = Url::parse("http://a/b/c/d;p?q").unwrap();
+        let examples = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g#s", "http://a/b/c/g#s"),
+            (";x", "http://a/b/c/;x"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+        ];
+
+        for (reference, expected) in examples {
+            assert_eq!(base.join(reference).unwrap().to_string(), expected);
+        }
+
+        let relative = Url::parse("/a").unwrap();
+        assert_eq!(relative.join("b"), Err(UrlError::RelativeBase));
+    }
+
+    #[test]
+    fn normalize() {
+        let mut url = Url::parse("HTTP://Example.COM:80/a/./b/../%7euser/%2f?%3d").unwrap();
+        url.normalize();
+
+        assert_eq!(url.to_string(), "http://example.com/a/~user/%2F?%3D");
+    }
+
+    #[test]
+    fn query() {
+        let mut query = Query::parse("a=1&b&a=2&c=x%26y");
+        assert_eq!(query.get_all("a").collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(query.get("b"), Some(""));
+        assert_eq!(query.get("c"), Some("x&y"));
+
+        query.insert("a", "3 4");
+        assert_eq!(query.to_string(), "a=3+4&b=&c=x%26y");
+    }
+}
@@ -0,0 +1,134 @@
+use super::UrlError;
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Characters that never need to be encoded (RFC 3986 2.3)
+pub(crate) fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+/// Characters with a special meaning inside some components (RFC 3986 2.2)
+pub(crate) fn is_sub_delim(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'='
+    )
+}
+
+/// Characters allowed unencoded in a path segment (RFC 3986 3.3)
+pub(crate) fn is_pchar(byte: u8) -> bool {
+    is_unreserved(byte) || is_sub_delim(byte) || matches!(byte, b':' | b'@')
+}
+
+/// Percent-encodes every byte of `input` that isn't unreserved, for use as a single path
+/// segment, query key or value and the like
+pub fn encode(input: &str) -> String {
+    encode_with(input.as_bytes(), is_unreserved)
+}
+
+/// Percent-encodes `input` for use as a path, `/` and the characters that are allowed in path
+/// segments are kept as they are
+pub fn encode_path(input: &str) -> String {
+    encode_with(input.as_bytes(), |byte| byte == b'/' || is_pchar(byte))
+}
+
+/// Decodes every `%XX` sequence in `input`, which has to decode to valid UTF-8
+pub fn decode(input: &str) -> Result<String, UrlError> {
+    String::from_utf8(decode_bytes(input)?).map_err(|_| UrlError::InvalidPercentEncoding)
+}
+
+/// Decodes every `%XX` sequence in `input`
+pub fn decode_bytes(input: &str) -> Result<Vec<u8>, UrlError> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        if bytes[pos] == b'%' {
+            let byte = bytes
+                .get(pos + 1..pos + 3)
+                .and_then(decode_hex_pair)
+                .ok_or(UrlError::InvalidPercentEncoding)?;
+
+            decoded.push(byte);
+            pos += 3;
+        } else {
+            decoded.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// `application/x-www-form-urlencoded` style encoding, spaces become `+`
+pub(crate) fn encode_form(input: &str) -> String {
+    encode_with(input.as_bytes(), is_unreserved).replace("%20", "+")
+}
+
+/// `application/x-www-form-urlencoded` style decoding, `+` becomes a space and invalid `%`
+/// sequences are kept as they are, as browsers do
+pub(crate) fn decode_form(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'+' => decoded.push(b' '),
+            b'%' => match bytes.get(pos + 1..pos + 3).and_then(decode_hex_pair) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    pos += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        pos += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub(crate) fn encode_with(input: &[u8], keep: impl Fn(u8) -> bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+
+    for &byte in input {
+        if keep(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push('%');
+            encoded.push(HEX[(byte >> 4) as usize] as char);
+            encoded.push(HEX[(byte & 0xF) as usize] as char);
+        }
+    }
+
+    encoded
+}
+
+fn decode_hex_pair(pair: &[u8]) -> Option<u8> {
+    let high = (pair[0] as char).to_digit(16)?;
+    let low = (pair[1] as char).to_digit(16)?;
+
+    Some((high * 16 + low) as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(encode("a b/c?ü"), "a%20b%2Fc%3F%C3%BC");
+        assert_eq!(encode_path("/a b/c:d@e"), "/a%20b/c:d@e");
+        assert_eq!(decode("a%20b%2fc%3F%C3%BC").unwrap(), "a b/c?ü");
+
+        assert_eq!(decode("100%"), Err(UrlError::InvalidPercentEncoding));
+        assert_eq!(decode("%zz"), Err(UrlError::InvalidPercentEncoding));
+        assert_eq!(decode("%FF"), Err(UrlError::InvalidPercentEncoding));
+
+        assert_eq!(encode_form("a b&c"), "a+b%26c");
+        assert_eq!(decode_form("a+b%26c%"), "a b&c%");
+    }
+}
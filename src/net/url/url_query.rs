@@ -0,0 +1,157 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::url_encoding::{decode_form, encode_form};
+
+/// The key and value pairs of a query string such as `a=1&b=2&a=3`
+///
+/// Keys are case-sensitive, a key can hold several values and pairs keep their order. Keys and
+/// values are stored decoded, a `+` in the query string decodes to a space
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self { pairs: Vec::new() }
+    }
+
+    /// Parses a query string without its leading `?`, a pair without a `=` has an empty value
+    pub fn parse(query: &str) -> Self {
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, val) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_form(key), decode_form(val))
+            })
+            .collect()
+    }
+
+    /// Returns the first value of the key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).next()
+    }
+
+    /// Returns every value of the key in order
+    pub fn get_all<'a: 'k, 'k>(&'a self, key: &'k str) -> impl Iterator<Item = &'a str> + 'k {
+        self.pairs
+            .iter()
+            .filter(move |(name, _)| name == key)
+            .map(|(_, val)| val.as_str())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(name, _)| name == key)
+    }
+
+    /// Replaces every value of the key with `value`, the key keeps the position of its first
+    /// occurrence
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        let value = value.into();
+
+        match self.pairs.iter().position(|(name, _)| *name == key) {
+            Some(pos) => {
+                self.pairs[pos].1 = value;
+
+                let mut index = pos + 1;
+                while index < self.pairs.len() {
+                    if self.pairs[index].0 == key {
+                        self.pairs.remove(index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+            None => self.pairs.push((key, value)),
+        }
+    }
+
+    /// Adds another value to the key, keeping the existing ones
+    pub fn append(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.pairs.push((key.into(), value.into()));
+    }
+
+    /// Removes the key, returning its values
+    pub fn remove(&mut self, key: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+
+        self.pairs.retain_mut(|(name, val)| {
+            if name == key {
+                removed.push(std::mem::take(val));
+                false
+            } else {
+                true
+            }
+        });
+
+        removed
+    }
+
+    /// Number of key and value pairs
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Iterates over every key and value pair in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+    }
+}
+
+impl FromStr for Query {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse(s))
+    }
+}
+
+/// Encodes the pairs as a query string without a leading `?`
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, (key, val)) in self.pairs.iter().enumerate() {
+            if index > 0 {
+                write!(f, "&")?;
+            }
+
+            write!(f, "{}={}", encode_form(key), encode_form(val))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl IntoIterator for Query {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pairs.into_iter()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Query {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut query = Self::new();
+        query.extend(iter);
+        query
+    }
+}
+
+/// Appends every pair, see [Query::append]
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for Query {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (key, val) in iter {
+            self.append(key, val);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! WebSockets as described by RFC 6455
+//!
+//! A [WebSocket] is created from an [HttpStream](crate::net::http::HttpStream) by completing the
+//! opening handshake over it, either as the server with [WebSocket::accept] or as the client with
+//! [WebSocket::connect]. Afterwards messages are exchanged with [WebSocket::send] and
+//! [WebSocket::recv], and the connection can be split into a [WebSocketReader] and a
+//! [WebSocketWriter] to receive and send from different places
+
+mod websocket_frame;
+mod websocket_handshake;
+mod websocket_stream;
+
+use std::fmt::Display;
+
+pub use websocket_frame::{Frame, OpCode};
+pub use websocket_handshake::{accept_key, is_upgrade_request, upgrade_response};
+pub use websocket_stream::{WebSocket, WebSocketReader, WebSocketWriter};
+
+use crate::net::http::HttpError;
+
+/// Which end of the connection a [WebSocket] is, clients mask the frames they send and servers
+/// don't
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(std::io::Error),
+    /// The opening handshake failed for the given reason
+    Handshake(&'static str),
+    /// The client asked for a WebSocket version other than 13
+    UnsupportedVersion,
+    /// The peer broke the protocol in the given way
+    Protocol(&'static str),
+    /// A text message or a close reason that isn't UTF-8
+    InvalidUtf8,
+    /// A message over the size limit, see [WebSocketReader::set_max_message_size]
+    MessageTooLarge,
+    /// The connection was closed without a close handshake
+    ConnectionClosed,
+    /// A Close frame has already been received or sent, so nothing more can be received or sent
+    /// respectively
+    AlreadyClosed,
+}
+
+impl WebSocketError {
+    /// The close code to fail the connection with, `None` for errors the peer isn't to blame for
+    pub fn close_code(&self) -> Option<CloseCode> {
+        match self {
+            Self::Protocol(_) => Some(CloseCode::PROTOCOL_ERROR),
+            Self::InvalidUtf8 => Some(CloseCode::INVALID_PAYLOAD),
+            Self::MessageTooLarge => Some(CloseCode::MESSAGE_TOO_BIG),
+            _ => None,
+        }
+    }
+}
+
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Handshake(reason) => write!(f, "WebSocket Handshake Failed: {}", reason),
+            Self::UnsupportedVersion => write!(f, "Unsupported WebSocket Version"),
+            Self::Protocol(reason) => write!(f, "WebSocket Protocol Error: {}", reason),
+            Self::InvalidUtf8 => write!(f, "WebSocket Text is not valid UTF-8"),
+            Self::MessageTooLarge => write!(f, "WebSocket Message Too Large"),
+            Self::ConnectionClosed => write!(f, "Connection closed without a close handshake"),
+            Self::AlreadyClosed => write!(f, "WebSocket already closed"),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for WebSocketError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<HttpError> for WebSocketError {
+    fn from(err: HttpError) -> Self {
+        Self::Io(err.into())
+    }
+}
+
+/// Why a WebSocket was closed (RFC 6455 7.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CloseCode(u16);
+
+impl CloseCode {
+    pub const NORMAL: Self = Self(1000);
+    pub const GOING_AWAY: Self = Self(1001);
+    pub const PROTOCOL_ERROR: Self = Self(1002);
+    pub const UNSUPPORTED_DATA: Self = Self(1003);
+    pub const INVALID_PAYLOAD: Self = Self(1007);
+    pub const POLICY_VIOLATION: Self = Self(1008);
+    pub const MESSAGE_TOO_BIG: Self = Self(1009);
+    pub const MANDATORY_EXTENSION: Self = Self(1010);
+    pub const INTERNAL_ERROR: Self = Self(1011);
+
+    pub fn new(code: u16) -> Self {
+        Self(code)
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// Whether the code may appear in a Close frame, 1005, 1006 and 1015 are reserved for
+    /// reporting closes that didn't send one
+    pub fn is_valid(&self) -> bool {
+        matches!(self.0, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        Self(code)
+    }
+}
+
+impl Display for CloseCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The payload of a Close frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: CloseCode,
+    /// At most 123 bytes, so the frame fits the control frame limit
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// `None` if the peer closed without giving a code
+    Close(Option<CloseFrame>),
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Binary(data)
+    }
+}
+
+impl From<&[u8]> for Message {
+    fn from(data: &[u8]) -> Self {
+        Self::Binary(data.to_vec())
+    }
+}
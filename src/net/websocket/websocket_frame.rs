@@ -0,0 +1,231 @@
+use super::WebSocketError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// Continues a fragmented text or binary message
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    pub fn from_u8(opcode: u8) -> Option<Self> {
+        Some(match opcode {
+            0x0 => Self::Continuation,
+            0x1 => Self::Text,
+            0x2 => Self::Binary,
+            0x8 => Self::Close,
+            0x9 => Self::Ping,
+            0xA => Self::Pong,
+            _ => return None,
+        })
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xA,
+        }
+    }
+
+    /// Control frames can be sent between the fragments of a message but can't be fragmented
+    /// themselves
+    pub fn is_control(&self) -> bool {
+        self.as_u8() & 0x8 != 0
+    }
+}
+
+/// A single WebSocket frame (RFC 6455 5.2) with its payload unmasked
+///
+/// Masking is applied when the frame is sent and removed when it is received, depending on which
+/// side of the connection the frame comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Set on the last frame of a message
+    pub fin: bool,
+    pub opcode: OpCode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(fin: bool, opcode: OpCode, payload: Vec<u8>) -> Self {
+        Self {
+            fin,
+            opcode,
+            payload,
+        }
+    }
+}
+
+/// The longest payload a control frame can have
+pub(crate) const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// Returns the frame at the start of `buf`, whether it was masked and its length in bytes, or
+/// `None` if the frame is not complete yet
+pub(crate) fn parse_frame(
+    buf: &[u8],
+    max_payload: usize,
+) -> Result<Option<(Frame, bool, usize)>, WebSocketError> {
+    let [first, second, ..] = *buf else {
+        return Ok(None);
+    };
+
+    // No extensions are negotiated, so the reserved bits have to be clear
+    if first & 0x70 != 0 {
+        return Err(WebSocketError::Protocol("Reserved bits set"));
+    }
+
+    let fin = first & 0x80 != 0;
+    let opcode = OpCode::from_u8(first & 0x0F).ok_or(WebSocketError::Protocol("Unknown opcode"))?;
+    let masked = second & 0x80 != 0;
+
+    let (len, mut pos) = match second & 0x7F {
+        126 => match buf.get(2..4) {
+            Some(len) => (u16::from_be_bytes(len.try_into().unwrap()) as u64, 4),
+            None => return Ok(None),
+        },
+        127 => match buf.get(2..10) {
+            Some(len) => (u64::from_be_bytes(len.try_into().unwrap()), 10),
+            None => return Ok(None),
+        },
+        len => (len as u64, 2),
+    };
+
+    if len >> 63 != 0 {
+        return Err(WebSocketError::Protocol("Invalid payload length"));
+    }
+
+    if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+        return Err(WebSocketError::Protocol(
+            "Fragmented or oversized control frame",
+        ));
+    }
+
+    if len > max_payload as u64 {
+        return Err(WebSocketError::MessageTooLarge);
+    }
+    let len = len as usize;
+
+    let mask = match masked {
+        true => match buf.get(pos..pos + 4) {
+            Some(mask) => {
+                pos += 4;
+                Some(<[u8; 4]>::try_from(mask).unwrap())
+            }
+            None => return Ok(None),
+        },
+        false => None,
+    };
+
+    if buf.len() - pos < len {
+        return Ok(None);
+    }
+
+    let mut payload = buf[pos..pos + len].to_vec();
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+
+    Ok(Some((Frame::new(fin, opcode, payload), masked, pos + len)))
+}
+
+/// Encodes `frame`, masking its payload with `mask` if there is one
+pub(crate) fn encode_frame(frame: &Frame, mask: Option<[u8; 4]>) -> Vec<u8> {
+    let len = frame.payload.len();
+    let mut bytes = Vec::with_capacity(len + 14);
+
+    bytes.push(((frame.fin as u8) << 7) | frame.opcode.as_u8());
+
+    let mask_bit = (mask.is_some() as u8) << 7;
+    match len {
+        0..=125 => bytes.push(mask_bit | len as u8),
+        126..=0xFFFF => {
+            bytes.push(mask_bit | 126);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            bytes.push(mask_bit | 127);
+            bytes.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    let start = bytes.len();
+    match mask {
+        Some(mask) => {
+            bytes.extend_from_slice(&mask);
+            bytes.extend_from_slice(&frame.payload);
+            apply_mask(&mut bytes[start + 4..], mask);
+        }
+        None => bytes.extend_from_slice(&frame.payload),
+    }
+
+    bytes
+}
+
+/// Masking and unmasking are the same operation (RFC 6455 5.3)
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rfc_examples() {
+        // A single-frame unmasked and a masked text message (RFC 6455 5.7)
+        let unmasked = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let hello = Frame::new(true, OpCode::Text, b"Hello".to_vec());
+
+        assert_eq!(
+            parse_frame(&unmasked, 1024).unwrap(),
+            Some((hello.clone(), false, 7))
+        );
+        assert_eq!(
+            parse_frame(&masked, 1024).unwrap(),
+            Some((hello.clone(), true, 11))
+        );
+        assert_eq!(encode_frame(&hello, None), unmasked);
+        assert_eq!(encode_frame(&hello, Some([0x37, 0xfa, 0x21, 0x3d])), masked);
+
+        for end in 0..masked.len() {
+            assert!(parse_frame(&masked[..end], 1024).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn lengths_and_limits() {
+        for len in [125, 126, 0xFFFF, 0x10000] {
+            let frame = Frame::new(true, OpCode::Binary, vec![7; len]);
+            let bytes = encode_frame(&frame, Some([1, 2, 3, 4]));
+
+            let (parsed, _, parsed_len) = parse_frame(&bytes, usize::MAX).unwrap().unwrap();
+            assert_eq!(parsed, frame);
+            assert_eq!(parsed_len, bytes.len());
+        }
+
+        let big = encode_frame(&Frame::new(true, OpCode::Binary, vec![0; 200]), None);
+        assert!(matches!(
+            parse_frame(&big, 100),
+            Err(WebSocketError::MessageTooLarge)
+        ));
+
+        let fragmented_ping = [0x09, 0x00];
+        assert!(matches!(
+            parse_frame(&fragmented_ping, 100),
+            Err(WebSocketError::Protocol(_))
+        ));
+    }
+}
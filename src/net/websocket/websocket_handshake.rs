@@ -0,0 +1,141 @@
+use super::WebSocketError;
+use crate::encoding::base64;
+use crate::hash::sha1;
+use crate::net::http::{HttpRequest, HttpResponse, Method, StatusCode, Version};
+
+/// Appended to the client's key before hashing it (RFC 6455 1.3)
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const VERSION: &str = "13";
+
+/// The `Sec-WebSocket-Accept` value that answers a `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    base64::encode(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+/// Whether `request` asks to be upgraded to a WebSocket, use [upgrade_response] to find out if
+/// the request is also valid
+pub fn is_upgrade_request(request: &HttpRequest) -> bool {
+    request.get_headers().contains_token("Upgrade", "websocket")
+}
+
+/// Checks the opening handshake in `request` and returns the 101 response that completes it
+/// (RFC 6455 4.2)
+pub fn upgrade_response(request: &HttpRequest) -> Result<HttpResponse, WebSocketError> {
+    let headers = request.get_headers();
+
+    if request.get_method() != &Method::Get || request.get_version() != Version::Http11 {
+        return Err(WebSocketError::Handshake("Not an HTTP/1.1 GET request"));
+    }
+
+    if !headers.contains_token("Upgrade", "websocket")
+        || !headers.contains_token("Connection", "upgrade")
+    {
+        return Err(WebSocketError::Handshake("Missing Upgrade or Connection"));
+    }
+
+    if headers.get("Sec-WebSocket-Version") != Some(VERSION) {
+        return Err(WebSocketError::UnsupportedVersion);
+    }
+
+    let key = headers
+        .get("Sec-WebSocket-Key")
+        .filter(|key| base64::decode(key).is_ok_and(|key| key.len() == 16))
+        .ok_or(WebSocketError::Handshake("Invalid Sec-WebSocket-Key"))?;
+
+    Ok(HttpResponse::builder()
+        .set_version(Version::Http11)
+        .set_status_code(StatusCode::SWITCHING_PROTOCOLS)
+        .set_header("Upgrade".into(), "websocket".into())
+        .set_header("Connection".into(), "Upgrade".into())
+        .set_header("Sec-WebSocket-Accept".into(), accept_key(key))
+        .set_body(Box::new([]))
+        .build()
+        .unwrap())
+}
+
+/// The response to a handshake that failed, a version the server doesn't speak is answered with
+/// the version it does
+pub(crate) fn rejection_response(err: &WebSocketError) -> HttpResponse {
+    match err {
+        WebSocketError::UnsupportedVersion => {
+            let mut response = HttpResponse::from_status(StatusCode::UPGRADE_REQUIRED);
+            response.set_header("Sec-WebSocket-Version".into(), VERSION.into());
+            response
+        }
+        _ => HttpResponse::from_status(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// The client's side of the opening handshake, along with the key the response has to answer
+pub(crate) fn handshake_request(host: &str, target: &str) -> (HttpRequest, String) {
+    let key = base64::encode(&crate::util::random_bytes::<16>());
+
+    let request = HttpRequest::builder()
+        .set_method(Method::Get)
+        .set_url(target.to_string())
+        .set_version(Version::Http11)
+        .set_header("Host".into(), host.to_string())
+        .set_header("Upgrade".into(), "websocket".into())
+        .set_header("Connection".into(), "Upgrade".into())
+        .set_header("Sec-WebSocket-Key".into(), key.clone())
+        .set_header("Sec-WebSocket-Version".into(), VERSION.into())
+        .set_body(Box::new([]))
+        .build()
+        .unwrap();
+
+    (request, key)
+}
+
+/// Checks that `response` completes the handshake started with `key`
+pub(crate) fn check_handshake_response(
+    response: &HttpResponse,
+    key: &str,
+) -> Result<(), WebSocketError> {
+    let headers = response.get_headers();
+
+    if response.get_status_code() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(WebSocketError::Handshake("Server did not switch protocols"));
+    }
+
+    if !headers.contains_token("Upgrade", "websocket")
+        || !headers.contains_token("Connection", "upgrade")
+    {
+        return Err(WebSocketError::Handshake("Missing Upgrade or Connection"));
+    }
+
+    if headers.get("Sec-WebSocket-Accept") != Some(accept_key(key).as_str()) {
+        return Err(WebSocketError::Handshake("Invalid Sec-WebSocket-Accept"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn handshake() {
+        // RFC 6455 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let (request, key) = handshake_request("example.com", "/chat");
+        assert!(is_upgrade_request(&request));
+
+        let response = upgrade_response(&request).unwrap();
+        check_handshake_response(&response, &key).unwrap();
+        assert!(check_handshake_response(&response, "c29tZSBvdGhlciBrZXk=").is_err());
+
+        let mut request = request;
+        request.set_header("Sec-WebSocket-Version".into(), "8".into());
+        let err = upgrade_response(&request).unwrap_err();
+        assert_eq!(
+            rejection_response(&err).get_status_code(),
+            StatusCode::UPGRADE_REQUIRED
+        );
+    }
+}
@@ -0,0 +1,569 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use super::websocket_frame::{encode_frame, parse_frame, MAX_CONTROL_PAYLOAD};
+use super::websocket_handshake::{
+    check_handshake_response, handshake_request, rejection_response, upgrade_response,
+};
+use super::{CloseCode, CloseFrame, Frame, Message, OpCode, Role, WebSocketError};
+use crate::io::{IntoSplit, SplitMut};
+use crate::net::http::{HttpRequest, HttpResponse, HttpStream, Method};
+use crate::net::url::Url;
+
+/// Receives WebSocket messages, see [WebSocket::recv]
+///
+/// On its own a reader doesn't answer pings or Close frames, that is up to whoever holds the
+/// matching [WebSocketWriter]
+pub struct WebSocketReader<R: Read> {
+    rx: R,
+    buf: Vec<u8>,
+    role: Role,
+    max_message_size: usize,
+    /// The opcode and data of a fragmented message that is still being received
+    partial: Option<(OpCode, Vec<u8>)>,
+    closed: bool,
+}
+
+impl<R: Read> WebSocketReader<R> {
+    /// `buf` holds bytes that were already read from `rx`
+    fn new(rx: R, buf: Vec<u8>, role: Role) -> Self {
+        Self {
+            rx,
+            buf,
+            role,
+            max_message_size: 16 * 1024 * 1024,
+            partial: None,
+            closed: false,
+        }
+    }
+
+    /// Messages and frames over the limit fail with [WebSocketError::MessageTooLarge], defaults
+    /// to 16 MiB
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+
+    /// Whether a Close frame has been received
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Receives the next message, reassembling fragmented messages. Control messages sent in
+    /// between fragments are returned as they arrive
+    ///
+    /// After a [Message::Close] nothing more can be received. If the underlying reader times out
+    /// or would block, whatever was received so far is kept for the next call
+    pub fn recv(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let frame = self.recv_frame()?;
+
+            let payload = match frame.opcode {
+                OpCode::Ping => return Ok(Message::Ping(frame.payload)),
+                OpCode::Pong => return Ok(Message::Pong(frame.payload)),
+                OpCode::Close => {
+                    self.closed = true;
+                    return parse_close(frame.payload).map(Message::Close);
+                }
+                OpCode::Continuation => {
+                    let Some((_, data)) = &mut self.partial else {
+                        return Err(WebSocketError::Protocol("Continuation of no message"));
+                    };
+
+                    if data.len() + frame.payload.len() > self.max_message_size {
+                        return Err(WebSocketError::MessageTooLarge);
+                    }
+                    data.extend_from_slice(&frame.payload);
+
+                    if !frame.fin {
+                        continue;
+                    }
+
+                    self.partial.take().unwrap()
+                }
+                OpCode::Text | OpCode::Binary => {
+                    if self.partial.is_some() {
+                        return Err(WebSocketError::Protocol(
+                            "New message before the last one was finished",
+                        ));
+                    }
+
+                    if !frame.fin {
+                        self.partial = Some((frame.opcode, frame.payload));
+                        continue;
+                    }
+
+                    (frame.opcode, frame.payload)
+                }
+            };
+
+            return match payload {
+                (OpCode::Text, data) => String::from_utf8(data)
+                    .map(Message::Text)
+                    .map_err(|_| WebSocketError::InvalidUtf8),
+                (_, data) => Ok(Message::Binary(data)),
+            };
+        }
+    }
+
+    /// Receives a single frame without reassembling messages
+    pub fn recv_frame(&mut self) -> Result<Frame, WebSocketError> {
+        if self.closed {
+            return Err(WebSocketError::AlreadyClosed);
+        }
+
+        loop {
+            if let Some((frame, masked, len)) = parse_frame(&self.buf, self.max_message_size)? {
+                self.buf.drain(..len);
+
+                // Clients mask every frame, servers never do (RFC 6455 5.1)
+                if masked != (self.role == Role::Server) {
+                    return Err(WebSocketError::Protocol(match masked {
+                        true => "Masked frame from the server",
+                        false => "Unmasked frame from the client",
+                    }));
+                }
+
+                return Ok(frame);
+            }
+
+            self.fill()?;
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), WebSocketError> {
+        let mut buffer = [0_u8; 8192];
+
+        loop {
+            match self.rx.read(&mut buffer) {
+                Ok(0) => return Err(WebSocketError::ConnectionClosed),
+                Ok(bytes_read) => {
+                    self.buf.extend_from_slice(&buffer[..bytes_read]);
+                    return Ok(());
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Sends WebSocket messages, see [WebSocket::send]
+pub struct WebSocketWriter<W: Write> {
+    tx: W,
+    role: Role,
+    max_frame_size: Option<usize>,
+    closed: bool,
+}
+
+impl<W: Write> WebSocketWriter<W> {
+    fn new(tx: W, role: Role) -> Self {
+        Self {
+            tx,
+            role,
+            max_frame_size: None,
+            closed: false,
+        }
+    }
+
+    /// Text and binary messages with more data than this are sent as several frames, by default
+    /// every message is sent as a single frame
+    pub fn set_max_frame_size(&mut self, max_frame_size: Option<usize>) {
+        self.max_frame_size = max_frame_size.map(|size| size.max(1));
+    }
+
+    /// Whether a Close frame has been sent
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Fails with [WebSocketError::AlreadyClosed] once a Close frame has been sent
+    pub fn send(&mut self, message: &Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Text(text) => self.send_data(OpCode::Text, text.as_bytes()),
+            Message::Binary(data) => self.send_data(OpCode::Binary, data),
+            Message::Ping(payload) => self.send_control(OpCode::Ping, payload.clone()),
+            Message::Pong(payload) => self.send_control(OpCode::Pong, payload.clone()),
+            Message::Close(frame) => self.send_close(frame.as_ref()),
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<(), WebSocketError> {
+        self.send_data(OpCode::Text, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError> {
+        self.send_data(OpCode::Binary, data)
+    }
+
+    /// Panics if `payload` is longer than 125 bytes
+    pub fn send_ping(&mut self, payload: &[u8]) -> Result<(), WebSocketError> {
+        self.send_control(OpCode::Ping, payload.to_vec())
+    }
+
+    /// Starts the close handshake, the peer answers with a Close frame of its own. Panics if
+    /// `reason` is longer than 123 bytes
+    pub fn close(&mut self, code: CloseCode, reason: &str) -> Result<(), WebSocketError> {
+        self.send_close(Some(&CloseFrame {
+            code,
+            reason: reason.to_string(),
+        }))
+    }
+
+    /// Sends a single frame as is, which allows fragmenting messages by hand
+    pub fn send_frame(&mut self, frame: &Frame) -> Result<(), WebSocketError> {
+        if self.closed {
+            return Err(WebSocketError::AlreadyClosed);
+        }
+
+        let mask = match self.role {
+            Role::Client => Some(crate::util::random_bytes::<4>()),
+            Role::Server => None,
+        };
+
+        self.tx.write_all(&encode_frame(frame, mask))?;
+        self.tx.flush()?;
+
+        if frame.opcode == OpCode::Close {
+            self.closed = true;
+        }
+
+        Ok(())
+    }
+
+    fn send_data(&mut self, opcode: OpCode, data: &[u8]) -> Result<(), WebSocketError> {
+        let frame_size = self.max_frame_size.unwrap_or(data.len()).max(1);
+        let mut chunks = data.chunks(frame_size).peekable();
+
+        if chunks.peek().is_none() {
+            return self.send_frame(&Frame::new(true, opcode, Vec::new()));
+        }
+
+        let mut opcode = opcode;
+        while let Some(chunk) = chunks.next() {
+            let fin = chunks.peek().is_none();
+            self.send_frame(&Frame::new(fin, opcode, chunk.to_vec()))?;
+            opcode = OpCode::Continuation;
+        }
+
+        Ok(())
+    }
+
+    fn send_control(&mut self, opcode: OpCode, payload: Vec<u8>) -> Result<(), WebSocketError> {
+        assert!(
+            payload.len() <= MAX_CONTROL_PAYLOAD,
+            "Control frame payloads are limited to 125 bytes"
+        );
+
+        self.send_frame(&Frame::new(true, opcode, payload))
+    }
+
+    fn send_close(&mut self, frame: Option<&CloseFrame>) -> Result<(), WebSocketError> {
+        let payload = match frame {
+            Some(frame) => {
+                let mut payload = frame.code.as_u16().to_be_bytes().to_vec();
+                payload.extend_from_slice(frame.reason.as_bytes());
+                payload
+            }
+            None => Vec::new(),
+        };
+
+        self.send_control(OpCode::Close, payload)
+    }
+}
+
+/// A WebSocket connection over any reader and writer pair
+///
+/// Unlike its split halves, a WebSocket answers pings with pongs and a Close frame with a Close
+/// frame of its own by itself, and fails the connection with the fitting close code when the
+/// peer breaks the protocol
+pub struct WebSocket<R: Read, W: Write> {
+    reader: WebSocketReader<R>,
+    writer: WebSocketWriter<W>,
+}
+
+impl<R: Read, W: Write> WebSocket<R, W> {
+    /// Wraps a connection that has already been upgraded, `buf` holds any bytes that were already
+    /// received after the handshake
+    pub fn from_parts(reader: R, writer: W, buf: Vec<u8>, role: Role) -> Self {
+        Self {
+            reader: WebSocketReader::new(reader, buf, role),
+            writer: WebSocketWriter::new(writer, role),
+        }
+    }
+
+    /// Completes the opening handshake for `request`, which was received on `http`, as the server
+    ///
+    /// If the handshake is invalid the request is answered with a 400, or a 426 for an
+    /// unsupported version, and the error is returned
+    pub fn accept(
+        mut http: HttpStream<R, W>,
+        request: &HttpRequest,
+    ) -> Result<Self, WebSocketError> {
+        let response = match upgrade_response(request) {
+            Ok(response) => response,
+            Err(err) => {
+                http.send_response(&rejection_response(&err))?;
+                return Err(err);
+            }
+        };
+
+        http.send_response(&response)?;
+
+        let (reader, writer, buf) = http.into_upgraded()?;
+        Ok(Self::from_parts(reader, writer, buf, Role::Server))
+    }
+
+    /// Performs the opening handshake over `http` as the client, asking for `target` on `host`,
+    /// and returns the WebSocket along with the server's response
+    pub fn connect(
+        mut http: HttpStream<R, W>,
+        host: &str,
+        target: &str,
+    ) -> Result<(Self, HttpResponse), WebSocketError> {
+        let (request, key) = handshake_request(host, target);
+        http.send_request(&request)?;
+
+        let response = http.recv_response_to(&Method::Get)?;
+        check_handshake_response(&response, &key)?;
+
+        let (reader, writer, buf) = http.into_upgraded()?;
+        Ok((
+            Self::from_parts(reader, writer, buf, Role::Client),
+            response,
+        ))
+    }
+
+    pub fn get_reader(&self) -> &WebSocketReader<R> {
+        &self.reader
+    }
+
+    pub fn get_writer(&self) -> &WebSocketWriter<W> {
+        &self.writer
+    }
+
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.reader.set_max_message_size(max_message_size);
+    }
+
+    pub fn set_max_frame_size(&mut self, max_frame_size: Option<usize>) {
+        self.writer.set_max_frame_size(max_frame_size);
+    }
+
+    /// See [WebSocketReader::recv]
+    ///
+    /// Pings are answered before they are returned. Once a [Message::Close] is returned the close
+    /// handshake is complete and the connection can be dropped
+    pub fn recv(&mut self) -> Result<Message, WebSocketError> {
+        let received = self.reader.recv();
+
+        if self.writer.closed {
+            return received;
+        }
+
+        match &received {
+            Ok(Message::Ping(payload)) => {
+                self.writer.send_control(OpCode::Pong, payload.clone())?
+            }
+            // Echo the code, but not the reason (RFC 6455 5.5.1)
+            Ok(Message::Close(frame)) => self.writer.send_close(
+                frame
+                    .as_ref()
+                    .map(|frame| CloseFrame {
+                        code: frame.code,
+                        reason: String::new(),
+                    })
+                    .as_ref(),
+            )?,
+            Err(err) => {
+                if let Some(code) = err.close_code() {
+                    let _ = self.writer.close(code, "");
+                }
+            }
+            _ => {}
+        }
+
+        received
+    }
+
+    /// See [WebSocketWriter::send]
+    pub fn send(&mut self, message: impl Into<Message>) -> Result<(), WebSocketError> {
+        self.writer.send(&message.into())
+    }
+
+    /// See [WebSocketWriter::send_ping]
+    pub fn send_ping(&mut self, payload: &[u8]) -> Result<(), WebSocketError> {
+        self.writer.send_ping(payload)
+    }
+
+    /// Starts the close handshake, keep receiving until the peer's [Message::Close] arrives. See
+    /// [WebSocketWriter::close]
+    pub fn close(&mut self, code: CloseCode, reason: &str) -> Result<(), WebSocketError> {
+        self.writer.close(code, reason)
+    }
+
+    /// Whether Close frames have been both sent and received
+    pub fn is_closed(&self) -> bool {
+        self.reader.closed && self.writer.closed
+    }
+}
+
+impl WebSocket<TcpStream, TcpStream> {
+    /// Connects to a `ws` URL, `wss` isn't supported
+    pub fn connect_url(url: &str) -> Result<(Self, HttpResponse), WebSocketError> {
+        let url = Url::parse(url).map_err(|_| WebSocketError::Handshake("Invalid URL"))?;
+
+        if url.scheme() != Some("ws") {
+            return Err(WebSocketError::Handshake("Only ws URLs are supported"));
+        }
+
+        let host = url
+            .host()
+            .filter(|host| !host.is_empty())
+            .ok_or(WebSocketError::Handshake("Invalid URL"))?;
+        let port = url.port_or_default().unwrap_or(80);
+
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_nodelay(true)?;
+
+        let authority = match host.contains(':') {
+            true => format!("[{}]", host),
+            false => host.to_string(),
+        };
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", authority, port),
+            None => authority,
+        };
+
+        let http = HttpStream::from_parts(stream.try_clone()?, stream);
+        Self::connect(http, &authority, &url.path_and_query())
+    }
+}
+
+impl<'ws, R: Read + 'ws, W: Write + 'ws>
+    SplitMut<'ws, &'ws mut WebSocketReader<R>, &'ws mut WebSocketWriter<W>> for WebSocket<R, W>
+{
+    fn split_mut(&'ws mut self) -> (&'ws mut WebSocketReader<R>, &'ws mut WebSocketWriter<W>) {
+        (&mut self.reader, &mut self.writer)
+    }
+}
+
+impl<R: Read, W: Write> IntoSplit<WebSocketReader<R>, WebSocketWriter<W>> for WebSocket<R, W> {
+    fn into_split(self) -> (WebSocketReader<R>, WebSocketWriter<W>) {
+        (self.reader, self.writer)
+    }
+}
+
+/// A Close frame's payload is empty or a code followed by a UTF-8 reason (RFC 6455 5.5.1)
+fn parse_close(payload: Vec<u8>) -> Result<Option<CloseFrame>, WebSocketError> {
+    if payload.is_empty() {
+        return Ok(None);
+    }
+
+    let [high, low, ..] = payload[..] else {
+        return Err(WebSocketError::Protocol("Close frame with half a code"));
+    };
+
+    let code = CloseCode::new(u16::from_be_bytes([high, low]));
+    if !code.is_valid() {
+        return Err(WebSocketError::Protocol("Invalid close code"));
+    }
+
+    let reason =
+        String::from_utf8(payload[2..].to_vec()).map_err(|_| WebSocketError::InvalidUtf8)?;
+
+    Ok(Some(CloseFrame { code, reason }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn fragments_and_control_frames() {
+        // Sent by a client: "Hel" + ping + "lo", then a close
+        let mut input = Vec::new();
+        for frame in [
+            Frame::new(false, OpCode::Text, b"Hel".to_vec()),
+            Frame::new(true, OpCode::Ping, b"?".to_vec()),
+            Frame::new(true, OpCode::Continuation, b"lo".to_vec()),
+            Frame::new(true, OpCode::Close, vec![0x03, 0xE8, b'o', b'k']),
+        ] {
+            input.extend(encode_frame(&frame, Some([9, 8, 7, 6])));
+        }
+
+        let mut output = Vec::new();
+        let mut ws = WebSocket::from_parts(&input[..], &mut output, Vec::new(), Role::Server);
+
+        assert_eq!(ws.recv().unwrap(), Message::Ping(b"?".to_vec()));
+        assert_eq!(ws.recv().unwrap(), Message::Text("Hello".into()));
+        assert_eq!(
+            ws.recv().unwrap(),
+            Message::Close(Some(CloseFrame {
+                code: CloseCode::NORMAL,
+                reason: "ok".into(),
+            }))
+        );
+        assert!(ws.is_closed());
+        assert!(matches!(ws.recv(), Err(WebSocketError::AlreadyClosed)));
+        drop(ws);
+
+        // The pong and the echoed close, unmasked
+        assert_eq!(output, [0x8A, 0x01, b'?', 0x88, 0x02, 0x03, 0xE8]);
+    }
+
+    #[test]
+    fn protocol_errors_fail_the_connection() {
+        // An unmasked frame from a client
+        let input = encode_frame(&Frame::new(true, OpCode::Text, b"hi".to_vec()), None);
+
+        let mut output = Vec::new();
+        let mut ws = WebSocket::from_parts(&input[..], &mut output, Vec::new(), Role::Server);
+
+        assert!(matches!(ws.recv(), Err(WebSocketError::Protocol(_))));
+        drop(ws);
+        assert_eq!(output, [0x88, 0x02, 0x03, 0xEA]);
+    }
+
+    #[test]
+    fn echo_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut http = HttpStream::new(&stream).unwrap();
+            let request = http.recv_request().unwrap();
+
+            let mut ws = WebSocket::accept(http, &request).unwrap();
+            loop {
+                match ws.recv().unwrap() {
+                    Message::Close(_) => break,
+                    message => ws.send(message).unwrap(),
+                }
+            }
+        });
+
+        let (mut ws, response) = WebSocket::connect_url(&format!("ws://{}/echo", addr)).unwrap();
+        assert_eq!(response.get_header("Upgrade"), Some("websocket"));
+
+        ws.set_max_frame_size(Some(4));
+        ws.send("hello there").unwrap();
+        assert_eq!(ws.recv().unwrap(), Message::Text("hello there".into()));
+
+        let (reader, writer) = ws.split_mut();
+        writer.send_binary(&[1, 2, 3]).unwrap();
+        assert_eq!(reader.recv().unwrap(), Message::Binary(vec![1, 2, 3]));
+
+        ws.close(CloseCode::GOING_AWAY, "bye").unwrap();
+        assert_eq!(
+            ws.recv().unwrap(),
+            Message::Close(Some(CloseFrame {
+                code: CloseCode::GOING_AWAY,
+                reason: String::new(),
+            }))
+        );
+
+        server.join().unwrap();
+    }
+}
@@ -42,3 +42,28 @@ impl IDGen {
         self.available_ids.clear();
     }
 }
+
+/// Random bytes for values that only have to be unpredictable to a peer, such as WebSocket
+/// masking keys. They come from std's randomly keyed SipHash rather than a vetted CSPRNG, so
+/// they must not be used for keys or secrets
+pub(crate) fn random_bytes<const N: usize>() -> [u8; N] {
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut bytes = [0; N];
+
+    for chunk in bytes.chunks_mut(8) {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+
+        if let Ok(elapsed) = std::time::UNIX_EPOCH.elapsed() {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+
+    bytes
+}